@@ -0,0 +1,25 @@
+use std::process::Command;
+
+fn main() {
+    // Lets artifacts name the exact commit that produced them. Builds from a
+    // source tarball have no git metadata and simply leave it unset.
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+    let version = env!("CARGO_PKG_VERSION");
+    match commit.as_deref().map(str::trim) {
+        Some(commit) => {
+            println!("cargo:rustc-env=RIMUSIC_GIT_COMMIT={}", commit);
+            println!(
+                "cargo:rustc-env=RIMUSIC_LONG_VERSION={} ({})",
+                version, commit
+            );
+        }
+        None => println!("cargo:rustc-env=RIMUSIC_LONG_VERSION={}", version),
+    }
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+}
@@ -0,0 +1,144 @@
+//! `--group-by album`: one CSV per album instead of one per playlist, each
+//! holding every track of the album found anywhere in the library.
+
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{collections::HashMap, error::Error};
+
+use crate::{
+    api::TrackWithContext,
+    export::PlaylistExport,
+    filter::TrackFilter,
+    output::OutputConfig,
+    paths::OutputPaths,
+    provenance::Provenance,
+    record::{write_track_records, Field, RecordOptions, TrackRecord},
+};
+
+/// Separates playlist names in the Playlists column; names often hold
+/// commas.
+const PLAYLIST_SEPARATOR: &str = "; ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum GroupBy {
+    /// One file per playlist
+    #[default]
+    Playlist,
+    /// One file per album, across every playlist
+    Album,
+}
+
+/// Every playlist item by album URI, or by album name for local files,
+/// in playlist order. Unavailable items and tracks without either are
+/// left out.
+pub fn group_tracks_by_album(exports: &[PlaylistExport]) -> HashMap<String, Vec<TrackWithContext>> {
+    let mut albums: HashMap<String, Vec<TrackWithContext>> = HashMap::new();
+    for export in exports {
+        for (position, item) in export.tracks.iter().enumerate() {
+            let Some(track) = &item.track else { continue };
+            let Some(key) = track.album.uri.as_ref().or(track.album.name.as_ref()) else {
+                continue;
+            };
+            albums
+                .entry(key.clone())
+                .or_default()
+                .push(TrackWithContext {
+                    playlist_id: export.playlist.id.clone(),
+                    playlist_name: export.playlist.name.clone(),
+                    position,
+                    item: item.clone(),
+                });
+        }
+    }
+    albums
+}
+
+/// Writes one CSV per album named after it, with one row per track in
+/// album order and a Playlists column naming where each was found. Returns
+/// the files written.
+pub fn write_album_groups(
+    exports: &[PlaylistExport],
+    config: &OutputConfig,
+    options: RecordOptions,
+    filter: &TrackFilter,
+    provenance: &Provenance,
+    paths: &mut OutputPaths,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let owners: HashMap<&str, &str> = exports
+        .iter()
+        .map(|export| {
+            (
+                export.playlist.id.as_str(),
+                export.playlist.owner.display_name.as_str(),
+            )
+        })
+        .collect();
+    let mut fields = config.fields.clone();
+    fields.push(Field::Playlists);
+    let preamble = if config.csv_preamble {
+        Some(provenance.to_preamble()?)
+    } else {
+        None
+    };
+
+    let mut albums: Vec<(String, Vec<TrackRecord>)> = Vec::new();
+    for tracks in group_tracks_by_album(exports).into_values() {
+        let mut records: Vec<TrackRecord> = Vec::new();
+        let mut playlists: Vec<Vec<&str>> = Vec::new();
+        let mut rows: HashMap<String, usize> = HashMap::new();
+        for entry in &tracks {
+            let Some(track) = &entry.item.track else {
+                continue;
+            };
+            let record = TrackRecord::from_track(
+                track,
+                owners
+                    .get(entry.playlist_id.as_str())
+                    .copied()
+                    .unwrap_or_default(),
+                chrono::Utc::now().to_string(),
+                options,
+            );
+            let row = *rows.entry(record.identity_key()).or_insert_with(|| {
+                records.push(record);
+                playlists.push(Vec::new());
+                records.len() - 1
+            });
+            if !playlists[row].contains(&entry.playlist_name.as_str()) {
+                playlists[row].push(&entry.playlist_name);
+            }
+        }
+        for (record, names) in records.iter_mut().zip(playlists) {
+            record.playlists = Some(names.join(PLAYLIST_SEPARATOR));
+        }
+        let mut records = filter.apply(records);
+        if records.is_empty() {
+            continue;
+        }
+        records.sort_by_key(|record| (record.disc_number, record.track_number));
+        let name = records[0]
+            .album_name
+            .clone()
+            .unwrap_or_else(|| "Unknown Album".to_string());
+        albums.push((name, records));
+    }
+    // Albums sharing a name get numbered suffixes in a stable order.
+    albums.sort_by(|a, b| {
+        a.0.cmp(&b.0)
+            .then_with(|| a.1[0].album_uri.cmp(&b.1[0].album_uri))
+    });
+
+    let mut written = Vec::with_capacity(albums.len());
+    for (name, records) in albums {
+        let file_name = paths.reserve(&name, "csv");
+        write_track_records(
+            &paths.path(&file_name),
+            &records,
+            &fields,
+            preamble.as_deref(),
+        )?;
+        written.push(file_name);
+    }
+    Ok(written)
+}
@@ -0,0 +1,218 @@
+//! Spotting where a playlist holds an album rather than loose tracks: a run
+//! of consecutive entries from one album, in the album's own track order.
+//! Marked runs can be imported as an album reference instead.
+
+use crate::record::TrackRecord;
+
+/// How strictly a run has to follow the album.
+#[derive(Debug, Clone, Copy)]
+pub struct AlbumRunRules {
+    /// Fewest entries that make a run.
+    pub min_length: usize,
+    /// Largest step in track number between neighbouring entries, so 1
+    /// means no track of the album may be missing from the run.
+    pub max_gap: u32,
+}
+
+/// `next` follows `previous` in album order within `max_gap` tracks. A
+/// missing disc number counts as disc 1; a missing track number never
+/// follows anything.
+fn follows(previous: &TrackRecord, next: &TrackRecord, max_gap: u32) -> bool {
+    if previous.album_uri.is_none() || previous.album_uri != next.album_uri {
+        return false;
+    }
+    let (Some(previous_track), Some(next_track)) = (previous.track_number, next.track_number)
+    else {
+        return false;
+    };
+    let previous_disc = previous.disc_number.unwrap_or(1);
+    let next_disc = next.disc_number.unwrap_or(1);
+    if next_disc == previous_disc {
+        next_track > previous_track && next_track - previous_track <= max_gap
+    } else {
+        // Starting the next disc; earlier tracks missing from it count
+        // against the gap like any other.
+        next_disc == previous_disc + 1 && next_track <= max_gap
+    }
+}
+
+/// Sets `album_run` to the album's URI on every entry of a qualifying run,
+/// clearing it everywhere else.
+pub fn mark_album_runs(records: &mut [TrackRecord], rules: AlbumRunRules) {
+    let mut start = 0;
+    while start < records.len() {
+        let mut end = start + 1;
+        while end < records.len() && follows(&records[end - 1], &records[end], rules.max_gap) {
+            end += 1;
+        }
+        let album = (end - start >= rules.min_length.max(2))
+            .then(|| records[start].album_uri.clone())
+            .flatten();
+        for record in &mut records[start..end] {
+            record.album_run = album.clone();
+        }
+        start = end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::tests::sample_record;
+
+    fn entry(album: &str, disc: u32, track: u32) -> TrackRecord {
+        let mut record = sample_record(
+            &format!("spotify:track:{}-{}-{}", album, disc, track),
+            album,
+        );
+        record.album_uri = Some(format!("spotify:album:{}", album));
+        record.disc_number = Some(disc);
+        record.track_number = Some(track);
+        record
+    }
+
+    fn runs(mut records: Vec<TrackRecord>, min_length: usize, max_gap: u32) -> Vec<Option<String>> {
+        mark_album_runs(
+            &mut records,
+            AlbumRunRules {
+                min_length,
+                max_gap,
+            },
+        );
+        records
+            .into_iter()
+            .map(|record| {
+                record
+                    .album_run
+                    .map(|uri| uri["spotify:album:".len()..].to_string())
+            })
+            .collect()
+    }
+
+    fn marked(albums: &[Option<&str>]) -> Vec<Option<String>> {
+        albums
+            .iter()
+            .map(|album| album.map(str::to_string))
+            .collect()
+    }
+
+    #[test]
+    fn interleaved_albums_are_not_runs() {
+        let records = vec![
+            entry("a", 1, 1),
+            entry("b", 1, 1),
+            entry("a", 1, 2),
+            entry("b", 1, 2),
+            entry("a", 1, 3),
+            entry("b", 1, 3),
+        ];
+        assert_eq!(runs(records, 2, 1), marked(&[None; 6]));
+    }
+
+    #[test]
+    fn marks_runs_between_loose_tracks() {
+        let records = vec![
+            entry("single", 1, 4),
+            entry("a", 1, 1),
+            entry("a", 1, 2),
+            entry("a", 1, 3),
+            entry("b", 1, 7),
+            entry("c", 1, 1),
+            entry("c", 1, 2),
+            entry("c", 1, 3),
+            entry("c", 1, 4),
+        ];
+        assert_eq!(
+            runs(records, 3, 1),
+            marked(&[
+                None,
+                Some("a"),
+                Some("a"),
+                Some("a"),
+                None,
+                Some("c"),
+                Some("c"),
+                Some("c"),
+                Some("c"),
+            ])
+        );
+    }
+
+    #[test]
+    fn minimum_length_is_configurable() {
+        let records = || vec![entry("a", 1, 1), entry("a", 1, 2), entry("b", 1, 5)];
+        assert_eq!(runs(records(), 3, 1), marked(&[None, None, None]));
+        assert_eq!(runs(records(), 2, 1), marked(&[Some("a"), Some("a"), None]));
+        // A single entry is never a run, whatever the setting.
+        assert_eq!(runs(vec![entry("a", 1, 1)], 0, 1), marked(&[None]));
+    }
+
+    #[test]
+    fn gap_tolerance_allows_skipped_tracks() {
+        let records = || {
+            vec![
+                entry("a", 1, 1),
+                entry("a", 1, 3),
+                entry("a", 1, 4),
+                entry("a", 1, 7),
+            ]
+        };
+        assert_eq!(runs(records(), 3, 1), marked(&[None; 4]));
+        assert_eq!(
+            runs(records(), 3, 2),
+            marked(&[Some("a"), Some("a"), Some("a"), None])
+        );
+        assert_eq!(runs(records(), 3, 3), marked(&[Some("a"); 4]));
+    }
+
+    #[test]
+    fn album_order_is_required() {
+        let shuffled = vec![entry("a", 1, 2), entry("a", 1, 1), entry("a", 1, 3)];
+        assert_eq!(runs(shuffled, 2, 2), marked(&[None, Some("a"), Some("a")]));
+
+        let repeated = vec![entry("a", 1, 1), entry("a", 1, 1), entry("a", 1, 2)];
+        assert_eq!(runs(repeated, 2, 1), marked(&[None, Some("a"), Some("a")]));
+    }
+
+    #[test]
+    fn runs_continue_onto_the_next_disc() {
+        let records = vec![
+            entry("a", 1, 11),
+            entry("a", 1, 12),
+            entry("a", 2, 1),
+            entry("a", 2, 2),
+            entry("a", 4, 1),
+        ];
+        assert_eq!(
+            runs(records, 3, 1),
+            marked(&[Some("a"), Some("a"), Some("a"), Some("a"), None])
+        );
+
+        let skipping_the_opener = vec![entry("a", 1, 11), entry("a", 2, 2), entry("a", 2, 3)];
+        assert_eq!(
+            runs(skipping_the_opener, 2, 1),
+            marked(&[None, Some("a"), Some("a")])
+        );
+    }
+
+    #[test]
+    fn entries_without_an_album_or_track_number_break_runs() {
+        let mut records = vec![
+            entry("a", 1, 1),
+            entry("a", 1, 2),
+            entry("a", 1, 3),
+            entry("a", 1, 4),
+        ];
+        records[2].track_number = None;
+        assert_eq!(
+            runs(records, 2, 1),
+            marked(&[Some("a"), Some("a"), None, None])
+        );
+
+        let mut records = vec![entry("a", 1, 1), entry("a", 1, 2)];
+        for record in &mut records {
+            record.album_uri = None;
+        }
+        assert_eq!(runs(records, 2, 1), marked(&[None, None]));
+    }
+}
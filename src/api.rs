@@ -0,0 +1,2037 @@
+use async_stream::stream;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use chrono::{DateTime, Utc};
+use futures_util::Stream;
+use log::{debug, error, info, warn};
+use reqwest::{header, header::HeaderMap, Client, Method, Response, StatusCode, Url};
+use serde::{de::IgnoredAny, Deserialize};
+use serde_json::json;
+use std::{error::Error, fmt, fs, path::Path, time::Duration};
+use tokio::time::sleep;
+
+use crate::auth::{decode_token_scopes, fetch_public_token};
+use crate::cache::{CacheError, ResponseCache, ANONYMOUS_ACCOUNT};
+use crate::cli::{require_token, ExportArgs, GlobalArgs};
+use crate::filter::partition_playlists;
+use crate::http::explain_send_error;
+use crate::ids::{normalize_id, IdKind};
+use crate::middleware::{
+    Body, Middleware, OutagePolicy, Outbound, RateLimitStatus, ServicePolicy, MAX_RETRIES,
+};
+use crate::retry::{endpoint_class, RetryClass, Trip};
+use crate::spotify::{
+    AudioAnalysis, CategoriesResponse, Category, CategoryPlaylistsResponse, Episode,
+    EpisodeResponse, FollowedArtistsResponse, FullArtist, PaginatedTrackResponse, Playlist,
+    PlaylistFollowers, PlaylistResponse, PlaylistTrackCount, PublicUser, SavedAlbumItem,
+    SavedAlbumResponse, SavedShowResponse, SearchResponse, Show, SnapshotResponse,
+    TopTracksResponse, Track, TrackCount, TrackItem, User,
+};
+use crate::strict::check_known_fields;
+use crate::warnings::{ErrorCollector, Severity};
+
+pub const API_BASE: &str = "https://api.spotify.com/v1";
+
+/// Items per page of a playlist, the most Spotify allows.
+const PLAYLIST_PAGE_LIMIT: u32 = 100;
+
+/// Playlists per page of a playlist list.
+const PLAYLIST_LIST_LIMIT: usize = 50;
+
+/// IDs per Liked Songs membership check.
+const SAVED_CHECK_LIMIT: usize = 50;
+
+/// Artists per follow or unfollow request.
+const FOLLOW_LIMIT: usize = 50;
+
+/// IDs per saved albums membership check.
+const SAVED_ALBUM_CHECK_LIMIT: usize = 20;
+
+/// `code` upper-cased, if it is a two-letter country code; `needed_for`
+/// names what asked for it in the error.
+fn country_code(code: &str, needed_for: &str) -> Result<String, Box<dyn Error>> {
+    if code.len() != 2 || !code.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(format!(
+            "{:?} is not a market; {} need a two-letter country code such as US",
+            code, needed_for
+        )
+        .into());
+    }
+    Ok(code.to_ascii_uppercase())
+}
+
+/// The largest cover upload Spotify takes, base64-encoded.
+const MAX_COVER_BYTES: u64 = 256 * 1024;
+
+/// The circuit breaker's name for every cover image download; images have
+/// no IDs in their paths to group them by.
+const CDN_ENDPOINT: &str = "cover images";
+
+/// Spotify's Web API: no fixed request rate, since it reports its own
+/// budget and sends 429s, and outages are waited out by probing `/me`.
+fn spotify_policy() -> ServicePolicy {
+    ServicePolicy {
+        name: "Spotify",
+        requests_per_sec: None,
+        retry_on: vec![RetryClass::RateLimit, RetryClass::ServerError],
+        max_retries: MAX_RETRIES,
+        retry_after: true,
+        outage: Some(OutagePolicy::default()),
+        probe_url: Some(format!("{}/me", API_BASE)),
+    }
+}
+
+/// Spotify's image CDN, which has no documented limit; kept to a modest
+/// rate so a large library's covers do not arrive as a burst.
+fn cdn_policy() -> ServicePolicy {
+    ServicePolicy {
+        name: "Spotify's image CDN",
+        requests_per_sec: Some(10.0),
+        retry_on: vec![RetryClass::ServerError, RetryClass::Timeout],
+        max_retries: MAX_RETRIES,
+        retry_after: true,
+        outage: None,
+        probe_url: None,
+    }
+}
+
+/// Requests an export is expected to make, by what they fetch.
+#[derive(Debug)]
+pub struct ApiCallEstimate {
+    pub playlist_list_calls: usize,
+    pub track_page_calls: usize,
+    pub follower_calls: usize,
+    pub blend_member_calls: usize,
+    /// At most; tracks already checked in another playlist are not again.
+    pub liked_status_calls: usize,
+    /// At most, assuming every track is from a different album.
+    pub saved_album_calls: usize,
+    /// One search per distinct explicit track, which is unknown until the
+    /// tracks are read.
+    pub clean_version_searches: bool,
+}
+
+impl ApiCallEstimate {
+    pub fn total(&self) -> usize {
+        self.playlist_list_calls
+            + self.track_page_calls
+            + self.follower_calls
+            + self.blend_member_calls
+            + self.liked_status_calls
+            + self.saved_album_calls
+    }
+}
+
+impl fmt::Display for ApiCallEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Playlist lists: {}", self.playlist_list_calls)?;
+        writeln!(f, "Track pages: {}", self.track_page_calls)?;
+        if self.follower_calls > 0 {
+            writeln!(f, "Follower counts: {}", self.follower_calls)?;
+        }
+        if self.blend_member_calls > 0 {
+            writeln!(f, "Blend members: {}", self.blend_member_calls)?;
+        }
+        if self.liked_status_calls > 0 {
+            writeln!(f, "Liked status checks: up to {}", self.liked_status_calls)?;
+        }
+        if self.saved_album_calls > 0 {
+            writeln!(f, "Saved album checks: up to {}", self.saved_album_calls)?;
+        }
+        write!(f, "Total: about {} requests", self.total())?;
+        if self.clean_version_searches {
+            write!(f, ", plus one search per distinct explicit track")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct SpotifyAPI {
+    auth_token: String,
+    client: Client,
+    /// Retries, pacing and counts for API requests.
+    http: Middleware,
+    /// Retries and pacing for cover image downloads.
+    cdn: Middleware,
+    strict: bool,
+    /// Using the web player's anonymous token from `--public`.
+    public: bool,
+    /// Where responses are saved with `--record-responses`, or answered
+    /// from with `--offline`.
+    responses: Option<ResponseCache>,
+    /// `--offline`: answer GETs from `responses` only and send nothing.
+    offline_mode: bool,
+}
+
+impl SpotifyAPI {
+    pub fn new(auth_token: String, client: Client) -> Self {
+        Self {
+            auth_token,
+            http: Middleware::new(client.clone(), spotify_policy()),
+            cdn: Middleware::new(client.clone(), cdn_policy()),
+            client,
+            strict: false,
+            public: false,
+            responses: None,
+            offline_mode: false,
+        }
+    }
+
+    /// Builds a client from the global command-line options. A given token
+    /// always wins; without one, `--public` fetches an anonymous token.
+    pub async fn from_args(global: &GlobalArgs) -> Result<Self, Box<dyn Error>> {
+        let client = global.http.build_client()?;
+        let cache_dir = global.cache_location();
+        if global.offline {
+            let Some(cache_dir) = cache_dir else {
+                return Err("--offline answers from the cache directory, and there is none; pass --cache-dir".into());
+            };
+            // Nothing is sent, so no token is needed.
+            let mut api = Self::new(global.token.clone().unwrap_or_default(), client)
+                .with_strict_mode(global.strict_api);
+            api.responses = Some(ResponseCache::for_offline(
+                &cache_dir,
+                global.offline_account.as_deref(),
+            )?);
+            api.offline_mode = true;
+            return Ok(api);
+        }
+        let public = global.token.is_none() && global.public;
+        let token = if public {
+            info!("No access token given; using an anonymous token for public playlists only");
+            fetch_public_token(&client).await?
+        } else {
+            require_token(global.token.as_deref())?
+        };
+        let mut api = Self::new(token, client)
+            .with_outage_policy(global.outage_policy())
+            .with_strict_mode(global.strict_api)
+            .with_retry_on(global.retry_classes());
+        api.public = public;
+        if let Some(cache_dir) = cache_dir.filter(|_| global.record_responses) {
+            ResponseCache::prune(&cache_dir);
+            let account = if public {
+                ANONYMOUS_ACCOUNT.to_string()
+            } else {
+                api.get_current_user().await?.id
+            };
+            api.responses = Some(ResponseCache::new(&cache_dir, &account));
+        }
+        Ok(api)
+    }
+
+    pub fn with_outage_policy(mut self, outage: OutagePolicy) -> Self {
+        self.http.policy_mut().outage = Some(outage);
+        self
+    }
+
+    /// Fails requests whose response has fields this tool does not know,
+    /// instead of ignoring them.
+    pub fn with_strict_mode(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Failures to retry; the rest fail the request on the first error.
+    pub fn with_retry_on(mut self, retry_on: Vec<RetryClass>) -> Self {
+        self.http.policy_mut().retry_on = retry_on;
+        self
+    }
+
+    /// Endpoints the circuit breaker stopped retrying during the run.
+    pub fn breaker_trips(&self) -> Vec<Trip> {
+        self.http.trips()
+    }
+
+    /// Fails with an explanation when running on an anonymous `--public`
+    /// token, which cannot read anything tied to an account.
+    pub fn require_user_token(&self, operation: &str) -> Result<(), Box<dyn Error>> {
+        if self.public {
+            return Err(format!(
+                "{} needs a user access token; --public only reads public playlists given with --playlist",
+                operation
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    pub fn is_public(&self) -> bool {
+        self.public
+    }
+
+    /// Requests sent so far, retries included.
+    pub fn requests_sent(&self) -> u32 {
+        self.http.requests_sent()
+    }
+
+    /// Responses so far that were HTTP 429.
+    pub fn rate_limited(&self) -> u32 {
+        self.http.rate_limited()
+    }
+
+    pub fn request_time(&self) -> Duration {
+        self.http.request_time()
+    }
+
+    pub fn estimate_remaining(
+        &self,
+        remaining_calls: usize,
+        overhead: Duration,
+    ) -> Option<Duration> {
+        self.http.estimate_remaining(remaining_calls, overhead)
+    }
+
+    /// The request budget as of the last response, for progress output.
+    /// `None` until a response has arrived.
+    pub fn last_rate_limit_status(&self) -> Option<RateLimitStatus> {
+        self.http.last_rate_limit_status()
+    }
+
+    /// Total time spent paused waiting for Spotify outages to end.
+    pub fn outage_pause(&self) -> Duration {
+        self.http.outage_pause()
+    }
+
+    /// Whether a Spotify outage outlasted `--max-outage-wait`.
+    pub fn outage_gave_up(&self) -> bool {
+        self.http.outage_gave_up()
+    }
+
+    /// Returns the entries of `required` the token was not granted. When the
+    /// scopes cannot be read from the token, nothing is reported missing.
+    pub fn check_token_scopes(&self, required: &[&str]) -> Vec<String> {
+        // An anonymous token has no scopes; `require_user_token` refuses
+        // what needs them with a clearer message. Offline, none is used.
+        if self.public || self.offline_mode {
+            return Vec::new();
+        }
+        match decode_token_scopes(&self.auth_token) {
+            Ok(granted) => required
+                .iter()
+                .filter(|scope| !granted.iter().any(|g| g == *scope))
+                .map(|scope| scope.to_string())
+                .collect(),
+            Err(e) => {
+                warn!("could not read scopes from access token: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    pub async fn get<T: for<'de> Deserialize<'de>>(&self, url: &str) -> Result<T, Box<dyn Error>> {
+        let endpoint = endpoint_class(url);
+        if self.offline_mode {
+            let body = match &self.responses {
+                Some(responses) => responses.lookup(url)?,
+                None => return Err(self.not_cached(url)),
+            };
+            if self.strict {
+                let value: serde_json::Value = serde_json::from_str(&body)?;
+                check_known_fields(&value, &endpoint)?;
+            }
+            return Ok(serde_json::from_str(&body)?);
+        }
+        let mut attempt = 0;
+        loop {
+            let res = self
+                .send_with_retry(Method::GET, url, None, &endpoint)
+                .await?;
+
+            let status = res.status();
+            let body = res.text().await?;
+
+            if !status.is_success() {
+                error!("HTTP {}: {}", status, body);
+                return Err(StatusError { status, body }.into());
+            }
+
+            if self.strict {
+                let value: serde_json::Value = serde_json::from_str(&body)?;
+                check_known_fields(&value, &endpoint)?;
+            }
+
+            match serde_json::from_str::<T>(&body) {
+                Ok(value) => {
+                    self.http.record_success(&endpoint);
+                    if let Some(responses) = &self.responses {
+                        responses.store(url, &body);
+                    }
+                    return Ok(value);
+                }
+                Err(e) => {
+                    self.http.record_failure(
+                        &endpoint,
+                        &format!("unparseable response ({:?})", e.classify()),
+                    );
+                    let max_retries = self.http.policy().max_retries;
+                    if self.http.should_retry(RetryClass::Parse, &endpoint) && attempt < max_retries
+                    {
+                        attempt += 1;
+                        warn!(
+                            "Unparseable response from {}; retrying ({}/{}): {}",
+                            url, attempt, max_retries, e
+                        );
+                        sleep(Duration::from_secs(1 << attempt)).await;
+                        continue;
+                    }
+                    error!("Deserialization error: {}", e);
+                    error!("Response body: {}", body);
+                    return Err(Box::new(e));
+                }
+            }
+        }
+    }
+
+    /// Sends `body` as JSON and parses the response. A POST is retried only
+    /// on rate limiting and failed connections, since one that timed out or
+    /// hit a server error may still have been applied.
+    pub async fn send_json<T: for<'de> Deserialize<'de>>(
+        &self,
+        method: Method,
+        url: &str,
+        body: &serde_json::Value,
+    ) -> Result<T, Box<dyn Error>> {
+        let endpoint = endpoint_class(url);
+        let res = self
+            .send_with_retry(method, url, Some(Body::Json(body)), &endpoint)
+            .await?;
+
+        let status = res.status();
+        let body = res.text().await?;
+        if !status.is_success() {
+            error!("HTTP {}: {}", status, body);
+            return Err(StatusError { status, body }.into());
+        }
+        self.http.record_success(&endpoint);
+        // Some endpoints, such as changing a playlist's details, answer
+        // with no body at all.
+        let body = if body.trim().is_empty() {
+            "null"
+        } else {
+            &body
+        };
+        Ok(serde_json::from_str(body)?)
+    }
+
+    /// Sends with the token through the API's middleware, which retries
+    /// the failures `--retry-on` allows and waits out outages.
+    async fn send_with_retry(
+        &self,
+        method: Method,
+        url: &str,
+        body: Option<Body<'_>>,
+        endpoint: &str,
+    ) -> Result<Response, Box<dyn Error>> {
+        if self.offline_mode {
+            return Err(self.not_cached(url));
+        }
+        let request = Outbound {
+            method,
+            url,
+            bearer: Some(&self.auth_token),
+            body,
+        };
+        self.http.send(&request, endpoint).await
+    }
+
+    /// Waits `delay` between pages of a listing, to go easy on the API.
+    /// Offline there is no API to go easy on.
+    async fn page_pause(&self, delay: Duration) {
+        if !self.offline_mode {
+            sleep(delay).await;
+        }
+    }
+
+    /// Only GET responses are cached, so with `--offline` anything else
+    /// fails the same way a GET that was never cached does.
+    fn not_cached(&self, url: &str) -> Box<dyn Error> {
+        Box::new(CacheError::OfflineNotCached {
+            url: url.to_string(),
+        })
+    }
+
+    /// Sends a GET and returns only the status and headers, without treating
+    /// a non-success status as an error.
+    pub async fn probe(&self, url: &str) -> Result<(StatusCode, HeaderMap), Box<dyn Error>> {
+        if self.offline_mode {
+            return Err(self.not_cached(url));
+        }
+        let res = self
+            .client
+            .get(url)
+            .header(header::AUTHORIZATION, format!("Bearer {}", self.auth_token))
+            .send()
+            .await
+            .map_err(explain_send_error)?;
+        Ok((res.status(), res.headers().clone()))
+    }
+
+    /// Fetches a file from Spotify's CDN, such as a cover image, which needs
+    /// no token.
+    pub async fn download(&self, url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        if self.offline_mode {
+            return Err(self.not_cached(url));
+        }
+        let request = Outbound {
+            method: Method::GET,
+            url,
+            bearer: None,
+            body: None,
+        };
+        let res = self
+            .cdn
+            .send(&request, CDN_ENDPOINT)
+            .await?
+            .error_for_status()?;
+        Ok(res.bytes().await?.to_vec())
+    }
+
+    /// The full playlist object, without its items beyond the first page.
+    /// Accepts anything `normalize_playlist_id` does.
+    pub async fn get_playlist_metadata(&self, playlist: &str) -> Result<Playlist, Box<dyn Error>> {
+        let id = normalize_playlist_id(playlist)?;
+        self.get(&format!("{}/playlists/{}", API_BASE, id)).await
+    }
+
+    /// How many items a playlist holds, in one small request that fetches
+    /// none of them.
+    pub async fn get_playlist_track_count(&self, playlist_id: &str) -> Result<u32, Box<dyn Error>> {
+        let response: PlaylistTrackCount = self
+            .get(&format!(
+                "{}/playlists/{}?fields=tracks.total",
+                API_BASE,
+                normalize_playlist_id(playlist_id)?
+            ))
+            .await?;
+        response
+            .tracks
+            .total
+            .ok_or_else(|| format!("{}: Spotify reported no track count", playlist_id).into())
+    }
+
+    /// How many items a playlist holds, from a one-item page of its
+    /// `tracks.href`, so even a 10,000-track playlist costs one request.
+    pub async fn get_playlist_total_tracks(
+        &self,
+        tracks_href: &str,
+    ) -> Result<u32, Box<dyn Error>> {
+        let separator = if tracks_href.contains('?') { '&' } else { '?' };
+        let response: TrackCount = self
+            .get(&format!(
+                "{}{}limit=1&offset=0&fields=total",
+                tracks_href, separator
+            ))
+            .await?;
+        response
+            .total
+            .ok_or_else(|| format!("{}: Spotify reported no track count", tracks_href).into())
+    }
+
+    pub async fn get_current_user(&self) -> Result<User, Box<dyn Error>> {
+        self.get(&format!("{}/me", API_BASE)).await
+    }
+
+    /// Takes the user ID as the API reports it, such as in `added_by`.
+    pub async fn get_user_public(&self, user_id: &str) -> Result<PublicUser, Box<dyn Error>> {
+        self.get(&user_url(user_id, &[])).await
+    }
+
+    /// Accepts an episode ID, `spotify:episode:` URI or link.
+    pub async fn get_episode(&self, episode_id: &str) -> Result<Episode, Box<dyn Error>> {
+        let id = normalize_id(IdKind::Episode, episode_id, false)?;
+        self.get(&format!("{}/episodes/{}", API_BASE, id)).await
+    }
+
+    /// Accepts a track ID, `spotify:track:` URI or link. The response runs
+    /// to hundreds of kilobytes, so this is for looking at one track at a
+    /// time and never called for a whole library.
+    pub async fn get_audio_analysis(
+        &self,
+        track_id: &str,
+    ) -> Result<AudioAnalysis, Box<dyn Error>> {
+        let id = normalize_id(IdKind::Track, track_id, false)?;
+        self.get(&format!("{}/audio-analysis/{}", API_BASE, id))
+            .await
+    }
+
+    /// An artist's most played tracks in `market`, a two-letter country
+    /// code. Unlike most endpoints this one has no default market.
+    pub async fn get_artist_top_tracks(
+        &self,
+        artist_id: &str,
+        market: &str,
+    ) -> Result<Vec<Track>, Box<dyn Error>> {
+        let market = country_code(market, "top tracks")?;
+        let id = normalize_id(IdKind::Artist, artist_id, false)?;
+        let response: TopTracksResponse = self
+            .get(&format!(
+                "{}/artists/{}/top-tracks?market={}",
+                API_BASE, id, market
+            ))
+            .await?;
+        Ok(response.tracks)
+    }
+
+    /// Follows each of `artist_ids` (IDs, URIs or links), 50 per request.
+    pub async fn follow_artists(&self, artist_ids: &[&str]) -> Result<(), Box<dyn Error>> {
+        self.set_following(Method::PUT, artist_ids).await
+    }
+
+    /// Unfollows each of `artist_ids` (IDs, URIs or links), 50 per request.
+    pub async fn unfollow_artists(&self, artist_ids: &[&str]) -> Result<(), Box<dyn Error>> {
+        self.set_following(Method::DELETE, artist_ids).await
+    }
+
+    async fn set_following(
+        &self,
+        method: Method,
+        artist_ids: &[&str],
+    ) -> Result<(), Box<dyn Error>> {
+        let ids = artist_ids
+            .iter()
+            .map(|id| normalize_id(IdKind::Artist, id, false))
+            .collect::<Result<Vec<_>, _>>()?;
+        let url = format!("{}/me/following?type=artist", API_BASE);
+        for chunk in ids.chunks(FOLLOW_LIMIT) {
+            let _: IgnoredAny = self
+                .send_json(method.clone(), &url, &json!({ "ids": chunk }))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Whether each of `track_ids` (IDs, URIs or links) is in Liked Songs,
+    /// in the same order, asking about 50 per request.
+    pub async fn check_saved_tracks(
+        &self,
+        track_ids: &[&str],
+    ) -> Result<Vec<bool>, Box<dyn Error>> {
+        let ids = track_ids
+            .iter()
+            .map(|id| normalize_id(IdKind::Track, id, false))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut saved = Vec::with_capacity(ids.len());
+        for chunk in ids.chunks(SAVED_CHECK_LIMIT) {
+            let page: Vec<bool> = self
+                .get(&format!(
+                    "{}/me/tracks/contains?ids={}",
+                    API_BASE,
+                    chunk.join(",")
+                ))
+                .await?;
+            if page.len() != chunk.len() {
+                return Err(format!(
+                    "asked about {} tracks in Liked Songs but got {} answers",
+                    chunk.len(),
+                    page.len()
+                )
+                .into());
+            }
+            saved.extend(page);
+        }
+        Ok(saved)
+    }
+
+    /// Whether each of `album_ids` (IDs, URIs or links) is among the saved
+    /// albums, in the same order, asking about 20 per request.
+    pub async fn check_saved_albums(
+        &self,
+        album_ids: &[&str],
+    ) -> Result<Vec<bool>, Box<dyn Error>> {
+        let ids = album_ids
+            .iter()
+            .map(|id| normalize_id(IdKind::Album, id, false))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut saved = Vec::with_capacity(ids.len());
+        for chunk in ids.chunks(SAVED_ALBUM_CHECK_LIMIT) {
+            let page: Vec<bool> = self
+                .get(&format!(
+                    "{}/me/albums/contains?ids={}",
+                    API_BASE,
+                    chunk.join(",")
+                ))
+                .await?;
+            if page.len() != chunk.len() {
+                return Err(format!(
+                    "asked about {} saved albums but got {} answers",
+                    chunk.len(),
+                    page.len()
+                )
+                .into());
+            }
+            saved.extend(page);
+        }
+        Ok(saved)
+    }
+
+    /// Every browse category, named as in `country` when one is given.
+    pub async fn get_categories(
+        &self,
+        country: Option<&str>,
+    ) -> Result<Vec<Category>, Box<dyn Error>> {
+        let mut url = format!("{}/browse/categories?limit=50", API_BASE);
+        if let Some(country) = country {
+            url.push_str(&format!(
+                "&country={}",
+                country_code(country, "categories")?
+            ));
+        }
+        let mut categories = Vec::new();
+        let mut next = Some(url);
+        while let Some(url) = next {
+            let response: CategoriesResponse = self.get(&url).await?;
+            categories.extend(response.categories.items);
+            next = response.categories.next;
+        }
+        Ok(categories)
+    }
+
+    /// Up to `limit` of Spotify's editorial playlists for a category, as
+    /// shown in `country` when one is given. Spotify no longer answers this
+    /// for some apps registered since late 2024.
+    pub async fn get_category_playlists(
+        &self,
+        category_id: &str,
+        country: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<Playlist>, Box<dyn Error>> {
+        if category_id.is_empty()
+            || !category_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(format!("{:?} is not a category ID", category_id).into());
+        }
+        let mut url = format!(
+            "{}/browse/categories/{}/playlists?limit={}",
+            API_BASE,
+            category_id,
+            limit.clamp(1, PLAYLIST_LIST_LIMIT as u32)
+        );
+        if let Some(country) = country {
+            url.push_str(&format!(
+                "&country={}",
+                country_code(country, "category playlists")?
+            ));
+        }
+        let mut playlists = Vec::new();
+        let mut next = Some(url);
+        while let Some(url) = next.filter(|_| playlists.len() < limit as usize) {
+            let response: CategoryPlaylistsResponse = self.get(&url).await?;
+            playlists.extend(response.playlists.items.into_iter().flatten());
+            next = response.playlists.next;
+        }
+        playlists.truncate(limit as usize);
+        Ok(playlists)
+    }
+
+    pub async fn get_saved_shows(&self) -> Result<Vec<Show>, Box<dyn Error>> {
+        let mut shows = Vec::new();
+        let mut next = Some(format!("{}/me/shows?limit=50", API_BASE));
+
+        while let Some(url) = next {
+            let response: SavedShowResponse = self.get(&url).await?;
+            shows.extend(response.items.into_iter().map(|saved| saved.show));
+            next = response.next;
+        }
+
+        Ok(shows)
+    }
+
+    /// Fetches a show's episodes newest first, stopping after the first page
+    /// that reaches back past `since` (a `YYYY-MM-DD` date).
+    pub async fn get_show_episodes_since(
+        &self,
+        show_id: &str,
+        since: &str,
+    ) -> Result<Vec<Episode>, Box<dyn Error>> {
+        let mut episodes = Vec::new();
+        let mut next = Some(format!("{}/shows/{}/episodes?limit=50", API_BASE, show_id));
+
+        while let Some(url) = next {
+            let response: EpisodeResponse = self.get(&url).await?;
+            let page: Vec<Episode> = response.items.into_iter().flatten().collect();
+            let reached_since = page
+                .iter()
+                .filter_map(|episode| episode.release_date.as_deref())
+                .any(|date| date < since);
+            episodes.extend(page);
+            next = response.next.filter(|_| !reached_since);
+        }
+
+        Ok(episodes)
+    }
+
+    /// Every track in Liked Songs, newest first.
+    pub async fn get_liked_tracks(&self) -> Result<Vec<TrackItem>, Box<dyn Error>> {
+        let mut tracks = Vec::new();
+        let mut next = Some(format!("{}/me/tracks?limit=50", API_BASE));
+
+        while let Some(url) = next {
+            let response: PaginatedTrackResponse = self.get(&url).await?;
+            tracks.extend(response.items);
+            next = response.next;
+        }
+
+        Ok(tracks)
+    }
+
+    /// Every saved album, newest first, each with its first page of tracks.
+    pub async fn get_all_liked_albums(&self) -> Result<Vec<SavedAlbumItem>, Box<dyn Error>> {
+        let mut albums = Vec::new();
+        let mut next = Some(format!("{}/me/albums?limit=50", API_BASE));
+
+        while let Some(url) = next {
+            let response: SavedAlbumResponse = self.get(&url).await?;
+            albums.reserve((response.total as usize).saturating_sub(albums.len()));
+            albums.extend(response.items);
+            debug!("Fetched {}/{} saved albums", albums.len(), response.total);
+            next = response.next;
+        }
+
+        Ok(albums)
+    }
+
+    /// URIs of every track on every saved album.
+    pub async fn get_saved_album_track_uris(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut uris = Vec::new();
+        for saved in self.get_all_liked_albums().await? {
+            let mut page = saved.album.tracks;
+            loop {
+                uris.extend(page.items.into_iter().filter_map(|track| track.uri));
+                let Some(url) = page.next else { break };
+                debug!(
+                    "Fetching more tracks of {} (saved {})",
+                    saved.album.name, saved.added_at
+                );
+                page = self.get(&url).await?;
+            }
+        }
+
+        Ok(uris)
+    }
+
+    pub async fn search_tracks(
+        &self,
+        query: &str,
+        limit: u32,
+    ) -> Result<Vec<Track>, Box<dyn Error>> {
+        let url = Url::parse_with_params(
+            &format!("{}/search", API_BASE),
+            &[
+                ("q", query),
+                ("type", "track"),
+                ("limit", &limit.to_string()),
+            ],
+        )?;
+        let response: SearchResponse = self.get(url.as_str()).await?;
+        Ok(response.tracks.items)
+    }
+
+    pub async fn get_all_playlists(&self, url: &str) -> Result<Vec<Playlist>, Box<dyn Error>> {
+        Ok(self.get_all_playlists_with_total(url).await?.0)
+    }
+
+    /// Every page of playlists from `url`, and how many Spotify said there
+    /// were, which sizes the progress shown while the pages come in. The
+    /// list can be shorter when playlists are removed meanwhile.
+    pub async fn get_all_playlists_with_total(
+        &self,
+        url: &str,
+    ) -> Result<(Vec<Playlist>, u32), Box<dyn Error>> {
+        let mut playlists = Vec::new();
+        let mut total = None;
+        let mut next = Some(url.to_string());
+
+        while let Some(url) = next {
+            let response: PlaylistResponse = self.get(&url).await?;
+            playlists.extend(response.items);
+            next = response.next;
+            total = response.total.or(total);
+
+            if next.is_some() {
+                if let Some(total) = total {
+                    info!("Listed {} of {} playlists...", playlists.len(), total);
+                }
+                self.page_pause(Duration::from_secs(2)).await;
+            }
+        }
+
+        let total = total.unwrap_or(playlists.len() as u32);
+        Ok((playlists, total))
+    }
+
+    /// Playlists in the library that the current user owns.
+    pub async fn get_owned_playlists(&self) -> Result<Vec<Playlist>, Box<dyn Error>> {
+        let user = self.get_current_user().await?;
+        let playlists = self.get_library_playlists().await?;
+        Ok(partition_playlists(playlists, &user.id).0)
+    }
+
+    /// Playlists in the library that the current user follows but does not own.
+    pub async fn get_followed_playlists(&self) -> Result<Vec<Playlist>, Box<dyn Error>> {
+        let user = self.get_current_user().await?;
+        let playlists = self.get_library_playlists().await?;
+        Ok(partition_playlists(playlists, &user.id).1)
+    }
+
+    /// Every playlist in the library, owned and followed.
+    pub async fn get_library_playlists(&self) -> Result<Vec<Playlist>, Box<dyn Error>> {
+        let (playlists, total) = self
+            .get_all_playlists_with_total(&format!("{}/me/playlists?limit=50", API_BASE))
+            .await?;
+        if playlists.len() != total as usize {
+            debug!(
+                "Spotify reported {} playlists but listed {}; the library changed while listing",
+                total,
+                playlists.len()
+            );
+        }
+        Ok(playlists)
+    }
+
+    /// Sums the track counts the playlist list already reports, so a run can
+    /// be sized up without another request. Playlists without one count as 0.
+    pub fn get_total_track_count(playlists: &[Playlist]) -> u64 {
+        playlists
+            .iter()
+            .filter_map(|playlist| playlist.tracks.total)
+            .map(u64::from)
+            .sum()
+    }
+
+    /// Roughly how many requests exporting `playlists` with `args` makes,
+    /// from the track counts the playlist list already reports. Reading
+    /// with --added-after usually takes fewer pages, and retries are not
+    /// counted, so this is an upper bound for a healthy run.
+    pub fn estimate_api_calls(playlists: &[Playlist], args: &ExportArgs) -> ApiCallEstimate {
+        let playlist_list_calls = if args.playlists.is_empty() {
+            playlists.len().div_ceil(PLAYLIST_LIST_LIMIT).max(1)
+        } else {
+            args.playlists.len()
+        };
+        // An empty or unsized playlist still takes one request to read.
+        let track_page_calls = playlists
+            .iter()
+            .map(|playlist| {
+                (playlist.tracks.total.unwrap_or(0) as usize)
+                    .div_ceil(PLAYLIST_PAGE_LIMIT as usize)
+                    .max(1)
+            })
+            .sum();
+        let follower_calls = if args.include_followers {
+            playlists.len()
+        } else {
+            0
+        };
+        let checks = |wanted: bool, per_request: usize| -> usize {
+            if !wanted {
+                return 0;
+            }
+            playlists
+                .iter()
+                .map(|playlist| (playlist.tracks.total.unwrap_or(0) as usize).div_ceil(per_request))
+                .sum()
+        };
+        ApiCallEstimate {
+            playlist_list_calls,
+            track_page_calls,
+            follower_calls,
+            // At least one page of public playlists per member.
+            blend_member_calls: args.blend_members.len(),
+            liked_status_calls: checks(args.liked_status(), SAVED_CHECK_LIMIT),
+            saved_album_calls: checks(args.saved_album_status(), SAVED_ALBUM_CHECK_LIMIT),
+            clean_version_searches: args.prefer_clean_version,
+        }
+    }
+
+    pub async fn get_playlist_followers_count(
+        &self,
+        playlist_id: &str,
+    ) -> Result<Option<u64>, Box<dyn Error>> {
+        let response: PlaylistFollowers = self
+            .get(&format!(
+                "{}/playlists/{}?fields=followers.total",
+                API_BASE,
+                normalize_playlist_id(playlist_id)?
+            ))
+            .await?;
+        Ok(response.followers.and_then(|f| f.total))
+    }
+
+    /// Fills in `followers_count`, which costs one request per playlist.
+    /// A playlist whose count cannot be fetched is left unknown.
+    pub async fn enrich_playlist_followers(
+        &self,
+        playlists: &mut [Playlist],
+        errors: &ErrorCollector,
+    ) {
+        for playlist in playlists {
+            match self.get_playlist_followers_count(&playlist.id).await {
+                Ok(count) => playlist.followers_count = count,
+                Err(e) => errors.report(
+                    Severity::Warning,
+                    &playlist.name,
+                    None,
+                    format!("could not fetch follower count: {}", e),
+                ),
+            }
+        }
+    }
+
+    /// Creates a playlist in the token's account and returns its ID.
+    pub async fn create_playlist(
+        &self,
+        name: &str,
+        public: bool,
+        description: &str,
+    ) -> Result<String, Box<dyn Error>> {
+        let user = self.get_current_user().await?;
+        let playlist: Playlist = self
+            .send_json(
+                Method::POST,
+                &user_url(&user.id, &["playlists"]),
+                &json!({
+                    "name": name,
+                    "public": public,
+                    "description": description,
+                }),
+            )
+            .await?;
+        Ok(playlist.id)
+    }
+
+    /// Changes the details given and leaves the rest as they are.
+    pub async fn update_playlist(
+        &self,
+        playlist_id: &str,
+        name: Option<&str>,
+        description: Option<&str>,
+        public: Option<bool>,
+        collaborative: Option<bool>,
+    ) -> Result<(), Box<dyn Error>> {
+        if collaborative == Some(true) && public == Some(true) {
+            return Err("a collaborative playlist cannot be public".into());
+        }
+        let mut body = serde_json::Map::new();
+        if let Some(name) = name {
+            body.insert("name".into(), json!(name));
+        }
+        if let Some(description) = description {
+            body.insert("description".into(), json!(description));
+        }
+        if let Some(public) = public {
+            body.insert("public".into(), json!(public));
+        }
+        if let Some(collaborative) = collaborative {
+            body.insert("collaborative".into(), json!(collaborative));
+        }
+        if body.is_empty() {
+            return Err("nothing to update".into());
+        }
+        let _: IgnoredAny = self
+            .send_json(
+                Method::PUT,
+                &format!(
+                    "{}/playlists/{}",
+                    API_BASE,
+                    normalize_playlist_id(playlist_id)?
+                ),
+                &body.into(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Replaces the playlist's cover with a JPEG file, which Spotify takes
+    /// base64-encoded and no larger than 256 KB that way.
+    pub async fn upload_playlist_cover(
+        &self,
+        playlist_id: &str,
+        image_path: &Path,
+    ) -> Result<(), Box<dyn Error>> {
+        let size = fs::metadata(image_path)
+            .map_err(|e| format!("{}: {}", image_path.display(), e))?
+            .len();
+        if size.div_ceil(3) * 4 > MAX_COVER_BYTES {
+            return Err(format!(
+                "{}: {} bytes is over {} once base64-encoded; shrink it first, \
+                 for example with ImageMagick: magick {} -resize 640x640 -quality 85 cover.jpg",
+                image_path.display(),
+                size,
+                MAX_COVER_BYTES,
+                image_path.display()
+            )
+            .into());
+        }
+        let image = fs::read(image_path).map_err(|e| format!("{}: {}", image_path.display(), e))?;
+        if !image.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            return Err(format!("{}: not a JPEG file", image_path.display()).into());
+        }
+        let url = format!(
+            "{}/playlists/{}/images",
+            API_BASE,
+            normalize_playlist_id(playlist_id)?
+        );
+        let endpoint = endpoint_class(&url);
+        let encoded = BASE64_STANDARD.encode(&image);
+        let res = self
+            .send_with_retry(Method::PUT, &url, Some(Body::Jpeg(&encoded)), &endpoint)
+            .await?;
+        let status = res.status();
+        if !status.is_success() {
+            let body = res.text().await?;
+            error!("HTTP {}: {}", status, body);
+            return Err(StatusError { status, body }.into());
+        }
+        self.http.record_success(&endpoint);
+        Ok(())
+    }
+
+    /// Creates a playlist holding `uris`, in order, and returns its ID. The
+    /// tracks go in 100 per request; if adding them fails partway the error
+    /// names the playlist, which is left holding the tracks added so far.
+    pub async fn create_playlist_from_tracks(
+        &self,
+        name: &str,
+        description: &str,
+        uris: &[&str],
+        public: bool,
+    ) -> Result<String, Box<dyn Error>> {
+        let playlist_id = self.create_playlist(name, public, description).await?;
+        self.add_tracks_to_playlist(&playlist_id, uris)
+            .await
+            .map_err(|e| {
+                format!(
+                    "created playlist {} but could not add every track: {}",
+                    playlist_id, e
+                )
+            })?;
+        Ok(playlist_id)
+    }
+
+    /// Appends `uris` to the playlist, in order, a page of 100 per request.
+    pub async fn add_tracks_to_playlist(
+        &self,
+        playlist_id: &str,
+        uris: &[&str],
+    ) -> Result<(), Box<dyn Error>> {
+        let url = format!(
+            "{}/playlists/{}/tracks",
+            API_BASE,
+            normalize_playlist_id(playlist_id)?
+        );
+        for chunk in uris.chunks(PLAYLIST_PAGE_LIMIT as usize) {
+            let _: IgnoredAny = self
+                .send_json(Method::POST, &url, &json!({ "uris": chunk }))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Removes every occurrence of each of `uris` from the playlist, a page
+    /// of 100 per request.
+    pub async fn remove_tracks_from_playlist(
+        &self,
+        playlist_id: &str,
+        uris: &[&str],
+    ) -> Result<(), Box<dyn Error>> {
+        let url = format!(
+            "{}/playlists/{}/tracks",
+            API_BASE,
+            normalize_playlist_id(playlist_id)?
+        );
+        for chunk in uris.chunks(PLAYLIST_PAGE_LIMIT as usize) {
+            let tracks: Vec<_> = chunk.iter().map(|uri| json!({ "uri": uri })).collect();
+            let _: IgnoredAny = self
+                .send_json(Method::DELETE, &url, &json!({ "tracks": tracks }))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Overwrites the playlist with `uris`: the first 100 replace its items,
+    /// the rest are appended. A failure partway leaves the playlist holding
+    /// only the tracks written so far.
+    pub async fn replace_playlist_tracks(
+        &self,
+        playlist_id: &str,
+        uris: &[&str],
+    ) -> Result<(), Box<dyn Error>> {
+        let url = format!(
+            "{}/playlists/{}/tracks",
+            API_BASE,
+            normalize_playlist_id(playlist_id)?
+        );
+        let first = uris.len().min(PLAYLIST_PAGE_LIMIT as usize);
+        let _: IgnoredAny = self
+            .send_json(Method::PUT, &url, &json!({ "uris": &uris[..first] }))
+            .await?;
+        self.add_tracks_to_playlist(playlist_id, &uris[first..])
+            .await
+    }
+
+    /// Moves the item at `range_start` to just before the item now at
+    /// `insert_before`, returning the playlist's new snapshot ID.
+    pub async fn reorder_playlist_track(
+        &self,
+        playlist_id: &str,
+        range_start: u32,
+        insert_before: u32,
+    ) -> Result<String, Box<dyn Error>> {
+        let response: SnapshotResponse = self
+            .send_json(
+                Method::PUT,
+                &format!(
+                    "{}/playlists/{}/tracks",
+                    API_BASE,
+                    normalize_playlist_id(playlist_id)?
+                ),
+                &json!({
+                    "range_start": range_start,
+                    "insert_before": insert_before,
+                    "range_length": 1,
+                }),
+            )
+            .await?;
+        Ok(response.snapshot_id)
+    }
+
+    /// Fetches the single page of a playlist's items starting at `offset`.
+    /// `url` is the playlist's items URL; any paging it already carries is
+    /// replaced.
+    pub async fn get_playlist_tracks_page(
+        &self,
+        url: &str,
+        offset: u32,
+        limit: u32,
+    ) -> Result<PaginatedTrackResponse, Box<dyn Error>> {
+        self.get(&page_url(url, offset, limit)?).await
+    }
+
+    /// Items added to a playlist after `after`. New items are appended, so
+    /// pages are read from the end backwards and reading stops at the first
+    /// page holding an older item; an item moved away from the end by hand
+    /// is missed. A page that fails is skipped and reported, like in
+    /// `get_playlist_tracks`, and reading goes on with the page before it;
+    /// if the first page fails the playlist's length is unknown and nothing
+    /// more is read.
+    pub async fn get_playlist_items_after(
+        &self,
+        playlist_id: &str,
+        after: DateTime<Utc>,
+    ) -> Result<(Vec<TrackItem>, Vec<FailedPage>), Box<dyn Error>> {
+        let url = format!(
+            "{}/playlists/{}/tracks",
+            API_BASE,
+            normalize_playlist_id(playlist_id)?
+        );
+        let failure = |offset: u32, error: Box<dyn Error>| -> Result<FailedPage, Box<dyn Error>> {
+            Ok(FailedPage {
+                url: page_url(&url, offset, PLAYLIST_PAGE_LIMIT)?,
+                offset,
+                limit: PLAYLIST_PAGE_LIMIT,
+                position: 0,
+                error: error.to_string(),
+            })
+        };
+        // The first page tells how long the playlist is.
+        let first = match self
+            .get_playlist_tracks_page(&url, 0, PLAYLIST_PAGE_LIMIT)
+            .await
+        {
+            Ok(first) => first,
+            Err(e) if aborts_run(&*e) => return Err(e),
+            Err(e) => {
+                warn!("Could not fetch the first page of {}: {}", url, e);
+                return Ok((Vec::new(), vec![failure(0, e)?]));
+            }
+        };
+        let total = first.total.unwrap_or(0);
+        let mut first_items = Some(first.items);
+
+        let is_newer = |item: &TrackItem| {
+            item.added_at
+                .as_deref()
+                .and_then(|added_at| DateTime::parse_from_rfc3339(added_at).ok())
+                .is_some_and(|added_at| added_at > after)
+        };
+        // Read from the end; each page's newer items, or the failure.
+        let mut pages = Vec::new();
+        let mut offset = total.saturating_sub(1) / PLAYLIST_PAGE_LIMIT * PLAYLIST_PAGE_LIMIT;
+        loop {
+            let cached = if offset == 0 {
+                first_items.take()
+            } else {
+                None
+            };
+            let items = match cached {
+                Some(items) => Ok(items),
+                None => {
+                    self.page_pause(Duration::from_secs(1)).await;
+                    self.get_playlist_tracks_page(&url, offset, PLAYLIST_PAGE_LIMIT)
+                        .await
+                        .map(|page| page.items)
+                }
+            };
+            let reached_older = match items {
+                Ok(items) => {
+                    let reached_older = items.iter().any(|item| !is_newer(item));
+                    pages.push(Ok(items.into_iter().filter(is_newer).collect::<Vec<_>>()));
+                    reached_older
+                }
+                Err(e) if aborts_run(&*e) => return Err(e),
+                Err(e) => {
+                    warn!(
+                        "Skipping {} of {}: {}",
+                        item_range(offset, PLAYLIST_PAGE_LIMIT),
+                        url,
+                        e
+                    );
+                    pages.push(Err(failure(offset, e)?));
+                    false
+                }
+            };
+            if reached_older || offset == 0 {
+                break;
+            }
+            offset -= PLAYLIST_PAGE_LIMIT;
+        }
+
+        let mut newer = Vec::new();
+        let mut failed = Vec::new();
+        for page in pages.into_iter().rev() {
+            match page {
+                Ok(mut items) => newer.append(&mut items),
+                Err(mut page) => {
+                    page.position = newer.len();
+                    failed.push(page);
+                }
+            }
+        }
+        Ok((newer, failed))
+    }
+
+    /// Fetches every page of a playlist's items. A page that still fails
+    /// after retries is skipped and reported, leaving a gap, rather than
+    /// failing the playlist. Without `total` the size of the playlist is
+    /// unknown, so nothing after a failed page is fetched.
+    pub async fn get_playlist_tracks(
+        &self,
+        url: &str,
+        total: Option<u32>,
+    ) -> Result<(Vec<TrackItem>, Vec<FailedPage>), Box<dyn Error>> {
+        let mut all_tracks = Vec::new();
+        let mut failed = Vec::new();
+        let mut next_url = Some(url.to_string());
+
+        while let Some(current_url) = next_url {
+            match self.get::<PaginatedTrackResponse>(&current_url).await {
+                Ok(response) => {
+                    all_tracks.extend(response.items);
+                    next_url = response.next;
+                }
+                Err(e) if aborts_run(&*e) => return Err(e),
+                Err(e) => {
+                    let (offset, limit) = page_window(&current_url)?;
+                    warn!(
+                        "Skipping {} after repeated failures: {}",
+                        item_range(offset, limit),
+                        e
+                    );
+                    next_url = match total {
+                        Some(total) if offset.saturating_add(limit) < total => {
+                            Some(page_url(&current_url, offset + limit, limit)?)
+                        }
+                        Some(_) => None,
+                        None => {
+                            warn!("Playlist size unknown; not fetching past the failed page");
+                            None
+                        }
+                    };
+                    failed.push(FailedPage {
+                        url: current_url,
+                        offset,
+                        limit,
+                        position: all_tracks.len(),
+                        error: e.to_string(),
+                    });
+                }
+            }
+
+            if next_url.is_some() {
+                self.page_pause(Duration::from_secs(1)).await;
+            }
+        }
+
+        Ok((all_tracks, failed))
+    }
+
+    /// Every item of `playlists`, one page at a time, each with the playlist
+    /// it came from. A page that still fails after retries is yielded as an
+    /// error and skipped, leaving a gap, as `get_playlist_tracks` does; the
+    /// positions after it stay those of the playlist. Without a known total
+    /// nothing after a failed page is fetched. A rejected token is yielded
+    /// as it is and ends the stream.
+    pub fn stream_all_user_tracks_with_context<'a>(
+        &'a self,
+        playlists: &'a [Playlist],
+    ) -> impl Stream<Item = Result<TrackWithContext, Box<dyn Error>>> + 'a {
+        stream! {
+            for playlist in playlists {
+                let mut position = 0;
+                let mut total = playlist.tracks.total;
+                let mut next_url = Some(playlist.tracks.href.clone());
+                while let Some(url) = next_url {
+                    let page = match self.get::<PaginatedTrackResponse>(&url).await {
+                        Ok(page) => page,
+                        Err(e) if aborts_run(&*e) => {
+                            yield Err(e);
+                            return;
+                        }
+                        Err(e) => {
+                            let (offset, limit) = match page_window(&url) {
+                                Ok(window) => window,
+                                Err(window_error) => {
+                                    yield Err(format!("{}: {}", playlist.name, window_error).into());
+                                    break;
+                                }
+                            };
+                            yield Err(format!(
+                                "{}: skipping {}: {}",
+                                playlist.name,
+                                item_range(offset, limit),
+                                e
+                            )
+                            .into());
+                            position = offset.saturating_add(limit) as usize;
+                            next_url = match total {
+                                Some(total) if offset.saturating_add(limit) < total => {
+                                    page_url(&url, offset + limit, limit).ok()
+                                }
+                                Some(_) => None,
+                                None => {
+                                    warn!(
+                                        "{}: size unknown; not fetching past the failed page",
+                                        playlist.name
+                                    );
+                                    None
+                                }
+                            };
+                            continue;
+                        }
+                    };
+                    total = page.total.or(total);
+                    for item in page.items {
+                        yield Ok(TrackWithContext {
+                            playlist_id: playlist.id.clone(),
+                            playlist_name: playlist.name.clone(),
+                            position,
+                            item,
+                        });
+                        position += 1;
+                    }
+                    next_url = page.next;
+                    if next_url.is_some() {
+                        self.page_pause(Duration::from_secs(1)).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The artists the user follows, one at a time as their pages arrive.
+    /// The endpoint pages by cursor rather than offset: each page names the
+    /// artist to continue after, which is added to `initial_url` for the
+    /// next. A page that fails after retries is yielded as an error and
+    /// ends the stream.
+    pub fn get_followed_artists_stream<'a>(
+        &'a self,
+        initial_url: &'a str,
+    ) -> impl Stream<Item = Result<FullArtist, Box<dyn Error>>> + 'a {
+        stream! {
+            let mut url = initial_url.to_string();
+            loop {
+                let page = match self.get::<FollowedArtistsResponse>(&url).await {
+                    Ok(response) => response.artists,
+                    Err(e) => {
+                        yield Err(e);
+                        break;
+                    }
+                };
+                let done = page.items.is_empty();
+                for artist in page.items {
+                    yield Ok(artist);
+                }
+                let Some(after) = page.cursors.and_then(|cursors| cursors.after) else {
+                    break;
+                };
+                if done {
+                    break;
+                }
+                url = format!("{}&after={}", initial_url, after);
+                self.page_pause(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+/// A response with an error status, after any retries.
+#[derive(Debug)]
+pub struct StatusError {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed request: {}: {}", self.status, self.body)
+    }
+}
+
+impl Error for StatusError {}
+
+/// Whether `error` would fail every request after it too, as a rejected
+/// token does, so skipping the page it hit would only lose every page
+/// left one by one.
+pub fn aborts_run(error: &(dyn Error + 'static)) -> bool {
+    error
+        .downcast_ref::<StatusError>()
+        .is_some_and(|e| e.status == StatusCode::UNAUTHORIZED)
+}
+
+/// "items 100-199" for the page at `offset` holding `limit` items.
+pub fn item_range(offset: u32, limit: u32) -> String {
+    match limit {
+        0 => format!("items from {}", offset),
+        1 => format!("item {}", offset),
+        _ => format!("items {}-{}", offset, offset.saturating_add(limit - 1)),
+    }
+}
+
+/// A page of playlist items that could not be fetched.
+#[derive(Debug)]
+pub struct FailedPage {
+    pub url: String,
+    pub offset: u32,
+    pub limit: u32,
+    /// How many items were fetched before this page.
+    pub position: usize,
+    pub error: String,
+}
+
+/// A playlist item together with where it sits.
+#[derive(Debug)]
+pub struct TrackWithContext {
+    pub playlist_id: String,
+    pub playlist_name: String,
+    /// Zero-based index of the item in its playlist.
+    pub position: usize,
+    pub item: TrackItem,
+}
+
+/// The API URL of user `user_id`, or of `rest` under it. Older user IDs,
+/// from accounts made through Facebook, may hold characters such as `#`,
+/// `?`, `/` and spaces, so the ID is percent-encoded as a path segment.
+pub fn user_url(user_id: &str, rest: &[&str]) -> String {
+    let mut url = Url::parse(API_BASE).expect("API_BASE is a valid URL");
+    url.path_segments_mut()
+        .expect("API_BASE has a path")
+        .push("users")
+        .push(user_id)
+        .extend(rest);
+    url.into()
+}
+
+/// The playlist ID in a bare ID, a `spotify:playlist:` URI or an
+/// open.spotify.com link (with or without its scheme, `?si=` or a locale
+/// prefix such as `/intl-de`).
+pub fn normalize_playlist_id(id_or_url: &str) -> Result<String, Box<dyn Error>> {
+    Ok(normalize_id(IdKind::Playlist, id_or_url, false)?)
+}
+
+/// The offset and limit a page URL asks for, with Spotify's defaults. A
+/// limit of 0, which Spotify rejects, counts as the default.
+fn page_window(url: &str) -> Result<(u32, u32), Box<dyn Error>> {
+    let url = Url::parse(url)?;
+    let param = |name: &str, default: u32| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map_or(Ok(default), |(_, value)| value.parse())
+    };
+    let limit = match param("limit", PLAYLIST_PAGE_LIMIT)? {
+        0 => PLAYLIST_PAGE_LIMIT,
+        limit => limit,
+    };
+    Ok((param("offset", 0)?, limit))
+}
+
+fn page_url(url: &str, offset: u32, limit: u32) -> Result<String, Box<dyn Error>> {
+    let mut url = Url::parse(url)?;
+    let query: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| key != "offset" && key != "limit")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(query)
+        .append_pair("offset", &offset.to_string())
+        .append_pair("limit", &limit.to_string());
+    Ok(url.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::EXPORT_SCOPES;
+    use crate::middleware::tests::serve;
+    use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+    use std::sync::{atomic::Ordering, Arc, Mutex};
+
+    #[test]
+    fn unreadable_scopes_report_nothing_missing() {
+        // Checked against the token's claims only when it has some.
+        let opaque = SpotifyAPI::new("BQDdDv4xL3lFoRr".to_string(), Client::new());
+        assert!(opaque.check_token_scopes(&EXPORT_SCOPES).is_empty());
+        let claims = BASE64_URL_SAFE_NO_PAD.encode(r#"{"scope":"playlist-read-private"}"#);
+        let jwt = SpotifyAPI::new(format!("e30.{}.c2ln", claims), Client::new());
+        assert_eq!(
+            jwt.check_token_scopes(&EXPORT_SCOPES),
+            ["playlist-read-collaborative"]
+        );
+    }
+
+    #[test]
+    fn playlist_ids_come_out_bare() {
+        let id = "37i9dQZF1DXcBWIGoYBM5M";
+        for input in [
+            id.to_string(),
+            format!("spotify:playlist:{}", id),
+            format!(
+                "https://open.spotify.com/playlist/{}?si=a1b2c3d4e5f64a7b",
+                id
+            ),
+        ] {
+            assert_eq!(normalize_playlist_id(&input).unwrap(), id);
+        }
+        assert!(normalize_playlist_id("spotify:album:4aawyAB9vmqN3uQ7FjRGTy").is_err());
+    }
+
+    #[tokio::test]
+    async fn unparseable_responses_are_retried_only_when_asked() {
+        for (retry_on, outcome) in [
+            (vec![RetryClass::Parse], (true, 2)),
+            (Vec::new(), (false, 1)),
+        ] {
+            let (base, served) = crate::middleware::tests::serve(|_, n| {
+                (
+                    200,
+                    if n == 0 {
+                        "{\"id\":".into()
+                    } else {
+                        "{}".into()
+                    },
+                )
+            })
+            .await;
+            let api = SpotifyAPI::new(String::new(), Client::new()).with_retry_on(retry_on);
+            let got = api
+                .get::<serde_json::Value>(&format!("{}/v1/me", base))
+                .await;
+            assert_eq!((got.is_ok(), served.load(Ordering::SeqCst)), outcome);
+        }
+    }
+
+    #[test]
+    fn user_ids_are_percent_encoded() {
+        assert_eq!(
+            user_url("wizzler", &[]),
+            "https://api.spotify.com/v1/users/wizzler"
+        );
+        assert_eq!(
+            user_url("a b#c/d?e%", &["playlists"]),
+            "https://api.spotify.com/v1/users/a%20b%23c%2Fd%3Fe%25/playlists"
+        );
+        assert_eq!(
+            endpoint_class(&user_url("a/b", &["playlists"])),
+            "/v1/users/{id}/playlists"
+        );
+    }
+
+    #[test]
+    fn item_ranges_never_underflow() {
+        assert_eq!(item_range(100, 100), "items 100-199");
+        assert_eq!(item_range(7, 1), "item 7");
+        assert_eq!(item_range(0, 0), "items from 0");
+        assert_eq!(
+            item_range(u32::MAX, 5),
+            format!("items {}-{}", u32::MAX, u32::MAX)
+        );
+    }
+
+    #[test]
+    fn a_zero_limit_counts_as_the_default() {
+        let url = "https://api.spotify.com/v1/playlists/x/tracks?offset=200&limit=0";
+        assert_eq!(page_window(url).unwrap(), (200, PLAYLIST_PAGE_LIMIT));
+        let url = "https://api.spotify.com/v1/playlists/x/tracks";
+        assert_eq!(page_window(url).unwrap(), (0, PLAYLIST_PAGE_LIMIT));
+    }
+
+    #[test]
+    fn only_a_rejected_token_aborts_the_run() {
+        let status = |status| -> Box<dyn Error> {
+            StatusError {
+                status,
+                body: String::new(),
+            }
+            .into()
+        };
+        assert!(aborts_run(&*status(StatusCode::UNAUTHORIZED)));
+        assert!(!aborts_run(&*status(StatusCode::FORBIDDEN)));
+        assert!(!aborts_run(&*status(StatusCode::BAD_GATEWAY)));
+        assert!(!aborts_run(&*Box::<dyn Error>::from("timed out")));
+    }
+
+    /// A client with Spotify's own policy whose outage probe goes to the
+    /// server at `base` in place of the API, probing every few milliseconds
+    /// rather than minutes.
+    fn against(base: &str) -> SpotifyAPI {
+        let mut api = SpotifyAPI::new("token".to_string(), Client::new());
+        let policy = api.http.policy_mut();
+        policy.probe_url = policy
+            .probe_url
+            .as_ref()
+            .map(|url| url.replace(API_BASE, &format!("{}/v1", base)));
+        if let Some(outage) = &mut policy.outage {
+            outage.probe_interval = Duration::from_millis(10);
+        }
+        api
+    }
+
+    /// Serves `respond` as `serve` does, also keeping the path of every
+    /// request in order.
+    async fn serve_logged<F>(respond: F) -> (String, Arc<Mutex<Vec<String>>>)
+    where
+        F: Fn(&str, usize) -> (u16, String) + Send + Sync + 'static,
+    {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let paths = Arc::clone(&log);
+        let (base, _) = serve(move |path, n| {
+            paths.lock().unwrap().push(path.to_string());
+            respond(path, n)
+        })
+        .await;
+        (base, log)
+    }
+
+    #[tokio::test]
+    async fn a_post_is_never_retried_on_a_server_error() {
+        let (base, log) = serve_logged(|_, n| match n {
+            0 => (502, "{}".into()),
+            _ => (201, r#"{"snapshot_id":"s"}"#.into()),
+        })
+        .await;
+        let api = against(&base);
+        let url = format!("{}/v1/playlists/p/tracks", base);
+
+        let sent: Result<serde_json::Value, _> = api
+            .send_json(Method::POST, &url, &serde_json::json!({"uris": []}))
+            .await;
+
+        let error = sent.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<StatusError>().map(|e| e.status),
+            Some(StatusCode::BAD_GATEWAY)
+        );
+        assert_eq!(*log.lock().unwrap(), ["/v1/playlists/p/tracks"]);
+    }
+
+    #[tokio::test]
+    async fn a_post_is_retried_when_rate_limited() {
+        let (base, log) = serve_logged(|_, n| match n {
+            0 => (429, "{}".into()),
+            _ => (201, r#"{"snapshot_id":"s"}"#.into()),
+        })
+        .await;
+        let api = against(&base);
+        let url = format!("{}/v1/playlists/p/tracks", base);
+
+        let sent: serde_json::Value = api
+            .send_json(Method::POST, &url, &serde_json::json!({"uris": []}))
+            .await
+            .unwrap();
+
+        assert_eq!(sent["snapshot_id"], "s");
+        assert_eq!(log.lock().unwrap().len(), 2);
+        assert_eq!(api.rate_limited(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_get_is_retried_on_a_server_error() {
+        let (base, log) = serve_logged(|_, n| match n {
+            0 => (500, "{}".into()),
+            _ => (200, r#"{"id":"me"}"#.into()),
+        })
+        .await;
+        let api = against(&base);
+
+        let user: serde_json::Value = api.get(&format!("{}/v1/users/me", base)).await.unwrap();
+
+        assert_eq!(user["id"], "me");
+        assert_eq!(log.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn an_outage_is_waited_out_by_probing_me() {
+        let threshold = OutagePolicy::default().threshold as usize;
+        let (base, log) = serve_logged(move |path, n| match path {
+            "/v1/me" => (200, r#"{"id":"me"}"#.into()),
+            _ if n < threshold => (503, "{}".into()),
+            _ => (200, r#"{"id":"p"}"#.into()),
+        })
+        .await;
+        let api = against(&base);
+        let url = format!("{}/v1/playlists/p", base);
+
+        // The first request runs out of retries before an outage is called;
+        // the next one's first failure reaches the threshold.
+        let first: Result<serde_json::Value, _> = api.get(&url).await;
+        assert!(first.is_err());
+        let second: serde_json::Value = api.get(&url).await.unwrap();
+
+        assert_eq!(second["id"], "p");
+        let log = log.lock().unwrap();
+        let probes: Vec<usize> = log
+            .iter()
+            .enumerate()
+            .filter(|(_, path)| *path == "/v1/me")
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(probes, [threshold], "{:?}", log);
+        assert_eq!(log.len(), threshold + 2);
+        assert!(api.http.outage_pause() > Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn a_post_never_waits_on_an_outage() {
+        let (base, log) = serve_logged(|_, _| (503, "{}".into())).await;
+        let api = against(&base);
+        let url = format!("{}/v1/playlists/p/tracks", base);
+
+        for _ in 0..OutagePolicy::default().threshold + 1 {
+            let sent: Result<serde_json::Value, _> = api
+                .send_json(Method::POST, &url, &serde_json::json!({"uris": []}))
+                .await;
+            assert!(sent.is_err());
+        }
+
+        let log = log.lock().unwrap();
+        assert!(
+            log.iter().all(|path| path == "/v1/playlists/p/tracks"),
+            "{:?}",
+            log
+        );
+        assert_eq!(api.http.outage_pause(), Duration::ZERO);
+    }
+
+    /// A client answering from the responses recorded in `dir` for
+    /// `account`, as with `--offline`.
+    fn offline(dir: &Path, account: &str) -> SpotifyAPI {
+        let mut api = SpotifyAPI::new(String::new(), Client::new());
+        api.responses = Some(ResponseCache::new(dir, account));
+        api.offline_mode = true;
+        api
+    }
+
+    #[tokio::test]
+    async fn saved_albums_are_read_page_by_page() {
+        let dir = crate::testdir::TestDir::new();
+        let responses = ResponseCache::new(dir.path(), "me");
+        let first = format!("{}/me/albums?limit=50", API_BASE);
+        let second = format!("{}/me/albums?offset=50&limit=50", API_BASE);
+        let more_tracks = format!("{}/albums/b/tracks?offset=50&limit=50", API_BASE);
+        let album = |name: &str, tracks: &[&str], next: Option<&str>| {
+            serde_json::json!({
+                "name": name,
+                "tracks": {
+                    "items": tracks.iter().map(|uri| serde_json::json!({"uri": uri})).collect::<Vec<_>>(),
+                    "next": next
+                }
+            })
+        };
+        responses.store(
+            &first,
+            &serde_json::json!({
+                "items": [
+                    {"added_at": "2024-03-01T00:00:00Z", "album": album("A", &["spotify:track:a1", "spotify:track:a2"], None)},
+                    {"added_at": "2024-02-01T00:00:00Z", "album": album("B", &["spotify:track:b1"], Some(&more_tracks))}
+                ],
+                "next": second,
+                "total": 3
+            })
+            .to_string(),
+        );
+        responses.store(
+            &second,
+            &serde_json::json!({
+                "items": [{"added_at": "2023-12-24T00:00:00Z", "album": album("C", &[], None)}],
+                "next": null,
+                "total": 3
+            })
+            .to_string(),
+        );
+        responses.store(
+            &more_tracks,
+            &serde_json::json!({"items": [{"uri": "spotify:track:b51"}, {"uri": ""}], "next": null}).to_string(),
+        );
+        let api = offline(dir.path(), "me");
+
+        let albums = api.get_all_liked_albums().await.unwrap();
+        let saved: Vec<_> = albums
+            .iter()
+            .map(|saved| (saved.album.name.as_str(), saved.added_at.as_str()))
+            .collect();
+        assert_eq!(
+            saved,
+            [
+                ("A", "2024-03-01T00:00:00Z"),
+                ("B", "2024-02-01T00:00:00Z"),
+                ("C", "2023-12-24T00:00:00Z"),
+            ]
+        );
+        assert_eq!(
+            api.get_saved_album_track_uris().await.unwrap(),
+            [
+                "spotify:track:a1",
+                "spotify:track:a2",
+                "spotify:track:b1",
+                "spotify:track:b51"
+            ]
+        );
+    }
+
+    /// Records a library of `count` playlists as the listing pages a run
+    /// with `--record-responses` leaves, a third of them "New Playlist".
+    fn record_library(responses: &ResponseCache, count: usize) {
+        let url = |offset: usize| match offset {
+            0 => format!("{}/me/playlists?limit=50", API_BASE),
+            _ => format!("{}/me/playlists?offset={}&limit=50", API_BASE, offset),
+        };
+        for offset in (0..count).step_by(PLAYLIST_LIST_LIMIT) {
+            let items: Vec<_> = (offset..count.min(offset + PLAYLIST_LIST_LIMIT))
+                .map(|n| {
+                    serde_json::json!({
+                        "id": format!("playlist{:014}", n),
+                        "name": match n % 3 {
+                            0 => "New Playlist".to_string(),
+                            1 => format!("Mix {}", n / 30),
+                            _ => format!("MIX {}", n / 30),
+                        },
+                        "collaborative": n % 7 == 0,
+                        "public": n % 2 == 0,
+                        "owner": {"id": if n % 4 == 0 { "friend" } else { "hoarder" }, "display_name": "Hoarder"},
+                        "tracks": {"href": format!("{}/playlists/playlist{:014}/tracks", API_BASE, n), "total": n % 400},
+                        "snapshot_id": format!("snapshot{}", n),
+                        "images": null
+                    })
+                })
+                .collect();
+            let next =
+                (offset + PLAYLIST_LIST_LIMIT < count).then(|| url(offset + PLAYLIST_LIST_LIMIT));
+            let page = serde_json::json!({"items": items, "next": next, "total": count});
+            responses.store(&url(offset), &page.to_string());
+        }
+    }
+
+    const PLAYLISTS: usize = 4_800;
+
+    /// Lists the library `record_library` recorded for `api` and plans an
+    /// export of it, as far as the first track request.
+    async fn plan_library(api: &SpotifyAPI) {
+        let Some(crate::cli::Command::Export(args)) =
+            <crate::cli::Cli as clap::Parser>::parse_from(["rimusic-convert", "export"]).command
+        else {
+            panic!("export arguments did not parse");
+        };
+
+        let playlists = api.get_library_playlists().await.unwrap();
+        assert_eq!(playlists.len(), PLAYLISTS);
+        let playlists = crate::filter::filter_playlists_by_visibility(
+            playlists,
+            crate::filter::VisibilityFilter::All,
+        );
+        let calls = SpotifyAPI::estimate_api_calls(&playlists, &args);
+        assert_eq!(calls.playlist_list_calls, PLAYLISTS / PLAYLIST_LIST_LIMIT);
+
+        // Every file name the export would claim, most of them colliding.
+        let mut paths = crate::paths::OutputPaths::new(false);
+        let names: std::collections::HashSet<String> = playlists
+            .iter()
+            .map(|playlist| paths.reserve(&playlist.name, "csv").to_lowercase())
+            .collect();
+        assert_eq!(names.len(), PLAYLISTS);
+        assert!(names.contains(&format!("new playlist ({}).csv", PLAYLISTS / 3)));
+
+        // A rerun that left every playlist as it was.
+        let previous = crate::index::RunIndex {
+            exported_at: String::new(),
+            provenance: None,
+            playlists: playlists
+                .iter()
+                .map(|playlist| crate::index::IndexEntry {
+                    id: playlist.id.clone(),
+                    name: playlist.name.clone(),
+                    owner: playlist.owner.display_name.clone(),
+                    track_count: playlist.tracks.total.unwrap_or(0) as usize,
+                    file: None,
+                    description: None,
+                    snapshot_id: playlist.snapshot_id.clone(),
+                    followers: None,
+                    collaborative: playlist.collaborative,
+                    url: None,
+                    owner_url: None,
+                    cover_url: None,
+                })
+                .collect(),
+            summary: None,
+        };
+        let mut index = crate::index::RunIndex {
+            exported_at: String::new(),
+            provenance: None,
+            playlists: Vec::new(),
+            summary: None,
+        };
+        index.carry_forward(&previous, &playlists);
+        let ids: Vec<String> = playlists
+            .iter()
+            .map(|playlist| playlist.id.clone())
+            .collect();
+        index.restore(Some(&previous), &ids);
+        assert_eq!(index.playlists.len(), PLAYLISTS);
+    }
+
+    #[tokio::test]
+    async fn lists_and_plans_thousands_of_playlists() {
+        let dir = crate::testdir::TestDir::new();
+        record_library(&ResponseCache::new(dir.path(), "hoarder"), PLAYLISTS);
+        plan_library(&offline(dir.path(), "hoarder")).await;
+    }
+
+    /// Wall-clock time depends on the machine and its load, so this only
+    /// runs when asked for: `cargo test --release -- --ignored`.
+    #[tokio::test]
+    #[ignore = "timing"]
+    async fn plans_thousands_of_playlists_in_time() {
+        let dir = crate::testdir::TestDir::new();
+        record_library(&ResponseCache::new(dir.path(), "hoarder"), PLAYLISTS);
+        let api = offline(dir.path(), "hoarder");
+        let started = std::time::Instant::now();
+        plan_library(&api).await;
+        // Scanning the previous index for every playlist, as this once did,
+        // took most of a second.
+        let elapsed = started.elapsed();
+        assert!(elapsed < Duration::from_millis(500), "took {:?}", elapsed);
+    }
+}
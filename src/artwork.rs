@@ -0,0 +1,283 @@
+//! Album covers saved next to the export for `--download-artwork`. Files are
+//! named by the SHA-256 of their contents, so a cover Spotify serves under
+//! several URLs (the same album in different markets, a single reusing its
+//! album's art) is stored once, and every row using it names the same file.
+//! Nothing is linked or copied, so this works on any filesystem.
+//!
+//! `artwork/index.json` remembers which file each URL gave, so a later run
+//! downloads only covers it has not seen.
+
+use log::info;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    api::SpotifyAPI,
+    atomic::{write_bytes_atomic, write_json_atomic, TEMP_DIR},
+    manifest::sha256_bytes,
+    store::{Store, SQLITE_HEADER, URL_SCHEME},
+};
+
+pub const ARTWORK_DIR: &str = "artwork";
+const ARTWORK_INDEX: &str = "index.json";
+
+/// The file extension for an image, told from its first bytes.
+fn image_extension(bytes: &[u8]) -> &'static str {
+    match bytes {
+        [0x89, b'P', b'N', b'G', ..] => "png",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "webp",
+        _ => "jpg",
+    }
+}
+
+#[derive(Debug)]
+pub struct ArtworkStore {
+    dir: PathBuf,
+    /// URL to file name within `dir`.
+    by_url: HashMap<String, String>,
+    pub downloaded: usize,
+    /// Downloads whose contents were already stored under another URL.
+    pub duplicates: usize,
+}
+
+impl ArtworkStore {
+    pub fn open(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let by_url = match File::open(dir.join(ARTWORK_INDEX)) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file))?,
+            Err(_) => HashMap::new(),
+        };
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            by_url,
+            downloaded: 0,
+            duplicates: 0,
+        })
+    }
+
+    /// The path of the cover at `url`, downloading it unless a file for the
+    /// URL is already stored.
+    pub async fn fetch(&mut self, api: &SpotifyAPI, url: &str) -> Result<String, Box<dyn Error>> {
+        if let Some(name) = self.by_url.get(url) {
+            if self.dir.join(name).is_file() {
+                return Ok(self.relative(name));
+            }
+        }
+        let bytes = api.download(url).await?;
+        self.downloaded += 1;
+        let name = format!("{}.{}", sha256_bytes(&bytes), image_extension(&bytes));
+        let path = self.dir.join(&name);
+        if path.is_file() {
+            self.duplicates += 1;
+        } else {
+            write_bytes_atomic(&path, &bytes)?;
+        }
+        let relative = self.relative(&name);
+        self.by_url.insert(url.to_string(), name);
+        Ok(relative)
+    }
+
+    fn relative(&self, name: &str) -> String {
+        self.dir.join(name).to_string_lossy().into_owned()
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        write_json_atomic(&self.dir.join(ARTWORK_INDEX), &self.by_url)
+    }
+}
+
+/// The cover file names `bytes` mentions as `artwork/<name>`, with either
+/// path separator and with backslashes escaped or not, so every format
+/// counts: CSVs, library.json, rendered templates, NDJSON deltas and the
+/// SQLite store alike.
+fn mentioned_covers(bytes: &[u8], referenced: &mut HashSet<String>) {
+    let prefix = ARTWORK_DIR.as_bytes();
+    let mut rest = bytes;
+    while let Some(at) = rest
+        .windows(prefix.len())
+        .position(|window| window == prefix)
+    {
+        rest = &rest[at + prefix.len()..];
+        let separators = rest
+            .iter()
+            .take_while(|&&b| b == b'/' || b == b'\\')
+            .count();
+        if separators == 0 {
+            continue;
+        }
+        let name: Vec<u8> = rest[separators..]
+            .iter()
+            .copied()
+            .take_while(|b| b.is_ascii_alphanumeric() || b"._-".contains(b))
+            .collect();
+        if !name.is_empty() {
+            referenced.insert(String::from_utf8_lossy(&name).into_owned());
+        }
+    }
+}
+
+/// Every cover file name mentioned by a file in `dir`, its subdirectories
+/// other than the artwork directory itself, or the `stores`. SQLite files
+/// are queried, since a long record can be split across pages. A file that
+/// cannot be read fails the scan, since the covers it uses are unknown.
+fn referenced_artwork(dir: &Path, stores: &[String]) -> Result<HashSet<String>, Box<dyn Error>> {
+    let mut referenced = HashSet::new();
+    for url in stores {
+        referenced.extend(Store::open(url)?.album_image_files()?);
+    }
+    let mut pending = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir).map_err(|e| format!("{}: {}", dir.display(), e))? {
+            let path = entry?.path();
+            if path.is_dir() {
+                if !path.ends_with(ARTWORK_DIR) && !path.ends_with(TEMP_DIR) {
+                    dirs.push(path);
+                }
+            } else {
+                pending.push(path);
+            }
+        }
+    }
+    for path in pending {
+        let bytes = fs::read(&path).map_err(|e| {
+            format!(
+                "{}: {}; not removing any covers, since this file may use them",
+                path.display(),
+                e
+            )
+        })?;
+        if bytes.starts_with(SQLITE_HEADER) {
+            let url = format!("{}{}", URL_SCHEME, path.display());
+            referenced.extend(Store::open(&url)?.album_image_files()?);
+        } else {
+            mentioned_covers(&bytes, &mut referenced);
+        }
+    }
+    Ok(referenced)
+}
+
+/// Deletes the covers in `dir`'s artwork directory that no export in `dir`,
+/// nor any of the `stores`, refers to any more. Returns how many files and
+/// bytes went.
+pub fn collect_garbage(dir: &Path, stores: &[String]) -> Result<(usize, u64), Box<dyn Error>> {
+    let referenced = referenced_artwork(dir, stores)?;
+    let artwork_dir = dir.join(ARTWORK_DIR);
+    let mut store = ArtworkStore::open(&artwork_dir)?;
+    let (mut removed, mut bytes) = (0, 0);
+    for entry in fs::read_dir(&artwork_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name == ARTWORK_INDEX || name.starts_with('.') {
+            continue;
+        }
+        if referenced.contains(&name) {
+            continue;
+        }
+        let relative = Path::new(ARTWORK_DIR).join(&name);
+        bytes += entry.metadata()?.len();
+        fs::remove_file(entry.path())?;
+        info!("Removed {}", relative.display());
+        removed += 1;
+    }
+    store
+        .by_url
+        .retain(|_, name| artwork_dir.join(name).is_file());
+    store.save()?;
+    Ok((removed, bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        output::PlaylistRecords, record::tests::sample_record, store::RunFilters, testdir::TestDir,
+    };
+
+    const KEPT: &str = "0a1b.jpg";
+    const TEMPLATED: &str = "2c3d.png";
+    const UNUSED: &str = "4e5f.jpg";
+
+    fn export_dir() -> TestDir {
+        let dir = TestDir::new();
+        let artwork = dir.path().join(ARTWORK_DIR);
+        fs::create_dir(&artwork).unwrap();
+        for name in [KEPT, TEMPLATED, UNUSED] {
+            fs::write(artwork.join(name), name).unwrap();
+        }
+        fs::write(
+            dir.path().join("Road Trip.csv"),
+            format!("Track Name,Album Image File\nA,artwork/{}\n", KEPT),
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("Road Trip.md"),
+            format!("![cover](artwork\\\\{})\n", TEMPLATED),
+        )
+        .unwrap();
+        dir
+    }
+
+    #[test]
+    fn removes_only_unreferenced_covers() {
+        let dir = export_dir();
+        let (removed, bytes) = collect_garbage(dir.path(), &[]).unwrap();
+        assert_eq!((removed, bytes), (1, UNUSED.len() as u64));
+        let artwork = dir.path().join(ARTWORK_DIR);
+        assert!(artwork.join(KEPT).is_file());
+        assert!(artwork.join(TEMPLATED).is_file());
+        assert!(!artwork.join(UNUSED).exists());
+    }
+
+    #[test]
+    fn covers_named_by_a_store_are_kept() {
+        let dir = export_dir();
+        let elsewhere = TestDir::new();
+        let url = format!(
+            "{}{}",
+            URL_SCHEME,
+            elsewhere.path().join("library.db").display()
+        );
+        let mut store = Store::open(&url).unwrap();
+        let run = store.begin_run(&[], None, &RunFilters::default()).unwrap();
+        let mut record = sample_record("spotify:track:1", "A");
+        record.album_image_file = Some(format!("{}/{}", ARTWORK_DIR, UNUSED));
+        let playlist = PlaylistRecords {
+            name: "Road Trip".to_string(),
+            owner: "me".to_string(),
+            tracks: vec![record],
+        };
+        store.record_playlist(run, "p1", &playlist, false).unwrap();
+        drop(store);
+
+        let (removed, _) = collect_garbage(dir.path(), std::slice::from_ref(&url)).unwrap();
+        assert_eq!(removed, 0);
+
+        // A store inside the export directory is found without being named.
+        fs::copy(
+            elsewhere.path().join("library.db"),
+            dir.path().join("library.db"),
+        )
+        .unwrap();
+        let (removed, _) = collect_garbage(dir.path(), &[]).unwrap();
+        assert_eq!(removed, 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn an_unreadable_file_stops_the_collection() {
+        let dir = export_dir();
+        std::os::unix::fs::symlink(
+            dir.path().join("gone.csv"),
+            dir.path().join("Hand Edited.csv"),
+        )
+        .unwrap();
+        assert!(collect_garbage(dir.path(), &[]).is_err());
+        assert!(dir.path().join(ARTWORK_DIR).join(UNUSED).is_file());
+    }
+}
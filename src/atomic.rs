@@ -0,0 +1,170 @@
+//! Replacing the files later runs read back: state.json, index.json,
+//! quarantine.json, library.json and spliced playlist CSVs. Each is written
+//! to a temporary file in `.rimusic-convert-tmp/` beside it and renamed over
+//! it, so a crash or a full disk mid-write leaves the previous version
+//! rather than a truncated one, and what a crash leaves behind is in one
+//! place for `recovery` to sweep. The directory itself is left for
+//! `recovery` to remove too: another run may be about to write into it.
+
+use serde::Serialize;
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+pub const TEMP_DIR: &str = ".rimusic-convert-tmp";
+
+/// Numbers this process's temporaries, so two tasks writing the same file
+/// at once never share one either.
+static NEXT_TEMP: AtomicU64 = AtomicU64::new(0);
+
+/// Creates a file in the temporary directory beside `path`, named
+/// `<name>.<pid>-<n>.tmp` so that no two writes, from this run or any
+/// other, share a temporary. Being on the same filesystem as `path`, it
+/// can be renamed over it.
+fn create_temp(path: &Path) -> Result<(PathBuf, File), Box<dyn Error>> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let dir = path.with_file_name(TEMP_DIR);
+    let temp = dir.join(format!(
+        "{}.{}-{}.tmp",
+        name,
+        process::id(),
+        NEXT_TEMP.fetch_add(1, Ordering::Relaxed)
+    ));
+    // A second try in case another run's `recovery` removed the directory,
+    // empty, between creating it and creating the file in it.
+    for _ in 0..2 {
+        fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        match File::create(&temp) {
+            Ok(file) => return Ok((temp, file)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("{}: {}", temp.display(), e).into()),
+        }
+    }
+    Err(format!("{}: the directory keeps disappearing", dir.display()).into())
+}
+
+/// Replaces `path` with whatever `write` writes, all or nothing.
+pub fn write_atomic<F>(path: &Path, write: F) -> Result<(), Box<dyn Error>>
+where
+    F: FnOnce(&mut BufWriter<File>) -> Result<(), Box<dyn Error>>,
+{
+    let (temp, file) = create_temp(path)?;
+    let result = (|| -> Result<(), Box<dyn Error>> {
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        fs::rename(&temp, path)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+pub fn write_json_atomic<T: Serialize + ?Sized>(
+    path: &Path,
+    value: &T,
+) -> Result<(), Box<dyn Error>> {
+    write_atomic(path, |writer| {
+        serde_json::to_writer_pretty(&mut *writer, value)?;
+        Ok(())
+    })
+}
+
+pub fn write_bytes_atomic(path: &Path, contents: &[u8]) -> Result<(), Box<dyn Error>> {
+    write_atomic(path, |writer| Ok(writer.write_all(contents)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cache::ResponseCache, testdir::TestDir};
+    use std::{collections::BTreeMap, sync::Arc};
+
+    const TASKS: usize = 48;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn concurrent_writers_never_leave_a_torn_file() {
+        let dir = Arc::new(TestDir::new());
+        let path = dir.path().join("state.json");
+        write_json_atomic(&path, &BTreeMap::from([("writer", usize::MAX)])).unwrap();
+
+        let mut tasks = Vec::new();
+        for writer in 0..TASKS {
+            let path = path.clone();
+            tasks.push(tokio::task::spawn_blocking(move || {
+                for round in 0..20 {
+                    let value = BTreeMap::from([("writer", writer), ("round", round)]);
+                    write_json_atomic(&path, &value).unwrap();
+                    // Whatever is there, whoever wrote it, is whole.
+                    let read: BTreeMap<String, usize> =
+                        serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+                    assert!(read.contains_key("writer"));
+                }
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let last: BTreeMap<String, usize> =
+            serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(last["round"], 19);
+        // Every temporary was renamed into place; only the directory is left.
+        assert_eq!(fs::read_dir(dir.path().join(TEMP_DIR)).unwrap().count(), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn concurrent_cache_writes_are_all_kept() {
+        let dir = Arc::new(TestDir::new());
+        let mut tasks = Vec::new();
+        for task in 0..TASKS {
+            let dir = Arc::clone(&dir);
+            tasks.push(tokio::task::spawn_blocking(move || {
+                let cache = ResponseCache::new(dir.path(), "alice");
+                for page in 0..10 {
+                    let url = format!("https://api.spotify.com/v1/x?task={}&page={}", task, page);
+                    cache.store(&url, &format!("{{\"page\":{}}}", page));
+                }
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let cache = ResponseCache::new(dir.path(), "alice");
+        for task in 0..TASKS {
+            for page in 0..10 {
+                let url = format!("https://api.spotify.com/v1/x?task={}&page={}", task, page);
+                assert_eq!(
+                    cache.lookup(&url).unwrap(),
+                    format!("{{\"page\":{}}}", page)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn a_write_stopped_midway_keeps_the_previous_version() {
+        let dir = TestDir::new();
+        let path = dir.path().join("index.json");
+        write_bytes_atomic(&path, b"{\"complete\":true}").unwrap();
+        let result = write_atomic(&path, |writer| {
+            writer.write_all(b"{\"compl")?;
+            Err("disk full".into())
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"{\"complete\":true}");
+        assert_eq!(fs::read_dir(dir.path().join(TEMP_DIR)).unwrap().count(), 0);
+    }
+}
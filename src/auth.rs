@@ -0,0 +1,175 @@
+use reqwest::Client;
+use serde::Deserialize;
+use std::{error::Error, fmt};
+
+use crate::http::explain_send_error;
+
+/// Scopes the library export relies on.
+pub const EXPORT_SCOPES: [&str; 2] = ["playlist-read-private", "playlist-read-collaborative"];
+
+/// Needed to read saved shows, albums and Liked Songs.
+pub const LIBRARY_SCOPES: [&str; 1] = ["user-library-read"];
+
+/// Needed to list followed artists.
+pub const FOLLOW_SCOPES: [&str; 1] = ["user-follow-read"];
+
+/// Needed to follow and unfollow artists.
+pub const FOLLOW_MODIFY_SCOPES: [&str; 1] = ["user-follow-modify"];
+
+/// Needed to create playlists and change their tracks.
+pub const MODIFY_SCOPES: [&str; 2] = ["playlist-modify-public", "playlist-modify-private"];
+
+/// Needed to change playlist covers, on top of `MODIFY_SCOPES`.
+pub const IMAGE_UPLOAD_SCOPES: [&str; 1] = ["ugc-image-upload"];
+
+/// Where the web player gets its anonymous token. Undocumented, so it may
+/// change or start refusing requests without notice.
+const PUBLIC_TOKEN_URL: &str =
+    "https://open.spotify.com/get_access_token?reason=transport&productType=web_player";
+
+#[derive(Debug)]
+pub enum AuthError {
+    /// The token is not a JWT or its payload could not be parsed.
+    Malformed(String),
+    /// The token decoded but carries no `scope` claim.
+    MissingScopeClaim,
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Malformed(reason) => write!(f, "malformed access token: {}", reason),
+            AuthError::MissingScopeClaim => write!(f, "access token has no scope claim"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ScopeClaim {
+    SpaceSeparated(String),
+    List(Vec<String>),
+}
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    scope: Option<ScopeClaim>,
+}
+
+/// Reads the `scope` claim from a JWT access token without checking its
+/// signature. This is only used to warn early; the API remains the judge
+/// of what the token may do.
+pub fn decode_token_scopes(token: &str) -> Result<Vec<String>, AuthError> {
+    let claims: Claims = jsonwebtoken::dangerous::insecure_decode_claims(token)
+        .map_err(|e| AuthError::Malformed(e.to_string()))?;
+
+    match claims.scope {
+        Some(ScopeClaim::SpaceSeparated(scope)) => {
+            Ok(scope.split_whitespace().map(str::to_string).collect())
+        }
+        Some(ScopeClaim::List(scopes)) => Ok(scopes),
+        None => Err(AuthError::MissingScopeClaim),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PublicToken {
+    access_token: String,
+}
+
+/// Fetches the anonymous token the Spotify web player uses. It reads public
+/// data only and is rate limited more strictly than a user token.
+pub async fn fetch_public_token(client: &Client) -> Result<String, Box<dyn Error>> {
+    let res = client
+        .get(PUBLIC_TOKEN_URL)
+        .send()
+        .await
+        .map_err(explain_send_error)?;
+    let status = res.status();
+    if !status.is_success() {
+        return Err(format!(
+            "the public token endpoint answered {}; it may have changed, so pass a user token instead",
+            status
+        )
+        .into());
+    }
+    let token: PublicToken = res.json().await.map_err(|e| {
+        format!(
+            "unexpected response from the public token endpoint ({}); pass a user token instead",
+            e
+        )
+    })?;
+    Ok(token.access_token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::prelude::{Engine, BASE64_URL_SAFE_NO_PAD};
+
+    /// A token with `payload` as its claims, signed with nonsense: the
+    /// signature is never checked.
+    fn token(payload: &str) -> String {
+        format!(
+            "{}.{}.c2lnbmF0dXJl",
+            BASE64_URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#),
+            BASE64_URL_SAFE_NO_PAD.encode(payload)
+        )
+    }
+
+    #[test]
+    fn reads_a_space_separated_scope_claim() {
+        let token = token(
+            r#"{
+                "sub": "wizzler",
+                "client_id": "5fe01282e44241328a84e7c5cc169165",
+                "scope": "playlist-read-private  playlist-read-collaborative user-library-read",
+                "iat": 1700000000,
+                "exp": 1700003600
+            }"#,
+        );
+        assert_eq!(
+            decode_token_scopes(&token).unwrap(),
+            [
+                "playlist-read-private",
+                "playlist-read-collaborative",
+                "user-library-read"
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_a_scope_list() {
+        let token = token(r#"{"sub": "wizzler", "scope": ["user-follow-read"]}"#);
+        assert_eq!(decode_token_scopes(&token).unwrap(), ["user-follow-read"]);
+    }
+
+    #[test]
+    fn a_token_without_a_scope_claim_is_reported() {
+        let token = token(r#"{"sub": "wizzler", "exp": 1700003600}"#);
+        assert!(matches!(
+            decode_token_scopes(&token),
+            Err(AuthError::MissingScopeClaim)
+        ));
+    }
+
+    #[test]
+    fn opaque_and_broken_tokens_are_malformed() {
+        for token in [
+            // Spotify's usual opaque access token.
+            "BQDdDv4xL3lFoRrCzNW3vVsnHa7hZCmc4rQEuOy2y-E0yV5Fh8tFc0ZKaOA".to_string(),
+            String::new(),
+            "a.b.c".to_string(),
+            token("not json"),
+        ] {
+            assert!(
+                matches!(decode_token_scopes(&token), Err(AuthError::Malformed(_))),
+                "{:?}",
+                token
+            );
+        }
+    }
+}
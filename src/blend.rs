@@ -0,0 +1,276 @@
+//! Best-effort attribution for Blend and other shared playlists. The API
+//! reports the Blend bot as `added_by`, so instead each track is compared
+//! against what every member keeps in their public playlists. The result is
+//! a guess and is labelled as one. Each member's playlists are read once
+//! per run and each track is attributed once, however many playlists it
+//! is in.
+
+use log::info;
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    api::{user_url, SpotifyAPI},
+    spotify::{Playlist, Track},
+    warnings::{ErrorCollector, Severity},
+};
+
+/// Spotify's own account, which owns the Blends it makes for its members.
+const SPOTIFY_USER: &str = "spotify";
+
+/// Public playlists read per member; enough to get a feel for their taste
+/// without turning the export into a crawl.
+const MAX_MEMBER_PLAYLISTS: usize = 20;
+
+/// What a member listens to, judged from their public playlists.
+#[derive(Debug)]
+struct MemberTaste {
+    user_id: String,
+    track_uris: HashSet<String>,
+    artist_uris: HashSet<String>,
+}
+
+impl MemberTaste {
+    fn new<'a>(user_id: &str, tracks: impl IntoIterator<Item = &'a Track>) -> Self {
+        let mut taste = MemberTaste {
+            user_id: user_id.to_string(),
+            track_uris: HashSet::new(),
+            artist_uris: HashSet::new(),
+        };
+        for track in tracks {
+            taste.track_uris.extend(track.uri.clone());
+            taste
+                .artist_uris
+                .extend(track.artists.iter().filter_map(|a| a.uri.clone()));
+        }
+        taste
+    }
+}
+
+#[derive(Debug)]
+pub struct BlendAttribution {
+    members: Vec<MemberTaste>,
+    /// What `attribute` answered for each track URI so far.
+    attributed: HashMap<String, Option<String>>,
+}
+
+impl BlendAttribution {
+    /// Fetches each member's public playlists once for the whole run. A
+    /// member that cannot be read is reported and left out.
+    pub async fn load(api: &SpotifyAPI, member_ids: &[String], errors: &ErrorCollector) -> Self {
+        let mut members = Vec::with_capacity(member_ids.len());
+
+        for user_id in member_ids {
+            info!("Reading public playlists of Blend member {}...", user_id);
+            let url = format!("{}?limit=50", user_url(user_id, &["playlists"]));
+            let playlists = match api.get_all_playlists(&url).await {
+                Ok(playlists) => playlists,
+                Err(e) => {
+                    errors.report(
+                        Severity::Warning,
+                        user_id,
+                        None,
+                        format!("Blend member left out of attribution: {}", e),
+                    );
+                    continue;
+                }
+            };
+
+            // Only the member's own playlists say what they like; shared
+            // ones would credit their tracks to every member.
+            let own_playlists = playlists
+                .iter()
+                .filter(|p| p.owner.id == *user_id && !p.collaborative);
+            let mut tracks = Vec::new();
+            for playlist in own_playlists.take(MAX_MEMBER_PLAYLISTS) {
+                let items = match api
+                    .get_playlist_tracks(&playlist.tracks.href, playlist.tracks.total)
+                    .await
+                {
+                    Ok((items, _)) => items,
+                    Err(e) => {
+                        errors.report(
+                            Severity::Warning,
+                            &playlist.name,
+                            None,
+                            format!(
+                                "could not read {}'s playlist for attribution: {}",
+                                user_id, e
+                            ),
+                        );
+                        continue;
+                    }
+                };
+                tracks.extend(items.into_iter().filter_map(|item| item.track));
+            }
+            members.push(MemberTaste::new(user_id, &tracks));
+        }
+
+        Self::from_members(members)
+    }
+
+    fn from_members(members: Vec<MemberTaste>) -> Self {
+        Self {
+            members,
+            attributed: HashMap::new(),
+        }
+    }
+
+    /// Whether several of the members add to `playlist`: it is
+    /// collaborative, or a Blend, which Spotify owns, or owned by one of
+    /// the members. The name says nothing; anyone can call a playlist
+    /// "Coffee Blend".
+    pub fn applies_to(&self, playlist: &Playlist) -> bool {
+        playlist.collaborative
+            || playlist.owner.id == SPOTIFY_USER
+            || self
+                .members
+                .iter()
+                .any(|member| member.user_id == playlist.owner.id)
+    }
+
+    /// Names the member a track was most likely added for, with how sure the
+    /// guess is. `None` when no member's playlists say anything about it.
+    pub fn attribute(&mut self, track: &Track) -> Option<String> {
+        let Some(uri) = &track.uri else {
+            return self.guess(track);
+        };
+        if let Some(known) = self.attributed.get(uri) {
+            return known.clone();
+        }
+        let guess = self.guess(track);
+        self.attributed.insert(uri.clone(), guess.clone());
+        guess
+    }
+
+    fn guess(&self, track: &Track) -> Option<String> {
+        // The track itself in a member's playlists outweighs any number of
+        // shared artists.
+        let score = |member: &MemberTaste| {
+            let track_match = track
+                .uri
+                .as_ref()
+                .is_some_and(|uri| member.track_uris.contains(uri));
+            let artist_matches = track
+                .artists
+                .iter()
+                .filter_map(|a| a.uri.as_ref())
+                .filter(|uri| member.artist_uris.contains(*uri))
+                .count();
+            (track_match, artist_matches)
+        };
+
+        let best = self.members.iter().map(score).max()?;
+        if best == (false, 0) {
+            return None;
+        }
+        let likely: Vec<&str> = self
+            .members
+            .iter()
+            .filter(|member| score(member) == best)
+            .map(|member| member.user_id.as_str())
+            .collect();
+
+        let confidence = match (likely.len(), best.0) {
+            (1, true) => "high",
+            (1, false) => "low",
+            _ => "ambiguous",
+        };
+        Some(format!(
+            "{} ({} confidence, heuristic)",
+            likely.join(" / "),
+            confidence
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(uri: &str, artist: &str) -> Track {
+        serde_json::from_value(serde_json::json!({
+            "uri": uri,
+            "name": uri,
+            "artists": [{"name": artist, "uri": format!("spotify:artist:{}", artist)}],
+            "album": {"name": "Album", "artists": [], "images": []},
+            "duration_ms": 200000,
+            "popularity": 50
+        }))
+        .unwrap()
+    }
+
+    fn playlist(name: &str, owner: &str, collaborative: bool) -> Playlist {
+        serde_json::from_value(serde_json::json!({
+            "id": "p",
+            "name": name,
+            "collaborative": collaborative,
+            "public": false,
+            "owner": {"id": owner, "display_name": owner},
+            "tracks": {"href": "https://api.spotify.com/v1/playlists/p/tracks", "total": 0}
+        }))
+        .unwrap()
+    }
+
+    /// Alice keeps indie in her playlists, Bob jazz; both have "shared".
+    fn blend() -> BlendAttribution {
+        let alice = [
+            track("spotify:track:indie1", "indie"),
+            track("spotify:track:shared", "pop"),
+        ];
+        let bob = [
+            track("spotify:track:jazz1", "jazz"),
+            track("spotify:track:shared", "pop"),
+        ];
+        BlendAttribution::from_members(vec![
+            MemberTaste::new("alice", &alice),
+            MemberTaste::new("bob", &bob),
+        ])
+    }
+
+    #[test]
+    fn attributes_tracks_to_the_member_whose_taste_they_match() {
+        let mut blend = blend();
+        assert_eq!(
+            blend
+                .attribute(&track("spotify:track:indie1", "indie"))
+                .as_deref(),
+            Some("alice (high confidence, heuristic)")
+        );
+        // Not in Bob's playlists, but by an artist who is.
+        assert_eq!(
+            blend
+                .attribute(&track("spotify:track:jazz2", "jazz"))
+                .as_deref(),
+            Some("bob (low confidence, heuristic)")
+        );
+        assert_eq!(
+            blend
+                .attribute(&track("spotify:track:shared", "pop"))
+                .as_deref(),
+            Some("alice / bob (ambiguous confidence, heuristic)")
+        );
+        assert_eq!(
+            blend.attribute(&track("spotify:track:metal1", "metal")),
+            None
+        );
+    }
+
+    #[test]
+    fn attributes_each_track_once() {
+        let mut blend = blend();
+        let indie = track("spotify:track:indie1", "indie");
+        let first = blend.attribute(&indie);
+        blend.members.clear();
+        assert_eq!(blend.attribute(&indie), first);
+        assert_eq!(blend.attribute(&track("spotify:track:jazz1", "jazz")), None);
+    }
+
+    #[test]
+    fn recognises_blends_by_owner_not_name() {
+        let blend = blend();
+        assert!(blend.applies_to(&playlist("Alice + Bob", SPOTIFY_USER, false)));
+        assert!(blend.applies_to(&playlist("Road Trip", "bob", false)));
+        assert!(blend.applies_to(&playlist("Party", "carol", true)));
+        assert!(!blend.applies_to(&playlist("Coffee Blend", "carol", false)));
+    }
+}
@@ -0,0 +1,473 @@
+//! `browse`: a read-only terminal view of a previous export, for looking
+//! through a backup over SSH. Reads the export's files only; nothing is
+//! fetched or written.
+//!
+//! [`Browser`] holds the selection and search and handles keys without a
+//! terminal; [`browse`] draws it and feeds it key presses.
+
+use clap::ValueEnum;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, List, ListState, Paragraph, Row, Table, TableState, Wrap},
+    Frame,
+};
+use std::{error::Error, io, path::Path};
+
+use crate::{
+    output::PlaylistRecords,
+    overlap::read_exported_playlists,
+    record::{Field, TrackRecord},
+};
+
+/// Rows moved by Page Up and Page Down.
+const PAGE: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+    Playlists,
+    Tracks,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Continue,
+    Quit,
+}
+
+pub struct Browser {
+    playlists: Vec<PlaylistRecords>,
+    focus: Focus,
+    playlist: usize,
+    /// Position within the tracks matching the search, not the playlist.
+    track: usize,
+    query: String,
+    /// Whether keys are going to the search box.
+    searching: bool,
+}
+
+impl Browser {
+    pub fn new(playlists: Vec<PlaylistRecords>) -> Self {
+        Self {
+            playlists,
+            focus: Focus::Playlists,
+            playlist: 0,
+            track: 0,
+            query: String::new(),
+            searching: false,
+        }
+    }
+
+    fn current(&self) -> Option<&PlaylistRecords> {
+        self.playlists.get(self.playlist)
+    }
+
+    /// Tracks of the selected playlist matching the search by name, artist
+    /// or album, ignoring case.
+    fn visible(&self) -> Vec<&TrackRecord> {
+        let Some(playlist) = self.current() else {
+            return Vec::new();
+        };
+        let query = self.query.to_lowercase();
+        playlist
+            .tracks
+            .iter()
+            .filter(|track| query.is_empty() || matches(track, &query))
+            .collect()
+    }
+
+    fn selected_track(&self) -> Option<&TrackRecord> {
+        self.visible().get(self.track).copied()
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> Action {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Action::Quit;
+        }
+        if self.searching {
+            match key.code {
+                KeyCode::Char(c) => self.query.push(c),
+                KeyCode::Backspace => {
+                    self.query.pop();
+                }
+                KeyCode::Enter => self.searching = false,
+                KeyCode::Esc => {
+                    self.query.clear();
+                    self.searching = false;
+                }
+                _ => return Action::Continue,
+            }
+            self.track = 0;
+            return Action::Continue;
+        }
+
+        match key.code {
+            KeyCode::Char('q') => return Action::Quit,
+            KeyCode::Esc if self.query.is_empty() => return Action::Quit,
+            KeyCode::Esc => {
+                self.query.clear();
+                self.track = 0;
+            }
+            KeyCode::Char('/') => {
+                self.searching = true;
+                self.focus = Focus::Tracks;
+            }
+            KeyCode::Tab | KeyCode::BackTab => {
+                self.focus = match self.focus {
+                    Focus::Playlists => Focus::Tracks,
+                    Focus::Tracks => Focus::Playlists,
+                }
+            }
+            KeyCode::Left | KeyCode::Char('h') => self.focus = Focus::Playlists,
+            KeyCode::Right | KeyCode::Char('l') | KeyCode::Enter => self.focus = Focus::Tracks,
+            KeyCode::Up | KeyCode::Char('k') => self.move_by(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_by(1),
+            KeyCode::PageUp => self.move_by(-(PAGE as isize)),
+            KeyCode::PageDown => self.move_by(PAGE as isize),
+            KeyCode::Home | KeyCode::Char('g') => self.move_by(isize::MIN),
+            KeyCode::End | KeyCode::Char('G') => self.move_by(isize::MAX),
+            _ => {}
+        }
+        Action::Continue
+    }
+
+    /// Moves the selection in the focused pane, stopping at either end.
+    fn move_by(&mut self, delta: isize) {
+        let (selected, len) = match self.focus {
+            Focus::Playlists => (&mut self.playlist, self.playlists.len()),
+            Focus::Tracks => {
+                let len = self.visible().len();
+                (&mut self.track, len)
+            }
+        };
+        let last = len.saturating_sub(1);
+        *selected = if delta < 0 {
+            selected.saturating_sub(delta.unsigned_abs())
+        } else {
+            selected.saturating_add(delta as usize).min(last)
+        };
+        if self.focus == Focus::Playlists {
+            self.track = 0;
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [left, right] =
+            Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)])
+                .areas(main);
+        let [tracks_area, details_area] =
+            Layout::vertical([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(right);
+        let highlight = Style::default().add_modifier(Modifier::REVERSED);
+
+        let items: Vec<String> = self
+            .playlists
+            .iter()
+            .map(|playlist| format!("{} ({})", playlist.name, playlist.tracks.len()))
+            .collect();
+        let list = List::new(items)
+            .block(self.block("Playlists", Focus::Playlists))
+            .highlight_style(highlight);
+        let mut list_state = ListState::default().with_selected(Some(self.playlist));
+        frame.render_stateful_widget(list, left, &mut list_state);
+
+        let visible = self.visible();
+        let rows = visible.iter().map(|track| {
+            Row::new([
+                Field::TrackName.value(track),
+                Field::ArtistNames.value(track),
+                Field::AlbumName.value(track),
+                track.duration_ms.map(format_duration).unwrap_or_default(),
+            ])
+        });
+        let title = match self.current() {
+            Some(playlist) if !self.query.is_empty() => format!(
+                "{} ({} of {})",
+                playlist.name,
+                visible.len(),
+                playlist.tracks.len()
+            ),
+            Some(playlist) => playlist.name.clone(),
+            None => String::new(),
+        };
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Percentage(35),
+                Constraint::Percentage(30),
+                Constraint::Percentage(25),
+                Constraint::Length(6),
+            ],
+        )
+        .header(
+            Row::new(["Track", "Artist(s)", "Album", "Length"])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .block(self.block(&title, Focus::Tracks))
+        .row_highlight_style(highlight);
+        let mut table_state = TableState::default().with_selected(
+            (self.focus == Focus::Tracks && !visible.is_empty()).then_some(self.track),
+        );
+        frame.render_stateful_widget(table, tracks_area, &mut table_state);
+
+        let details: Vec<Line> = self
+            .selected_track()
+            .map(|track| {
+                Field::value_variants()
+                    .iter()
+                    .map(|field| (field.header(), field.value(track)))
+                    .filter(|(_, value)| !value.is_empty())
+                    .map(|(header, value)| Line::from(format!("{}: {}", header, value)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let details = Paragraph::new(details)
+            .block(Block::bordered().title("Details"))
+            .wrap(Wrap { trim: false });
+        frame.render_widget(details, details_area);
+
+        let help = if self.searching {
+            format!("/{}", self.query)
+        } else if !self.query.is_empty() {
+            format!("filter: {}  (Esc clears, / edits, q quits)", self.query)
+        } else {
+            "↑/↓ move  Tab switch pane  / search  q quit".to_string()
+        };
+        frame.render_widget(Paragraph::new(help), status);
+    }
+
+    fn block(&self, title: &str, pane: Focus) -> Block<'static> {
+        let block = Block::bordered().title(title.to_string());
+        if self.focus == pane {
+            block.border_style(Style::default().add_modifier(Modifier::BOLD))
+        } else {
+            block
+        }
+    }
+}
+
+fn matches(track: &TrackRecord, query: &str) -> bool {
+    [&track.track_name, &track.album_name]
+        .into_iter()
+        .flatten()
+        .chain([&track.artist_names])
+        .any(|text| text.to_lowercase().contains(query))
+}
+
+fn format_duration(ms: u32) -> String {
+    let seconds = ms / 1000;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Opens the browser on the export in `dir` until the user quits, restoring
+/// the terminal afterwards.
+pub fn browse(dir: &Path) -> Result<(), Box<dyn Error>> {
+    let playlists = read_exported_playlists(dir)?;
+    if playlists.is_empty() {
+        return Err(format!("{}: no exported playlists found", dir.display()).into());
+    }
+    let mut browser = Browser::new(playlists);
+    ratatui::run(|terminal| -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| browser.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && browser.handle_key(key) == Action::Quit {
+                    return Ok(());
+                }
+            }
+        }
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        record::{tests::sample_record, write_track_records, DEFAULT_FIELDS},
+        testdir::TestDir,
+    };
+    use ratatui::{backend::TestBackend, Terminal};
+    use std::fs;
+
+    fn track(name: &str, artist: &str, album: &str) -> TrackRecord {
+        let mut record = sample_record(&format!("spotify:track:{}", name), name);
+        record.artist_names = artist.to_string();
+        record.album_name = Some(album.to_string());
+        record.duration_ms = Some(185_000);
+        record
+    }
+
+    /// Writes a small export as CSVs and opens the browser on it.
+    fn browser() -> Browser {
+        let dir = TestDir::new();
+        let road_trip: Vec<TrackRecord> = (1..=25)
+            .map(|n| track(&format!("Road {:02}", n), "Driver", "Highway"))
+            .collect();
+        let chill = vec![
+            track("Teardrop", "Massive Attack", "Mezzanine"),
+            track("Angel", "Massive Attack", "Mezzanine"),
+            track("Roads", "Portishead", "Dummy"),
+        ];
+        write_track_records(&dir.path().join("Chill.csv"), &chill, &DEFAULT_FIELDS, None).unwrap();
+        write_track_records(
+            &dir.path().join("Road Trip.csv"),
+            &road_trip,
+            &DEFAULT_FIELDS,
+            None,
+        )
+        .unwrap();
+        // Not a playlist.
+        fs::write(dir.path().join("notes.txt"), "hello").unwrap();
+        Browser::new(read_exported_playlists(dir.path()).unwrap())
+    }
+
+    fn press(browser: &mut Browser, keys: &[KeyCode]) -> Action {
+        keys.iter().fold(Action::Continue, |_, key| {
+            browser.handle_key(KeyEvent::new(*key, KeyModifiers::NONE))
+        })
+    }
+
+    fn type_text(browser: &mut Browser, text: &str) {
+        for c in text.chars() {
+            assert_eq!(press(browser, &[KeyCode::Char(c)]), Action::Continue);
+        }
+    }
+
+    fn selected(browser: &Browser) -> Option<String> {
+        browser
+            .selected_track()
+            .and_then(|track| track.track_name.clone())
+    }
+
+    /// What the browser draws on a 100x30 screen, one string per row.
+    fn screen(browser: &Browser) -> Vec<String> {
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        terminal.draw(|frame| browser.draw(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        buffer
+            .content
+            .chunks(buffer.area.width as usize)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn reads_the_playlists_of_an_export() {
+        let browser = browser();
+        let names: Vec<_> = browser.playlists.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["Chill", "Road Trip"]);
+        let screen = screen(&browser).join("\n");
+        assert!(screen.contains("Chill (3)"), "{}", screen);
+        assert!(screen.contains("Road Trip (25)"), "{}", screen);
+        assert!(screen.contains("Teardrop"), "{}", screen);
+    }
+
+    #[test]
+    fn arrows_move_within_the_focused_pane_and_stop_at_the_ends() {
+        let mut browser = browser();
+        press(&mut browser, &[KeyCode::Up, KeyCode::Down, KeyCode::Down]);
+        assert_eq!(browser.current().unwrap().name, "Road Trip");
+
+        press(
+            &mut browser,
+            &[KeyCode::Tab, KeyCode::Char('j'), KeyCode::Down],
+        );
+        assert_eq!(browser.focus, Focus::Tracks);
+        assert_eq!(selected(&browser).as_deref(), Some("Road 03"));
+        press(
+            &mut browser,
+            &[KeyCode::PageDown, KeyCode::PageDown, KeyCode::PageDown],
+        );
+        assert_eq!(selected(&browser).as_deref(), Some("Road 25"));
+        press(&mut browser, &[KeyCode::Char('k'), KeyCode::PageUp]);
+        assert_eq!(selected(&browser).as_deref(), Some("Road 14"));
+        press(&mut browser, &[KeyCode::Home]);
+        assert_eq!(selected(&browser).as_deref(), Some("Road 01"));
+        press(&mut browser, &[KeyCode::Char('G')]);
+        assert_eq!(selected(&browser).as_deref(), Some("Road 25"));
+
+        // Picking another playlist starts at its first track.
+        press(
+            &mut browser,
+            &[KeyCode::Left, KeyCode::Char('g'), KeyCode::Enter],
+        );
+        assert_eq!(browser.current().unwrap().name, "Chill");
+        assert_eq!(selected(&browser).as_deref(), Some("Teardrop"));
+    }
+
+    #[test]
+    fn search_narrows_the_tracks_as_it_is_typed() {
+        let mut browser = browser();
+        press(&mut browser, &[KeyCode::Char('/')]);
+        assert_eq!(browser.focus, Focus::Tracks);
+
+        type_text(&mut browser, "MASSIVE");
+        assert_eq!(browser.visible().len(), 2);
+        type_text(&mut browser, "x");
+        assert!(browser.visible().is_empty());
+        assert_eq!(selected(&browser), None);
+        press(&mut browser, &[KeyCode::Backspace]);
+        assert_eq!(browser.visible().len(), 2);
+
+        // Searching covers albums too, and `q` is just a letter here.
+        press(&mut browser, &[KeyCode::Esc, KeyCode::Char('/')]);
+        type_text(&mut browser, "dummy q");
+        assert_eq!(browser.query, "dummy q");
+        press(&mut browser, &[KeyCode::Backspace, KeyCode::Backspace]);
+        press(&mut browser, &[KeyCode::Enter]);
+        assert!(!browser.searching);
+        assert_eq!(selected(&browser).as_deref(), Some("Roads"));
+        let screen = screen(&browser).join("\n");
+        assert!(screen.contains("Chill (1 of 3)"), "{}", screen);
+        assert!(screen.contains("filter: dummy"), "{}", screen);
+    }
+
+    #[test]
+    fn moving_through_search_results_only_visits_matches() {
+        let mut browser = browser();
+        press(&mut browser, &[KeyCode::Char('/')]);
+        type_text(&mut browser, "attack");
+        press(
+            &mut browser,
+            &[KeyCode::Enter, KeyCode::Down, KeyCode::Down],
+        );
+        assert_eq!(selected(&browser).as_deref(), Some("Angel"));
+
+        // Esc first clears the filter, keeping the browser open.
+        assert_eq!(press(&mut browser, &[KeyCode::Esc]), Action::Continue);
+        assert_eq!(browser.visible().len(), 3);
+        assert_eq!(selected(&browser).as_deref(), Some("Teardrop"));
+    }
+
+    #[test]
+    fn details_show_every_column_of_the_selected_track() {
+        let mut browser = browser();
+        press(&mut browser, &[KeyCode::Tab, KeyCode::Down]);
+        let screen = screen(&browser).join("\n");
+        assert!(
+            screen.contains("Track URI: spotify:track:Angel"),
+            "{}",
+            screen
+        );
+        assert!(screen.contains("Album Name: Mezzanine"), "{}", screen);
+        assert!(screen.contains("Track Duration (ms): 185000"), "{}", screen);
+        assert!(screen.contains("3:05"), "{}", screen);
+        // Empty columns are left out.
+        assert!(!screen.contains("ISRC:"), "{}", screen);
+    }
+
+    #[test]
+    fn quits_on_q_escape_or_ctrl_c() {
+        assert_eq!(press(&mut browser(), &[KeyCode::Char('q')]), Action::Quit);
+        assert_eq!(press(&mut browser(), &[KeyCode::Esc]), Action::Quit);
+
+        let mut searching = browser();
+        press(&mut searching, &[KeyCode::Char('/')]);
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(searching.handle_key(ctrl_c), Action::Quit);
+    }
+}
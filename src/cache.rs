@@ -0,0 +1,262 @@
+//! Files kept between runs that are not part of any export, such as the
+//! playlist list `list --compare-previous` compares against and the API
+//! responses `--offline` answers from. They live in one cache directory,
+//! `~/.cache/rimusic-convert/` unless `--cache-dir` says otherwise, so they
+//! can be cleared with `clear-cache` or left out entirely with `--no-cache`.
+
+use log::{debug, info};
+use std::{
+    env,
+    error::Error,
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    atomic::{write_bytes_atomic, TEMP_DIR},
+    library_diff::PLAYLISTS_JSON,
+    manifest::sha256_bytes,
+};
+
+/// Every file written to the cache; `clear-cache` removes only these.
+pub const CACHE_FILES: [&str; 1] = [PLAYLISTS_JSON];
+
+/// Subdirectory of API responses, one directory per account and one file
+/// per URL within it.
+pub const RESPONSES_DIR: &str = "responses";
+
+/// The account directory for responses recorded with an anonymous
+/// `--public` token.
+pub const ANONYMOUS_ACCOUNT: &str = "anonymous";
+
+/// How long a recorded response is answered from. Older ones are removed
+/// the next time responses are recorded.
+pub const RESPONSE_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[derive(Debug)]
+pub enum CacheError {
+    /// `--offline` needed a response no earlier run saved, or one saved
+    /// longer ago than `RESPONSE_MAX_AGE`.
+    OfflineNotCached { url: String },
+    /// `--offline` without `--offline-account`, and responses were recorded
+    /// for several accounts, or for none.
+    OfflineAccount { recorded: Vec<String> },
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheError::OfflineNotCached { url } => write!(
+                f,
+                "{} is not in the response cache; run once with --record-responses to fetch it",
+                url
+            ),
+            CacheError::OfflineAccount { recorded } if recorded.is_empty() => write!(
+                f,
+                "no responses are recorded; run once with --record-responses first"
+            ),
+            CacheError::OfflineAccount { recorded } => write!(
+                f,
+                "responses are recorded for several accounts ({}); choose one with --offline-account",
+                recorded.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CacheError {}
+
+/// Successful GET responses by URL, saved with `--record-responses` so
+/// that an `--offline` run can answer from them instead of the API. Each
+/// account's responses are kept apart, since the same URL, such as
+/// `/me/playlists`, answers differently for each.
+#[derive(Debug)]
+pub struct ResponseCache {
+    dir: PathBuf,
+}
+
+/// When the file at `path` was last written, as an age.
+fn age(path: &Path) -> Option<Duration> {
+    let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok()?;
+    // A modification time in the future counts as just written.
+    Some(modified.elapsed().unwrap_or_default())
+}
+
+impl ResponseCache {
+    /// The responses of the account `account`, a Spotify user ID or
+    /// `ANONYMOUS_ACCOUNT`.
+    pub fn new(cache_dir: &Path, account: &str) -> Self {
+        Self {
+            dir: cache_dir.join(RESPONSES_DIR).join(account),
+        }
+    }
+
+    /// The responses `--offline` answers from: `account`'s, or without one
+    /// the only account with recorded responses.
+    pub fn for_offline(cache_dir: &Path, account: Option<&str>) -> Result<Self, CacheError> {
+        if let Some(account) = account {
+            return Ok(Self::new(cache_dir, account));
+        }
+        let mut recorded: Vec<String> = fs::read_dir(cache_dir.join(RESPONSES_DIR))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect();
+        recorded.sort_unstable();
+        match recorded.as_slice() {
+            [account] => Ok(Self::new(cache_dir, account)),
+            _ => Err(CacheError::OfflineAccount { recorded }),
+        }
+    }
+
+    fn path(&self, url: &str) -> PathBuf {
+        self.dir
+            .join(format!("{}.json", sha256_bytes(url.as_bytes())))
+    }
+
+    /// The response recorded for `url`, unless it has expired.
+    pub fn lookup(&self, url: &str) -> Result<String, CacheError> {
+        let path = self.path(url);
+        let not_cached = || CacheError::OfflineNotCached {
+            url: url.to_string(),
+        };
+        if age(&path).is_none_or(|age| age > RESPONSE_MAX_AGE) {
+            return Err(not_cached());
+        }
+        fs::read_to_string(path).map_err(|_| not_cached())
+    }
+
+    /// Removes every account's responses older than `RESPONSE_MAX_AGE`,
+    /// and account directories left empty, returning how many files went.
+    pub fn prune(cache_dir: &Path) -> usize {
+        let mut removed = 0;
+        let accounts = fs::read_dir(cache_dir.join(RESPONSES_DIR))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir());
+        for account in accounts {
+            for entry in fs::read_dir(&account).into_iter().flatten().flatten() {
+                let path = entry.path();
+                if age(&path).is_some_and(|age| age > RESPONSE_MAX_AGE)
+                    && fs::remove_file(&path).is_ok()
+                {
+                    removed += 1;
+                }
+            }
+            // Only succeed once they are empty.
+            let _ = fs::remove_dir(account.join(TEMP_DIR));
+            let _ = fs::remove_dir(&account);
+        }
+        if removed > 0 {
+            info!("Removed {} expired responses from the cache", removed);
+        }
+        removed
+    }
+
+    /// Saves `body` for `url`. A response that cannot be saved is only
+    /// missing from a later `--offline` run, so failures are not errors.
+    pub fn store(&self, url: &str, body: &str) {
+        let saved = fs::create_dir_all(&self.dir)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|_| write_bytes_atomic(&self.path(url), body.as_bytes()));
+        if let Err(e) = saved {
+            debug!("Could not cache the response from {}: {}", url, e);
+        }
+    }
+}
+
+/// `$XDG_CACHE_HOME/rimusic-convert`, or `~/.cache/rimusic-convert`.
+pub fn default_cache_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .map(|base| base.join("rimusic-convert"))
+}
+
+/// Where the cache file `name` goes, creating the cache directory if need
+/// be.
+pub fn cache_file(cache_dir: &Path, name: &str) -> Result<PathBuf, Box<dyn Error>> {
+    fs::create_dir_all(cache_dir).map_err(|e| format!("{}: {}", cache_dir.display(), e))?;
+    Ok(cache_dir.join(name))
+}
+
+/// Deletes the cache files and saved responses in `cache_dir` and returns
+/// those removed.
+pub fn clear_cache(cache_dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut removed = Vec::new();
+    for name in CACHE_FILES {
+        let path = cache_dir.join(name);
+        if path.is_file() {
+            fs::remove_file(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            removed.push(path);
+        }
+    }
+    let responses = cache_dir.join(RESPONSES_DIR);
+    if responses.is_dir() {
+        fs::remove_dir_all(&responses).map_err(|e| format!("{}: {}", responses.display(), e))?;
+        removed.push(responses);
+    }
+    // Left in place when something else is in it.
+    let _ = fs::remove_dir(cache_dir);
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+    use std::fs::File;
+
+    const URL: &str = "https://api.spotify.com/v1/me/playlists?limit=50";
+
+    #[test]
+    fn keeps_accounts_apart() {
+        let dir = TestDir::new();
+        ResponseCache::new(dir.path(), "alice").store(URL, "{\"items\":[]}");
+        assert!(ResponseCache::new(dir.path(), "bob").lookup(URL).is_err());
+        let alice = ResponseCache::new(dir.path(), "alice");
+        assert_eq!(alice.lookup(URL).unwrap(), "{\"items\":[]}");
+    }
+
+    #[test]
+    fn offline_picks_the_only_account_or_asks() {
+        let dir = TestDir::new();
+        assert!(matches!(
+            ResponseCache::for_offline(dir.path(), None),
+            Err(CacheError::OfflineAccount { .. })
+        ));
+        ResponseCache::new(dir.path(), "alice").store(URL, "{}");
+        let only = ResponseCache::for_offline(dir.path(), None).unwrap();
+        assert!(only.lookup(URL).is_ok());
+        ResponseCache::new(dir.path(), "bob").store(URL, "{}");
+        match ResponseCache::for_offline(dir.path(), None) {
+            Err(CacheError::OfflineAccount { recorded }) => {
+                assert_eq!(recorded, ["alice", "bob"])
+            }
+            other => panic!("expected OfflineAccount, got {:?}", other),
+        }
+        assert!(ResponseCache::for_offline(dir.path(), Some("bob")).is_ok());
+    }
+
+    #[test]
+    fn expired_responses_are_not_answered_and_get_pruned() {
+        let dir = TestDir::new();
+        let cache = ResponseCache::new(dir.path(), "alice");
+        cache.store(URL, "{}");
+        let expired = std::time::SystemTime::now() - RESPONSE_MAX_AGE - Duration::from_secs(60);
+        File::options()
+            .write(true)
+            .open(cache.path(URL))
+            .unwrap()
+            .set_modified(expired)
+            .unwrap();
+        assert!(cache.lookup(URL).is_err());
+        assert_eq!(ResponseCache::prune(dir.path()), 1);
+        assert!(!dir.path().join(RESPONSES_DIR).join("alice").exists());
+    }
+}
@@ -0,0 +1,290 @@
+//! CHANGES.md: what changed since the previous run in the same directory,
+//! in plain sentences, for reading rather than for tools. The previous run
+//! is read before the export overwrites it.
+
+use log::warn;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::Write as _,
+    path::Path,
+};
+
+use crate::{
+    atomic::write_bytes_atomic,
+    export::PlaylistExport,
+    index::{read_index, IndexEntry, RunIndex, INDEX_JSON},
+    overlap::read_exported_playlists,
+    paths::file_stem,
+    playlist_diff::TrackMatch,
+    record::TrackRecord,
+};
+
+pub const CHANGES_MD: &str = "CHANGES.md";
+
+/// Track lines listed per playlist unless `--changes-detail-lines` says
+/// otherwise.
+pub const DEFAULT_DETAIL_LINES: usize = 20;
+
+/// The index and tracks of the run an export is about to replace.
+pub struct PreviousRun {
+    index: RunIndex,
+    /// By the name of the file the playlist was read from.
+    tracks: HashMap<String, Vec<TrackRecord>>,
+}
+
+impl PreviousRun {
+    /// `None` when `dir` holds no earlier run. A run whose tracks cannot
+    /// be read still gives playlist-level changes.
+    pub fn load(dir: &Path) -> Option<Self> {
+        let index = read_index(&dir.join(INDEX_JSON)).ok()?;
+        let tracks = match read_exported_playlists(dir) {
+            Ok(playlists) => playlists
+                .into_iter()
+                .map(|playlist| (playlist.name, playlist.tracks))
+                .collect(),
+            Err(e) => {
+                warn!("Could not read the previous run's tracks: {}", e);
+                HashMap::new()
+            }
+        };
+        Some(Self { index, tracks })
+    }
+
+    pub fn index(&self) -> &RunIndex {
+        &self.index
+    }
+
+    /// `None` when the playlist's file could not be read.
+    pub fn tracks_of(&self, entry: &IndexEntry) -> Option<&Vec<TrackRecord>> {
+        if let Some(file) = &entry.file {
+            let stem = Path::new(file).file_stem()?.to_string_lossy();
+            return self.tracks.get(stem.as_ref());
+        }
+        // Older runs did not record the file; CSV files are named after
+        // their playlist, made safe by file_stem.
+        self.tracks
+            .get(&entry.name)
+            .or_else(|| self.tracks.get(&file_stem(&entry.name)))
+    }
+}
+
+/// Playlists match by ID, or by name for entries recorded without one.
+pub fn playlist_key(id: &str, name: &str) -> String {
+    if id.is_empty() {
+        format!("name:{}", name)
+    } else {
+        id.to_string()
+    }
+}
+
+fn describe(record: &TrackRecord) -> String {
+    let name = record.track_name.as_deref().unwrap_or("Untitled");
+    if record.artist_names.is_empty() {
+        format!("\"{}\"", name)
+    } else {
+        format!("\"{}\" by {}", name, record.artist_names)
+    }
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+/// Writes CHANGES.md, comparing the rows written for `exported` with
+/// `previous` and listing at most `detail_lines` tracks per playlist.
+/// `full` is false for runs that exported only some playlists, which then
+/// say nothing about the others.
+pub fn write_changelog(
+    path: &Path,
+    previous: &PreviousRun,
+    exported: &[PlaylistExport],
+    full: bool,
+    detail_lines: usize,
+) -> Result<(), Box<dyn Error>> {
+    let before: HashMap<String, &IndexEntry> = previous
+        .index
+        .playlists
+        .iter()
+        .map(|entry| (playlist_key(&entry.id, &entry.name), entry))
+        .collect();
+    let now: HashSet<String> = exported
+        .iter()
+        .map(|export| playlist_key(&export.playlist.id, &export.playlist.name))
+        .collect();
+
+    let mut playlist_lines = Vec::new();
+    let mut sections = String::new();
+    let (mut added_playlists, mut renamed) = (0, 0);
+    let (mut added_tracks, mut removed_tracks, mut changed) = (0, 0, 0);
+
+    for export in exported {
+        let playlist = &export.playlist;
+        let current = &export.records;
+        let Some(entry) = before.get(&playlist_key(&playlist.id, &playlist.name)) else {
+            added_playlists += 1;
+            playlist_lines.push(format!(
+                "- Added \"{}\" ({}).",
+                playlist.name,
+                plural(current.len(), "track")
+            ));
+            continue;
+        };
+        if entry.name != playlist.name {
+            renamed += 1;
+            playlist_lines.push(format!(
+                "- Renamed \"{}\" to \"{}\".",
+                entry.name, playlist.name
+            ));
+        }
+
+        let Some(old) = previous.tracks_of(entry) else {
+            if entry.track_count != export.tracks.len() {
+                changed += 1;
+                let _ = writeln!(
+                    sections,
+                    "\n## {}\n\nWent from {} to {}; the previous tracks could not be read to say which.",
+                    playlist.name,
+                    plural(entry.track_count, "track"),
+                    export.tracks.len()
+                );
+            }
+            continue;
+        };
+        let found = TrackMatch::new(old, current);
+        let added: Vec<&TrackRecord> = found.added.iter().map(|&i| &current[i]).collect();
+        let removed: Vec<&TrackRecord> = found.removed.iter().map(|&i| &old[i]).collect();
+        if added.is_empty() && removed.is_empty() {
+            continue;
+        }
+        changed += 1;
+        added_tracks += added.len();
+        removed_tracks += removed.len();
+
+        let _ = writeln!(sections, "\n## {}\n", playlist.name);
+        let lines = added
+            .iter()
+            .map(|record| format!("- Added {}.", describe(record)))
+            .chain(
+                removed
+                    .iter()
+                    .map(|record| format!("- Removed {}.", describe(record))),
+            );
+        let total = added.len() + removed.len();
+        for line in lines.take(detail_lines) {
+            let _ = writeln!(sections, "{}", line);
+        }
+        if total > detail_lines {
+            let _ = writeln!(sections, "- …and {} more.", total - detail_lines);
+        }
+    }
+
+    let mut removed_playlists = 0;
+    if full {
+        for entry in &previous.index.playlists {
+            if !now.contains(&playlist_key(&entry.id, &entry.name)) {
+                removed_playlists += 1;
+                playlist_lines.push(format!(
+                    "- Removed \"{}\" ({}).",
+                    entry.name,
+                    plural(entry.track_count, "track")
+                ));
+            }
+        }
+    }
+
+    let mut out = format!("# Changes since {}\n\n", previous.index.exported_at);
+    if playlist_lines.is_empty() && changed == 0 {
+        out.push_str("Nothing changed since the previous run.\n");
+    } else {
+        let _ = writeln!(
+            out,
+            "{} added, {} removed and {} renamed; {} added and {} removed across {}.",
+            plural(added_playlists, "playlist"),
+            removed_playlists,
+            renamed,
+            plural(added_tracks, "track"),
+            removed_tracks,
+            plural(changed, "playlist"),
+        );
+        if !playlist_lines.is_empty() {
+            let _ = writeln!(out, "\n## Playlists\n\n{}", playlist_lines.join("\n"));
+        }
+        out.push_str(&sections);
+    }
+    write_bytes_atomic(path, out.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        index::write_index,
+        provenance::Provenance,
+        record::{tests::sample_record, write_track_records, DEFAULT_FIELDS},
+        testdir::TestDir,
+    };
+    use std::fs;
+
+    fn tracks(names: &[&str]) -> Vec<TrackRecord> {
+        names
+            .iter()
+            .map(|name| sample_record(&format!("spotify:track:{}", name), name))
+            .collect()
+    }
+
+    fn export(id: &str, name: &str, file: &str, records: Vec<TrackRecord>) -> PlaylistExport {
+        let playlist = serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": name,
+            "public": true,
+            "owner": {"display_name": "owner"},
+            "tracks": {"href": "", "total": records.len()},
+        }))
+        .unwrap();
+        PlaylistExport {
+            playlist,
+            tracks: Vec::new(),
+            file: Some(file.to_string()),
+            records,
+            quarantined: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn compares_the_rows_in_the_recorded_file() {
+        let dir = TestDir::new();
+        // Two playlists named alike; the second got a suffixed file.
+        let first = [
+            export("1", "Mix", "Mix.csv", tracks(&["a"])),
+            export("2", "Mix", "Mix (2).csv", tracks(&["b", "c"])),
+        ];
+        for export in &first {
+            let path = dir.path().join(export.file.as_ref().unwrap());
+            write_track_records(&path, &export.records, &DEFAULT_FIELDS, None).unwrap();
+        }
+        let index = RunIndex::from_exports(&first, &Provenance::new(None, &()), false);
+        write_index(&dir.path().join(INDEX_JSON), &index).unwrap();
+        let previous = PreviousRun::load(dir.path()).unwrap();
+
+        let second = [
+            export("1", "Mix", "Mix.csv", tracks(&["a"])),
+            export("2", "Mix", "Mix (2).csv", tracks(&["b", "b", "d"])),
+        ];
+        let path = dir.path().join(CHANGES_MD);
+        write_changelog(&path, &previous, &second, true, DEFAULT_DETAIL_LINES).unwrap();
+        let changes = fs::read_to_string(&path).unwrap();
+        assert!(
+            changes.contains("2 tracks added and 1 removed across 1 playlist"),
+            "{}",
+            changes
+        );
+        assert!(changes.contains("- Added \"b\" by Artist."));
+        assert!(changes.contains("- Added \"d\" by Artist."));
+        assert!(changes.contains("- Removed \"c\" by Artist."));
+    }
+}
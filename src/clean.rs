@@ -0,0 +1,340 @@
+use std::error::Error;
+
+use crate::{api::SpotifyAPI, spotify::Track};
+
+/// Title fragments that mark a clean edit.
+const CLEAN_MARKERS: [&str; 5] = ["clean", "radio edit", "radio version", "edited", "censored"];
+
+/// Below this a candidate is reported but never substituted.
+pub const MIN_SUBSTITUTION_CONFIDENCE: f64 = 0.7;
+
+/// Searches for a non-explicit edit of `track`, returning the best candidate
+/// with its confidence. Callers decide whether the confidence is enough.
+pub async fn find_clean_version(
+    api: &SpotifyAPI,
+    track: &Track,
+) -> Result<Option<(Track, f64)>, Box<dyn Error>> {
+    let (Some(title), Some(artist)) = (
+        track.name.as_deref(),
+        track.artists.first().and_then(|a| a.name.as_deref()),
+    ) else {
+        return Ok(None);
+    };
+
+    let query = format!("track:{} artist:{}", base_title(title), artist);
+    let candidates = api.search_tracks(&query, 20).await?;
+
+    Ok(best_clean_candidate(track, candidates))
+}
+
+/// The candidate most likely to be a clean edit of `track`, if any could be.
+pub fn best_clean_candidate(track: &Track, candidates: Vec<Track>) -> Option<(Track, f64)> {
+    candidates
+        .into_iter()
+        .map(|candidate| {
+            let confidence = clean_match_confidence(track, &candidate);
+            (candidate, confidence)
+        })
+        .filter(|(_, confidence)| *confidence > 0.0)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+}
+
+/// Scores how likely `candidate` is a clean edit of the same recording as
+/// `original`, from 0 (not a match) to 1.
+///
+/// Matching title and primary artist are required and only reach 0.4; the
+/// rest has to come from ISRC registrant/year, duration and a clean marker
+/// in the title, so a same-named song alone never clears the threshold.
+pub fn clean_match_confidence(original: &Track, candidate: &Track) -> f64 {
+    if candidate.explicit != Some(false) || candidate.uri == original.uri {
+        return 0.0;
+    }
+
+    let titles_match = match (&original.name, &candidate.name) {
+        (Some(a), Some(b)) => base_title(a) == base_title(b),
+        _ => false,
+    };
+    let primary_artist = |t: &Track| {
+        t.artists
+            .first()
+            .and_then(|a| a.name.as_deref())
+            .map(str::to_lowercase)
+    };
+    let artists_match =
+        primary_artist(original).is_some() && primary_artist(original) == primary_artist(candidate);
+    if !titles_match || !artists_match {
+        return 0.0;
+    }
+
+    let mut confidence = 0.4;
+
+    // CC-XXX-YY: country, registrant and year of the recording.
+    let prefixes = (
+        original.isrc().and_then(|isrc| isrc.get(..7)),
+        candidate.isrc().and_then(|isrc| isrc.get(..7)),
+    );
+    if let (Some(a), Some(b)) = prefixes {
+        if a.eq_ignore_ascii_case(b) {
+            confidence += 0.3;
+        }
+    }
+
+    if let (Some(a), Some(b)) = (original.duration_ms, candidate.duration_ms) {
+        match a.abs_diff(b) {
+            0..=3_000 => confidence += 0.3,
+            3_001..=10_000 => confidence += 0.15,
+            10_001..=30_000 => {}
+            // A different length is a different version, not an edit.
+            _ => return 0.0,
+        }
+    }
+
+    if candidate.name.as_deref().is_some_and(has_clean_marker) {
+        confidence += 0.2;
+    }
+
+    f64::min(confidence, 1.0)
+}
+
+/// Lowercases a title and strips clean-edit markers, whether in brackets
+/// ("Song (Clean)") or after a dash ("Song - Radio Edit").
+///
+/// Brackets go first, so the dash part of "Song - Live (Clean)" is judged on
+/// its own and the live version keeps its "- live".
+pub fn base_title(title: &str) -> String {
+    let title = title.to_lowercase();
+
+    let mut result = String::with_capacity(title.len());
+    let mut rest = title.as_str();
+    while let Some(open) = rest.find(['(', '[']) {
+        let close_char = if rest[open..].starts_with('(') {
+            ')'
+        } else {
+            ']'
+        };
+        let Some(close) = rest[open..].find(close_char).map(|i| open + i) else {
+            break;
+        };
+        result.push_str(&rest[..open]);
+        if !has_clean_marker(&rest[open..=close]) {
+            result.push_str(&rest[open..=close]);
+        }
+        rest = &rest[close + 1..];
+    }
+    result.push_str(rest);
+
+    if let Some(index) = result.rfind(" - ") {
+        if has_clean_marker(&result[index..]) {
+            result.truncate(index);
+        }
+    }
+
+    result.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn has_clean_marker(text: &str) -> bool {
+    let text = text.to_lowercase();
+    CLEAN_MARKERS.iter().any(|marker| text.contains(marker))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(uri: &str, name: &str, explicit: bool, isrc: Option<&str>, duration_ms: u32) -> Track {
+        serde_json::from_value(serde_json::json!({
+            "uri": uri,
+            "name": name,
+            "artists": [{"name": "Artist", "uri": "spotify:artist:1"}],
+            "album": {"name": "Album", "artists": [], "images": []},
+            "duration_ms": duration_ms,
+            "popularity": 50,
+            "explicit": explicit,
+            "external_ids": {"isrc": isrc}
+        }))
+        .unwrap()
+    }
+
+    fn original() -> Track {
+        track(
+            "spotify:track:explicit",
+            "Song",
+            true,
+            Some("USABC1100001"),
+            200_000,
+        )
+    }
+
+    #[test]
+    fn strips_only_clean_markers_from_titles() {
+        assert_eq!(base_title("Song (Clean)"), "song");
+        assert_eq!(base_title("Song [Radio Edit]"), "song");
+        assert_eq!(base_title("Song - Radio Version"), "song");
+        assert_eq!(
+            base_title("Song (feat. Guest) (Clean)"),
+            "song (feat. guest)"
+        );
+        assert_eq!(base_title("Song - Live"), "song - live");
+        assert_eq!(base_title("Song - Live (Clean)"), "song - live");
+        assert_eq!(
+            base_title("Song - 2011 Remaster (Clean)"),
+            "song - 2011 remaster"
+        );
+    }
+
+    #[test]
+    fn a_clean_edit_of_the_same_recording_is_confident() {
+        let clean = track(
+            "spotify:track:clean",
+            "Song (Clean)",
+            false,
+            Some("USABC1100002"),
+            200_500,
+        );
+        assert_eq!(clean_match_confidence(&original(), &clean), 1.0);
+
+        // Same registrant and year but no duration to compare.
+        let mut undated = clean.clone();
+        undated.duration_ms = None;
+        assert!(clean_match_confidence(&original(), &undated) >= MIN_SUBSTITUTION_CONFIDENCE);
+    }
+
+    #[test]
+    fn live_and_remastered_versions_are_not_clean_edits() {
+        for name in [
+            "Song - Live",
+            "Song - Live (Clean)",
+            "Song (Live at Wembley)",
+            "Song - 2011 Remaster",
+            "Song - Remastered (Clean)",
+        ] {
+            let candidate = track(
+                "spotify:track:other",
+                name,
+                false,
+                Some("USABC1100002"),
+                200_000,
+            );
+            assert_eq!(
+                clean_match_confidence(&original(), &candidate),
+                0.0,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn a_remaster_matches_its_own_clean_edit() {
+        let remaster = track(
+            "spotify:track:r",
+            "Song - 2011 Remaster",
+            true,
+            Some("GBXYZ1100001"),
+            200_000,
+        );
+        let clean = track(
+            "spotify:track:rc",
+            "Song - 2011 Remaster (Clean)",
+            false,
+            Some("GBXYZ1100002"),
+            201_000,
+        );
+        assert_eq!(clean_match_confidence(&remaster, &clean), 1.0);
+    }
+
+    #[test]
+    fn a_same_named_song_alone_stays_below_the_threshold() {
+        // Marked clean, but from another registrant and of unknown length.
+        let mut lookalike = track(
+            "spotify:track:other",
+            "Song (Clean)",
+            false,
+            Some("GBXYZ0900001"),
+            0,
+        );
+        lookalike.duration_ms = None;
+        let confidence = clean_match_confidence(&original(), &lookalike);
+        assert!(
+            confidence > 0.0 && confidence < MIN_SUBSTITUTION_CONFIDENCE,
+            "{}",
+            confidence
+        );
+
+        let lookalike = track("spotify:track:other", "Song", false, None, 205_000);
+        let confidence = clean_match_confidence(&original(), &lookalike);
+        assert!(confidence < MIN_SUBSTITUTION_CONFIDENCE, "{}", confidence);
+    }
+
+    #[test]
+    fn never_matches_explicit_identical_or_much_longer_tracks() {
+        let explicit = track(
+            "spotify:track:other",
+            "Song (Clean)",
+            true,
+            Some("USABC1100002"),
+            200_000,
+        );
+        assert_eq!(clean_match_confidence(&original(), &explicit), 0.0);
+
+        let mut unknown = explicit.clone();
+        unknown.explicit = None;
+        assert_eq!(clean_match_confidence(&original(), &unknown), 0.0);
+
+        let mut same = original();
+        same.explicit = Some(false);
+        assert_eq!(clean_match_confidence(&original(), &same), 0.0);
+
+        let extended = track(
+            "spotify:track:other",
+            "Song (Clean)",
+            false,
+            Some("USABC1100002"),
+            240_001,
+        );
+        assert_eq!(clean_match_confidence(&original(), &extended), 0.0);
+
+        let mut other_artist = track(
+            "spotify:track:other",
+            "Song (Clean)",
+            false,
+            Some("USABC1100002"),
+            200_000,
+        );
+        other_artist.artists[0].name = Some("Someone Else".into());
+        assert_eq!(clean_match_confidence(&original(), &other_artist), 0.0);
+    }
+
+    #[test]
+    fn picks_the_most_confident_candidate() {
+        let candidates = vec![
+            track(
+                "spotify:track:live",
+                "Song - Live (Clean)",
+                false,
+                None,
+                200_000,
+            ),
+            track("spotify:track:weak", "Song (Clean)", false, None, 208_000),
+            track(
+                "spotify:track:strong",
+                "Song (Radio Edit)",
+                false,
+                Some("USABC1100002"),
+                199_000,
+            ),
+        ];
+        let (best, confidence) = best_clean_candidate(&original(), candidates).unwrap();
+        assert_eq!(best.uri.as_deref(), Some("spotify:track:strong"));
+        assert_eq!(confidence, 1.0);
+
+        let live = vec![track(
+            "spotify:track:live",
+            "Song - Live",
+            false,
+            None,
+            200_000,
+        )];
+        assert!(best_clean_candidate(&original(), live).is_none());
+    }
+}
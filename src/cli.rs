@@ -0,0 +1,1162 @@
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use clap::{error::ErrorKind, ArgGroup, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use std::{error::Error, path::PathBuf, time::Duration};
+
+use crate::{
+    album_groups::GroupBy,
+    album_runs::AlbumRunRules,
+    cache::default_cache_dir,
+    changelog::DEFAULT_DETAIL_LINES,
+    compilations::CompilationPolicy,
+    dashboard::{DEFAULT_RUNS, STATUS_HTML},
+    dedupe::{DedupeKey, PreferRelease},
+    filter::{SortKey, TrackFilter, VisibilityFilter},
+    http::HttpOptions,
+    local_edits::LocalEditPolicy,
+    manifest::MANIFEST_JSON,
+    middleware::OutagePolicy,
+    output::OutputFormat,
+    quarantine::QUARANTINE_JSON,
+    record::{ImageSelectionStrategy, RecordOptions},
+    retry::RetryClass,
+    schema::SchemaKind,
+    schema_check::ApiEndpoint,
+    setup::FORMAT_ENV,
+    share_pack::ShareFormat,
+};
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    long_version = env!("RIMUSIC_LONG_VERSION"),
+    about = "Convert Spotify playlists into CSV files RiMusic can import"
+)]
+pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub export: ExportArgs,
+}
+
+impl Cli {
+    /// The subcommand to run, falling back to `export` when none was given.
+    pub fn command(self) -> (GlobalArgs, Command) {
+        let command = match self.command {
+            None => Command::Export(Box::new(self.export)),
+            Some(Command::ExportCategory(args)) if args.category.is_none() => {
+                <Self as CommandFactory>::command()
+                    .error(
+                        ErrorKind::MissingRequiredArgument,
+                        "export-category needs --category <CATEGORY>",
+                    )
+                    .exit()
+            }
+            Some(command) => command,
+        };
+        (self.global, command)
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct GlobalArgs {
+    /// Spotify access token
+    #[arg(long, env = "SPOTIFY_TOKEN", hide_env_values = true, global = true)]
+    pub token: Option<String>,
+
+    /// Print tables as tab-separated lines even on a terminal
+    #[arg(long, global = true)]
+    pub plain: bool,
+
+    /// Only print warnings, errors and the final summary
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Print debug output
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+
+    /// Without a token, use the anonymous token of the Spotify web player.
+    /// It only reads public playlists (export them with --playlist), is
+    /// rate limited more strictly, and may stop working at any time
+    #[arg(long, global = true)]
+    pub public: bool,
+
+    /// Failures to retry, comma-separated
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "429,5xx",
+        conflicts_with = "no_retry"
+    )]
+    pub retry_on: Vec<RetryClass>,
+
+    /// Fail each request on its first error, without retrying or waiting
+    /// out outages
+    #[arg(long, global = true)]
+    pub no_retry: bool,
+
+    /// Fail on API responses with fields this tool does not know about, to
+    /// notice when Spotify changes its responses
+    #[arg(long, global = true)]
+    pub strict_api: bool,
+
+    /// How long to wait out a Spotify outage before giving up (e.g. 30m, 2h)
+    #[arg(long, global = true, default_value = "1h", value_parser = parse_duration)]
+    pub max_outage_wait: Duration,
+
+    /// How often to check whether Spotify has recovered during an outage
+    #[arg(long, global = true, default_value = "5m", value_parser = parse_duration)]
+    pub outage_probe_interval: Duration,
+
+    /// Keep files reused between runs here instead of
+    /// ~/.cache/rimusic-convert
+    #[arg(long, global = true, value_name = "PATH")]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Neither read nor write cached files, e.g. in CI
+    #[arg(long, global = true, conflicts_with = "cache_dir")]
+    pub no_cache: bool,
+
+    /// Save successful API responses in the cache directory, by account,
+    /// for later `--offline` runs; they expire after a week
+    #[arg(long, global = true, conflicts_with_all = ["no_cache", "offline"])]
+    pub record_responses: bool,
+
+    /// Send no requests; answer from the responses `--record-responses`
+    /// saved in the cache directory, failing on any not saved
+    #[arg(long, global = true, conflicts_with = "no_cache")]
+    pub offline: bool,
+
+    /// With `--offline`, answer from this account's recorded responses;
+    /// needed once several accounts have recorded some
+    #[arg(long, global = true, value_name = "USER_ID", requires = "offline")]
+    pub offline_account: Option<String>,
+
+    /// On Windows, write through extended-length paths so output deeper
+    /// than 260 characters works; other platforms ignore it
+    #[arg(long, global = true)]
+    pub long_paths: bool,
+
+    #[command(flatten)]
+    pub http: HttpOptions,
+}
+
+impl GlobalArgs {
+    /// The cache directory, or `None` with `--no-cache` or when there is
+    /// no home directory to put it in.
+    pub fn cache_location(&self) -> Option<PathBuf> {
+        if self.no_cache {
+            return None;
+        }
+        self.cache_dir.clone().or_else(default_cache_dir)
+    }
+
+    pub fn retry_classes(&self) -> Vec<RetryClass> {
+        if self.no_retry {
+            Vec::new()
+        } else {
+            self.retry_on.clone()
+        }
+    }
+
+    pub fn outage_policy(&self) -> OutagePolicy {
+        OutagePolicy {
+            probe_interval: self.outage_probe_interval,
+            max_wait: self.max_outage_wait,
+            ..OutagePolicy::default()
+        }
+    }
+}
+
+/// Parses durations such as `90s`, `15m`, `24h` or `7d`. A bare number is
+/// taken as seconds.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", value))?;
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "unknown duration unit '{}' (use s, m, h or d)",
+                unit
+            ))
+        }
+    };
+    let secs = number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("duration '{}' is too long", value))?;
+    Ok(Duration::from_secs(secs))
+}
+
+/// Parses an RFC 3339 timestamp, or a `YYYY-MM-DD` date meaning the start
+/// of that day in UTC.
+pub fn parse_added_after(value: &str) -> Result<DateTime<Utc>, String> {
+    let value = value.trim();
+    if let Ok(at) = DateTime::parse_from_rfc3339(value) {
+        return Ok(at.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.and_time(NaiveTime::MIN).and_utc())
+        .map_err(|_| {
+            format!(
+                "invalid date '{}' (use YYYY-MM-DD or an RFC 3339 timestamp such as 2024-05-01T12:00:00Z)",
+                value
+            )
+        })
+}
+
+/// Parses sizes such as `500MB` or `2GB`, in powers of 1000 like `df -H`.
+/// A bare number is taken as bytes.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{}'", value))?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "KB" => 1_000,
+        "MB" => 1_000_000,
+        "GB" => 1_000_000_000,
+        "TB" => 1_000_000_000_000,
+        _ => {
+            return Err(format!(
+                "unknown size unit '{}' (use B, KB, MB, GB or TB)",
+                unit
+            ))
+        }
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size '{}' is too large", value))
+}
+
+pub fn require_token(token: Option<&str>) -> Result<String, Box<dyn Error>> {
+    token
+        .map(str::to_string)
+        .ok_or_else(|| {
+            "no access token given; pass --token or set SPOTIFY_TOKEN, or use --public for public playlists"
+                .into()
+        })
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Export every playlist in the library to CSV (the default)
+    Export(Box<ExportArgs>),
+    /// Export Spotify's editorial playlists for a browse category; takes
+    /// the same options as export, with --category required
+    ExportCategory(Box<ExportArgs>),
+    /// List browse categories and their IDs, for export-category
+    Categories(CategoriesArgs),
+    /// Print statistics for previously exported CSV files
+    Stats(StatsArgs),
+    /// Compare two exported CSVs of a playlist: tracks added and removed
+    Diff(DiffArgs),
+    /// Run quick live checks against the API and the local setup
+    Doctor,
+    /// Delete the files kept in the cache directory
+    ClearCache,
+    /// Regenerate output from an existing JSON export without calling the API
+    Render(RenderArgs),
+    /// Print the details of a single podcast episode
+    EpisodeInfo(EpisodeInfoArgs),
+    /// Print a single track's tempo, key, loudness and sections
+    TrackAnalysis(TrackAnalysisArgs),
+    /// Print an artist's most played tracks in a market
+    TopTracks(TopTracksArgs),
+    /// List the artists you follow with their genres and popularity
+    FollowedArtists,
+    /// Follow, or unfollow, the top artists of an artist_frequency.csv
+    FollowArtists(FollowArtistsArgs),
+    /// List playlists with their track counts, without fetching any tracks
+    List(ListArgs),
+    /// Build per-playlist follower count history from the index.json of past
+    /// runs exported with --include-followers
+    FollowersHistory(FollowersHistoryArgs),
+    /// Retry the pages an export could not fetch and splice them into its CSVs
+    RetryQuarantine(RetryQuarantineArgs),
+    /// Print which version, account and options produced an exported file
+    Inspect(InspectArgs),
+    /// List new episodes of saved shows in a dated "New Episodes" CSV
+    EpisodesNew(EpisodesNewArgs),
+    /// Report how much of Liked Songs is already in saved albums or playlists
+    Overlap(OverlapArgs),
+    /// Write an HTML status page from the index.json of past runs
+    Dashboard(DashboardArgs),
+    /// Create a new Spotify playlist from the tracks of an exported CSV
+    Import(ImportArgs),
+    /// Create a Spotify playlist from the tracks of one or more CSVs
+    Create(CreateArgs),
+    /// Remove the copies listed in a duplicates.csv from a playlist on Spotify
+    RemoveTracks(RemoveTracksArgs),
+    /// Walk through first-time setup: token, output directory and format
+    Setup,
+    /// Replace a playlist's cover on Spotify with a JPEG file
+    SetCover(SetCoverArgs),
+    /// Reorder a playlist's tracks on Spotify with as few requests as possible
+    SortPlaylist(SortPlaylistArgs),
+    /// Change a playlist's name, description or visibility on Spotify
+    UpdatePlaylist(UpdatePlaylistArgs),
+    /// Print the JSON Schema of a JSON file this tool writes
+    Schema(SchemaArgs),
+    /// Check a live API response for fields the tool reads that are gone,
+    /// and for new ones it does not read
+    ValidateSchema(ValidateSchemaArgs),
+    /// Write one Markdown or HTML page linking every playlist of an export
+    SharePack(SharePackArgs),
+    /// Check an export's files against the checksums in its manifest.json
+    VerifyChecksums(VerifyChecksumsArgs),
+    /// Manage covers saved with --download-artwork
+    Art(ArtArgs),
+    /// Look through an export in the terminal, without changing it
+    Browse(BrowseArgs),
+    /// Rewrite playlist CSVs from any earlier version in the current column layout
+    Reimport(ReimportArgs),
+}
+
+#[derive(Debug, Args, Serialize)]
+pub struct ExportArgs {
+    #[command(flatten)]
+    pub output: OutputArgs,
+
+    /// Write artist_frequency.csv counting each artist's tracks and
+    /// playlists across the library, with their average popularity
+    #[arg(long, alias = "artist-report")]
+    pub artist_frequency_report: bool,
+
+    /// How the artist report counts tracks from compilations
+    #[arg(long, value_enum, default_value_t)]
+    pub compilations: CompilationPolicy,
+
+    /// Write one CSV per playlist, or one per album listing the playlists
+    /// each track was found in
+    #[arg(long, value_enum, default_value_t)]
+    pub group_by: GroupBy,
+
+    /// Album image to link: first, last, largest, smallest, or the first at
+    /// least <N>px in both dimensions (e.g. 300px)
+    #[arg(long, default_value = "first")]
+    pub image_size: ImageSelectionStrategy,
+
+    /// Add Album Image Width and Album Image Height columns
+    #[arg(long)]
+    pub include_image_dimensions: bool,
+
+    /// Save each album cover under artwork/, once per distinct image, and
+    /// add an Album Image File column naming it
+    #[arg(long)]
+    pub download_artwork: bool,
+
+    /// Add an Added By Profile column with the display name of whoever
+    /// added each track; costs one request per distinct user
+    #[arg(long)]
+    pub enrich_added_by: bool,
+
+    /// CSV of Key,Video ID rows pinning tracks, by spotify:track: URI or
+    /// ISRC, to a YouTube video, written in a YouTube Video ID column
+    #[arg(long, value_name = "PATH")]
+    pub video_overrides: Option<PathBuf>,
+
+    /// Look for local file tracks in this music library, as
+    /// <artist>/<album>/<track> or "<artist> - <track>" FLAC or MP3 files,
+    /// and write where each was found in a Local File Path column
+    #[arg(long, value_name = "PATH")]
+    pub music_dir: Option<PathBuf>,
+
+    /// Add a Liked column saying whether each track is in Liked Songs;
+    /// costs one request per 50 distinct tracks
+    #[arg(long)]
+    pub include_liked_status: bool,
+
+    /// Add a Saved Album column saying whether each track's album is among
+    /// your saved albums; costs one request per 20 distinct albums
+    #[arg(long)]
+    pub include_saved_status: bool,
+
+    /// Both --include-liked-status and --include-saved-status, checked
+    /// together for each playlist
+    #[arg(long)]
+    pub enrich_saved_status: bool,
+
+    /// Add Market Count and Rare (fewer than 10 markets) columns, and list
+    /// the rarest tracks after the export
+    #[arg(long)]
+    pub market_count: bool,
+
+    /// Normalize artist names (case, a leading "The", "(feat. ...)" suffixes
+    /// and Unicode lookalikes) so spellings of the same artist match
+    #[arg(long)]
+    pub normalize_artists: bool,
+
+    /// Keep share-tracking parameters such as ?si= in exported URLs
+    #[arg(long)]
+    pub keep_url_params: bool,
+
+    /// Add a Playlist Followers column and record follower counts in
+    /// index.json; costs one extra request per playlist
+    #[arg(long)]
+    pub include_followers: bool,
+
+    /// Spotify user IDs of the people sharing Blend or collaborative
+    /// playlists; adds a heuristic "Likely Added For" column guessed from
+    /// their public playlists
+    #[arg(long, value_delimiter = ',', value_name = "USER_IDS")]
+    pub blend_members: Vec<String>,
+
+    /// Replace explicit tracks with a confidently matched clean version,
+    /// recording the original URI in a Substituted column
+    #[arg(long)]
+    pub prefer_clean_version: bool,
+
+    /// Refuse to start when the export would leave less than this much
+    /// free space (e.g. 500MB, 2GB); without it a tight fit only warns
+    #[arg(long, value_parser = parse_size, value_name = "SIZE")]
+    pub min_free_space: Option<u64>,
+
+    /// Only export tracks added after this date or RFC 3339 timestamp, to
+    /// sync what is new, into a separate "<name> (added after <date>)" CSV
+    /// per playlist; assumes new tracks were added at the end
+    #[arg(long, value_name = "DATE", value_parser = parse_added_after)]
+    pub added_after: Option<DateTime<Utc>>,
+
+    /// Leave playlists alone whose CSV was written less than this long ago
+    /// (e.g. 24h, 7d), fetching none of their tracks; with --snapshot-check,
+    /// only when both agree
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, conflicts_with = "added_after")]
+    pub max_age: Option<Duration>,
+
+    /// Leave playlists alone whose snapshot ID is the one index.json
+    /// recorded for their CSV, fetching none of their tracks; with
+    /// --max-age, only when both agree
+    #[arg(long, conflicts_with = "added_after")]
+    pub snapshot_check: bool,
+
+    /// Also record the run in an SQLite library keeping every run's
+    /// playlists and track history (sqlite://<path>); read it back with
+    /// `render --store`
+    #[arg(long, value_name = "URL", conflicts_with = "added_after")]
+    pub store: Option<String>,
+
+    /// Also refuse Spotify IDs without a capital letter, which are almost
+    /// always lowercased copies
+    #[arg(long)]
+    pub strict_ids: bool,
+
+    /// What to do with a CSV edited by hand since the last export, as told
+    /// by its manifest.json
+    #[arg(long, value_enum, default_value_t)]
+    pub on_local_edit: LocalEditPolicy,
+
+    /// Export only this playlist (ID, URI or link) instead of the library;
+    /// repeat for several. Works with --public for public playlists
+    #[arg(
+        long = "playlist",
+        value_name = "PLAYLIST",
+        conflicts_with = "ownership"
+    )]
+    pub playlists: Vec<String>,
+
+    /// Export Spotify's editorial playlists for this browse category
+    /// instead of the library; see `categories` for the IDs
+    #[arg(
+        long,
+        value_name = "CATEGORY",
+        conflicts_with_all = ["playlists", "ownership"]
+    )]
+    pub category: Option<String>,
+
+    /// Country whose editorial playlists --category exports, e.g. US
+    #[arg(long, requires = "category")]
+    pub country: Option<String>,
+
+    /// At most this many of the category's playlists
+    #[arg(long, default_value_t = 50, requires = "category")]
+    pub category_limit: u32,
+
+    /// Stop after the first playlist that is missing data (failed pages,
+    /// unavailable items, fewer items than Spotify reports) and exit with
+    /// code 3, instead of finishing with warnings
+    #[arg(long)]
+    pub strict: bool,
+
+    /// List the playlists and print how many API requests the export would
+    /// make, without exporting anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Leave volatile fields, such as when the export ran, out of the
+    /// provenance, so exporting the same library again records the same
+    /// provenance. CSV rows are not covered: popularity and the like are
+    /// whatever Spotify returns, and Added At in album group files is still
+    /// a wall-clock stamp of the run
+    #[arg(long)]
+    pub deterministic: bool,
+
+    /// After the run, commit the files it wrote to this git repository
+    /// (created if needed), which should be or contain the output directory
+    #[arg(long, value_name = "REPO")]
+    pub git_backup: Option<PathBuf>,
+
+    /// Also write what changed since the previous run in this directory as
+    /// added, removed, moved and changed records (NDJSON, one subdirectory
+    /// per run) for another system to apply
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["added_after", "category"]
+    )]
+    pub emit_delta: Option<PathBuf>,
+
+    /// Most tracks CHANGES.md lists per playlist before summing up the rest
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_DETAIL_LINES)]
+    pub changes_detail_lines: usize,
+
+    /// After the run, regenerate status.html in this directory of past runs
+    /// (one subdirectory each), for scheduled exports
+    #[arg(long, value_name = "RUNS_ROOT")]
+    pub auto_dashboard: Option<PathBuf>,
+
+    /// Only export playlists you own
+    #[arg(long, group = "ownership")]
+    pub only_owned: bool,
+
+    /// Only export playlists you follow but do not own
+    #[arg(long, group = "ownership")]
+    pub only_followed: bool,
+
+    /// Only export collaborative playlists
+    #[arg(long, group = "visibility")]
+    pub collaborative_only: bool,
+
+    /// Only export playlists known to be private
+    #[arg(long, group = "visibility")]
+    pub private_only: bool,
+
+    /// Only export playlists known to be public
+    #[arg(long, group = "visibility")]
+    pub public_only: bool,
+}
+
+impl ExportArgs {
+    /// Whether to fill the Liked column.
+    pub fn liked_status(&self) -> bool {
+        self.include_liked_status || self.enrich_saved_status
+    }
+
+    /// Whether to fill the Saved Album column.
+    pub fn saved_album_status(&self) -> bool {
+        self.include_saved_status || self.enrich_saved_status
+    }
+
+    /// Exporting the whole library, rather than chosen playlists or a
+    /// category's.
+    pub fn whole_library(&self) -> bool {
+        self.playlists.is_empty() && self.category.is_none()
+    }
+
+    /// Whether the run goes into the library's history: index.json,
+    /// CHANGES.md and the delta files. Runs writing only new tracks, or a
+    /// category's editorial playlists, are kept out of it.
+    pub fn continues_history(&self) -> bool {
+        self.added_after.is_none() && self.category.is_none()
+    }
+
+    pub fn record_options(&self) -> RecordOptions {
+        RecordOptions {
+            normalize_artists: self.normalize_artists,
+            image_size: self.image_size,
+            keep_url_params: self.keep_url_params,
+            market_count: self.market_count,
+        }
+    }
+
+    pub fn visibility_filter(&self) -> VisibilityFilter {
+        if self.collaborative_only {
+            VisibilityFilter::CollaborativeOnly
+        } else if self.private_only {
+            VisibilityFilter::PrivateOnly
+        } else if self.public_only {
+            VisibilityFilter::PublicOnly
+        } else {
+            VisibilityFilter::All
+        }
+    }
+}
+
+/// Options shared by everything that writes playlist files.
+#[derive(Debug, Args, Serialize)]
+pub struct OutputArgs {
+    /// Output format
+    #[arg(long, value_enum, default_value_t, env = FORMAT_ENV)]
+    pub format: OutputFormat,
+
+    /// Tera template rendered once per playlist with --format template
+    #[arg(long, value_name = "PATH", required_if_eq("format", "template"))]
+    pub template_file: Option<PathBuf>,
+
+    /// Drop tracks with a popularity below this value
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub min_popularity: Option<u8>,
+
+    /// Drop explicit tracks
+    #[arg(long)]
+    pub clean_only: bool,
+
+    /// Start each CSV with a "# provenance:" comment line recording the tool
+    /// version and options that produced it
+    #[arg(long)]
+    pub csv_preamble: bool,
+
+    /// Write explicit tracks to a separate "<playlist> (explicit)" playlist
+    #[arg(long)]
+    pub split_explicit: bool,
+
+    /// Also drop tracks whose value for a filtered field is unknown
+    #[arg(long)]
+    pub strict_filters: bool,
+
+    /// Sort each playlist's tracks; tracks with an unknown value go last
+    #[arg(long, value_enum)]
+    pub sort_by: Option<SortKey>,
+
+    /// Sort in descending order
+    #[arg(long, requires = "sort_by")]
+    pub descending: bool,
+
+    /// Keep one copy of each recording per playlist, matched by this key;
+    /// dropped copies are listed in duplicates.csv
+    #[arg(long, value_enum)]
+    pub dedupe_key: Option<DedupeKey>,
+
+    /// Which copy of a duplicated recording --dedupe-key keeps
+    #[arg(long, value_enum, default_value_t, requires = "dedupe_key")]
+    pub prefer_release: PreferRelease,
+
+    /// Add a Position column numbering each written playlist's tracks
+    /// 1..N after filtering, for importers that would otherwise reorder them
+    #[arg(long)]
+    pub include_position: bool,
+
+    /// Re-read each written CSV and fail unless its positions run 1..N in
+    /// the intended order
+    #[arg(long, requires = "include_position")]
+    pub verify_order: bool,
+
+    /// Add an Album Run column marking consecutive tracks of one album in
+    /// album order with the album's URI, so they can be imported as an album
+    #[arg(long)]
+    pub album_runs: bool,
+
+    /// Fewest consecutive tracks that make an album run
+    #[arg(long, default_value_t = 3, requires = "album_runs", value_parser = clap::value_parser!(u64).range(2..))]
+    pub album_run_min_length: u64,
+
+    /// Largest step in track number within an album run; 1 allows no
+    /// skipped tracks
+    #[arg(long, default_value_t = 1, requires = "album_runs", value_parser = clap::value_parser!(u32).range(1..))]
+    pub album_run_max_gap: u32,
+}
+
+impl OutputArgs {
+    pub fn track_filter(&self) -> TrackFilter {
+        TrackFilter {
+            min_popularity: self.min_popularity,
+            clean_only: self.clean_only,
+            strict: self.strict_filters,
+        }
+    }
+
+    pub fn album_run_rules(&self) -> Option<AlbumRunRules> {
+        self.album_runs.then_some(AlbumRunRules {
+            min_length: self.album_run_min_length as usize,
+            max_gap: self.album_run_max_gap,
+        })
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct StatsArgs {
+    /// Exported CSV files to summarize
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+
+    /// Also chart each file's tracks by release year
+    #[arg(long)]
+    pub release_years: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct DiffArgs {
+    /// The earlier CSV
+    pub old: PathBuf,
+
+    /// The later CSV
+    pub new: PathBuf,
+
+    #[arg(long, value_enum, default_value_t = DiffFormat::Table)]
+    pub format: DiffFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DiffFormat {
+    /// A table of changed tracks and a summary line
+    Table,
+    /// One JSON object with the changed tracks' full records
+    Json,
+    /// `-` and `+` lines, as in git diff
+    Unified,
+}
+
+#[derive(Debug, Args)]
+pub struct CategoriesArgs {
+    /// Name the categories as in this country, e.g. US
+    #[arg(long)]
+    pub country: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct ListArgs {
+    /// List only this playlist (ID, URI or link); repeat for several.
+    /// Without any, the whole library is listed
+    #[arg(long = "playlist", value_name = "PLAYLIST")]
+    pub playlists: Vec<String>,
+
+    /// Ask each playlist for its count, one request apiece, rather than
+    /// trusting the listing's, which can lag behind recent changes
+    #[arg(long)]
+    pub with_counts: bool,
+
+    /// Print the playlists added, removed or changed since the previous
+    /// listing with this flag, kept in the cache directory
+    #[arg(long, conflicts_with = "playlists")]
+    pub compare_previous: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct EpisodeInfoArgs {
+    /// Episode ID or spotify:episode: URI
+    pub episode: String,
+}
+
+#[derive(Debug, Args)]
+pub struct TrackAnalysisArgs {
+    /// Track ID or spotify:track: URI
+    #[arg(long)]
+    pub track: String,
+}
+
+#[derive(Debug, Args)]
+pub struct TopTracksArgs {
+    /// Artist ID, spotify:artist: URI or link
+    #[arg(long)]
+    pub artist: String,
+
+    /// Two-letter country code whose listening counts; Spotify needs one
+    /// for this list
+    #[arg(long)]
+    pub market: String,
+
+    #[arg(long, value_enum, default_value_t = TopTracksFormat::Table)]
+    pub format: TopTracksFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TopTracksFormat {
+    /// A table on the terminal
+    Table,
+    /// CSV with the export's default columns, on stdout
+    Csv,
+}
+
+#[derive(Debug, Args)]
+pub struct FollowersHistoryArgs {
+    /// Directory holding one subdirectory per export run
+    pub backups_root: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct RetryQuarantineArgs {
+    /// The quarantine file written by the export
+    #[arg(default_value = QUARANTINE_JSON)]
+    pub quarantine: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct EpisodesNewArgs {
+    /// List episodes released since the previous --since-last-run, tracked
+    /// in state.json; the first run falls back to --days
+    #[arg(long)]
+    pub since_last_run: bool,
+
+    /// List episodes released in this many past days
+    #[arg(long, default_value_t = 7)]
+    pub days: u32,
+
+    /// Also write an M3U of the new episodes whose description links their
+    /// YouTube upload, for shows that publish there too
+    #[arg(long)]
+    pub m3u: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct OverlapArgs {
+    /// Where playlists are read from: "live" for the API, or the directory
+    /// of a previous export. Liked Songs and saved albums are always read
+    /// live.
+    #[arg(default_value = "live", value_name = "DIR_OR_LIVE")]
+    pub source: String,
+
+    /// Also write an "overlap <bucket>.csv" of the tracks in each bucket
+    #[arg(long)]
+    pub write_buckets: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct DashboardArgs {
+    /// Directory holding one subdirectory per export run
+    pub runs_root: PathBuf,
+
+    /// Where to write the page
+    #[arg(short, long, default_value = STATUS_HTML)]
+    pub output: PathBuf,
+
+    /// How many of the most recent runs to show
+    #[arg(long, default_value_t = DEFAULT_RUNS)]
+    pub runs: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct ImportArgs {
+    /// A playlist CSV written by this tool, or any CSV with a Track URI column
+    pub file: PathBuf,
+
+    /// Name of the playlist to create
+    #[arg(long, required_unless_present = "replace_playlist")]
+    pub playlist_name: Option<String>,
+
+    /// Make the new playlist public; it is private otherwise
+    #[arg(long)]
+    pub public_playlist: bool,
+
+    /// Description of the new playlist
+    #[arg(long, default_value = "")]
+    pub description: String,
+
+    /// Overwrite the tracks of this playlist (ID, URI or link) instead of
+    /// creating one
+    #[arg(
+        long,
+        value_name = "PLAYLIST",
+        conflicts_with_all = ["playlist_name", "public_playlist", "description"]
+    )]
+    pub replace_playlist: Option<String>,
+
+    /// Also refuse Spotify IDs without a capital letter, which are almost
+    /// always lowercased copies
+    #[arg(long)]
+    pub strict_ids: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct CreateArgs {
+    /// Name of the playlist to create
+    #[arg(long)]
+    pub name: String,
+
+    /// CSV to take tracks from, in order; repeat to combine several
+    #[arg(long = "from-csv", value_name = "FILE", required = true)]
+    pub from_csv: Vec<PathBuf>,
+
+    /// Make the new playlist public; it is private otherwise
+    #[arg(long)]
+    pub public_playlist: bool,
+
+    /// Description of the new playlist
+    #[arg(long, default_value = "")]
+    pub description: String,
+
+    /// Also refuse Spotify IDs without a capital letter, which are almost
+    /// always lowercased copies
+    #[arg(long)]
+    pub strict_ids: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct FollowArtistsArgs {
+    /// artist_frequency.csv written by an export with
+    /// --artist-frequency-report
+    #[arg(long = "from-csv", value_name = "FILE")]
+    pub from_csv: PathBuf,
+
+    /// How many artists to take from the top of the report
+    #[arg(long, default_value_t = 20)]
+    pub top: usize,
+
+    /// Unfollow these artists instead
+    #[arg(long)]
+    pub unfollow: bool,
+
+    /// Print the artists without following or unfollowing them
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct RemoveTracksArgs {
+    /// Playlist to remove tracks from (ID, URI or link)
+    #[arg(long)]
+    pub playlist: String,
+
+    /// duplicates.csv written by an export with --dedupe-key
+    #[arg(long = "from-csv", value_name = "FILE")]
+    pub from_csv: PathBuf,
+
+    /// Print the tracks that would be removed without changing the playlist
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Also refuse Spotify IDs without a capital letter, which are almost
+    /// always lowercased copies
+    #[arg(long)]
+    pub strict_ids: bool,
+}
+
+#[derive(Debug, Args)]
+#[command(group(
+    ArgGroup::new("changes")
+        .required(true)
+        .multiple(true)
+        .args(["name", "description", "public", "collaborative"])
+))]
+pub struct UpdatePlaylistArgs {
+    /// Playlist to change (ID, URI or link)
+    #[arg(long)]
+    pub playlist: String,
+
+    /// New name
+    #[arg(long)]
+    pub name: Option<String>,
+
+    /// New description
+    #[arg(long)]
+    pub description: Option<String>,
+
+    /// Make the playlist public or private
+    #[arg(long, value_name = "BOOL")]
+    pub public: Option<bool>,
+
+    /// Let followers edit the playlist; it must also be private
+    #[arg(long, value_name = "BOOL")]
+    pub collaborative: Option<bool>,
+}
+
+#[derive(Debug, Args)]
+pub struct SetCoverArgs {
+    /// Playlist to change (ID, URI or link)
+    #[arg(long)]
+    pub playlist: String,
+
+    /// JPEG of at most 192 KB, which is 256 KB once base64-encoded
+    #[arg(long, value_name = "FILE")]
+    pub image: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct SortPlaylistArgs {
+    /// Playlist to sort (ID, URI or link)
+    #[arg(long)]
+    pub playlist: String,
+
+    /// What to sort by; tracks with an unknown value go last
+    #[arg(long, value_enum)]
+    pub sort_by: SortKey,
+
+    /// Sort in descending order
+    #[arg(long)]
+    pub descending: bool,
+
+    /// Print the sorted order and the number of requests without changing
+    /// the playlist
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ValidateSchemaArgs {
+    #[arg(long, value_enum, default_value_t = ApiEndpoint::Playlists)]
+    pub endpoint: ApiEndpoint,
+}
+
+#[derive(Debug, Args)]
+pub struct SchemaArgs {
+    #[arg(value_enum)]
+    pub kind: SchemaKind,
+}
+
+#[derive(Debug, Args)]
+pub struct ReimportArgs {
+    /// Playlist CSVs written by this or an earlier version
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+
+    /// Directory to write the rewritten files to, under their own names
+    #[arg(long, default_value = "reimported")]
+    pub out_dir: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct BrowseArgs {
+    /// Directory of a previous export
+    pub dir: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct ArtArgs {
+    #[command(subcommand)]
+    pub command: ArtCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ArtCommand {
+    /// Delete saved covers that no file in the directory (CSVs,
+    /// library.json, rendered templates, deltas) refers to any more
+    Gc {
+        /// Export directory holding the artwork directory
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+
+        /// Also keep the covers an `export --store` library kept outside
+        /// the directory refers to (sqlite://<path>)
+        #[arg(long, value_name = "URL")]
+        store: Vec<String>,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyChecksumsArgs {
+    /// manifest.json written by an export
+    #[arg(long, default_value = MANIFEST_JSON)]
+    pub manifest: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct SharePackArgs {
+    /// Directory of a previous export, holding its index.json
+    pub dir: PathBuf,
+
+    #[arg(long, value_enum, default_value_t)]
+    pub format: ShareFormat,
+
+    /// Where to write the page; defaults to share-pack.md or
+    /// share-pack.html in the export directory
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct InspectArgs {
+    /// A CSV, JSON or SQLite file written by this tool
+    pub artifact: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct RenderArgs {
+    /// A library.json written by `export --format json`, or its directory
+    #[arg(required_unless_present = "store")]
+    pub input: Option<PathBuf>,
+
+    /// Render a run recorded with `export --store` instead (sqlite://<path>)
+    #[arg(long, value_name = "URL", conflicts_with = "input")]
+    pub store: Option<String>,
+
+    /// The stored run to render; defaults to the latest complete one
+    #[arg(long, value_name = "ID", requires = "store")]
+    pub run: Option<i64>,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[derive(Parser)]
+    struct OutputOnly {
+        #[command(flatten)]
+        output: OutputArgs,
+    }
+
+    /// Output options as parsed from `args`, for tests elsewhere.
+    pub fn output_args(args: &[&str]) -> OutputArgs {
+        OutputOnly::parse_from(["rimusic-convert"].iter().chain(args)).output
+    }
+
+    #[test]
+    fn durations_do_not_overflow() {
+        assert_eq!(parse_duration("90m"), Ok(Duration::from_secs(90 * 60)));
+        assert_eq!(
+            parse_duration("2d"),
+            Ok(Duration::from_secs(2 * 24 * 60 * 60))
+        );
+        assert!(parse_duration(&format!("{}d", u64::MAX / 1000)).is_err());
+        assert!(parse_duration(&format!("{}s", u64::MAX)).is_ok());
+        assert!(parse_duration("5w").is_err());
+    }
+
+    #[test]
+    fn export_category_takes_the_export_options() {
+        let cli = Cli::try_parse_from([
+            "rimusic-convert",
+            "export-category",
+            "--category",
+            "toplists",
+            "--country",
+            "US",
+        ])
+        .unwrap();
+        let (_, Command::ExportCategory(args)) = cli.command() else {
+            panic!("not export-category");
+        };
+        assert_eq!(args.category.as_deref(), Some("toplists"));
+        assert_eq!(args.country.as_deref(), Some("US"));
+        assert!(!args.continues_history());
+    }
+
+    #[test]
+    fn added_after_takes_dates_and_timestamps() {
+        assert_eq!(
+            parse_added_after("2024-05-01").unwrap().to_rfc3339(),
+            "2024-05-01T00:00:00+00:00"
+        );
+        assert_eq!(
+            parse_added_after("2024-05-01T12:30:00+02:00")
+                .unwrap()
+                .to_rfc3339(),
+            "2024-05-01T10:30:00+00:00"
+        );
+        for bad in ["yesterday", "2024-13-01", "2024-05-01 12:00", ""] {
+            assert!(parse_added_after(bad).is_err(), "{:?} was accepted", bad);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Compilations, such as soundtracks and "Various Artists" collections,
+//! whose album artist says nothing about who is on them.
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{record::TrackRecord, spotify::Track};
+
+/// The album artist Spotify credits compilations to.
+const VARIOUS_ARTISTS: &str = "Various Artists";
+
+/// The artist report row compilation tracks share under `own-group`.
+pub const COMPILATIONS_GROUP: &str = "Compilations";
+
+/// How the artist report counts tracks from compilations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompilationPolicy {
+    /// Count them together in one "Compilations" row
+    OwnGroup,
+    /// Count each track for its own artists, like any other track
+    #[default]
+    ByTrackArtist,
+    /// Leave them out
+    Skip,
+}
+
+/// Whether Spotify marks the album as a compilation or credits it to
+/// "Various Artists".
+pub fn is_compilation(track: &Track) -> bool {
+    track.album.album_type.as_deref() == Some("compilation")
+        || track
+            .album
+            .artists
+            .iter()
+            .filter_map(|artist| artist.name.as_deref())
+            .any(is_various_artists)
+}
+
+/// [`is_compilation`] for an exported row. The album type is not a CSV
+/// column, so rows read back from CSV are judged by album artist alone.
+pub fn is_compilation_record(record: &TrackRecord) -> bool {
+    record.album_type.as_deref() == Some("compilation")
+        || record
+            .album_artist_names
+            .split(", ")
+            .any(is_various_artists)
+}
+
+fn is_various_artists(name: &str) -> bool {
+    name.trim().eq_ignore_ascii_case(VARIOUS_ARTISTS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::{tests::sample_record, RecordOptions};
+
+    fn track(album_type: Option<&str>, album_artists: &[&str]) -> Track {
+        serde_json::from_value(serde_json::json!({
+            "uri": "spotify:track:1",
+            "name": "Song",
+            "artists": [{"name": "Performer", "uri": "spotify:artist:p"}],
+            "album": {
+                "name": "Album",
+                "album_type": album_type,
+                "artists": album_artists
+                    .iter()
+                    .map(|name| serde_json::json!({"name": name, "uri": null}))
+                    .collect::<Vec<_>>(),
+                "images": []
+            },
+            "duration_ms": 200000,
+            "popularity": 50
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn spots_compilations_by_type_or_album_artist() {
+        assert!(is_compilation(&track(
+            Some("compilation"),
+            &["Film Studio"]
+        )));
+        assert!(is_compilation(&track(Some("album"), &["Various Artists"])));
+        assert!(is_compilation(&track(None, &[" various artists "])));
+        assert!(is_compilation(&track(None, &["Label", "VARIOUS ARTISTS"])));
+
+        assert!(!is_compilation(&track(Some("album"), &["Performer"])));
+        assert!(!is_compilation(&track(
+            Some("single"),
+            &["Various Artists Tribute Band"]
+        )));
+        assert!(!is_compilation(&track(None, &[])));
+    }
+
+    #[test]
+    fn rows_read_back_are_judged_alike() {
+        let from_track = |track: &Track| {
+            TrackRecord::from_track(track, "owner", String::new(), RecordOptions::default())
+        };
+        assert!(is_compilation_record(&from_track(&track(
+            Some("compilation"),
+            &["Film Studio"]
+        ))));
+        assert!(is_compilation_record(&from_track(&track(
+            None,
+            &["Label", "Various Artists"]
+        ))));
+        assert!(!is_compilation_record(&from_track(&track(
+            Some("album"),
+            &["Performer"]
+        ))));
+
+        // Rows from files without the "Album Type" column go by the album artist.
+        let mut row = sample_record("spotify:track:1", "Song");
+        row.album_artist_names = "Various Artists".to_string();
+        assert!(is_compilation_record(&row));
+        row.album_artist_names = "Film Studio".to_string();
+        assert!(!is_compilation_record(&row));
+    }
+}
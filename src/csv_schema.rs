@@ -0,0 +1,146 @@
+//! Reading playlist CSVs whatever columns they were written with, for
+//! `reimport`. Every release writes the default columns first; optional
+//! columns follow in the order their flags added them, and files edited
+//! by hand or made by other tools may lack or reorder columns. Each is
+//! brought to the layout this version writes: the default columns, then
+//! the optional ones in their usual order.
+
+use clap::ValueEnum;
+use csv::StringRecord;
+use std::{error::Error, fmt, fs, path::Path};
+
+use crate::record::{csv_reader, Field, DEFAULT_FIELDS};
+
+/// The layout a CSV was written with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaVersion {
+    /// The default columns only, which every release writes.
+    Base,
+    /// The default columns, then these optional ones in the order written.
+    Extended(Vec<Field>),
+    /// Not a layout this tool writes: default columns are missing or out of
+    /// place. Each column's field, `None` for columns this version does not
+    /// know.
+    Foreign(Vec<Option<Field>>),
+}
+
+impl fmt::Display for SchemaVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaVersion::Base => write!(f, "default columns"),
+            SchemaVersion::Extended(optional) => {
+                write!(f, "default columns and {} optional", optional.len())
+            }
+            SchemaVersion::Foreign(columns) => {
+                let missing = DEFAULT_FIELDS
+                    .iter()
+                    .filter(|field| !columns.contains(&Some(**field)))
+                    .count();
+                let unknown = columns.iter().filter(|column| column.is_none()).count();
+                write!(
+                    f,
+                    "non-standard layout, {} default columns missing and {} unknown",
+                    missing, unknown
+                )
+            }
+        }
+    }
+}
+
+fn field_for(header: &str) -> Option<Field> {
+    let header = header.trim();
+    Field::value_variants()
+        .iter()
+        .copied()
+        .find(|field| field.header().eq_ignore_ascii_case(header))
+}
+
+pub fn detect_csv_schema_version(headers: &[&str]) -> SchemaVersion {
+    let columns: Vec<Option<Field>> = headers.iter().map(|header| field_for(header)).collect();
+    let base = DEFAULT_FIELDS.len();
+    let starts_with_defaults = columns.len() >= base
+        && columns
+            .iter()
+            .zip(DEFAULT_FIELDS)
+            .all(|(column, field)| *column == Some(field));
+    let optional: Option<Vec<Field>> = columns
+        .iter()
+        .skip(base)
+        .map(|column| column.filter(|field| !DEFAULT_FIELDS.contains(field)))
+        .collect();
+    match optional {
+        Some(optional) if starts_with_defaults && optional.is_empty() => SchemaVersion::Base,
+        Some(optional) if starts_with_defaults => SchemaVersion::Extended(optional),
+        _ => SchemaVersion::Foreign(columns),
+    }
+}
+
+/// The columns a file in `from` is rewritten with: the default ones, then
+/// any optional ones it has, in the order `Field` lists them.
+pub fn latest_fields(from: &SchemaVersion) -> Vec<Field> {
+    let present: Vec<Field> = match from {
+        SchemaVersion::Base => Vec::new(),
+        SchemaVersion::Extended(optional) => optional.clone(),
+        SchemaVersion::Foreign(columns) => columns.iter().flatten().copied().collect(),
+    };
+    let mut fields = DEFAULT_FIELDS.to_vec();
+    fields.extend(
+        Field::value_variants()
+            .iter()
+            .filter(|field| !DEFAULT_FIELDS.contains(field) && present.contains(field)),
+    );
+    fields
+}
+
+/// `record` in the layout of [`latest_fields`]. Columns it lacks are
+/// empty, which every field reads as missing; unknown columns are dropped.
+pub fn normalize_record_to_latest(record: &StringRecord, from: &SchemaVersion) -> StringRecord {
+    let columns: Vec<Option<Field>> = match from {
+        SchemaVersion::Base => DEFAULT_FIELDS.iter().copied().map(Some).collect(),
+        SchemaVersion::Extended(optional) => DEFAULT_FIELDS
+            .iter()
+            .chain(optional)
+            .copied()
+            .map(Some)
+            .collect(),
+        SchemaVersion::Foreign(columns) => columns.clone(),
+    };
+    latest_fields(from)
+        .into_iter()
+        .map(|field| {
+            columns
+                .iter()
+                .position(|column| *column == Some(field))
+                .and_then(|index| record.get(index))
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Rewrites the CSV at `input` to `output` in the latest layout, keeping
+/// its `#` preamble. Returns the layout it was in and the rows written.
+pub fn reimport_csv(input: &Path, output: &Path) -> Result<(SchemaVersion, usize), Box<dyn Error>> {
+    let text = fs::read_to_string(input).map_err(|e| format!("{}: {}", input.display(), e))?;
+    let preamble = text.lines().next().filter(|line| line.starts_with('#'));
+
+    let mut reader = csv_reader(text.as_bytes());
+    let headers = reader.headers()?.clone();
+    let version = detect_csv_schema_version(&headers.iter().collect::<Vec<_>>());
+
+    let mut out = Vec::new();
+    if let Some(preamble) = preamble {
+        out.extend_from_slice(preamble.as_bytes());
+        out.push(b'\n');
+    }
+    let mut writer = csv::Writer::from_writer(&mut out);
+    writer.write_record(latest_fields(&version).iter().map(|field| field.header()))?;
+    let mut rows = 0;
+    for record in reader.records() {
+        writer.write_record(&normalize_record_to_latest(&record?, &version))?;
+        rows += 1;
+    }
+    writer.flush()?;
+    drop(writer);
+    fs::write(output, out).map_err(|e| format!("{}: {}", output.display(), e))?;
+    Ok((version, rows))
+}
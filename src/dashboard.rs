@@ -0,0 +1,248 @@
+//! A static status page over the runs of a scheduled export, built from the
+//! index.json each run leaves behind. It needs no network and no assets: the
+//! page is one HTML file with its styles and sparklines inline.
+
+use chrono::{DateTime, FixedOffset};
+use log::{info, warn};
+use std::{collections::HashMap, error::Error, fmt::Write as _, fs, path::Path};
+
+use crate::{
+    followers::{find_run_indexes, Run},
+    index::RunSummary,
+    stats::format_hms,
+};
+
+pub const STATUS_HTML: &str = "status.html";
+
+/// Runs shown unless asked otherwise.
+pub const DEFAULT_RUNS: usize = 30;
+
+const STYLE: &str = "
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.4em; }
+table { border-collapse: collapse; }
+th, td { padding: 0.3em 0.8em; border-bottom: 1px solid #ddd; text-align: right; }
+th:first-child, td:first-child { text-align: left; }
+tr.warn td:first-child { border-left: 4px solid #e0a800; }
+tr.failed td:first-child { border-left: 4px solid #c62828; }
+tr.ok td:first-child { border-left: 4px solid #2e7d32; }
+.trends { display: flex; gap: 2em; margin-bottom: 1.5em; }
+.trend span { display: block; font-size: 0.85em; color: #666; }
+polyline { fill: none; stroke: #1565c0; stroke-width: 1.5; }
+.muted { color: #999; }
+";
+
+/// One run as the dashboard shows it.
+struct RunRow {
+    date: DateTime<FixedOffset>,
+    playlists: usize,
+    tracks: usize,
+    /// Per-playlist track count changes since the previous run, summed
+    /// separately, so a playlist that gained 5 tracks and lost 2 shows as 3
+    /// added. A new playlist counts all of its tracks as added.
+    added: usize,
+    removed: usize,
+    summary: Option<RunSummary>,
+}
+
+impl RunRow {
+    fn status(&self) -> &'static str {
+        match &self.summary {
+            Some(summary)
+                if summary.out_of_space
+                    || summary.outage_stop
+                    || summary.incomplete_playlists > 0 =>
+            {
+                "failed"
+            }
+            Some(summary) if summary.warnings > 0 => "warn",
+            Some(_) => "ok",
+            None => "",
+        }
+    }
+}
+
+fn run_rows(runs: &[Run]) -> Vec<RunRow> {
+    let mut previous: Option<HashMap<&str, usize>> = None;
+    let mut rows = Vec::with_capacity(runs.len());
+    for (date, index) in runs {
+        let counts: HashMap<&str, usize> = index
+            .playlists
+            .iter()
+            .map(|entry| (entry.id.as_str(), entry.track_count))
+            .collect();
+        let (mut added, mut removed) = (0, 0);
+        if let Some(previous) = &previous {
+            for (id, &count) in &counts {
+                let before = previous.get(id).copied().unwrap_or(0);
+                added += count.saturating_sub(before);
+                removed += before.saturating_sub(count);
+            }
+            for (id, &before) in previous {
+                if !counts.contains_key(id) {
+                    removed += before;
+                }
+            }
+        }
+        rows.push(RunRow {
+            date: *date,
+            playlists: counts.len(),
+            tracks: counts.values().sum(),
+            added,
+            removed,
+            summary: index.summary.clone(),
+        });
+        previous = Some(counts);
+    }
+    rows
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// An inline SVG line over `values`, oldest first, or nothing with fewer
+/// than two points to connect.
+fn sparkline(values: &[f64]) -> String {
+    const WIDTH: f64 = 160.0;
+    const HEIGHT: f64 = 32.0;
+    if values.len() < 2 {
+        return String::new();
+    }
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let range = if max > min { max - min } else { 1.0 };
+    let step = WIDTH / (values.len() - 1) as f64;
+    let points: Vec<String> = values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let y = HEIGHT - 2.0 - (value - min) / range * (HEIGHT - 4.0);
+            format!("{:.1},{:.1}", i as f64 * step, y)
+        })
+        .collect();
+    format!(
+        "<svg width=\"{}\" height=\"{}\"><polyline points=\"{}\"/></svg>",
+        WIDTH,
+        HEIGHT,
+        points.join(" ")
+    )
+}
+
+/// The last `limit` runs, newest first, as a complete HTML page.
+fn render(rows: &[RunRow], limit: usize) -> String {
+    let shown = &rows[rows.len().saturating_sub(limit)..];
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Export status</title>\
+         <style>{}</style></head><body>\n<h1>Export status</h1>\n",
+        STYLE
+    );
+    if let Some(latest) = shown.last() {
+        let _ = writeln!(
+            html,
+            "<p>Last run {}, {} runs shown.</p>",
+            escape_html(&latest.date.to_rfc3339()),
+            shown.len()
+        );
+    }
+
+    let summarized = |metric: fn(&RunSummary) -> f64| -> Vec<f64> {
+        shown
+            .iter()
+            .filter_map(|row| row.summary.as_ref().map(metric))
+            .collect()
+    };
+    let trends = [
+        (
+            "Tracks",
+            shown
+                .iter()
+                .map(|row| row.tracks as f64)
+                .collect::<Vec<_>>(),
+        ),
+        ("Requests", summarized(|s| s.requests as f64)),
+        ("Rate limited", summarized(|s| s.rate_limited as f64)),
+        ("Duration", summarized(|s| s.duration_secs as f64)),
+    ];
+    html.push_str("<div class=\"trends\">\n");
+    for (label, values) in &trends {
+        let line = sparkline(values);
+        if !line.is_empty() {
+            let _ = writeln!(
+                html,
+                "<div class=\"trend\"><span>{}</span>{}</div>",
+                label, line
+            );
+        }
+    }
+    html.push_str("</div>\n");
+
+    html.push_str(
+        "<table>\n<tr><th>Run</th><th>Duration</th><th>Playlists</th><th>Incomplete</th>\
+         <th>Tracks</th><th>Added</th><th>Removed</th><th>Warnings</th><th>Requests</th>\
+         <th>Rate limited</th><th>Outage pause</th></tr>\n",
+    );
+    let unknown = "<span class=\"muted\">&ndash;</span>".to_string();
+    for (i, row) in shown.iter().enumerate().rev() {
+        let summary = row.summary.as_ref();
+        let metric =
+            |value: fn(&RunSummary) -> String| summary.map(value).unwrap_or(unknown.clone());
+        // The oldest run shown may still have a predecessor outside the page.
+        let first = i == 0 && rows.len() == shown.len();
+        let change = |count: usize| {
+            if first {
+                unknown.clone()
+            } else {
+                count.to_string()
+            }
+        };
+        let _ = writeln!(
+            html,
+            "<tr class=\"{}\"><td>{}{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>\
+             <td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            row.status(),
+            escape_html(&row.date.format("%Y-%m-%d %H:%M").to_string()),
+            match summary {
+                Some(s) if s.out_of_space => " (out of space)",
+                Some(s) if s.outage_stop => " (Spotify down)",
+                _ => "",
+            },
+            metric(|s| format_hms(s.duration_secs)),
+            row.playlists,
+            metric(|s| s.incomplete_playlists.to_string()),
+            row.tracks,
+            change(row.added),
+            change(row.removed),
+            metric(|s| s.warnings.to_string()),
+            metric(|s| s.requests.to_string()),
+            metric(|s| s.rate_limited.to_string()),
+            metric(|s| format_hms(s.outage_pause_secs)),
+        );
+    }
+    html.push_str("</table>\n</body></html>\n");
+    html
+}
+
+/// Writes the dashboard for `runs` (oldest first) to `output`.
+pub fn write_dashboard(runs: &[Run], output: &Path, limit: usize) -> Result<(), Box<dyn Error>> {
+    let html = render(&run_rows(runs), limit);
+    fs::write(output, html).map_err(|e| format!("{}: {}", output.display(), e))?;
+    Ok(())
+}
+
+/// Regenerates status.html in `runs_root` after an export. The run itself is
+/// already saved by then, so a failure here only warns.
+pub fn refresh_dashboard(runs_root: &Path) {
+    let output = runs_root.join(STATUS_HTML);
+    let result =
+        find_run_indexes(runs_root).and_then(|runs| write_dashboard(&runs, &output, DEFAULT_RUNS));
+    match result {
+        Ok(()) => info!("Finished writing: {}", output.display()),
+        Err(e) => warn!("Could not update {}: {}", output.display(), e),
+    }
+}
@@ -0,0 +1,203 @@
+//! Collapsing copies of the same recording within a playlist. Re-releases
+//! (the original album, a compilation, a deluxe reissue) each have their own
+//! URI, so matching on ISRC or on a fuzzy artist and title key catches what
+//! URI matching cannot. Every dropped copy is listed with the one kept.
+
+use clap::ValueEnum;
+use csv::{Reader, Writer};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, collections::HashMap, error::Error, fs, path::Path};
+
+use crate::record::TrackRecord;
+
+pub const DUPLICATES_CSV: &str = "duplicates.csv";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DedupeKey {
+    /// The same Spotify track
+    Uri,
+    /// The same recording, falling back to the URI without an ISRC
+    Isrc,
+    /// The same artists and title, ignoring case and suffixes such as
+    /// "- Remastered 2011" or "(Live)"
+    Fuzzy,
+}
+
+impl DedupeKey {
+    fn describe(self) -> &'static str {
+        match self {
+            DedupeKey::Uri => "URI",
+            DedupeKey::Isrc => "ISRC",
+            DedupeKey::Fuzzy => "artists and title",
+        }
+    }
+}
+
+/// Which copy of a duplicated recording to keep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PreferRelease {
+    /// The earliest release
+    #[default]
+    Original,
+    /// The most recent release
+    Latest,
+    /// Anything but a compilation, then the earliest release
+    AlbumOverCompilation,
+}
+
+impl PreferRelease {
+    fn reason(self) -> &'static str {
+        match self {
+            PreferRelease::Original => "kept the earliest release",
+            PreferRelease::Latest => "kept the latest release",
+            PreferRelease::AlbumOverCompilation => {
+                "kept the earliest release that is not a compilation"
+            }
+        }
+    }
+
+    /// `Less` when `a` is the better copy to keep. Unknown dates lose.
+    fn compare(self, a: &TrackRecord, b: &TrackRecord) -> Ordering {
+        let is_compilation =
+            |record: &TrackRecord| record.album_type.as_deref() == Some("compilation");
+        let by_date = |descending: bool| match (&a.album_release_date, &b.album_release_date) {
+            (Some(a), Some(b)) if descending => b.cmp(a),
+            (Some(a), Some(b)) => a.cmp(b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+        match self {
+            PreferRelease::Original => by_date(false),
+            PreferRelease::Latest => by_date(true),
+            PreferRelease::AlbumOverCompilation => is_compilation(a)
+                .cmp(&is_compilation(b))
+                .then_with(|| by_date(false)),
+        }
+    }
+}
+
+/// A copy dropped in favour of another.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Duplicate {
+    #[serde(rename = "Playlist")]
+    pub playlist: String,
+    #[serde(rename = "Dropped URI")]
+    pub dropped_uri: Option<String>,
+    #[serde(rename = "Dropped Album")]
+    pub dropped_album: Option<String>,
+    #[serde(rename = "Dropped Release Date")]
+    pub dropped_release_date: Option<String>,
+    #[serde(rename = "Kept URI")]
+    pub kept_uri: Option<String>,
+    #[serde(rename = "Kept Album")]
+    pub kept_album: Option<String>,
+    #[serde(rename = "Kept Release Date")]
+    pub kept_release_date: Option<String>,
+    #[serde(rename = "Reason")]
+    pub reason: String,
+}
+
+fn dedupe_key(record: &TrackRecord, key: DedupeKey) -> Option<String> {
+    match key {
+        DedupeKey::Uri => record.track_uri.clone(),
+        DedupeKey::Isrc => record
+            .isrc
+            .as_ref()
+            .map(|isrc| format!("isrc:{}", isrc.to_uppercase()))
+            .or_else(|| record.track_uri.clone()),
+        DedupeKey::Fuzzy => {
+            let title = record.track_name.as_deref()?;
+            // "Song - Remastered 2011" and "Song (Live)" are the same song.
+            let title = title.split(" - ").next().unwrap_or(title);
+            let title = title.split(['(', '[']).next().unwrap_or(title);
+            let fold = |text: &str| {
+                text.to_lowercase()
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+            Some(format!("{}|{}", fold(&record.artist_names), fold(title)))
+        }
+    }
+}
+
+/// Keeps one copy of each recording in `records`, at the place of its first
+/// occurrence. Records without a key are always kept.
+pub fn dedupe(
+    records: Vec<TrackRecord>,
+    playlist: &str,
+    key: DedupeKey,
+    prefer: PreferRelease,
+) -> (Vec<TrackRecord>, Vec<Duplicate>) {
+    let mut kept: Vec<TrackRecord> = Vec::with_capacity(records.len());
+    let mut groups: HashMap<String, usize> = HashMap::new();
+    let mut dropped: Vec<(TrackRecord, usize)> = Vec::new();
+
+    for record in records {
+        let Some(group_key) = dedupe_key(&record, key) else {
+            kept.push(record);
+            continue;
+        };
+        match groups.get(&group_key) {
+            None => {
+                groups.insert(group_key, kept.len());
+                kept.push(record);
+            }
+            Some(&index) => {
+                let record = if prefer.compare(&record, &kept[index]) == Ordering::Less {
+                    std::mem::replace(&mut kept[index], record)
+                } else {
+                    record
+                };
+                dropped.push((record, index));
+            }
+        }
+    }
+
+    let duplicates = dropped
+        .into_iter()
+        .map(|(record, index)| {
+            let keeper = &kept[index];
+            Duplicate {
+                playlist: playlist.to_string(),
+                dropped_uri: record.track_uri,
+                dropped_album: record.album_name,
+                dropped_release_date: record.album_release_date,
+                kept_uri: keeper.track_uri.clone(),
+                kept_album: keeper.album_name.clone(),
+                kept_release_date: keeper.album_release_date.clone(),
+                reason: format!("same {}; {}", key.describe(), prefer.reason()),
+            }
+        })
+        .collect();
+    (kept, duplicates)
+}
+
+/// Writes the dropped copies, or removes a stale list when there are none.
+pub fn write_duplicates(path: &Path, duplicates: &[Duplicate]) -> Result<(), Box<dyn Error>> {
+    if duplicates.is_empty() {
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        return Ok(());
+    }
+    let mut writer = Writer::from_path(path)?;
+    for duplicate in duplicates {
+        writer.serialize(duplicate)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads a list written by `write_duplicates`.
+pub fn read_duplicates(path: &Path) -> Result<Vec<Duplicate>, Box<dyn Error>> {
+    let mut reader = Reader::from_path(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let duplicates = reader
+        .deserialize()
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(duplicates)
+}
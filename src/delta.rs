@@ -0,0 +1,434 @@
+//! `--emit-delta`: what changed since the previous run, as records another
+//! system can apply instead of reloading every file. Starting from the
+//! previous run's playlists, dropping each `removed` and `moved` record at
+//! its old position, then inserting each `added` and `moved` record at its
+//! new position in ascending order, then replacing the record at each
+//! `changed` record's new position, gives this run's playlists. Deltas of
+//! consecutive runs therefore compose.
+
+use log::warn;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    atomic::{write_atomic, write_json_atomic},
+    changelog::{playlist_key, PreviousRun},
+    export::PlaylistExport,
+    index::RunIndex,
+    paths::file_stem,
+    playlist_diff::TrackMatch,
+    record::TrackRecord,
+    reorder::longest_increasing,
+};
+
+pub const ADDED_NDJSON: &str = "added.ndjson";
+pub const REMOVED_NDJSON: &str = "removed.ndjson";
+pub const MOVED_NDJSON: &str = "moved.ndjson";
+pub const CHANGED_NDJSON: &str = "changed.ndjson";
+pub const DELTA_MANIFEST_JSON: &str = "delta-manifest.json";
+
+/// One line of a delta file. Added records have no old position and
+/// removed ones no new position. Changed records have both, as the track
+/// kept its place.
+#[derive(Debug, Serialize)]
+struct DeltaRecord<'a> {
+    playlist_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    old_position: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    new_position: Option<usize>,
+    track: &'a TrackRecord,
+}
+
+#[derive(Debug, Serialize)]
+struct DeltaManifest {
+    /// `exported_at` of the run the delta applies to; absent when there was
+    /// none, so every track is added.
+    from_run: Option<String>,
+    /// `exported_at` of the run the delta produces.
+    to_run: String,
+    changed_playlists: usize,
+    added: usize,
+    removed: usize,
+    moved: usize,
+    changed: usize,
+    /// Playlists left out because the previous run's tracks could not be
+    /// read; reload them in full.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    skipped_playlists: Vec<String>,
+}
+
+#[derive(Default)]
+struct PlaylistDelta {
+    /// Old positions.
+    removed: Vec<usize>,
+    /// New positions.
+    added: Vec<usize>,
+    /// Old and new positions.
+    moved: Vec<(usize, usize)>,
+    /// Old and new positions of tracks that kept their place but whose
+    /// records differ, such as a new popularity.
+    changed: Vec<(usize, usize)>,
+}
+
+/// Tracks pair up as `TrackMatch` has them. Matched tracks keep their
+/// place unless the order of the rest has to change around them; those few
+/// are `moved`, and of the rest those whose records differ are `changed`.
+fn diff_playlist(old: &[TrackRecord], new: &[TrackRecord]) -> PlaylistDelta {
+    let found = TrackMatch::new(old, new);
+    let old_positions: Vec<usize> = found.matched.iter().map(|(old, _)| *old).collect();
+    let in_place = longest_increasing(&old_positions);
+    let (kept, moved): (Vec<_>, Vec<_>) = found
+        .matched
+        .into_iter()
+        .enumerate()
+        .partition(|(i, _)| in_place[*i]);
+    PlaylistDelta {
+        removed: found.removed,
+        added: found.added,
+        moved: moved.into_iter().map(|(_, pair)| pair).collect(),
+        changed: kept
+            .into_iter()
+            .map(|(_, pair)| pair)
+            .filter(|&(from, to)| old[from] != new[to])
+            .collect(),
+    }
+}
+
+fn write_ndjson(path: &Path, lines: &[DeltaRecord]) -> Result<(), Box<dyn Error>> {
+    write_atomic(path, |writer| {
+        for line in lines {
+            serde_json::to_writer(&mut *writer, line)?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    })
+}
+
+/// Writes the delta from `previous` to `exported`, as its files hold it,
+/// in a subdirectory of `dir` named after the run, and returns it. `full`
+/// is false for runs that exported only some playlists, which then leave
+/// the others alone.
+pub fn write_delta(
+    dir: &Path,
+    previous: Option<&PreviousRun>,
+    exported: &[PlaylistExport],
+    index: &RunIndex,
+    full: bool,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let before = previous.map(|previous| {
+        previous
+            .index()
+            .playlists
+            .iter()
+            .map(|entry| (playlist_key(&entry.id, &entry.name), entry))
+            .collect::<HashMap<_, _>>()
+    });
+    let (mut added, mut removed, mut moved, mut changed_records) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    let mut changed = 0;
+    let mut skipped = Vec::new();
+    let mut seen = HashSet::new();
+    for export in exported {
+        let (playlist_id, records) = (export.playlist.id.as_str(), &export.records);
+        let key = playlist_key(playlist_id, &export.playlist.name);
+        seen.insert(key.clone());
+        let entry = before.as_ref().and_then(|before| before.get(&key));
+        let old: &[TrackRecord] = match (previous, entry) {
+            (Some(previous), Some(entry)) => match previous.tracks_of(entry) {
+                Some(old) => old,
+                None => {
+                    warn!(
+                        "Could not read the previous tracks of {}; leaving it out of the delta",
+                        export.playlist.name
+                    );
+                    skipped.push(playlist_id.to_string());
+                    continue;
+                }
+            },
+            _ => &[],
+        };
+        let delta = diff_playlist(old, records);
+        if delta.added.is_empty()
+            && delta.removed.is_empty()
+            && delta.moved.is_empty()
+            && delta.changed.is_empty()
+        {
+            continue;
+        }
+        changed += 1;
+        removed.extend(delta.removed.iter().map(|&position| DeltaRecord {
+            playlist_id,
+            old_position: Some(position),
+            new_position: None,
+            track: &old[position],
+        }));
+        added.extend(delta.added.iter().map(|&position| DeltaRecord {
+            playlist_id,
+            old_position: None,
+            new_position: Some(position),
+            track: &records[position],
+        }));
+        moved.extend(delta.moved.iter().map(|&(from, to)| DeltaRecord {
+            playlist_id,
+            old_position: Some(from),
+            new_position: Some(to),
+            track: &records[to],
+        }));
+        changed_records.extend(delta.changed.iter().map(|&(from, to)| DeltaRecord {
+            playlist_id,
+            old_position: Some(from),
+            new_position: Some(to),
+            track: &records[to],
+        }));
+    }
+    if let (true, Some(previous)) = (full, previous) {
+        for entry in &previous.index().playlists {
+            if seen.contains(&playlist_key(&entry.id, &entry.name)) {
+                continue;
+            }
+            let Some(old) = previous.tracks_of(entry) else {
+                skipped.push(entry.id.clone());
+                continue;
+            };
+            if !old.is_empty() {
+                changed += 1;
+            }
+            removed.extend(old.iter().enumerate().map(|(position, track)| DeltaRecord {
+                playlist_id: &entry.id,
+                old_position: Some(position),
+                new_position: None,
+                track,
+            }));
+        }
+    }
+
+    let out = dir.join(file_stem(&index.exported_at));
+    fs::create_dir_all(&out).map_err(|e| format!("{}: {}", out.display(), e))?;
+    write_ndjson(&out.join(ADDED_NDJSON), &added)?;
+    write_ndjson(&out.join(REMOVED_NDJSON), &removed)?;
+    write_ndjson(&out.join(MOVED_NDJSON), &moved)?;
+    write_ndjson(&out.join(CHANGED_NDJSON), &changed_records)?;
+    write_json_atomic(
+        &out.join(DELTA_MANIFEST_JSON),
+        &DeltaManifest {
+            from_run: previous.map(|previous| previous.index().exported_at.clone()),
+            to_run: index.exported_at.clone(),
+            changed_playlists: changed,
+            added: added.len(),
+            removed: removed.len(),
+            moved: moved.len(),
+            changed: changed_records.len(),
+            skipped_playlists: skipped,
+        },
+    )?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        index::{write_index, INDEX_JSON},
+        provenance::Provenance,
+        record::{tests::sample_record, write_track_records, DEFAULT_FIELDS},
+        spotify::Playlist,
+        testdir::TestDir,
+    };
+    use serde_json::Value;
+
+    type Library = Vec<(&'static str, Vec<TrackRecord>)>;
+
+    fn tracks(names: &[&str]) -> Vec<TrackRecord> {
+        names
+            .iter()
+            .map(|name| sample_record(&format!("spotify:track:{}", name), name))
+            .collect()
+    }
+
+    /// `record` as a later run sees it: more popular and re-added.
+    fn refreshed(mut record: TrackRecord, popularity: u8) -> TrackRecord {
+        record.popularity = Some(popularity);
+        record.added_at = Some("2024-05-01T10:00:00Z".to_string());
+        record
+    }
+
+    fn export(id: &str, records: Vec<TrackRecord>) -> PlaylistExport {
+        let playlist: Playlist = serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": id,
+            "public": true,
+            "owner": {"display_name": "owner"},
+            "tracks": {"href": "", "total": records.len()},
+        }))
+        .unwrap();
+        PlaylistExport {
+            playlist,
+            tracks: Vec::new(),
+            file: Some(format!("{}.csv", id)),
+            records,
+            quarantined: Vec::new(),
+        }
+    }
+
+    /// Writes `library` into `dir` as run `run` would, returning its index.
+    fn write_run(dir: &Path, run: &str, library: &Library) -> (Vec<PlaylistExport>, RunIndex) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_file() {
+                fs::remove_file(path).unwrap();
+            }
+        }
+        let exported: Vec<PlaylistExport> = library
+            .iter()
+            .map(|(id, records)| export(id, records.clone()))
+            .collect();
+        for export in &exported {
+            let path = dir.join(export.file.as_ref().unwrap());
+            write_track_records(&path, &export.records, &DEFAULT_FIELDS, None).unwrap();
+        }
+        let mut index = RunIndex::from_exports(&exported, &Provenance::new(None, &()), false);
+        index.exported_at = run.to_string();
+        write_index(&dir.join(INDEX_JSON), &index).unwrap();
+        (exported, index)
+    }
+
+    fn read_lines(path: &Path) -> Vec<Value> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    /// Applies the delta in `dir` to `playlists` as the module describes.
+    fn apply(playlists: &mut HashMap<String, Vec<Value>>, dir: &Path) {
+        let removed = read_lines(&dir.join(REMOVED_NDJSON));
+        let added = read_lines(&dir.join(ADDED_NDJSON));
+        let moved = read_lines(&dir.join(MOVED_NDJSON));
+        let changed = read_lines(&dir.join(CHANGED_NDJSON));
+        let mut drops: HashMap<String, Vec<usize>> = HashMap::new();
+        for line in removed.iter().chain(&moved) {
+            drops
+                .entry(line["playlist_id"].as_str().unwrap().to_string())
+                .or_default()
+                .push(line["old_position"].as_u64().unwrap() as usize);
+        }
+        for (id, mut positions) in drops {
+            let tracks = playlists.entry(id).or_default();
+            positions.sort_unstable_by(|a, b| b.cmp(a));
+            for position in positions {
+                tracks.remove(position);
+            }
+        }
+        let mut inserts: Vec<&Value> = added.iter().chain(&moved).collect();
+        inserts.sort_by_key(|line| line["new_position"].as_u64().unwrap());
+        for line in inserts {
+            let tracks = playlists
+                .entry(line["playlist_id"].as_str().unwrap().to_string())
+                .or_default();
+            let position = line["new_position"].as_u64().unwrap() as usize;
+            tracks.insert(position, line["track"].clone());
+        }
+        for line in changed {
+            let tracks = playlists
+                .get_mut(line["playlist_id"].as_str().unwrap())
+                .unwrap();
+            let position = line["new_position"].as_u64().unwrap() as usize;
+            tracks[position] = line["track"].clone();
+        }
+        playlists.retain(|_, tracks| !tracks.is_empty());
+    }
+
+    fn as_values(library: &Library) -> HashMap<String, Vec<Value>> {
+        library
+            .iter()
+            .filter(|(_, records)| !records.is_empty())
+            .map(|(id, records)| {
+                let values = records
+                    .iter()
+                    .map(|record| serde_json::to_value(record).unwrap())
+                    .collect();
+                (id.to_string(), values)
+            })
+            .collect()
+    }
+
+    fn uris(playlists: &HashMap<String, Vec<Value>>) -> Vec<(String, Vec<String>)> {
+        let mut uris: Vec<(String, Vec<String>)> = playlists
+            .iter()
+            .map(|(id, tracks)| {
+                let tracks = tracks
+                    .iter()
+                    .map(|track| track["Track URI"].as_str().unwrap().to_string())
+                    .collect();
+                (id.clone(), tracks)
+            })
+            .collect();
+        uris.sort();
+        uris
+    }
+
+    #[test]
+    fn deltas_of_three_runs_replay_onto_the_first() {
+        let runs: [Library; 3] = [
+            vec![
+                ("mix", tracks(&["a", "b", "c", "d"])),
+                ("old", tracks(&["x"])),
+            ],
+            {
+                let mut mix = tracks(&["b", "a", "d", "e"]);
+                mix[2] = refreshed(mix[2].clone(), 40);
+                let mut old = tracks(&["x", "y"]);
+                old[0] = refreshed(old[0].clone(), 10);
+                vec![("mix", mix), ("old", old)]
+            },
+            {
+                let mut mix = tracks(&["e", "b", "f", "b"]);
+                mix[1] = refreshed(mix[1].clone(), 70);
+                vec![("mix", mix), ("new", tracks(&["g", "g"]))]
+            },
+        ];
+        let export_dir = TestDir::new();
+        let delta_dir = TestDir::new();
+        write_run(export_dir.path(), "run-1", &runs[0]);
+        let mut replayed = as_values(&runs[0]);
+
+        for (number, library) in runs.iter().enumerate().skip(1) {
+            let previous = PreviousRun::load(export_dir.path()).unwrap();
+            let run = format!("run-{}", number + 1);
+            let (exported, index) = write_run(export_dir.path(), &run, library);
+            let out =
+                write_delta(delta_dir.path(), Some(&previous), &exported, &index, true).unwrap();
+            apply(&mut replayed, &out);
+            assert_eq!(uris(&replayed), uris(&as_values(library)), "after {}", run);
+        }
+        assert_eq!(replayed, as_values(&runs[2]));
+    }
+
+    #[test]
+    fn moves_only_what_has_to_move() {
+        let old = tracks(&["a", "b", "c", "d"]);
+        let new = tracks(&["a", "c", "d", "b"]);
+        let delta = diff_playlist(&old, &new);
+        assert!(delta.added.is_empty() && delta.removed.is_empty());
+        assert_eq!(delta.moved, [(1, 3)]);
+        assert!(delta.changed.is_empty());
+    }
+
+    #[test]
+    fn tracks_kept_in_place_with_new_fields_are_changed() {
+        let old = tracks(&["a", "b", "c"]);
+        let mut new = old.clone();
+        new[1] = refreshed(new[1].clone(), 55);
+        let delta = diff_playlist(&old, &new);
+        assert!(delta.added.is_empty() && delta.removed.is_empty() && delta.moved.is_empty());
+        assert_eq!(delta.changed, [(1, 1)]);
+    }
+}
@@ -0,0 +1,116 @@
+//! Keeping an export from running the output filesystem out of space: a
+//! rough size estimate checked up front, and recognising a full disk when a
+//! write fails anyway.
+
+use log::{info, warn};
+use std::{error::Error, io, path::Path};
+
+use crate::output::OutputFormat;
+
+/// Typical size of one written row with the default columns. JSON repeats
+/// every key and is pretty-printed, so it runs about twice as large.
+const CSV_ROW_BYTES: u64 = 600;
+const JSON_ROW_BYTES: u64 = 1_300;
+
+/// Roughly how much an export of `track_count` tracks writes.
+pub fn estimate_output_size(track_count: u64, format: OutputFormat) -> u64 {
+    let row = match format {
+        OutputFormat::Json => JSON_ROW_BYTES,
+        // A template's size is up to the template; assume it is CSV-like.
+        OutputFormat::Csv | OutputFormat::Template => CSV_ROW_BYTES,
+    };
+    track_count * row
+}
+
+/// Compares the estimated output size with the free space in `dir`. The
+/// estimate is rough, so a tight fit only warns; with `min_free`, an export
+/// that would leave less than that free is refused before it starts.
+pub fn check_free_space(
+    dir: &Path,
+    estimate: u64,
+    min_free: Option<u64>,
+) -> Result<(), Box<dyn Error>> {
+    let Some(available) = available_space(dir) else {
+        warn!("Could not determine the free space in {}", dir.display());
+        return Ok(());
+    };
+    info!(
+        "Output needs about {}; {} free",
+        format_size(estimate),
+        format_size(available)
+    );
+    let left = available.saturating_sub(estimate);
+    match min_free {
+        Some(min_free) if left < min_free => Err(format!(
+            "the export needs about {} and would leave {} free, below --min-free-space {}",
+            format_size(estimate),
+            format_size(left),
+            format_size(min_free)
+        )
+        .into()),
+        None if estimate > available => {
+            warn!(
+                "The export needs about {} but only {} is free; it may not fit",
+                format_size(estimate),
+                format_size(available)
+            );
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Bytes available to this user on the filesystem holding `dir`, or `None`
+/// where that cannot be determined.
+#[cfg(unix)]
+fn available_space(dir: &Path) -> Option<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is NUL-terminated and `stat` is a valid out-pointer.
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn available_space(_dir: &Path) -> Option<u64> {
+    None
+}
+
+/// Whether `error`, or anything it wraps, is the disk filling up.
+pub fn is_disk_full(error: &(dyn Error + 'static)) -> bool {
+    let mut current = Some(error);
+    while let Some(error) = current {
+        // csv::Error keeps its io::Error to itself rather than as a source.
+        let io_error = error.downcast_ref::<io::Error>().or_else(|| {
+            match error.downcast_ref::<csv::Error>()?.kind() {
+                csv::ErrorKind::Io(e) => Some(e),
+                _ => None,
+            }
+        });
+        if io_error.is_some_and(|e| e.kind() == io::ErrorKind::StorageFull) {
+            return true;
+        }
+        current = error.source();
+    }
+    false
+}
+
+/// `bytes` in the largest unit that keeps it at least 1, e.g. `1.4 GB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1_000 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1_000.0;
+    let mut unit = 0;
+    while size >= 1_000.0 && unit < UNITS.len() - 1 {
+        size /= 1_000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
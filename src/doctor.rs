@@ -0,0 +1,346 @@
+use chrono::{DateTime, Utc};
+use reqwest::{header, Url};
+use std::{error::Error, fmt, fs, future::Future, path::Path, time::Duration};
+use tokio::time::timeout;
+
+use crate::{
+    api::{SpotifyAPI, API_BASE},
+    auth::{decode_token_scopes, fetch_public_token, EXPORT_SCOPES},
+    cli::GlobalArgs,
+    middleware::parse_rate_limit_headers,
+    spotify::{PaginatedTrackResponse, PlaylistResponse},
+};
+
+/// Per-check time limit; the whole run stays within a few seconds.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Local clocks this far from Spotify's cause token expiry confusion.
+const MAX_CLOCK_SKEW_SECS: i64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckStatus::Pass => write!(f, "PASS"),
+            CheckStatus::Warn => write!(f, "WARN"),
+            CheckStatus::Fail => write!(f, "FAIL"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+    pub hint: Option<&'static str>,
+}
+
+impl CheckResult {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Pass,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>, hint: &'static str) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Warn,
+            detail: detail.into(),
+            hint: Some(hint),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, hint: &'static str) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Fail,
+            detail: detail.into(),
+            hint: Some(hint),
+        }
+    }
+}
+
+/// Runs the live checks and prints a table. Returns `false` when any check
+/// failed so the caller can exit non-zero.
+pub async fn run_doctor(global: GlobalArgs) -> Result<bool, Box<dyn Error>> {
+    let mut results = vec![check_output_dir(Path::new(".")), check_cache_dir(&global)];
+
+    match global.token {
+        None if global.public => {
+            results.push(check_public_token(&global.http.build_client()?).await)
+        }
+        None => results.push(CheckResult::fail(
+            "token",
+            "no access token given",
+            "pass --token or set SPOTIFY_TOKEN",
+        )),
+        Some(token) => results.extend(check_token(token, global.http.build_client()?).await),
+    }
+
+    print_results(&results);
+    Ok(results.iter().all(|r| r.status != CheckStatus::Fail))
+}
+
+/// The checks of a user token: its scopes, then live requests with it.
+pub async fn check_token(token: String, client: reqwest::Client) -> Vec<CheckResult> {
+    let mut results = vec![check_scopes(&token)];
+    let api = SpotifyAPI::new(token, client);
+    results.extend(check_api(&api).await);
+    results
+}
+
+/// With `--public` there is no user token to check, only whether the
+/// anonymous one can still be had.
+async fn check_public_token(client: &reqwest::Client) -> CheckResult {
+    match limited(fetch_public_token(client)).await {
+        Ok(_) => CheckResult::warn(
+            "token",
+            "anonymous public token",
+            "only public playlists given with --playlist can be exported; pass a user token for the library",
+        ),
+        Err(e) => CheckResult::fail(
+            "token",
+            e.to_string(),
+            "pass --token or set SPOTIFY_TOKEN",
+        ),
+    }
+}
+
+fn check_scopes(token: &str) -> CheckResult {
+    match decode_token_scopes(token) {
+        Ok(granted) => {
+            let missing: Vec<_> = EXPORT_SCOPES
+                .iter()
+                .filter(|scope| !granted.iter().any(|g| g == *scope))
+                .copied()
+                .collect();
+            if missing.is_empty() {
+                CheckResult::pass("token scopes", granted.join(" "))
+            } else {
+                CheckResult::fail(
+                    "token scopes",
+                    format!("missing {}", missing.join(", ")),
+                    "request a new token including the missing scopes",
+                )
+            }
+        }
+        Err(_) => CheckResult::warn(
+            "token scopes",
+            "scopes cannot be read from this token format",
+            "the playlist checks below still show whether the token works",
+        ),
+    }
+}
+
+async fn check_api(api: &SpotifyAPI) -> Vec<CheckResult> {
+    let mut results = Vec::new();
+
+    match limited(api.probe(&format!("{}/me", API_BASE))).await {
+        Err(e) => {
+            results.push(CheckResult::fail(
+                "api reachable",
+                e.to_string(),
+                "check network access, proxies and the TLS flags (--ca-bundle, --use-native-roots)",
+            ));
+            return results;
+        }
+        Ok((status, headers)) => {
+            results.push(check_rate_limit(status, &headers));
+            results.push(check_clock(&headers));
+        }
+    }
+
+    match limited(api.get_current_user()).await {
+        Ok(user) => results.push(CheckResult::pass(
+            "identity",
+            format!(
+                "{} ({})",
+                user.display_name
+                    .unwrap_or_else(|| "no display name".into()),
+                user.id
+            ),
+        )),
+        Err(e) => {
+            results.push(CheckResult::fail(
+                "identity",
+                e.to_string(),
+                "the token is invalid or expired; fetch a new one",
+            ));
+            return results;
+        }
+    }
+
+    let page =
+        limited(api.get::<PlaylistResponse>(&format!("{}/me/playlists?limit=1", API_BASE))).await;
+    let playlist = match page {
+        Ok(page) => {
+            results.push(CheckResult::pass(
+                "playlist page",
+                "fetched one playlist page",
+            ));
+            page.items.into_iter().next()
+        }
+        Err(e) => {
+            results.push(CheckResult::fail(
+                "playlist page",
+                e.to_string(),
+                "the token needs playlist-read-private",
+            ));
+            None
+        }
+    };
+
+    if let Some(playlist) = playlist {
+        match Url::parse_with_params(&playlist.tracks.href, &[("limit", "1")]) {
+            Ok(url) => match limited(api.get::<PaginatedTrackResponse>(url.as_str())).await {
+                Ok(_) => results.push(CheckResult::pass(
+                    "track page",
+                    format!("fetched tracks of \"{}\"", playlist.name),
+                )),
+                Err(e) => results.push(CheckResult::fail(
+                    "track page",
+                    e.to_string(),
+                    "playlist tracks could not be read; check scopes and retry later",
+                )),
+            },
+            Err(e) => results.push(CheckResult::fail(
+                "track page",
+                e.to_string(),
+                "Spotify returned an unexpected tracks URL",
+            )),
+        }
+    }
+
+    results
+}
+
+fn check_rate_limit(status: reqwest::StatusCode, headers: &header::HeaderMap) -> CheckResult {
+    if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = headers
+            .get(header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("unknown");
+        return CheckResult::fail(
+            "rate limit",
+            format!("rate limited, retry after {}s", retry_after),
+            "wait before exporting; another app may share this client ID",
+        );
+    }
+    match parse_rate_limit_headers(headers) {
+        Some(info) => CheckResult::pass(
+            "rate limit",
+            format!(
+                "not currently rate limited; {} requests left for {}s",
+                info.remaining,
+                info.reset
+                    .saturating_duration_since(std::time::Instant::now())
+                    .as_secs()
+            ),
+        ),
+        None => CheckResult::pass("rate limit", "not currently rate limited"),
+    }
+}
+
+fn check_clock(headers: &header::HeaderMap) -> CheckResult {
+    let server = headers
+        .get(header::DATE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| DateTime::parse_from_rfc2822(v).ok());
+
+    match server {
+        None => CheckResult::warn(
+            "clock",
+            "server sent no usable Date header",
+            "make sure the system clock is synchronized",
+        ),
+        Some(server) => {
+            let skew = (Utc::now() - server.with_timezone(&Utc)).num_seconds();
+            if skew.abs() > MAX_CLOCK_SKEW_SECS {
+                CheckResult::warn(
+                    "clock",
+                    format!("local clock is {}s off from Spotify", skew),
+                    "synchronize the system clock (e.g. enable NTP)",
+                )
+            } else {
+                CheckResult::pass("clock", format!("{}s skew", skew))
+            }
+        }
+    }
+}
+
+fn check_output_dir(dir: &Path) -> CheckResult {
+    match probe_writable(dir) {
+        Ok(()) => CheckResult::pass("output dir", dir.display().to_string()),
+        Err(e) => CheckResult::fail(
+            "output dir",
+            format!("{}: {}", dir.display(), e),
+            "run from a writable directory",
+        ),
+    }
+}
+
+fn check_cache_dir(global: &GlobalArgs) -> CheckResult {
+    if global.no_cache {
+        return CheckResult::pass("cache dir", "disabled by --no-cache");
+    }
+    let Some(dir) = global.cache_location() else {
+        return CheckResult::warn(
+            "cache dir",
+            "no home directory found",
+            "set HOME or XDG_CACHE_HOME",
+        );
+    };
+
+    if !dir.exists() {
+        return CheckResult::pass("cache dir", format!("{} (will be created)", dir.display()));
+    }
+    match probe_writable(&dir) {
+        Ok(()) => CheckResult::pass("cache dir", dir.display().to_string()),
+        Err(e) => CheckResult::warn(
+            "cache dir",
+            format!("{}: {}", dir.display(), e),
+            "fix the directory's permissions",
+        ),
+    }
+}
+
+fn probe_writable(dir: &Path) -> std::io::Result<()> {
+    let probe = dir.join(".rimusic-convert-doctor");
+    fs::write(&probe, b"ok")?;
+    fs::remove_file(&probe)
+}
+
+async fn limited<T>(
+    future: impl Future<Output = Result<T, Box<dyn Error>>>,
+) -> Result<T, Box<dyn Error>> {
+    match timeout(CHECK_TIMEOUT, future).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("timed out after {}s", CHECK_TIMEOUT.as_secs()).into()),
+    }
+}
+
+pub fn print_results(results: &[CheckResult]) {
+    let width = results.iter().map(|r| r.name.len()).max().unwrap_or(0);
+    for result in results {
+        println!(
+            "{}  {:<width$}  {}",
+            result.status,
+            result.name,
+            result.detail,
+            width = width
+        );
+        if let Some(hint) = result.hint {
+            println!("      {:<width$}  hint: {}", "", hint, width = width);
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use csv::Writer;
+use std::{collections::BTreeSet, error::Error, fs, path::Path};
+
+use crate::{
+    api::SpotifyAPI,
+    ids::normalize_video_id,
+    spotify::{Episode, Show},
+    state::{RunState, ShowState},
+    warnings::{ErrorCollector, Severity},
+};
+
+/// An episode released since the last run, with the show it belongs to.
+#[derive(Debug)]
+pub struct NewEpisode {
+    pub show: String,
+    pub episode: Episode,
+}
+
+/// Whether `episode` has not been listed yet. Episodes without a release
+/// date are judged by ID alone so they are listed once and never again.
+fn is_new(episode: &Episode, since: &str, state: &ShowState) -> bool {
+    let listed = state.listed.contains(&episode.id);
+    match episode.release_date.as_deref() {
+        Some(date) => date > since || (date == since && !listed),
+        None => !listed,
+    }
+}
+
+/// Which of a show's `episodes`, fetched back to `since`, are new given
+/// what the previous run listed, and what to remember for the next one.
+fn select_new(
+    episodes: Vec<Episode>,
+    since: &str,
+    previous: &ShowState,
+) -> (Vec<Episode>, ShowState) {
+    let newest = episodes
+        .iter()
+        .filter_map(|episode| episode.release_date.clone())
+        .fold(since.to_string(), String::max);
+    let mut listed: BTreeSet<String> = episodes
+        .iter()
+        .filter(|episode| match episode.release_date.as_deref() {
+            Some(date) => date == newest,
+            None => true,
+        })
+        .map(|episode| episode.id.clone())
+        .collect();
+    if previous.last_release_date.as_deref() == Some(newest.as_str()) {
+        listed.extend(previous.listed.iter().cloned());
+    }
+    let new = episodes
+        .into_iter()
+        .filter(|episode| is_new(episode, since, previous))
+        .collect();
+    let state = ShowState {
+        last_release_date: Some(newest),
+        listed,
+    };
+    (new, state)
+}
+
+/// Lists each saved show's episodes released after `default_since`, or with
+/// `state`, after the newest one listed by the previous run. `state` is
+/// updated for every show that could be read.
+pub async fn find_new_episodes(
+    api: &SpotifyAPI,
+    shows: &[Show],
+    default_since: &str,
+    mut state: Option<&mut RunState>,
+    errors: &ErrorCollector,
+) -> Vec<NewEpisode> {
+    let mut new_episodes = Vec::new();
+
+    for show in shows {
+        let name = show.name.as_deref().unwrap_or(&show.id);
+        let previous = state
+            .as_ref()
+            .and_then(|state| state.shows.get(&show.id))
+            .cloned()
+            .unwrap_or_default();
+        let since = previous
+            .last_release_date
+            .clone()
+            .unwrap_or_else(|| default_since.to_string());
+
+        let episodes = match api.get_show_episodes_since(&show.id, &since).await {
+            Ok(episodes) => episodes,
+            Err(e) => {
+                errors.report(
+                    Severity::Warning,
+                    name,
+                    show.uri.as_deref(),
+                    format!("could not fetch episodes: {}", e),
+                );
+                continue;
+            }
+        };
+
+        let (new, show_state) = select_new(episodes, &since, &previous);
+        new_episodes.extend(new.into_iter().map(|episode| NewEpisode {
+            show: name.to_string(),
+            episode,
+        }));
+
+        if let Some(state) = state.as_deref_mut() {
+            state.shows.insert(show.id.clone(), show_state);
+        }
+    }
+
+    new_episodes
+}
+
+pub fn write_new_episodes(path: &Path, episodes: &[NewEpisode]) -> Result<(), Box<dyn Error>> {
+    let mut writer = Writer::from_path(path)?;
+    writer.write_record([
+        "Show",
+        "Episode",
+        "Release Date",
+        "Duration (ms)",
+        "Episode URI",
+    ])?;
+    for new in episodes {
+        let episode = &new.episode;
+        writer.write_record([
+            new.show.as_str(),
+            episode.name.as_deref().unwrap_or_default(),
+            episode.release_date.as_deref().unwrap_or_default(),
+            &episode
+                .duration_ms
+                .map(|ms| ms.to_string())
+                .unwrap_or_default(),
+            episode.uri.as_deref().unwrap_or_default(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// The YouTube video an episode's description links to, for shows that
+/// also publish there and say so. Only a direct `watch?v=` or `youtu.be`
+/// link counts; channel and playlist links name no one video.
+pub fn linked_video(episode: &Episode) -> Option<String> {
+    let description = episode.description.as_deref()?;
+    description
+        .split(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '<' | '>' | '(' | ')'))
+        .filter(|word| word.contains("youtube.com/watch") || word.contains("youtu.be/"))
+        .find_map(|word| {
+            let link = word.trim_end_matches(['.', ',', ';', '!', '?']);
+            let link = if link.starts_with("http") {
+                link.to_string()
+            } else {
+                format!("https://{}", link)
+            };
+            normalize_video_id(&link).ok()
+        })
+}
+
+/// Writes an M3U playlist of the new episodes that link a YouTube video,
+/// returning how many did.
+pub fn write_new_episodes_m3u(
+    path: &Path,
+    episodes: &[NewEpisode],
+) -> Result<usize, Box<dyn Error>> {
+    let mut m3u = String::from("#EXTM3U\n");
+    let mut count = 0;
+    for new in episodes {
+        let Some(video_id) = linked_video(&new.episode) else {
+            continue;
+        };
+        let seconds = new
+            .episode
+            .duration_ms
+            .map_or(-1, |ms| i64::from(ms / 1000));
+        let title = new.episode.name.as_deref().unwrap_or(&new.episode.id);
+        m3u.push_str(&format!(
+            "#EXTINF:{},{} - {}\nhttps://www.youtube.com/watch?v={}\n",
+            seconds, new.show, title, video_id
+        ));
+        count += 1;
+    }
+    fs::write(path, m3u).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn episode(id: &str, release_date: Option<&str>) -> Episode {
+        Episode {
+            id: id.to_string(),
+            uri: Some(format!("spotify:episode:{}", id)),
+            name: Some(format!("Episode {}", id)),
+            description: None,
+            duration_ms: Some(3_600_000),
+            release_date: release_date.map(str::to_string),
+            explicit: None,
+            show: None,
+        }
+    }
+
+    /// The IDs `select_new` picks out of `episodes`, threading `state`
+    /// from one run to the next as state.json does.
+    fn run(episodes: &[Episode], since: &str, state: &mut ShowState) -> Vec<String> {
+        let since = state.last_release_date.clone().unwrap_or(since.to_string());
+        let (new, next) = select_new(episodes.to_vec(), &since, state);
+        *state = next;
+        new.into_iter().map(|episode| episode.id).collect()
+    }
+
+    #[test]
+    fn lists_each_episode_once_across_runs() {
+        let mut state = ShowState::default();
+        let first = [
+            episode("b", Some("2024-03-08")),
+            episode("a", Some("2024-03-01")),
+            episode("old", Some("2024-02-01")),
+        ];
+        assert_eq!(run(&first, "2024-02-28", &mut state), ["b", "a"]);
+        // Nothing new: the feed is fetched again as it was.
+        assert!(run(&first, "2024-02-28", &mut state).is_empty());
+        let second = [
+            episode("c", Some("2024-03-15")),
+            episode("b", Some("2024-03-08")),
+            episode("a", Some("2024-03-01")),
+        ];
+        assert_eq!(run(&second, "2024-02-28", &mut state), ["c"]);
+    }
+
+    #[test]
+    fn episodes_sharing_the_newest_date_are_not_listed_again() {
+        let mut state = ShowState::default();
+        // Two episodes a day, the second published after the first run.
+        let first = [episode("am", Some("2024-03-08"))];
+        assert_eq!(run(&first, "2024-03-01", &mut state), ["am"]);
+        let second = [
+            episode("pm", Some("2024-03-08")),
+            episode("am", Some("2024-03-08")),
+        ];
+        assert_eq!(run(&second, "2024-03-01", &mut state), ["pm"]);
+        assert!(run(&second, "2024-03-01", &mut state).is_empty());
+    }
+
+    #[test]
+    fn undated_and_coarse_dates_are_not_listed_again() {
+        let mut state = ShowState::default();
+        let first = [
+            episode("undated", None),
+            episode("year", Some("2024")),
+            episode("day", Some("2024-03-08")),
+        ];
+        assert_eq!(
+            run(&first, "2023-12-31", &mut state),
+            ["undated", "year", "day"]
+        );
+        // A newer episode moves the date on; the undated one stays listed.
+        let second = [
+            episode("next", Some("2024-03-15")),
+            episode("undated", None),
+            episode("year", Some("2024")),
+            episode("day", Some("2024-03-08")),
+        ];
+        assert_eq!(run(&second, "2023-12-31", &mut state), ["next"]);
+        assert!(run(&second, "2023-12-31", &mut state).is_empty());
+        // Backdated episodes, released before the last one listed, are
+        // not surfaced late.
+        let third = [episode("late", Some("2024-03-10"))];
+        assert!(run(&third, "2023-12-31", &mut state).is_empty());
+    }
+
+    #[test]
+    fn finds_a_linked_video_in_the_description() {
+        let mut linked = episode("a", Some("2024-03-08"));
+        for (description, video) in [
+            (
+                "Watch it: https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=10s.",
+                Some("dQw4w9WgXcQ"),
+            ),
+            (
+                "Video (youtu.be/dQw4w9WgXcQ), audio here",
+                Some("dQw4w9WgXcQ"),
+            ),
+            (
+                "<a href=\"https://youtu.be/dQw4w9WgXcQ\">video</a>",
+                Some("dQw4w9WgXcQ"),
+            ),
+            ("Subscribe: https://www.youtube.com/@channel", None),
+            ("https://www.youtube.com/watch?v=short", None),
+        ] {
+            linked.description = Some(description.to_string());
+            assert_eq!(linked_video(&linked).as_deref(), video, "{}", description);
+        }
+    }
+}
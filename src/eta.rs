@@ -0,0 +1,62 @@
+//! How long an export has left, from how long its requests have been
+//! taking: the requests still to make at the recent average, plus the time
+//! spent between requests writing files.
+
+use std::{collections::VecDeque, time::Duration};
+
+/// Responses averaged over; older ones are forgotten so a slow start or a
+/// rate limited stretch does not skew the estimate for the rest of the run.
+const WINDOW: usize = 50;
+
+/// Responses needed before the average is worth showing.
+const MIN_SAMPLES: usize = 10;
+
+#[derive(Debug, Default)]
+pub struct TimeEstimator {
+    samples: VecDeque<Duration>,
+    /// Every response time recorded, outside the window too.
+    total: Duration,
+}
+
+impl TimeEstimator {
+    pub fn record(&mut self, elapsed: Duration) {
+        if self.samples.len() == WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(elapsed);
+        self.total += elapsed;
+    }
+
+    /// Time spent waiting on responses so far.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// The average over the window, once there are enough responses.
+    fn average(&self) -> Option<Duration> {
+        if self.samples.len() < MIN_SAMPLES {
+            return None;
+        }
+        Some(self.samples.iter().sum::<Duration>() / self.samples.len() as u32)
+    }
+
+    /// `remaining_calls` at the average response time, plus `overhead`.
+    pub fn estimate(&self, remaining_calls: usize, overhead: Duration) -> Option<Duration> {
+        Some(self.average()? * remaining_calls as u32 + overhead)
+    }
+}
+
+/// `ETA: ~3m 20s`, or `ETA: calculating...` without an estimate yet.
+pub fn eta_label(estimate: Option<Duration>) -> String {
+    let Some(estimate) = estimate else {
+        return "ETA: calculating...".to_string();
+    };
+    let secs = estimate.as_secs();
+    if secs >= 3600 {
+        format!("ETA: ~{}h {}m", secs / 3600, secs % 3600 / 60)
+    } else if secs >= 60 {
+        format!("ETA: ~{}m {}s", secs / 60, secs % 60)
+    } else {
+        format!("ETA: ~{}s", secs)
+    }
+}
@@ -0,0 +1,942 @@
+use log::{debug, info, warn};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    path::Path,
+    slice,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    album_groups::{write_album_groups, GroupBy},
+    album_runs::mark_album_runs,
+    api::{item_range, SpotifyAPI},
+    artwork::{ArtworkStore, ARTWORK_DIR},
+    blend::BlendAttribution,
+    clean::{find_clean_version, MIN_SUBSTITUTION_CONFIDENCE},
+    cli::{ExportArgs, OutputArgs},
+    dedupe::{dedupe, write_duplicates, Duplicate, DUPLICATES_CSV},
+    disk::is_disk_full,
+    eta::eta_label,
+    filter::sort_records,
+    index::{read_index, INDEX_JSON},
+    local_edits::LocalEdits,
+    local_files::find_local_file,
+    output::{
+        number_positions, split_explicit, write_library, write_playlist, OutputConfig,
+        OutputFormat, PlaylistRecords, EXPORT_CONFIG_JSON, LIBRARY_JSON,
+    },
+    overrides::VideoOverrides,
+    paths::OutputPaths,
+    provenance::Provenance,
+    quarantine::QuarantinedPage,
+    record::{is_valid_isrc, read_track_records, Field, TrackRecord, DEFAULT_FIELDS},
+    spotify::{Playlist, Track, TrackItem},
+    store::{RunFilters, Store},
+    template::TemplateWriter,
+    urls::looks_expiring,
+    warnings::{ErrorCollector, Severity},
+};
+
+/// A playlist together with every item fetched for it.
+#[derive(Debug)]
+pub struct PlaylistExport {
+    pub playlist: Playlist,
+    pub tracks: Vec<TrackItem>,
+    /// The playlist file it was written to, for formats with one.
+    pub file: Option<String>,
+    /// The rows its files hold, as the next run reads them back: filtered,
+    /// deduplicated, sorted and overridden, or as edited by hand.
+    pub records: Vec<TrackRecord>,
+    /// Pages that could not be fetched; the playlist is partial if any.
+    pub quarantined: Vec<QuarantinedPage>,
+}
+
+/// What an export run got through.
+#[derive(Debug)]
+pub struct ExportOutcome {
+    /// Playlists written in full.
+    pub exported: Vec<PlaylistExport>,
+    /// The disk filled up and the run stopped early; what was cut short is
+    /// in the collected errors.
+    pub out_of_space: bool,
+    /// `--strict` stopped the run after the first playlist missing data.
+    pub strict_stop: bool,
+    /// A Spotify outage outlasted `--max-outage-wait` and the run stopped
+    /// after the playlist it hit.
+    pub outage_stop: bool,
+    /// Left alone by `--max-age`, their files being recent enough.
+    pub skipped: Vec<Playlist>,
+    /// IDs of the exported playlists whose CSV kept its local edits, so
+    /// it still holds what an earlier run wrote.
+    pub kept_local: Vec<String>,
+}
+
+/// How long ago the file at `path` was last written.
+pub fn file_age(path: &Path) -> Result<Duration, Box<dyn Error>> {
+    // A modification time in the future counts as just written.
+    Ok(path.metadata()?.modified()?.elapsed().unwrap_or_default())
+}
+
+/// Whether every check that was asked for, those that are `Some`, passed.
+fn all_agree(checks: &[Option<bool>]) -> bool {
+    checks.iter().any(Option::is_some) && checks.iter().flatten().all(|&passed| passed)
+}
+
+/// The snapshot ID and file the index at `path` recorded for each playlist
+/// that has both, for `--snapshot-check`.
+fn recorded_snapshots(path: &Path) -> HashMap<String, (String, String)> {
+    let Ok(index) = read_index(path) else {
+        return HashMap::new();
+    };
+    index
+        .playlists
+        .into_iter()
+        .filter_map(|entry| Some((entry.id, (entry.snapshot_id?, entry.file?))))
+        .collect()
+}
+
+/// Writes every playlist in the requested format and hands back the fetched
+/// tracks so library-wide reports can be built without fetching them again.
+/// Running out of disk space stops the run instead of failing it, so what
+/// was written can still be recorded.
+pub async fn export_playlists(
+    playlists: Vec<Playlist>,
+    api: &SpotifyAPI,
+    args: &ExportArgs,
+    paths: &mut OutputPaths,
+    provenance: &Provenance,
+    errors: &ErrorCollector,
+    edits: &LocalEdits,
+) -> Result<ExportOutcome, Box<dyn Error>> {
+    if args.group_by == GroupBy::Album && args.output.format != OutputFormat::Csv {
+        return Err("--group-by album writes CSV files; drop --format".into());
+    }
+    if args.max_age.is_some()
+        && (args.output.format != OutputFormat::Csv
+            || args.group_by != GroupBy::Playlist
+            || args.output.split_explicit)
+    {
+        return Err("--max-age needs one CSV file per playlist".into());
+    }
+    if args.added_after.is_some()
+        && (args.output.format != OutputFormat::Csv || args.group_by != GroupBy::Playlist)
+    {
+        return Err("--added-after writes one CSV file per playlist".into());
+    }
+    info!("Exporting playlists to {:?}...", args.output.format);
+    let mut exported = Vec::with_capacity(playlists.len());
+    let mut rendered = Vec::with_capacity(playlists.len());
+    let template = match (&args.output.format, &args.output.template_file) {
+        (OutputFormat::Template, Some(path)) => {
+            Some(TemplateWriter::from_file(path, provenance.clone())?)
+        }
+        _ => None,
+    };
+    let mut fields = DEFAULT_FIELDS.to_vec();
+    if args.prefer_clean_version {
+        fields.push(Field::Substituted);
+    }
+    if args.include_followers {
+        fields.push(Field::PlaylistFollowers);
+    }
+    if args.include_image_dimensions {
+        fields.extend([Field::AlbumImageWidth, Field::AlbumImageHeight]);
+    }
+    if args.output.include_position {
+        fields.push(Field::Position);
+    }
+    if args.output.album_runs {
+        fields.push(Field::AlbumRun);
+    }
+    if args.enrich_added_by {
+        fields.push(Field::AddedByProfile);
+    }
+    if args.market_count {
+        fields.extend([Field::MarketCount, Field::Rare]);
+    }
+    let mut overrides = match &args.video_overrides {
+        Some(path) => {
+            fields.push(Field::YoutubeVideoId);
+            Some(VideoOverrides::load(path, args.strict_ids)?)
+        }
+        None => None,
+    };
+    if args.music_dir.is_some() {
+        fields.push(Field::LocalFilePath);
+    }
+    if args.liked_status() {
+        fields.push(Field::Liked);
+    }
+    if args.saved_album_status() {
+        fields.push(Field::SavedAlbum);
+    }
+    let mut artwork = if args.download_artwork {
+        fields.push(Field::AlbumImageFile);
+        Some(ArtworkStore::open(Path::new(ARTWORK_DIR))?)
+    } else {
+        None
+    };
+    let mut blend = if args.blend_members.is_empty() {
+        None
+    } else {
+        fields.push(Field::LikelyAddedFor);
+        Some(BlendAttribution::load(api, &args.blend_members, errors).await)
+    };
+    let config = OutputConfig::new(&args.output, fields);
+    let mut store = match &args.store {
+        Some(url) => {
+            let store = Store::open(url)?;
+            let run = store.begin_run(
+                &config.fields,
+                Some(provenance),
+                &RunFilters::from_args(args),
+            )?;
+            Some((store, run))
+        }
+        None => None,
+    };
+    // Explicit tracks recur across playlists; search for each one only once.
+    let mut clean_versions: HashMap<String, Option<Track>> = HashMap::new();
+    // Display names by user ID; `None` for users that could not be looked up.
+    let mut profiles: HashMap<String, Option<String>> = HashMap::new();
+    let mut saved = SavedStatus::default();
+
+    let mut duplicates = Vec::new();
+    let mut out_of_space = false;
+    let mut strict_stop = false;
+    let mut outage_stop = false;
+    let mut skipped = Vec::new();
+    let mut kept_local = Vec::new();
+    let snapshots = if args.snapshot_check {
+        recorded_snapshots(&paths.path(INDEX_JSON))
+    } else {
+        HashMap::new()
+    };
+    // For the ETA: requests still to make, and the time spent between them.
+    let total = playlists.len();
+    let mut remaining_calls = SpotifyAPI::estimate_api_calls(&playlists, args).track_page_calls;
+    let started = Instant::now();
+    let request_time_before = api.request_time();
+    let mut seen = 0;
+    let mut playlists = playlists.into_iter();
+    'playlists: for playlist in playlists.by_ref() {
+        seen += 1;
+        remaining_calls = remaining_calls.saturating_sub(
+            SpotifyAPI::estimate_api_calls(slice::from_ref(&playlist), args).track_page_calls,
+        );
+        if args.max_age.is_some() || args.snapshot_check {
+            // A name already taken would get a suffix, and a different file.
+            let unchanged = paths.peek(&playlist.name, "csv").filter(|file_name| {
+                let recent = args
+                    .max_age
+                    .map(|max_age| file_age(&paths.path(file_name)).is_ok_and(|age| age < max_age));
+                let same_snapshot = args.snapshot_check.then(|| {
+                    snapshots
+                        .get(&playlist.id)
+                        .is_some_and(|(snapshot_id, file)| {
+                            playlist.snapshot_id.as_ref() == Some(snapshot_id)
+                                && file == file_name
+                                && paths.path(file_name).is_file()
+                        })
+                });
+                all_agree(&[recent, same_snapshot])
+            });
+            if let Some(file_name) = unchanged {
+                paths.reserve(&playlist.name, "csv");
+                edits.keep(&file_name);
+                info!("Skipping {}: {} is up to date", playlist.name, file_name);
+                skipped.push(playlist);
+                continue;
+            }
+        }
+        let (mut tracks, failed) = match args.added_after {
+            Some(after) => api.get_playlist_items_after(&playlist.id, after).await?,
+            None => {
+                api.get_playlist_tracks(&playlist.tracks.href, playlist.tracks.total)
+                    .await?
+            }
+        };
+
+        if args.liked_status() || args.saved_album_status() {
+            saved
+                .mark(
+                    api,
+                    &mut tracks,
+                    args.liked_status(),
+                    args.saved_album_status(),
+                    &playlist.name,
+                    errors,
+                )
+                .await;
+        }
+
+        let mut records = Vec::with_capacity(tracks.len());
+        for item in &tracks {
+            let Some(track) = &item.track else {
+                continue;
+            };
+            let clean = match (&track.uri, track.explicit) {
+                (Some(uri), Some(true)) if args.prefer_clean_version => {
+                    if !clean_versions.contains_key(uri) {
+                        let found = substitute_for(api, track, &playlist.name, errors).await;
+                        clean_versions.insert(uri.clone(), found);
+                    }
+                    clean_versions[uri].as_ref()
+                }
+                _ => None,
+            };
+
+            if let Some(isrc) = track.isrc().filter(|isrc| !is_valid_isrc(isrc)) {
+                errors.report(
+                    Severity::Warning,
+                    &playlist.name,
+                    track.uri.as_deref(),
+                    format!("malformed ISRC {:?}", isrc),
+                );
+            }
+
+            let mut record = TrackRecord::from_track(
+                clean.unwrap_or(track),
+                &playlist.owner.display_name,
+                item.added_at.clone().unwrap_or_default(),
+                args.record_options(),
+            );
+            if clean.is_some() {
+                record.substituted = track.uri.clone();
+            }
+            if let Some(overrides) = &mut overrides {
+                overrides.apply(&mut record);
+            }
+            if let (Some(music_dir), Some(uri)) = (&args.music_dir, &track.uri) {
+                record.local_file_path =
+                    find_local_file(uri, music_dir).map(|path| path.to_string_lossy().into_owned());
+            }
+            record.playlist_followers = playlist.followers_count;
+            record.likely_added_for = blend
+                .as_mut()
+                .filter(|blend| blend.applies_to(&playlist))
+                .and_then(|blend| blend.attribute(track));
+            if let Some(added_by) = item.added_by.as_ref().filter(|_| args.enrich_added_by) {
+                if !profiles.contains_key(&added_by.id) {
+                    let profile = match api.get_user_public(&added_by.id).await {
+                        Ok(user) => Some(user.display_name.unwrap_or(user.id)),
+                        Err(e) => {
+                            errors.report(
+                                Severity::Warning,
+                                &playlist.name,
+                                track.uri.as_deref(),
+                                format!("could not look up user {}: {}", added_by.id, e),
+                            );
+                            None
+                        }
+                    };
+                    profiles.insert(added_by.id.clone(), profile);
+                }
+                record.added_by_profile = profiles[&added_by.id].clone();
+            }
+            records.push(record);
+        }
+
+        if let Some(artwork) = &mut artwork {
+            for record in &mut records {
+                let Some(url) = &record.album_image_url else {
+                    continue;
+                };
+                match artwork.fetch(api, url).await {
+                    Ok(file) => record.album_image_file = Some(file),
+                    Err(e) => errors.report(
+                        Severity::Warning,
+                        &playlist.name,
+                        record.track_uri.as_deref(),
+                        format!("could not download album art {}: {}", url, e),
+                    ),
+                }
+            }
+        }
+
+        let expiring: HashSet<&str> = records
+            .iter()
+            .filter_map(|record| record.album_image_url.as_deref())
+            .filter(|url| looks_expiring(url))
+            .collect();
+        if !expiring.is_empty() {
+            errors.report(
+                Severity::Warning,
+                &playlist.name,
+                None,
+                format!(
+                    "{} album image URLs look signed or expiring; links to them may stop working",
+                    expiring.len()
+                ),
+            );
+        }
+
+        // Where each failed page's rows would have started in the written
+        // file. Sorting, splitting or deduplicating moves rows, and inserting
+        // rows would break the numbering or album runs, so there is no such
+        // place.
+        let fixed_rows = args.output.format == OutputFormat::Csv
+            && args.output.sort_by.is_none()
+            && !args.output.split_explicit
+            && args.output.dedupe_key.is_none()
+            && !args.output.include_position
+            && !args.output.album_runs;
+        let filter = args.output.track_filter();
+        let gap_rows: Vec<Option<usize>> = failed
+            .iter()
+            .map(|page| {
+                let before = tracks[..page.position]
+                    .iter()
+                    .filter(|item| item.track.is_some())
+                    .count();
+                let row = records[..before]
+                    .iter()
+                    .filter(|r| filter.matches(r))
+                    .count();
+                Some(row).filter(|_| fixed_rows)
+            })
+            .collect();
+
+        let records = PlaylistRecords {
+            name: match args.added_after {
+                // Beside the full export rather than over it.
+                Some(after) => format!(
+                    "{} (added after {})",
+                    playlist.name,
+                    after.format("%Y-%m-%d %H-%M-%S")
+                ),
+                None => playlist.name.clone(),
+            },
+            owner: playlist.owner.display_name.clone(),
+            tracks: prepare_records(records, &playlist.name, &args.output, &mut duplicates),
+        };
+        if let Some((store, run)) = &mut store {
+            store.record_playlist(*run, &playlist.id, &records, !failed.is_empty())?;
+        }
+
+        if let Some(status) = api.last_rate_limit_status() {
+            if let (Some(remaining), Some(reset_at)) = (status.requests_remaining, status.reset_at)
+            {
+                debug!(
+                    "Rate limit as of {}: {} requests left until {}",
+                    status.last_request_at.format("%H:%M:%S"),
+                    remaining,
+                    reset_at.format("%H:%M:%S")
+                );
+            }
+        }
+        if let Some(template) = &template {
+            let written = template.write(&playlist, &tracks, &records.tracks, paths);
+            let Some(file_name) = unless_out_of_space(written, &playlist.name, errors)? else {
+                out_of_space = true;
+                break 'playlists;
+            };
+            info!("Finished writing: {}", file_name);
+        }
+        let mut file = None;
+        let mut written = Vec::new();
+        for records in split_if_requested(records, &args.output) {
+            let result = match args.group_by {
+                GroupBy::Playlist => {
+                    write_playlist(&records, &config, Some(provenance), paths, Some(edits))
+                }
+                // Written once every playlist is in.
+                GroupBy::Album => Ok(None),
+            };
+            match unless_out_of_space(result, &records.name, errors)? {
+                Some(Some(file_name)) => {
+                    if edits.kept_local(&file_name) {
+                        if !kept_local.contains(&playlist.id) {
+                            kept_local.push(playlist.id.clone());
+                        }
+                    } else {
+                        info!("Finished writing: {}", file_name);
+                    }
+                    // Merged into or left with local edits, the file holds
+                    // other rows than the export.
+                    let local = edits
+                        .state(&file_name)
+                        .filter(|state| state.local_edits)
+                        .and_then(|_| read_track_records(&paths.path(&file_name)).ok());
+                    written.extend(local.unwrap_or_else(|| records.tracks.clone()));
+                    file = Some(file_name);
+                }
+                Some(None) => written.extend(records.tracks.iter().cloned()),
+                None => {
+                    out_of_space = true;
+                    break 'playlists;
+                }
+            }
+            rendered.push(records);
+        }
+
+        for page in &failed {
+            errors.report(
+                Severity::Error,
+                &playlist.name,
+                None,
+                format!(
+                    "{} missing from the output: {}",
+                    item_range(page.offset, page.limit),
+                    page.error
+                ),
+            );
+        }
+        let unavailable = tracks.iter().filter(|item| item.track.is_none()).count();
+        if unavailable > 0 {
+            errors.report_incomplete(
+                &playlist.name,
+                None,
+                format!("{} unavailable items left out", unavailable),
+            );
+        }
+        // Failed pages and --added-after already explain a shortfall.
+        let expected = playlist
+            .tracks
+            .total
+            .filter(|_| failed.is_empty() && args.added_after.is_none());
+        if let Some(expected) = expected.filter(|&total| total as usize != tracks.len()) {
+            errors.report_incomplete(
+                &playlist.name,
+                None,
+                format!("received {} of {} items", tracks.len(), expected),
+            );
+        }
+        let quarantined = failed
+            .into_iter()
+            .zip(gap_rows)
+            .map(|(page, row)| QuarantinedPage {
+                playlist_id: playlist.id.clone(),
+                playlist_name: playlist.name.clone(),
+                owner: playlist.owner.display_name.clone(),
+                url: page.url,
+                offset: page.offset,
+                limit: page.limit,
+                error: page.error,
+                file: file.clone().filter(|_| fixed_rows),
+                row,
+            })
+            .collect();
+        exported.push(PlaylistExport {
+            playlist,
+            tracks,
+            file,
+            records: written,
+            quarantined,
+        });
+        let between_requests = started
+            .elapsed()
+            .saturating_sub(api.request_time().saturating_sub(request_time_before));
+        let overhead = between_requests / exported.len() as u32 * (total - seen) as u32;
+        info!(
+            "{}/{} playlists exported, {}",
+            seen,
+            total,
+            eta_label(api.estimate_remaining(remaining_calls, overhead))
+        );
+        if errors.is_strict() && errors.incomplete_playlists() > 0 {
+            strict_stop = true;
+            break;
+        }
+        if api.outage_gave_up() {
+            outage_stop = true;
+            break;
+        }
+    }
+
+    if out_of_space {
+        for playlist in playlists {
+            errors.report(
+                Severity::Error,
+                &playlist.name,
+                None,
+                "not exported: ran out of disk space",
+            );
+        }
+    } else if strict_stop || outage_stop {
+        let reason = if strict_stop {
+            "not exported: --strict stopped the run"
+        } else {
+            "not exported: Spotify was down for longer than --max-outage-wait"
+        };
+        // Left for the next run; not violations themselves.
+        for playlist in playlists {
+            errors.report(Severity::Warning, &playlist.name, None, reason);
+        }
+    } else {
+        if args.output.dedupe_key.is_some() {
+            write_duplicates(&paths.library_file(DUPLICATES_CSV), &duplicates)?;
+            info!(
+                "Dropped {} duplicate tracks; see {}",
+                duplicates.len(),
+                DUPLICATES_CSV
+            );
+        }
+        if args.group_by == GroupBy::Album {
+            let written = write_album_groups(
+                &exported,
+                &config,
+                args.record_options(),
+                &args.output.track_filter(),
+                provenance,
+                paths,
+            );
+            match unless_out_of_space(written, "albums", errors)? {
+                Some(files) => info!("Finished writing {} album files", files.len()),
+                None => out_of_space = true,
+            }
+        }
+        let written = write_library(rendered, &config, Some(provenance), paths);
+        match unless_out_of_space(written, LIBRARY_JSON, errors)? {
+            Some(Some(file_name)) => info!("Finished writing: {}", file_name),
+            Some(None) => {}
+            None => out_of_space = true,
+        }
+        let written = config.write(&paths.library_file(EXPORT_CONFIG_JSON));
+        if unless_out_of_space(written, EXPORT_CONFIG_JSON, errors)?.is_none() {
+            out_of_space = true;
+        }
+    }
+
+    // A partial run says nothing about overrides for the playlists it left
+    // out.
+    if let Some(overrides) = overrides.as_ref().filter(|_| args.whole_library()) {
+        for (line, key) in overrides.unused() {
+            errors.report(
+                Severity::Warning,
+                &overrides.path().display().to_string(),
+                None,
+                format!(
+                    "override on line {} for {} matched no exported track",
+                    line, key
+                ),
+            );
+        }
+    }
+    if let Some(artwork) = &artwork {
+        artwork.save()?;
+        info!(
+            "Downloaded {} album covers ({} identical to one already saved)",
+            artwork.downloaded, artwork.duplicates
+        );
+    }
+    if let Some((store, run)) = &store {
+        let complete = !out_of_space
+            && !strict_stop
+            && !outage_stop
+            && args.whole_library()
+            && skipped.is_empty();
+        store.finish_run(*run, complete)?;
+    }
+
+    Ok(ExportOutcome {
+        exported,
+        out_of_space,
+        strict_stop,
+        outage_stop,
+        skipped,
+        kept_local,
+    })
+}
+
+/// Passes a write's result through, except that a full disk is reported
+/// against `artifact` as incomplete and becomes `None`.
+fn unless_out_of_space<T>(
+    result: Result<T, Box<dyn Error>>,
+    artifact: &str,
+    errors: &ErrorCollector,
+) -> Result<Option<T>, Box<dyn Error>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if is_disk_full(e.as_ref()) => {
+            errors.report(
+                Severity::Error,
+                artifact,
+                None,
+                format!("incomplete: ran out of disk space: {}", e),
+            );
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Applies the filters, deduplication and ordering every writer shares.
+/// Copies dropped as duplicates are added to `duplicates`.
+pub fn prepare_records(
+    records: Vec<TrackRecord>,
+    playlist: &str,
+    args: &OutputArgs,
+    duplicates: &mut Vec<Duplicate>,
+) -> Vec<TrackRecord> {
+    let mut records = args.track_filter().apply(records);
+    if let Some(key) = args.dedupe_key {
+        let (kept, dropped) = dedupe(records, playlist, key, args.prefer_release);
+        duplicates.extend(dropped);
+        records = kept;
+    }
+    if let Some(key) = args.sort_by {
+        sort_records(&mut records, key, args.descending);
+    }
+    records
+}
+
+/// Splits off explicit tracks when `--split-explicit` is set, then numbers
+/// each resulting playlist when `--include-position` is and marks its album
+/// runs when `--album-runs` is.
+pub fn split_if_requested(playlist: PlaylistRecords, args: &OutputArgs) -> Vec<PlaylistRecords> {
+    let mut playlists = if args.split_explicit {
+        split_explicit(playlist)
+    } else {
+        vec![playlist]
+    };
+    if args.include_position {
+        playlists.iter_mut().for_each(number_positions);
+    }
+    if let Some(rules) = args.album_run_rules() {
+        for playlist in &mut playlists {
+            mark_album_runs(&mut playlist.tracks, rules);
+        }
+    }
+    playlists
+}
+
+/// Liked Songs and saved albums membership by URI, kept for the whole run
+/// so a track or album in several playlists is only asked about once.
+#[derive(Debug, Default)]
+struct SavedStatus {
+    tracks: HashMap<String, bool>,
+    albums: HashMap<String, bool>,
+}
+
+/// The distinct URIs among `uris` that start with `prefix` and `known` has
+/// no answer for.
+fn unknown_uris<'a>(
+    uris: impl Iterator<Item = &'a String>,
+    prefix: &str,
+    known: &HashMap<String, bool>,
+) -> Vec<String> {
+    let mut unknown: Vec<String> = uris
+        .filter(|uri| uri.starts_with(prefix) && !known.contains_key(*uri))
+        .cloned()
+        .collect();
+    unknown.sort_unstable();
+    unknown.dedup();
+    unknown
+}
+
+impl SavedStatus {
+    /// Sets `is_liked` and `is_saved_album` on each track, as asked for,
+    /// checking only what no earlier playlist did. If a check fails the
+    /// tracks are left unmarked. Local files and episodes are in neither.
+    async fn mark(
+        &mut self,
+        api: &SpotifyAPI,
+        items: &mut [TrackItem],
+        tracks: bool,
+        albums: bool,
+        playlist: &str,
+        errors: &ErrorCollector,
+    ) {
+        let present = || items.iter().filter_map(|item| item.track.as_ref());
+        let unknown_tracks = if tracks {
+            unknown_uris(
+                present().filter_map(|track| track.uri.as_ref()),
+                "spotify:track:",
+                &self.tracks,
+            )
+        } else {
+            Vec::new()
+        };
+        let unknown_albums = if albums {
+            unknown_uris(
+                present().filter_map(|track| track.album.uri.as_ref()),
+                "spotify:album:",
+                &self.albums,
+            )
+        } else {
+            Vec::new()
+        };
+        if !unknown_tracks.is_empty() {
+            let ids: Vec<&str> = unknown_tracks.iter().map(String::as_str).collect();
+            match api.check_saved_tracks(&ids).await {
+                Ok(saved) => self.tracks.extend(unknown_tracks.into_iter().zip(saved)),
+                Err(e) => errors.report(
+                    Severity::Warning,
+                    playlist,
+                    None,
+                    format!("could not check which tracks are in Liked Songs: {}", e),
+                ),
+            }
+        }
+        if !unknown_albums.is_empty() {
+            let ids: Vec<&str> = unknown_albums.iter().map(String::as_str).collect();
+            match api.check_saved_albums(&ids).await {
+                Ok(saved) => self.albums.extend(unknown_albums.into_iter().zip(saved)),
+                Err(e) => errors.report(
+                    Severity::Warning,
+                    playlist,
+                    None,
+                    format!("could not check which albums are saved: {}", e),
+                ),
+            }
+        }
+        for track in items.iter_mut().filter_map(|item| item.track.as_mut()) {
+            if tracks {
+                track.is_liked = track
+                    .uri
+                    .as_ref()
+                    .and_then(|uri| self.tracks.get(uri))
+                    .copied();
+            }
+            if albums {
+                track.is_saved_album = track
+                    .album
+                    .uri
+                    .as_ref()
+                    .and_then(|uri| self.albums.get(uri))
+                    .copied();
+            }
+        }
+    }
+}
+
+/// Looks up a clean version of an explicit track, keeping the original when
+/// the best match is not confident enough or the search fails.
+async fn substitute_for(
+    api: &SpotifyAPI,
+    track: &Track,
+    playlist: &str,
+    errors: &ErrorCollector,
+) -> Option<Track> {
+    let found = find_clean_version(api, track).await;
+    choose_substitute(track, found, playlist, errors)
+}
+
+/// What `substitute_for` makes of the search result for `track`.
+fn choose_substitute(
+    track: &Track,
+    found: Result<Option<(Track, f64)>, Box<dyn Error>>,
+    playlist: &str,
+    errors: &ErrorCollector,
+) -> Option<Track> {
+    let name = track.name.as_deref().unwrap_or("<unknown>");
+    match found {
+        Ok(Some((clean, confidence))) if confidence >= MIN_SUBSTITUTION_CONFIDENCE => {
+            info!(
+                "Substituting clean version of {} (confidence {:.2})",
+                name, confidence
+            );
+            Some(clean)
+        }
+        Ok(Some((_, confidence))) => {
+            warn!(
+                "Keeping explicit {}: best clean match has confidence {:.2}",
+                name, confidence
+            );
+            None
+        }
+        Ok(None) => {
+            warn!("Keeping explicit {}: no clean version found", name);
+            None
+        }
+        Err(e) => {
+            errors.report(
+                Severity::Warning,
+                playlist,
+                track.uri.as_deref(),
+                format!(
+                    "keeping explicit {}: clean version search failed: {}",
+                    name, e
+                ),
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(uri: &str, name: &str) -> Track {
+        serde_json::from_value(serde_json::json!({
+            "uri": uri,
+            "name": name,
+            "artists": [{"name": "Artist", "uri": "spotify:artist:1"}],
+            "album": {"name": "Album", "artists": [], "images": []},
+            "duration_ms": 200000,
+            "popularity": 50
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn substitutes_only_from_the_confidence_threshold_up() {
+        let original = track("spotify:track:explicit", "Song");
+        let clean = track("spotify:track:clean", "Song (Clean)");
+        let errors = ErrorCollector::new(false);
+
+        let at_threshold = Ok(Some((clean.clone(), MIN_SUBSTITUTION_CONFIDENCE)));
+        let chosen = choose_substitute(&original, at_threshold, "Mix", &errors).unwrap();
+        assert_eq!(chosen.uri.as_deref(), Some("spotify:track:clean"));
+
+        let just_below = Ok(Some((clean, MIN_SUBSTITUTION_CONFIDENCE - 0.01)));
+        assert!(choose_substitute(&original, just_below, "Mix", &errors).is_none());
+
+        assert!(choose_substitute(&original, Ok(None), "Mix", &errors).is_none());
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn a_failed_search_keeps_the_original_and_is_reported() {
+        let original = track("spotify:track:explicit", "Song");
+        let errors = ErrorCollector::new(false);
+
+        let failed = Err("search unavailable".into());
+        assert!(choose_substitute(&original, failed, "Mix", &errors).is_none());
+        assert_eq!(errors.len(), 1);
+        assert!(errors.violations().is_empty());
+    }
+
+    #[test]
+    fn skip_checks_combine_with_and() {
+        assert!(all_agree(&[Some(true), None]));
+        assert!(all_agree(&[None, Some(true)]));
+        assert!(all_agree(&[Some(true), Some(true)]));
+        assert!(!all_agree(&[Some(true), Some(false)]));
+        assert!(!all_agree(&[Some(false), Some(true)]));
+        assert!(!all_agree(&[None, None]));
+    }
+
+    #[test]
+    fn snapshots_are_read_for_playlists_with_a_file() {
+        let dir = crate::testdir::TestDir::new();
+        let path = dir.path().join(INDEX_JSON);
+        std::fs::write(
+            &path,
+            serde_json::json!({
+                "exported_at": "2024-05-01T10:00:00+00:00",
+                "playlists": [
+                    {"id": "a", "name": "A", "owner": "o", "track_count": 1,
+                     "file": "A.csv", "snapshot_id": "MTcsNDg"},
+                    {"id": "b", "name": "B", "owner": "o", "track_count": 1,
+                     "snapshot_id": "MTcsNDk"},
+                    {"id": "c", "name": "C", "owner": "o", "track_count": 1,
+                     "file": "C.csv"}
+                ]
+            })
+            .to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            recorded_snapshots(&path),
+            HashMap::from([(
+                "a".to_string(),
+                ("MTcsNDg".to_string(), "A.csv".to_string())
+            )])
+        );
+        assert!(recorded_snapshots(&dir.path().join("missing.json")).is_empty());
+    }
+}
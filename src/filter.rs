@@ -0,0 +1,218 @@
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+use crate::{record::TrackRecord, spotify::Playlist};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct TrackFilter {
+    pub min_popularity: Option<u8>,
+    /// Drop explicit tracks.
+    pub clean_only: bool,
+    /// When set, tracks whose value for a filtered field is unknown are
+    /// excluded instead of being given the benefit of the doubt.
+    pub strict: bool,
+}
+
+impl TrackFilter {
+    pub fn matches(&self, record: &TrackRecord) -> bool {
+        let popular_enough = match (self.min_popularity, record.popularity) {
+            (Some(min), Some(popularity)) => popularity >= min,
+            (Some(_), None) => !self.strict,
+            (None, _) => true,
+        };
+        let clean_enough = match (self.clean_only, record.explicit) {
+            (true, Some(explicit)) => !explicit,
+            (true, None) => !self.strict,
+            (false, _) => true,
+        };
+        popular_enough && clean_enough
+    }
+
+    pub fn apply(&self, records: Vec<TrackRecord>) -> Vec<TrackRecord> {
+        records.into_iter().filter(|r| self.matches(r)).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortKey {
+    Name,
+    Artist,
+    Popularity,
+    Duration,
+    ReleaseDate,
+}
+
+/// Sorts in place. The sort is stable and records with an unknown value for
+/// the key always end up last, whichever direction is requested.
+pub fn sort_records(records: &mut [TrackRecord], key: SortKey, descending: bool) {
+    records.sort_by(|a, b| compare_records(a, b, key, descending));
+}
+
+/// How `a` and `b` compare for `sort_records`.
+pub fn compare_records(
+    a: &TrackRecord,
+    b: &TrackRecord,
+    key: SortKey,
+    descending: bool,
+) -> Ordering {
+    match key {
+        SortKey::Name => compare_known(&a.track_name, &b.track_name, descending),
+        SortKey::Artist => compare_known(
+            &non_empty(&a.artist_names),
+            &non_empty(&b.artist_names),
+            descending,
+        ),
+        SortKey::Popularity => compare_known(&a.popularity, &b.popularity, descending),
+        SortKey::Duration => compare_known(&a.duration_ms, &b.duration_ms, descending),
+        SortKey::ReleaseDate => {
+            compare_known(&a.album_release_date, &b.album_release_date, descending)
+        }
+    }
+}
+
+fn compare_known<T: Ord>(a: &Option<T>, b: &Option<T>, descending: bool) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) if descending => b.cmp(a),
+        (Some(a), Some(b)) => a.cmp(b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+fn non_empty(value: &str) -> Option<&str> {
+    Some(value).filter(|v| !v.is_empty())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VisibilityFilter {
+    #[default]
+    All,
+    CollaborativeOnly,
+    /// Playlists with unknown visibility are excluded.
+    PrivateOnly,
+    /// Playlists with unknown visibility are excluded.
+    PublicOnly,
+}
+
+pub fn filter_playlists_by_visibility(
+    playlists: Vec<Playlist>,
+    visibility: VisibilityFilter,
+) -> Vec<Playlist> {
+    playlists
+        .into_iter()
+        .filter(|playlist| match visibility {
+            VisibilityFilter::All => true,
+            VisibilityFilter::CollaborativeOnly => playlist.collaborative,
+            VisibilityFilter::PrivateOnly => playlist.public == Some(false),
+            VisibilityFilter::PublicOnly => playlist.public == Some(true),
+        })
+        .collect()
+}
+
+/// Splits playlists into those owned by `user_id` and those only followed.
+pub fn partition_playlists(
+    playlists: Vec<Playlist>,
+    user_id: &str,
+) -> (Vec<Playlist>, Vec<Playlist>) {
+    playlists
+        .into_iter()
+        .partition(|playlist| playlist.owner.id == user_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::tests::sample_record;
+
+    fn with_popularity(uri: &str, popularity: Option<u8>) -> TrackRecord {
+        let mut record = sample_record(uri, uri);
+        record.popularity = popularity;
+        record
+    }
+
+    fn uris(records: &[TrackRecord]) -> Vec<&str> {
+        records
+            .iter()
+            .map(|record| record.track_uri.as_deref().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn unknown_popularity_passes_unless_strict() {
+        let records = vec![
+            with_popularity("spotify:track:low", Some(10)),
+            with_popularity("spotify:track:high", Some(80)),
+            with_popularity("spotify:track:unknown", None),
+        ];
+        let filter = TrackFilter {
+            min_popularity: Some(50),
+            ..TrackFilter::default()
+        };
+        assert_eq!(
+            uris(&filter.apply(records.clone())),
+            ["spotify:track:high", "spotify:track:unknown"]
+        );
+
+        let strict = TrackFilter {
+            strict: true,
+            ..filter
+        };
+        assert_eq!(uris(&strict.apply(records.clone())), ["spotify:track:high"]);
+
+        // Without a threshold, strictness has nothing to act on.
+        let unfiltered = TrackFilter {
+            strict: true,
+            ..TrackFilter::default()
+        };
+        assert_eq!(unfiltered.apply(records).len(), 3);
+    }
+
+    #[test]
+    fn unknown_popularity_sorts_last_either_way() {
+        let mut records = vec![
+            with_popularity("spotify:track:a", None),
+            with_popularity("spotify:track:b", Some(40)),
+            with_popularity("spotify:track:c", None),
+            with_popularity("spotify:track:d", Some(90)),
+            with_popularity("spotify:track:e", Some(0)),
+        ];
+
+        sort_records(&mut records, SortKey::Popularity, false);
+        assert_eq!(
+            uris(&records),
+            [
+                "spotify:track:e",
+                "spotify:track:b",
+                "spotify:track:d",
+                "spotify:track:a",
+                "spotify:track:c",
+            ]
+        );
+
+        sort_records(&mut records, SortKey::Popularity, true);
+        assert_eq!(
+            uris(&records),
+            [
+                "spotify:track:d",
+                "spotify:track:b",
+                "spotify:track:e",
+                "spotify:track:a",
+                "spotify:track:c",
+            ]
+        );
+    }
+
+    #[test]
+    fn compares_known_values_before_unknown_ones() {
+        assert_eq!(compare_known(&Some(1), &None, false), Ordering::Less);
+        assert_eq!(compare_known(&Some(1), &None, true), Ordering::Less);
+        assert_eq!(compare_known(&None, &Some(1), true), Ordering::Greater);
+        assert_eq!(compare_known::<u8>(&None, &None, true), Ordering::Equal);
+        assert_eq!(compare_known(&Some(1), &Some(2), false), Ordering::Less);
+        assert_eq!(compare_known(&Some(1), &Some(2), true), Ordering::Greater);
+    }
+}
@@ -0,0 +1,167 @@
+use chrono::{DateTime, FixedOffset};
+use csv::Writer;
+use log::{info, warn};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    index::{read_index, RunIndex, INDEX_JSON},
+    paths::OutputPaths,
+};
+
+/// A run's timestamp and its index.
+pub type Run = (DateTime<FixedOffset>, RunIndex);
+
+/// The latest recorded count and the one before it, if any.
+pub struct FollowerChange {
+    pub latest: u64,
+    pub previous: Option<(u64, DateTime<FixedOffset>)>,
+}
+
+/// One playlist's follower counts, one point per run since it first appeared.
+/// A `None` count is a gap: the run did not record it.
+#[derive(Debug)]
+pub struct FollowerSeries {
+    pub name: String,
+    pub points: Vec<(DateTime<FixedOffset>, Option<u64>)>,
+}
+
+impl FollowerSeries {
+    /// Compares the last two recorded counts, skipping gaps.
+    pub fn latest_change(&self) -> Option<FollowerChange> {
+        let mut known = self
+            .points
+            .iter()
+            .rev()
+            .filter_map(|(date, count)| count.map(|c| (c, *date)));
+        let (latest, _) = known.next()?;
+        Some(FollowerChange {
+            latest,
+            previous: known.next(),
+        })
+    }
+}
+
+impl fmt::Display for FollowerSeries {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.latest_change() {
+            Some(FollowerChange {
+                latest,
+                previous: Some((previous, since)),
+            }) => write!(
+                f,
+                "{}: {} followers ({:+} since {})",
+                self.name,
+                latest,
+                latest as i64 - previous as i64,
+                since.date_naive()
+            ),
+            Some(FollowerChange {
+                latest,
+                previous: None,
+            }) => write!(f, "{}: {} followers (no earlier data)", self.name, latest),
+            None => write!(f, "{}: no follower data", self.name),
+        }
+    }
+}
+
+/// Finds every run index under `root`, oldest first. Runs are expected in
+/// dated subdirectories, but any depth works.
+pub fn find_run_indexes(root: &Path) -> Result<Vec<Run>, Box<dyn Error>> {
+    let mut paths = Vec::new();
+    collect_index_paths(root, &mut paths)?;
+
+    let mut runs = Vec::with_capacity(paths.len());
+    for path in paths {
+        let mut index = read_index(&path)?;
+        // Nothing comparing runs reads these, and over hundreds of runs of
+        // a large library they are most of the memory held.
+        index.provenance = None;
+        index
+            .playlists
+            .iter_mut()
+            .for_each(|entry| entry.description = None);
+        match DateTime::parse_from_rfc3339(&index.exported_at) {
+            Ok(date) => runs.push((date, index)),
+            Err(e) => warn!("Skipping {}: bad exported_at: {}", path.display(), e),
+        }
+    }
+    runs.sort_by_key(|(date, _)| *date);
+    Ok(runs)
+}
+
+fn collect_index_paths(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
+    for entry in fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_index_paths(&path, paths)?;
+        } else if path.file_name().is_some_and(|name| name == INDEX_JSON) {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Builds a series per playlist, keyed by ID so renames do not split it,
+/// and named after the playlist's most recent name. Runs after a playlist
+/// first appeared that did not include it become gaps.
+pub fn follower_series(runs: &[Run]) -> Vec<FollowerSeries> {
+    struct Seen {
+        name: String,
+        first_run: usize,
+        counts: BTreeMap<usize, Option<u64>>,
+    }
+
+    let mut playlists: BTreeMap<&str, Seen> = BTreeMap::new();
+    for (run, (_, index)) in runs.iter().enumerate() {
+        for entry in &index.playlists {
+            let key = if entry.id.is_empty() {
+                entry.name.as_str()
+            } else {
+                entry.id.as_str()
+            };
+            let seen = playlists.entry(key).or_insert_with(|| Seen {
+                name: String::new(),
+                first_run: run,
+                counts: BTreeMap::new(),
+            });
+            seen.name = entry.name.clone();
+            seen.counts.insert(run, entry.followers);
+        }
+    }
+
+    playlists
+        .into_values()
+        .map(|seen| FollowerSeries {
+            name: seen.name,
+            points: (seen.first_run..runs.len())
+                .map(|run| (runs[run].0, seen.counts.get(&run).copied().flatten()))
+                .collect(),
+        })
+        .collect()
+}
+
+/// Writes `<playlist> followers.csv` for each series; gaps are empty cells.
+pub fn write_follower_series(
+    series: &[FollowerSeries],
+    paths: &mut OutputPaths,
+) -> Result<(), Box<dyn Error>> {
+    for playlist in series {
+        let file_name = paths.reserve(&format!("{} followers", playlist.name), "csv");
+        let mut writer = Writer::from_path(paths.path(&file_name))?;
+        writer.write_record(["Date", "Followers"])?;
+        for (date, count) in &playlist.points {
+            writer.write_record([
+                date.date_naive().to_string(),
+                count.map(|c| c.to_string()).unwrap_or_default(),
+            ])?;
+        }
+        writer.flush()?;
+        info!("Finished writing: {}", file_name);
+    }
+    Ok(())
+}
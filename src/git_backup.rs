@@ -0,0 +1,227 @@
+//! Committing each export to a git repository, so the repository's history
+//! becomes a history of the library. Uses the `git` executable rather than
+//! a library, so the user's own git configuration applies.
+//!
+//! Only the files the run wrote are staged, never the whole directory: the
+//! output directory is also where `setup` leaves its profile, which holds
+//! the access token.
+
+use log::{info, warn};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    artwork::ARTWORK_DIR,
+    index::RunIndex,
+    paths::{OutputPaths, RESERVED_NAMES},
+    setup::SETUP_ENV,
+};
+
+const GITIGNORE: &str = ".gitignore";
+
+/// Runs `git -C <repo> <args>`, failing with its stderr.
+fn git(repo: &Path, args: &[&str]) -> Result<String, Box<dyn Error>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()
+        .map_err(|e| format!("could not run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// "Backup: N playlists, M tracks, YYYY-MM-DD", then the playlists whose
+/// snapshot ID differs from `previous`, the index of the last run.
+fn commit_message(index: &RunIndex, previous: Option<&RunIndex>) -> String {
+    let tracks: usize = index.playlists.iter().map(|entry| entry.track_count).sum();
+    let mut message = format!(
+        "Backup: {} playlists, {} tracks, {}",
+        index.playlists.len(),
+        tracks,
+        chrono::Local::now().format("%Y-%m-%d")
+    );
+
+    let before: HashMap<&str, &Option<String>> = previous
+        .iter()
+        .flat_map(|previous| &previous.playlists)
+        .map(|old| (old.id.as_str(), &old.snapshot_id))
+        .collect();
+    let changed: Vec<String> = index
+        .playlists
+        .iter()
+        .filter(|entry| before.get(entry.id.as_str()) != Some(&&entry.snapshot_id))
+        .map(|entry| match &entry.snapshot_id {
+            Some(snapshot) => format!("- {} ({})", entry.name, snapshot),
+            None => format!("- {}", entry.name),
+        })
+        .collect();
+    if !changed.is_empty() {
+        message.push_str("\n\nChanged playlists:\n");
+        message.push_str(&changed.join("\n"));
+    }
+    message
+}
+
+/// The files an export run wrote into the current directory: every name
+/// `paths` handed out, the library-wide artifacts and the album covers.
+pub fn backup_files(paths: &OutputPaths) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = paths
+        .claimed()
+        .iter()
+        .map(|file_name| paths.path(file_name))
+        .collect();
+    files.extend(
+        RESERVED_NAMES
+            .iter()
+            .filter(|name| !paths.claimed().iter().any(|claimed| claimed == *name))
+            .map(PathBuf::from)
+            .filter(|path| path.is_file()),
+    );
+    let artwork = PathBuf::from(ARTWORK_DIR);
+    if artwork.is_dir() {
+        files.push(artwork);
+    }
+    files
+}
+
+/// Makes sure `repo`'s .gitignore excludes the setup profile, so not even
+/// a `git add .` by hand commits the token. Returns its path.
+fn ignore_profile(repo: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let path = repo.join(GITIGNORE);
+    let mut contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("{}: {}", path.display(), e).into()),
+    };
+    if !contents.lines().any(|line| line.trim() == SETUP_ENV) {
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            contents.push('\n');
+        }
+        contents.push_str(SETUP_ENV);
+        contents.push('\n');
+        fs::write(&path, contents).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    Ok(path)
+}
+
+/// Commits `files` in `repo`, creating the repository first if needed.
+/// Files outside the repository are left out with a warning. A run that
+/// changed nothing makes no commit.
+pub fn commit_backup(
+    repo: &Path,
+    files: &[PathBuf],
+    index: &RunIndex,
+    previous: Option<&RunIndex>,
+) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(repo).map_err(|e| format!("{}: {}", repo.display(), e))?;
+    if git(repo, &["rev-parse", "--git-dir"]).is_err() {
+        git(repo, &["init", "--quiet"])?;
+        info!("Created a git repository in {}", repo.display());
+    }
+    let root = repo
+        .canonicalize()
+        .map_err(|e| format!("{}: {}", repo.display(), e))?;
+    let mut staged = vec![ignore_profile(&root)?];
+    for file in files {
+        if file.file_name().is_some_and(|name| name == SETUP_ENV) {
+            warn!(
+                "Not backing up {}: it holds the access token",
+                file.display()
+            );
+            continue;
+        }
+        let Ok(absolute) = file.canonicalize() else {
+            warn!("Not backing up {}: it is gone", file.display());
+            continue;
+        };
+        if !absolute.starts_with(&root) {
+            warn!(
+                "Not backing up {}: it is outside {}",
+                file.display(),
+                repo.display()
+            );
+            continue;
+        }
+        staged.push(absolute);
+    }
+    let mut args = vec!["add".to_string(), "--all".to_string(), "--".to_string()];
+    args.extend(staged.iter().filter_map(|path| {
+        path.strip_prefix(&root)
+            .ok()
+            .map(|relative| relative.to_string_lossy().into_owned())
+    }));
+    git(&root, &args.iter().map(String::as_str).collect::<Vec<_>>())?;
+    if git(repo, &["diff", "--cached", "--name-only"])?
+        .trim()
+        .is_empty()
+    {
+        info!("Nothing changed since the last backup commit");
+        return Ok(());
+    }
+    git(
+        repo,
+        &["commit", "--quiet", "-m", &commit_message(index, previous)],
+    )?;
+    info!("Committed the backup to {}", repo.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    fn git_in(repo: &Path, args: &[&str]) -> String {
+        git(repo, args).unwrap()
+    }
+
+    #[test]
+    fn never_stages_the_setup_profile() {
+        let dir = TestDir::new();
+        let repo = dir.path();
+        git_in(repo, &["init", "--quiet"]);
+        git_in(repo, &["config", "user.name", "Test"]);
+        git_in(repo, &["config", "user.email", "test@example.com"]);
+        fs::write(repo.join(SETUP_ENV), "export SPOTIFY_TOKEN=secret\n").unwrap();
+        fs::write(repo.join("Road Trip.csv"), "Track Name\n").unwrap();
+        fs::write(repo.join("notes.txt"), "not written by the export\n").unwrap();
+
+        let files = [repo.join("Road Trip.csv"), repo.join(SETUP_ENV)];
+        let index = RunIndex {
+            exported_at: String::new(),
+            provenance: None,
+            playlists: Vec::new(),
+            summary: None,
+        };
+        commit_backup(repo, &files, &index, None).unwrap();
+
+        let tracked = git_in(repo, &["ls-files"]);
+        let tracked: Vec<&str> = tracked.lines().collect();
+        assert_eq!(tracked, [GITIGNORE, "Road Trip.csv"]);
+        let ignore = fs::read_to_string(repo.join(GITIGNORE)).unwrap();
+        assert!(ignore.lines().any(|line| line == SETUP_ENV));
+    }
+
+    #[test]
+    fn keeps_an_existing_gitignore() {
+        let dir = TestDir::new();
+        fs::write(dir.path().join(GITIGNORE), "*.log").unwrap();
+        ignore_profile(dir.path()).unwrap();
+        ignore_profile(dir.path()).unwrap();
+        let ignore = fs::read_to_string(dir.path().join(GITIGNORE)).unwrap();
+        assert_eq!(ignore, format!("*.log\n{}\n", SETUP_ENV));
+    }
+}
@@ -0,0 +1,96 @@
+use clap::Args;
+use reqwest::{Certificate, Client};
+use std::{error::Error, fs, path::PathBuf, time::Duration};
+
+/// Longest a single request may take before it counts as timed out.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// TLS settings shared by every outbound client, so an option given once
+/// applies to Spotify and any other service the tool talks to.
+#[derive(Debug, Clone, Default, Args)]
+pub struct HttpOptions {
+    /// Also trust the certificates in this PEM bundle (e.g. a corporate proxy's root)
+    #[arg(long, global = true, value_name = "PATH")]
+    pub ca_bundle: Option<PathBuf>,
+
+    /// Trust the operating system's certificate store instead of the bundled roots
+    #[arg(long, global = true)]
+    pub use_native_roots: bool,
+
+    /// Disable certificate verification entirely. Only for isolated testing setups
+    #[arg(long, global = true)]
+    pub insecure_skip_tls_verify: bool,
+}
+
+impl HttpOptions {
+    pub fn build_client(&self) -> Result<Client, Box<dyn Error>> {
+        let mut builder = Client::builder().use_rustls_tls().timeout(REQUEST_TIMEOUT);
+
+        if self.use_native_roots {
+            builder = builder
+                .tls_built_in_webpki_certs(false)
+                .tls_built_in_native_certs(true);
+        } else {
+            builder = builder
+                .tls_built_in_webpki_certs(true)
+                .tls_built_in_native_certs(false);
+        }
+
+        if let Some(path) = &self.ca_bundle {
+            let pem = fs::read(path)
+                .map_err(|e| format!("could not read CA bundle {}: {}", path.display(), e))?;
+            for cert in Certificate::from_pem_bundle(&pem)? {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+
+        if self.insecure_skip_tls_verify {
+            eprintln!("################################################################");
+            eprintln!("WARNING: TLS certificate verification is DISABLED.");
+            eprintln!("Anyone on the network path can read and alter this traffic,");
+            eprintln!("including your access token. Never use this outside testing.");
+            eprintln!("################################################################");
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+
+        Ok(builder.build()?)
+    }
+}
+
+/// Turns a failed `send()` into an error that names the host and, for
+/// certificate problems, points at the flags that usually fix them.
+pub fn explain_send_error(err: reqwest::Error) -> Box<dyn Error> {
+    if !is_certificate_error(&err) {
+        return Box::new(err);
+    }
+
+    let host = err
+        .url()
+        .and_then(|url| url.host_str())
+        .unwrap_or("unknown host")
+        .to_string();
+    format!(
+        "TLS certificate verification failed for {}: {}\n\
+         hint: behind a TLS-intercepting proxy, pass --ca-bundle <path> with the proxy's \
+         root certificate, or --use-native-roots to use the system trust store",
+        host,
+        error_chain(&err)
+    )
+    .into()
+}
+
+fn is_certificate_error(err: &reqwest::Error) -> bool {
+    let chain = error_chain(err).to_lowercase();
+    chain.contains("certificate") || chain.contains("unknownissuer")
+}
+
+fn error_chain(err: &dyn Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
@@ -0,0 +1,370 @@
+//! Checking Spotify and YouTube IDs where they enter the tool: hand-edited
+//! CSVs, override files and command-line arguments. What can be repaired,
+//! such as a link pasted where an ID belongs, is; the rest is reported
+//! with where it came from before any request is made, rather than as a
+//! 400 from the API later on.
+
+use reqwest::Url;
+use std::fmt;
+
+/// Spotify IDs are 22 base62 characters.
+const SPOTIFY_ID_LEN: usize = 22;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdKind {
+    Track,
+    Album,
+    Artist,
+    Playlist,
+    Episode,
+    Show,
+    User,
+}
+
+impl IdKind {
+    const ALL: [IdKind; 7] = [
+        IdKind::Track,
+        IdKind::Album,
+        IdKind::Artist,
+        IdKind::Playlist,
+        IdKind::Episode,
+        IdKind::Show,
+        IdKind::User,
+    ];
+
+    /// As in `spotify:<name>:` URIs and open.spotify.com paths.
+    pub fn name(self) -> &'static str {
+        match self {
+            IdKind::Track => "track",
+            IdKind::Album => "album",
+            IdKind::Artist => "artist",
+            IdKind::Playlist => "playlist",
+            IdKind::Episode => "episode",
+            IdKind::Show => "show",
+            IdKind::User => "user",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+}
+
+impl fmt::Display for IdKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The kind and ID in a `spotify:` URI or an open.spotify.com link (with
+/// or without its scheme, `?si=` or a locale prefix such as `/intl-de`).
+fn split_reference(input: &str) -> Option<(&str, String)> {
+    if let Some(rest) = input.strip_prefix("spotify:") {
+        let (kind, id) = rest.split_once(':')?;
+        return Some((kind, id.to_string()));
+    }
+    if !input.contains("open.spotify.com/") {
+        return None;
+    }
+    let url = if input.starts_with("http") {
+        Url::parse(input).ok()?
+    } else {
+        Url::parse(&format!("https://{}", input)).ok()?
+    };
+    // Older links put the owner first: /user/<owner>/playlist/<id>.
+    let segments: Vec<&str> = url.path_segments()?.collect();
+    segments
+        .windows(2)
+        .rev()
+        .find_map(|pair| Some((IdKind::from_name(pair[0])?.name(), pair[1].to_string())))
+}
+
+/// Why `id` cannot be a Spotify ID of `kind`, if it cannot. User IDs are
+/// chosen by their users, so only need to be URI-safe. With `strict`, an
+/// ID without a capital letter is also refused: in a random base62 ID that
+/// almost never happens, while a lowercased copy is a common corruption.
+fn check_id(kind: IdKind, id: &str, strict: bool) -> Result<(), String> {
+    if kind == IdKind::User {
+        return if !id.is_empty() && id.chars().all(|c| !c.is_whitespace() && c != ':') {
+            Ok(())
+        } else {
+            Err(format!("{:?} is not a Spotify user ID", id))
+        };
+    }
+    if let Some(c) = id.chars().find(|c| !c.is_ascii_alphanumeric()) {
+        return Err(format!(
+            "{:?} is not a Spotify {} ID: {:?} is not allowed",
+            id, kind, c
+        ));
+    }
+    if id.len() != SPOTIFY_ID_LEN {
+        return Err(format!(
+            "{:?} is not a Spotify {} ID: it has {} characters instead of {}",
+            id,
+            kind,
+            id.len(),
+            SPOTIFY_ID_LEN
+        ));
+    }
+    if strict && !id.chars().any(|c| c.is_ascii_uppercase()) {
+        return Err(format!(
+            "{:?} has no capital letters, so it looks lowercased; Spotify IDs are case-sensitive",
+            id
+        ));
+    }
+    Ok(())
+}
+
+/// The bare ID of `kind` in an ID, URI or link.
+pub fn normalize_id(kind: IdKind, input: &str, strict: bool) -> Result<String, String> {
+    let input = input.trim();
+    let id = match split_reference(input) {
+        Some((found, id)) if found == kind.name() => id,
+        Some((found, _)) => return Err(format!("{:?} is a {}, not a {}", input, found, kind)),
+        None => input.to_string(),
+    };
+    check_id(kind, &id, strict)?;
+    Ok(id)
+}
+
+/// The `spotify:<kind>:<id>` URI for a URI or link of any kind. Local files
+/// (`spotify:local:`) have no ID and are passed through as they are.
+pub fn normalize_uri(input: &str, strict: bool) -> Result<String, String> {
+    let input = input.trim();
+    if input.starts_with("spotify:local:") {
+        return Ok(input.to_string());
+    }
+    let (kind, id) = split_reference(input)
+        .ok_or_else(|| format!("{:?} is not a Spotify URI or link", input))?;
+    let kind = IdKind::from_name(kind)
+        .ok_or_else(|| format!("{:?} is not a Spotify URI or link", input))?;
+    check_id(kind, &id, strict)?;
+    Ok(format!("spotify:{}:{}", kind, id))
+}
+
+/// The video ID in an ID or a youtube.com or youtu.be link. Video IDs are
+/// 11 characters of letters, digits, `-` and `_`.
+pub fn normalize_video_id(input: &str) -> Result<String, String> {
+    let input = input.trim();
+    let id = match Url::parse(input) {
+        Ok(url) if url.host_str() == Some("youtu.be") => url
+            .path_segments()
+            .and_then(|mut segments| segments.next())
+            .unwrap_or_default()
+            .to_string(),
+        Ok(url) => url
+            .query_pairs()
+            .find(|(key, _)| key == "v")
+            .map(|(_, value)| value.into_owned())
+            .unwrap_or_default(),
+        Err(_) => input.to_string(),
+    };
+    let valid = id.len() == 11
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(id)
+    } else {
+        Err(format!("{:?} is not a YouTube video ID", input))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::{prelude::*, sample::select};
+
+    const ID: &str = "37i9dQZF1DXcBWIGoYBM5M";
+
+    fn playlist(input: &str) -> Result<String, String> {
+        normalize_id(IdKind::Playlist, input, false)
+    }
+
+    #[test]
+    fn reads_a_playlist_id_in_every_form() {
+        for input in [
+            ID.to_string(),
+            format!("  {}\n", ID),
+            format!("spotify:playlist:{}", ID),
+            format!("https://open.spotify.com/playlist/{}", ID),
+            format!(
+                "https://open.spotify.com/playlist/{}?si=a1b2c3d4e5f64a7b",
+                ID
+            ),
+            format!("open.spotify.com/playlist/{}?si=a1b2c3d4e5f64a7b", ID),
+            format!("https://open.spotify.com/intl-de/playlist/{}?si=x", ID),
+            format!("https://open.spotify.com/intl-pt/playlist/{}", ID),
+            format!("https://open.spotify.com/user/spotify/playlist/{}", ID),
+            format!(
+                "https://open.spotify.com/user/some.user_1/playlist/{}?si=x",
+                ID
+            ),
+        ] {
+            assert_eq!(playlist(&input).as_deref(), Ok(ID), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn refuses_other_kinds_and_broken_ids() {
+        assert_eq!(
+            playlist("spotify:track:4uLU6hMCjMI75M1A2tKUQC").unwrap_err(),
+            "\"spotify:track:4uLU6hMCjMI75M1A2tKUQC\" is a track, not a playlist"
+        );
+        assert!(playlist("https://open.spotify.com/album/4aawyAB9vmqN3uQ7FjRGTy").is_err());
+        assert!(playlist("37i9dQZF1DXcBWIGoYBM5").is_err());
+        assert!(playlist("37i9dQZF1DXcBWIGoYBM5M!").is_err());
+        assert!(playlist("").is_err());
+        // Lowercased IDs only fail strict checking.
+        assert!(normalize_id(IdKind::Playlist, &ID.to_lowercase(), false).is_ok());
+        assert!(normalize_id(IdKind::Playlist, &ID.to_lowercase(), true).is_err());
+    }
+
+    #[test]
+    fn uris_keep_their_kind() {
+        assert_eq!(
+            normalize_uri(
+                "https://open.spotify.com/intl-fr/track/4uLU6hMCjMI75M1A2tKUQC?si=1",
+                false
+            )
+            .unwrap(),
+            "spotify:track:4uLU6hMCjMI75M1A2tKUQC"
+        );
+        assert_eq!(
+            normalize_uri("spotify:local:Artist:Album:Song:180", false).unwrap(),
+            "spotify:local:Artist:Album:Song:180"
+        );
+        assert!(normalize_uri(ID, false).is_err());
+    }
+
+    /// Kinds whose IDs Spotify generates, as opposed to user names.
+    fn generated_kind() -> impl Strategy<Value = IdKind> {
+        select(
+            IdKind::ALL
+                .into_iter()
+                .filter(|kind| *kind != IdKind::User)
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn valid_ids_pass_in_every_form(id in "[0-9A-Za-z]{22}", kind in select(IdKind::ALL.to_vec())) {
+            prop_assume!(id.chars().any(|c| c.is_ascii_uppercase()));
+            for input in [
+                id.clone(),
+                format!(" {}\t", id),
+                format!("spotify:{}:{}", kind, id),
+                format!("https://open.spotify.com/{}/{}?si=0123abcd", kind, id),
+                format!("open.spotify.com/intl-ja/{}/{}", kind, id),
+            ] {
+                prop_assert_eq!(normalize_id(kind, &input, true), Ok(id.clone()), "{:?}", input);
+            }
+            prop_assert_eq!(
+                normalize_uri(&format!("https://open.spotify.com/{}/{}", kind, id), true),
+                Ok(format!("spotify:{}:{}", kind, id))
+            );
+        }
+
+        #[test]
+        fn truncated_and_padded_ids_are_caught(
+            id in "[0-9A-Za-z]{22}",
+            extra in "[0-9A-Za-z]",
+            kind in generated_kind(),
+        ) {
+            let truncated = &id[..21];
+            let padded = format!("{}{}", id, extra);
+            for bad in [truncated.to_string(), padded] {
+                prop_assert!(normalize_id(kind, &bad, false).is_err(), "{:?}", bad);
+                let uri = format!("spotify:{}:{}", kind, bad);
+                prop_assert!(normalize_uri(&uri, false).is_err(), "{:?}", uri);
+            }
+        }
+
+        #[test]
+        fn lowercased_ids_are_caught_when_strict(id in "[0-9A-Za-z]{22}", kind in generated_kind()) {
+            let lowered = id.to_lowercase();
+            prop_assert!(normalize_id(kind, &lowered, true).is_err());
+            let uri = format!("spotify:{}:{}", kind, lowered);
+            prop_assert!(normalize_uri(&uri, true).is_err());
+            prop_assert_eq!(normalize_id(kind, &lowered, false), Ok(lowered));
+        }
+
+        #[test]
+        fn valid_video_ids_pass_bare_or_linked(id in "[0-9A-Za-z_-]{11}") {
+            for input in [
+                id.clone(),
+                format!("https://www.youtube.com/watch?v={}", id),
+                format!("https://www.youtube.com/watch?v={}&t=42s", id),
+                format!("https://music.youtube.com/watch?list=RD&v={}", id),
+                format!("https://youtu.be/{}?si=abc", id),
+            ] {
+                prop_assert_eq!(normalize_video_id(&input), Ok(id.clone()), "{:?}", input);
+            }
+        }
+
+        #[test]
+        fn video_ids_of_the_wrong_length_are_caught(id in "[0-9A-Za-z_-]{1,10}|[0-9A-Za-z_-]{12,20}") {
+            prop_assert!(normalize_video_id(&id).is_err());
+            let linked = format!("https://www.youtube.com/watch?v={}", id);
+            prop_assert!(normalize_video_id(&linked).is_err());
+        }
+    }
+
+    /// Inputs seen in hand-edited CSVs, takeout files and overrides.
+    #[test]
+    fn catches_observed_bad_inputs() {
+        for (kind, bad) in [
+            (IdKind::Playlist, "37i9dQZF1DXcBWIGoYBM5"),
+            (IdKind::Playlist, "spotify:playlist:37i9dQZF1DXcBWIGoYBM5"),
+            (IdKind::Playlist, "37i9dQZF1DXcBWIGoYBM5M%20"),
+            (
+                IdKind::Playlist,
+                "https://www.youtube.com/playlist?list=PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI",
+            ),
+            (IdKind::Playlist, "https://open.spotify.com/"),
+            (
+                IdKind::Track,
+                "https://open.spotify.com/album/4aawyAB9vmqN3uQ7FjRGTy",
+            ),
+            (IdKind::Track, "4uLU6hMCjMI75M1A2tKUQC,"),
+            (IdKind::Track, "spotify:track:"),
+            (
+                IdKind::Artist,
+                "0OdUWJ0sBjDrqHygGUXeCF0OdUWJ0sBjDrqHygGUXeCF",
+            ),
+            (IdKind::User, "spotify:user:"),
+            (IdKind::User, "some user"),
+        ] {
+            assert!(
+                normalize_id(kind, bad, false).is_err(),
+                "{} {:?}",
+                kind,
+                bad
+            );
+        }
+        for bad in [
+            "spotify:tracks:4uLU6hMCjMI75M1A2tKUQC",
+            "spotify:track:4uLU6hMCjMI75M1A2tKUQ",
+            "4uLU6hMCjMI75M1A2tKUQC",
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+            "",
+        ] {
+            assert!(normalize_uri(bad, false).is_err(), "{:?}", bad);
+        }
+        assert!(normalize_uri("spotify:track:4ulu6hmcjmi75m1a2tkuqc", true).is_err());
+        for bad in [
+            "dQw4w9WgXc",
+            "dQw4w9WgXcQQ",
+            "dQw4w9WgXc!",
+            "dQw4w9WgXc Q",
+            "https://www.youtube.com/watch?feature=share",
+            "https://youtu.be/",
+            "https://www.youtube.com/watch?v=dQw4w9WgXc",
+            "spotify:track:4uLU6hMCjMI75M1A2tKUQC",
+            "",
+        ] {
+            assert!(normalize_video_id(bad).is_err(), "{:?}", bad);
+        }
+    }
+}
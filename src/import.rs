@@ -0,0 +1,221 @@
+//! Writing an exported playlist back to Spotify: the rows of a CSV become a
+//! new playlist in the token's account, or the new contents of an existing
+//! one, in the same order.
+
+use log::{info, warn};
+use std::{
+    collections::HashSet,
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    api::SpotifyAPI, ids::normalize_uri, passport::warn_if_other_account,
+    record::read_numbered_track_records,
+};
+
+/// Where imported tracks go.
+#[derive(Debug)]
+pub enum ImportTarget<'a> {
+    New {
+        name: &'a str,
+        public: bool,
+        description: &'a str,
+    },
+    /// Overwrite this playlist (ID, URI or link).
+    Replace(&'a str),
+}
+
+/// What an import wrote.
+#[derive(Debug)]
+pub struct ImportOutcome {
+    pub playlist_id: String,
+    pub added: usize,
+}
+
+/// Local files have URIs too, but only the app that added them can add them.
+fn is_addable(uri: &str) -> bool {
+    uri.starts_with("spotify:track:") || uri.starts_with("spotify:episode:")
+}
+
+/// The track and episode URIs in the CSV at `path`, in order, with links
+/// turned into URIs. Fails before anything is sent, naming every line,
+/// when a URI cannot be valid; rows without one are skipped with a warning.
+fn read_addable_uris(path: &Path, strict_ids: bool) -> Result<Vec<String>, Box<dyn Error>> {
+    let records =
+        read_numbered_track_records(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut uris = Vec::with_capacity(records.len());
+    let mut problems = Vec::new();
+    for (line, record) in &records {
+        let Some(uri) = record
+            .track_uri
+            .as_deref()
+            .filter(|uri| !uri.trim().is_empty())
+        else {
+            continue;
+        };
+        match normalize_uri(uri, strict_ids) {
+            Ok(uri) if is_addable(&uri) => uris.push(uri),
+            Ok(_) => {}
+            Err(e) => problems.push(format!("{} line {}: {}", path.display(), line, e)),
+        }
+    }
+    if !problems.is_empty() {
+        return Err(problems.join("\n").into());
+    }
+    let skipped = records.len() - uris.len();
+    if skipped > 0 {
+        warn!(
+            "{}: skipping {} rows without a Spotify track or episode URI (local files cannot be added)",
+            path.display(),
+            skipped
+        );
+    }
+    Ok(uris)
+}
+
+/// Writes the track URIs in the CSV at `path` to `target`. Nothing is
+/// created or replaced when the CSV has no track that can be added.
+pub async fn import_playlist(
+    api: &SpotifyAPI,
+    path: &Path,
+    target: ImportTarget<'_>,
+    strict_ids: bool,
+) -> Result<ImportOutcome, Box<dyn Error>> {
+    let uris = read_addable_uris(path, strict_ids)?;
+    let uris: Vec<&str> = uris.iter().map(String::as_str).collect();
+    if uris.is_empty() {
+        return Err(format!("{} has no tracks that can be added", path.display()).into());
+    }
+
+    if let Some(dir) = path.parent() {
+        if !api.is_public() {
+            warn_if_other_account(dir, &api.get_current_user().await?.id);
+        }
+    }
+
+    let playlist_id = match target {
+        ImportTarget::New {
+            name,
+            public,
+            description,
+        } => {
+            api.create_playlist_from_tracks(name, description, &uris, public)
+                .await?
+        }
+        ImportTarget::Replace(playlist) => {
+            api.replace_playlist_tracks(playlist, &uris)
+                .await
+                .map_err(|e| format!("could not replace the tracks of {}: {}", playlist, e))?;
+            playlist.to_string()
+        }
+    };
+    Ok(ImportOutcome {
+        playlist_id,
+        added: uris.len(),
+    })
+}
+
+/// Creates a playlist from the rows of every CSV in `files`, in order. A
+/// track in more than one file is added once, where it first appears.
+pub async fn create_from_csvs(
+    api: &SpotifyAPI,
+    files: &[PathBuf],
+    name: &str,
+    description: &str,
+    public: bool,
+    strict_ids: bool,
+) -> Result<ImportOutcome, Box<dyn Error>> {
+    // Every file is checked before anything is created.
+    let mut all = Vec::new();
+    for path in files {
+        all.extend(read_addable_uris(path, strict_ids)?);
+    }
+    let mut seen = HashSet::new();
+    let uris: Vec<&str> = all
+        .iter()
+        .map(String::as_str)
+        .filter(|uri| seen.insert(*uri))
+        .collect();
+    if uris.is_empty() {
+        return Err("the given CSVs have no tracks that can be added".into());
+    }
+    if uris.len() < all.len() {
+        info!(
+            "Adding {} tracks found in more than one place once each",
+            all.len() - uris.len()
+        );
+    }
+    let playlist_id = api
+        .create_playlist_from_tracks(name, description, &uris, public)
+        .await?;
+    Ok(ImportOutcome {
+        playlist_id,
+        added: uris.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        record::{tests::sample_record, write_track_records, DEFAULT_FIELDS},
+        testdir::TestDir,
+    };
+
+    fn write_csv(dir: &TestDir, uris: &[&str]) -> PathBuf {
+        let path = dir.path().join("Road Trip.csv");
+        let records: Vec<_> = uris.iter().map(|uri| sample_record(uri, "Song")).collect();
+        write_track_records(&path, &records, &DEFAULT_FIELDS, None).unwrap();
+        path
+    }
+
+    #[test]
+    fn links_become_uris_and_local_files_are_skipped() {
+        let dir = TestDir::new();
+        let path = write_csv(
+            &dir,
+            &[
+                "spotify:track:4uLU6hMCjMI75M1A2tKUQC",
+                "https://open.spotify.com/intl-de/track/0VjIjW4GlUZAMYd2vXMi3b?si=1",
+                "spotify:local:Artist:Album:Song:200",
+                "",
+                "spotify:episode:512ojhOuo1ktJprKbVcKyQ",
+            ],
+        );
+
+        assert_eq!(
+            read_addable_uris(&path, true).unwrap(),
+            vec![
+                "spotify:track:4uLU6hMCjMI75M1A2tKUQC",
+                "spotify:track:0VjIjW4GlUZAMYd2vXMi3b",
+                "spotify:episode:512ojhOuo1ktJprKbVcKyQ",
+            ]
+        );
+    }
+
+    #[test]
+    fn every_bad_uri_is_reported_with_its_line() {
+        let dir = TestDir::new();
+        let path = write_csv(
+            &dir,
+            &[
+                "spotify:track:4uLU6hMCjMI75M1A2tKUQC",
+                "spotify:track:4uLU6hMCjMI75M1A2tKUQ",
+                "4uLU6hMCjMI75M1A2tKUQC",
+                "spotify:track:0vjijw4gluzamyd2vxmi3b",
+            ],
+        );
+
+        let error = read_addable_uris(&path, false).unwrap_err().to_string();
+        let lines: Vec<&str> = error.lines().collect();
+        assert_eq!(lines.len(), 2, "{:?}", error);
+        assert!(lines[0].starts_with(&format!("{} line 3: ", path.display())));
+        assert!(lines[0].contains("21 characters"));
+        assert!(lines[1].starts_with(&format!("{} line 4: ", path.display())));
+
+        let error = read_addable_uris(&path, true).unwrap_err().to_string();
+        assert_eq!(error.lines().count(), 3, "{:?}", error);
+        assert!(error.contains("line 5: ") && error.contains("lowercased"));
+    }
+}
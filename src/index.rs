@@ -0,0 +1,231 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs::File,
+    io::BufReader,
+    path::Path,
+};
+
+use crate::{
+    atomic::write_json_atomic,
+    export::PlaylistExport,
+    provenance::Provenance,
+    spotify::{ExternalUrls, Playlist},
+    urls::output_url,
+};
+
+pub const INDEX_JSON: &str = "index.json";
+
+/// What a single export run wrote, kept next to its output so later tools
+/// can compare runs without contacting the API.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct RunIndex {
+    /// RFC 3339 timestamp of the run.
+    pub exported_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+    pub playlists: Vec<IndexEntry>,
+    /// Absent from runs made before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<RunSummary>,
+}
+
+/// How a run went, for the `dashboard` of scheduled runs.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RunSummary {
+    pub duration_secs: u64,
+    pub requests: u32,
+    pub rate_limited: u32,
+    /// Entries written to export_warnings.json.
+    pub warnings: usize,
+    /// Playlists with tracks missing from their output.
+    pub incomplete_playlists: usize,
+    pub out_of_space: bool,
+    /// Cleaned up at the start of the run after an earlier one crashed;
+    /// see `recovery`.
+    #[serde(default)]
+    pub removed_temp_files: usize,
+    #[serde(default)]
+    pub interrupted_writes: usize,
+    #[serde(default)]
+    pub missing_files: usize,
+    /// Time spent waiting on Spotify outages.
+    #[serde(default)]
+    pub outage_pause_secs: u64,
+    /// An outage outlasted `--max-outage-wait` and stopped the run.
+    #[serde(default)]
+    pub outage_stop: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct IndexEntry {
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    pub owner: String,
+    pub track_count: usize,
+    /// The playlist file, relative to the index. Absent from older runs
+    /// and from formats without one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub snapshot_id: Option<String>,
+    /// Only recorded with `--include-followers`, so older runs and runs
+    /// without it leave gaps.
+    #[serde(default)]
+    pub followers: Option<u64>,
+    /// Sharing details, for `share-pack`. Absent from older runs.
+    #[serde(default)]
+    pub collaborative: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cover_url: Option<String>,
+}
+
+impl RunIndex {
+    /// Copies the previous run's entries for `playlists`, which this run
+    /// left as they were.
+    pub fn carry_forward(&mut self, previous: &RunIndex, playlists: &[Playlist]) {
+        let ids: HashSet<&str> = playlists
+            .iter()
+            .map(|playlist| playlist.id.as_str())
+            .collect();
+        self.playlists.extend(
+            previous
+                .playlists
+                .iter()
+                .filter(|entry| ids.contains(entry.id.as_str()))
+                .cloned(),
+        );
+    }
+
+    /// Puts back the previous run's entries for the playlists `ids`, whose
+    /// files this run left as they were, or drops them when there is none.
+    pub fn restore(&mut self, previous: Option<&RunIndex>, ids: &[String]) {
+        if ids.is_empty() {
+            return;
+        }
+        let ids: HashSet<&str> = ids.iter().map(String::as_str).collect();
+        let old_entries: HashMap<&str, &IndexEntry> = previous
+            .iter()
+            .flat_map(|previous| &previous.playlists)
+            .map(|old| (old.id.as_str(), old))
+            .collect();
+        self.playlists.retain_mut(|entry| {
+            if !ids.contains(entry.id.as_str()) {
+                return true;
+            }
+            match old_entries.get(entry.id.as_str()) {
+                Some(old) => {
+                    *entry = (*old).clone();
+                    true
+                }
+                None => false,
+            }
+        });
+    }
+
+    /// The index of `exports`. Playlist, owner and cover links are cleaned
+    /// like the records' URLs unless `keep_url_params` is set.
+    pub fn from_exports(
+        exports: &[PlaylistExport],
+        provenance: &Provenance,
+        keep_url_params: bool,
+    ) -> Self {
+        let url = |url: Option<String>| url.map(|url| output_url(&url, keep_url_params));
+        let playlists = exports
+            .iter()
+            .map(|export| IndexEntry {
+                id: export.playlist.id.clone(),
+                name: export.playlist.name.clone(),
+                owner: export.playlist.owner.display_name.clone(),
+                track_count: export.tracks.len(),
+                file: export.file.clone(),
+                description: export.playlist.description.clone(),
+                snapshot_id: export.playlist.snapshot_id.clone(),
+                followers: export.playlist.followers_count,
+                collaborative: export.playlist.collaborative,
+                url: url(spotify_url(&export.playlist.external_urls)),
+                owner_url: url(spotify_url(&export.playlist.owner.external_urls)),
+                cover_url: url(export
+                    .playlist
+                    .images
+                    .iter()
+                    .flatten()
+                    .next()
+                    .map(|image| image.url.clone())),
+            })
+            .collect();
+
+        Self {
+            exported_at: chrono::Utc::now().to_rfc3339(),
+            provenance: Some(provenance.clone()),
+            playlists,
+            summary: None,
+        }
+    }
+}
+
+fn spotify_url(urls: &Option<ExternalUrls>) -> Option<String> {
+    urls.as_ref().and_then(|urls| urls.spotify.clone())
+}
+
+pub fn write_index(path: &Path, index: &RunIndex) -> Result<(), Box<dyn Error>> {
+    write_json_atomic(path, index)
+}
+
+pub fn read_index(path: &Path) -> Result<RunIndex, Box<dyn Error>> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(serde_json::from_reader(BufReader::new(file))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, track_count: usize) -> IndexEntry {
+        IndexEntry {
+            id: id.to_string(),
+            name: id.to_string(),
+            owner: "owner".to_string(),
+            track_count,
+            file: None,
+            description: None,
+            snapshot_id: Some(format!("{}-{}", id, track_count)),
+            followers: None,
+            collaborative: false,
+            url: None,
+            owner_url: None,
+            cover_url: None,
+        }
+    }
+
+    fn index(playlists: Vec<IndexEntry>) -> RunIndex {
+        RunIndex {
+            exported_at: String::new(),
+            provenance: None,
+            playlists,
+            summary: None,
+        }
+    }
+
+    #[test]
+    fn restore_puts_back_what_kept_files_hold() {
+        let previous = index(vec![entry("a", 1), entry("b", 2)]);
+        let mut current = index(vec![entry("a", 5), entry("b", 6), entry("c", 7)]);
+        current.restore(Some(&previous), &["b".to_string(), "c".to_string()]);
+        let counts: Vec<(&str, usize)> = current
+            .playlists
+            .iter()
+            .map(|entry| (entry.id.as_str(), entry.track_count))
+            .collect();
+        assert_eq!(counts, [("a", 5), ("b", 2)]);
+    }
+}
@@ -0,0 +1,61 @@
+//! `list --compare-previous`: which playlists the library gained, lost or
+//! changed since the last such listing, which saves the playlist list it
+//! fetched for the next one to compare against.
+
+use std::{collections::HashMap, error::Error, fs::File, io::BufReader, path::Path};
+
+use crate::{atomic::write_json_atomic, spotify::Playlist};
+
+pub const PLAYLISTS_JSON: &str = "playlists.json";
+
+#[derive(Debug, Default)]
+pub struct PlaylistListDiff<'a> {
+    /// In the new list only: created or followed since.
+    pub added: Vec<&'a Playlist>,
+    /// In the old list only: deleted or unfollowed since.
+    pub removed: Vec<&'a Playlist>,
+    /// Old and new versions of playlists whose snapshot ID changed.
+    pub changed: Vec<(&'a Playlist, &'a Playlist)>,
+}
+
+impl PlaylistListDiff<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Matches playlists by ID, keeping each list's order.
+pub fn diff_playlist_lists<'a>(old: &'a [Playlist], new: &'a [Playlist]) -> PlaylistListDiff<'a> {
+    let old_by_id: HashMap<&str, &Playlist> = old
+        .iter()
+        .map(|playlist| (playlist.id.as_str(), playlist))
+        .collect();
+    let new_by_id: HashMap<&str, &Playlist> = new
+        .iter()
+        .map(|playlist| (playlist.id.as_str(), playlist))
+        .collect();
+    let mut diff = PlaylistListDiff::default();
+    for playlist in new {
+        match old_by_id.get(playlist.id.as_str()) {
+            None => diff.added.push(playlist),
+            Some(before) if before.snapshot_id != playlist.snapshot_id => {
+                diff.changed.push((before, playlist))
+            }
+            Some(_) => {}
+        }
+    }
+    diff.removed = old
+        .iter()
+        .filter(|playlist| !new_by_id.contains_key(playlist.id.as_str()))
+        .collect();
+    diff
+}
+
+pub fn read_playlist_snapshot(path: &Path) -> Result<Vec<Playlist>, Box<dyn Error>> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(serde_json::from_reader(BufReader::new(file))?)
+}
+
+pub fn write_playlist_snapshot(path: &Path, playlists: &[Playlist]) -> Result<(), Box<dyn Error>> {
+    write_json_atomic(path, playlists)
+}
@@ -0,0 +1,425 @@
+//! Noticing hand edits to exported CSVs before an export overwrites them.
+//! The previous run's manifest.json holds the hash of each CSV as written
+//! and the tracks it held; a CSV that no longer matches its hash has been
+//! edited, and `--on-local-edit` decides what happens to it.
+
+use clap::ValueEnum;
+use log::warn;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    error::Error,
+    io::{self, BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{
+    atomic::write_json_atomic,
+    manifest::{read_manifest, sha256_file, Manifest, ManifestEntry},
+    record::{read_track_records, TrackRecord},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LocalEditPolicy {
+    /// Ask for each edited file; without a terminal to ask on, the export
+    /// fails at the first one
+    #[default]
+    Ask,
+    /// Leave the edited file as it is
+    KeepLocal,
+    /// Replace it with the export, dropping the edits
+    Overwrite,
+    /// Add and remove the rows the playlist gained and lost, keeping the
+    /// edited values and the rows deleted by hand
+    Merge,
+}
+
+/// What to write to a playlist's CSV.
+#[derive(Debug)]
+pub enum Resolution {
+    /// The exported records.
+    Upstream,
+    /// Nothing; the file keeps its local edits.
+    KeepLocal,
+    /// The exported rows merged into the edited file.
+    Merged(Vec<TrackRecord>),
+}
+
+/// What the manifest should say about a CSV after this run.
+#[derive(Debug, Clone)]
+pub struct FileState {
+    /// The tracks the export last wrote, as `TrackRecord::identity_key`s.
+    pub track_keys: Vec<String>,
+    /// The file holds edits the export did not write, so it is treated as
+    /// edited on the next run even when its hash matches.
+    pub local_edits: bool,
+}
+
+#[derive(Debug)]
+pub struct LocalEdits {
+    policy: LocalEditPolicy,
+    /// Whether `Ask` has a terminal to ask on.
+    interactive: bool,
+    manifest_path: PathBuf,
+    previous: HashMap<String, ManifestEntry>,
+    states: Mutex<HashMap<String, FileState>>,
+    /// Files left as they were by `Resolution::KeepLocal`.
+    kept: Mutex<HashSet<String>>,
+    /// The previous manifest with every CSV this run has written so far,
+    /// saved after each one so a run that stops early does not leave
+    /// its files looking edited by hand.
+    manifest: Mutex<Manifest>,
+}
+
+impl LocalEdits {
+    /// Reads the previous run's manifest, if there is one.
+    pub fn load(manifest: &Path, policy: LocalEditPolicy) -> Self {
+        Self::open(manifest, policy, io::stdin().is_terminal())
+    }
+
+    fn open(manifest_path: &Path, policy: LocalEditPolicy, interactive: bool) -> Self {
+        let manifest = read_manifest(manifest_path).unwrap_or(Manifest { files: Vec::new() });
+        let previous = manifest
+            .files
+            .iter()
+            .map(|entry| (entry.path.clone(), entry.clone()))
+            .collect();
+        Self {
+            policy,
+            interactive,
+            manifest_path: manifest_path.to_path_buf(),
+            previous,
+            states: Mutex::new(HashMap::new()),
+            kept: Mutex::new(HashSet::new()),
+            manifest: Mutex::new(manifest),
+        }
+    }
+
+    fn is_edited(&self, path: &Path, entry: &ManifestEntry) -> bool {
+        match sha256_file(path) {
+            Ok((hash, _)) => entry.local_edits || hash != entry.sha256,
+            // Deleted files are simply written again.
+            Err(_) => false,
+        }
+    }
+
+    /// Decides what to write to the CSV `file_name`, found at `path`, for
+    /// `upstream`, and remembers it for the manifest. A file left as it is
+    /// goes into the manifest straight away; one written must be passed to
+    /// `written` once it is.
+    pub fn resolve(
+        &self,
+        file_name: &str,
+        path: &Path,
+        upstream: &[TrackRecord],
+    ) -> Result<Resolution, Box<dyn Error>> {
+        let upstream_keys = || upstream.iter().map(TrackRecord::identity_key).collect();
+        let previous = self.previous.get(file_name);
+        let Some(entry) = previous.filter(|entry| self.is_edited(path, entry)) else {
+            self.remember(file_name, upstream_keys(), false);
+            return Ok(Resolution::Upstream);
+        };
+
+        let policy = match self.policy {
+            LocalEditPolicy::Ask if self.interactive => ask(file_name)?,
+            LocalEditPolicy::Ask => {
+                return Err(format!(
+                    "{}: edited since the last export, and there is no terminal to ask \
+                     what to do; pass --on-local-edit keep-local, overwrite or merge",
+                    file_name
+                )
+                .into())
+            }
+            policy => policy,
+        };
+        let resolution = match policy {
+            LocalEditPolicy::Overwrite => Resolution::Upstream,
+            LocalEditPolicy::Merge => match read_track_records(path) {
+                Ok(local) => {
+                    Resolution::Merged(merge(upstream, local, entry.track_keys.as_deref()))
+                }
+                Err(e) => {
+                    warn!("{}: cannot merge, keeping local edits: {}", file_name, e);
+                    Resolution::KeepLocal
+                }
+            },
+            LocalEditPolicy::Ask | LocalEditPolicy::KeepLocal => Resolution::KeepLocal,
+        };
+        match &resolution {
+            Resolution::Upstream => {
+                warn!("{}: overwriting local edits", file_name);
+                self.remember(file_name, upstream_keys(), false);
+            }
+            Resolution::KeepLocal => {
+                warn!("{}: has local edits; left as it is", file_name);
+                // The export still has not been applied to it.
+                let keys = entry.track_keys.clone().unwrap_or_default();
+                self.remember(file_name, keys, true);
+                self.kept.lock().unwrap().insert(file_name.to_string());
+                self.written(file_name, path)?;
+            }
+            Resolution::Merged(_) => {
+                warn!("{}: merged the export into local edits", file_name);
+                self.remember(file_name, upstream_keys(), true);
+            }
+        }
+        Ok(resolution)
+    }
+
+    /// Carries the previous run's state for `file_name` over to a run that
+    /// left the file alone.
+    pub fn keep(&self, file_name: &str) {
+        if let Some(entry) = self.previous.get(file_name) {
+            self.remember(
+                file_name,
+                entry.track_keys.clone().unwrap_or_default(),
+                entry.local_edits,
+            );
+        }
+    }
+
+    fn remember(&self, file_name: &str, track_keys: Vec<String>, local_edits: bool) {
+        self.states.lock().unwrap().insert(
+            file_name.to_string(),
+            FileState {
+                track_keys,
+                local_edits,
+            },
+        );
+    }
+
+    /// What this run decided for `file_name`, if it wrote a CSV there.
+    pub fn state(&self, file_name: &str) -> Option<FileState> {
+        self.states.lock().unwrap().get(file_name).cloned()
+    }
+
+    /// Whether `file_name` was left with its local edits instead of the
+    /// export, so it still holds what an earlier run wrote.
+    pub fn kept_local(&self, file_name: &str) -> bool {
+        self.kept.lock().unwrap().contains(file_name)
+    }
+
+    /// Records in the manifest on disk what `file_name`, found at `path`,
+    /// now holds.
+    pub fn written(&self, file_name: &str, path: &Path) -> Result<(), Box<dyn Error>> {
+        let (sha256, size) = sha256_file(path).map_err(|e| format!("{}: {}", file_name, e))?;
+        let state = self.state(file_name);
+        let entry = ManifestEntry {
+            path: file_name.to_string(),
+            sha256,
+            size,
+            local_edits: state.as_ref().is_some_and(|state| state.local_edits),
+            track_keys: state.map(|state| state.track_keys),
+        };
+        let mut manifest = self.manifest.lock().unwrap();
+        match manifest.files.iter_mut().find(|old| old.path == file_name) {
+            Some(old) => *old = entry,
+            None => manifest.files.push(entry),
+        }
+        write_json_atomic(&self.manifest_path, &*manifest)
+    }
+}
+
+/// Asks on the terminal what to do with an edited file.
+fn ask(file_name: &str) -> Result<LocalEditPolicy, Box<dyn Error>> {
+    loop {
+        print!(
+            "{} was edited since the last export. [k]eep local, [o]verwrite or [m]erge? [k] ",
+            file_name
+        );
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if io::stdin().lock().read_line(&mut line)? == 0 {
+            return Ok(LocalEditPolicy::KeepLocal);
+        }
+        match line.trim().to_lowercase().as_str() {
+            "" | "k" | "keep" => return Ok(LocalEditPolicy::KeepLocal),
+            "o" | "overwrite" => return Ok(LocalEditPolicy::Overwrite),
+            "m" | "merge" => return Ok(LocalEditPolicy::Merge),
+            _ => println!("Please answer k, o or m."),
+        }
+    }
+}
+
+/// Three-way merge of rows by track. `base` is what the last export wrote;
+/// without it every local row counts as exported, so no row is known to
+/// have been deleted by hand.
+///
+/// The result follows the upstream order. A track in both keeps its local
+/// row, edits included; one only upstream and not in `base` is new and is
+/// added; one only upstream but in `base` was deleted by hand and stays
+/// out. Local rows upstream no longer has are dropped if they were in
+/// `base`, and otherwise were added by hand and are kept at the end.
+pub fn merge(
+    upstream: &[TrackRecord],
+    local: Vec<TrackRecord>,
+    base: Option<&[String]>,
+) -> Vec<TrackRecord> {
+    let local_keys: Vec<String> = local.iter().map(TrackRecord::identity_key).collect();
+    let mut base_counts: HashMap<&str, usize> = HashMap::new();
+    for key in base.map_or(&local_keys[..], |base| base) {
+        *base_counts.entry(key.as_str()).or_default() += 1;
+    }
+    let mut local_rows: HashMap<&str, VecDeque<usize>> = HashMap::new();
+    for (index, key) in local_keys.iter().enumerate() {
+        local_rows.entry(key.as_str()).or_default().push_back(index);
+    }
+    let mut local: Vec<Option<TrackRecord>> = local.into_iter().map(Some).collect();
+
+    let mut merged = Vec::with_capacity(upstream.len());
+    for record in upstream {
+        let key = record.identity_key();
+        let in_base = match base_counts.get_mut(key.as_str()) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                true
+            }
+            _ => false,
+        };
+        let local_row = local_rows
+            .get_mut(key.as_str())
+            .and_then(VecDeque::pop_front);
+        match local_row {
+            Some(index) => merged.extend(local[index].take()),
+            None if in_base => {}
+            None => merged.push(record.clone()),
+        }
+    }
+    for (index, key) in local_keys.iter().enumerate() {
+        let Some(record) = local[index].take() else {
+            continue;
+        };
+        match base_counts.get_mut(key.as_str()) {
+            Some(count) if *count > 0 => *count -= 1,
+            _ => merged.push(record),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        manifest::{write_manifest, MANIFEST_JSON},
+        record::{tests::sample_record, write_track_records, DEFAULT_FIELDS},
+        testdir::TestDir,
+    };
+
+    const CSV: &str = "Road Trip.csv";
+
+    fn names(records: &[TrackRecord]) -> Vec<&str> {
+        records
+            .iter()
+            .map(|record| record.track_name.as_deref().unwrap())
+            .collect()
+    }
+
+    /// A directory holding `CSV` with `records` and the manifest of the
+    /// run that wrote it.
+    fn exported(records: &[TrackRecord]) -> TestDir {
+        let dir = TestDir::new();
+        write_track_records(&dir.path().join(CSV), records, &DEFAULT_FIELDS, None).unwrap();
+        let edits = LocalEdits::open(
+            &dir.path().join(MANIFEST_JSON),
+            LocalEditPolicy::Overwrite,
+            false,
+        );
+        edits.resolve(CSV, &dir.path().join(CSV), records).unwrap();
+        write_manifest(dir.path(), &[CSV.to_string()], &edits).unwrap();
+        dir
+    }
+
+    #[test]
+    fn merge_keeps_edited_and_added_rows_and_deletions() {
+        let base = [
+            sample_record("spotify:track:a", "A"),
+            sample_record("spotify:track:b", "B"),
+            sample_record("spotify:track:c", "C"),
+        ];
+        let base_keys: Vec<String> = base.iter().map(TrackRecord::identity_key).collect();
+        // Retitled A, deleted B, added X by hand.
+        let local = vec![
+            sample_record("spotify:track:a", "A (fixed)"),
+            sample_record("spotify:track:c", "C"),
+            sample_record("spotify:track:x", "X"),
+        ];
+        // The playlist lost C and gained D.
+        let upstream = [
+            sample_record("spotify:track:a", "A"),
+            sample_record("spotify:track:b", "B"),
+            sample_record("spotify:track:d", "D"),
+        ];
+        let merged = merge(&upstream, local, Some(&base_keys));
+        assert_eq!(names(&merged), ["A (fixed)", "D", "X"]);
+    }
+
+    #[test]
+    fn asking_without_a_terminal_fails() {
+        let records = [sample_record("spotify:track:a", "A")];
+        let dir = exported(&records);
+        let path = dir.path().join(CSV);
+        fs_append(&path, "\nhand-written line");
+
+        let manifest = dir.path().join(MANIFEST_JSON);
+        let edits = LocalEdits::open(&manifest, LocalEditPolicy::Ask, false);
+        let error = edits.resolve(CSV, &path, &records).unwrap_err();
+        assert!(error.to_string().contains("--on-local-edit"));
+
+        let edits = LocalEdits::open(&manifest, LocalEditPolicy::Overwrite, false);
+        let resolution = edits.resolve(CSV, &path, &records).unwrap();
+        assert!(matches!(resolution, Resolution::Upstream));
+    }
+
+    #[test]
+    fn a_run_that_stops_early_leaves_no_edits_behind() {
+        let first = [sample_record("spotify:track:a", "A")];
+        let dir = exported(&first);
+        let path = dir.path().join(CSV);
+        let manifest = dir.path().join(MANIFEST_JSON);
+
+        // The playlist changes and the run dies before writing the full
+        // manifest.
+        let second = [
+            sample_record("spotify:track:a", "A"),
+            sample_record("spotify:track:b", "B"),
+        ];
+        let edits = LocalEdits::open(&manifest, LocalEditPolicy::Ask, false);
+        edits.resolve(CSV, &path, &second).unwrap();
+        write_track_records(&path, &second, &DEFAULT_FIELDS, None).unwrap();
+        edits.written(CSV, &path).unwrap();
+        drop(edits);
+
+        let edits = LocalEdits::open(&manifest, LocalEditPolicy::Ask, false);
+        let resolution = edits.resolve(CSV, &path, &second).unwrap();
+        assert!(matches!(resolution, Resolution::Upstream));
+    }
+
+    #[test]
+    fn keeping_local_edits_is_recorded_straight_away() {
+        let records = [sample_record("spotify:track:a", "A")];
+        let dir = exported(&records);
+        let path = dir.path().join(CSV);
+        fs_append(&path, "\nhand-written line");
+
+        let manifest = dir.path().join(MANIFEST_JSON);
+        let edits = LocalEdits::open(&manifest, LocalEditPolicy::KeepLocal, false);
+        let upstream = [sample_record("spotify:track:b", "B")];
+        let resolution = edits.resolve(CSV, &path, &upstream).unwrap();
+        assert!(matches!(resolution, Resolution::KeepLocal));
+        assert!(edits.kept_local(CSV));
+
+        let entry = read_manifest(&manifest).unwrap().files.remove(0);
+        assert!(entry.local_edits);
+        assert_eq!(entry.sha256, sha256_file(&path).unwrap().0);
+        assert_eq!(entry.track_keys.unwrap(), ["spotify:track:a"]);
+    }
+
+    fn fs_append(path: &Path, text: &str) {
+        let mut contents = std::fs::read_to_string(path).unwrap();
+        contents.push_str(text);
+        std::fs::write(path, contents).unwrap();
+    }
+}
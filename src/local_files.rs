@@ -0,0 +1,138 @@
+//! `--music-dir`: where on disk a local file track probably is. Spotify
+//! only knows local files by a `spotify:local:<artist>:<album>:<track>:<seconds>`
+//! URI, with each part form-encoded, so the usual library layouts are
+//! tried in turn.
+
+use std::path::{Component, Path, PathBuf};
+
+/// Tried in this order for each layout.
+const EXTENSIONS: [&str; 2] = ["flac", "mp3"];
+
+/// Decodes `+` as a space and `%XX` escapes, as in form-encoded URI parts.
+fn decode_part(part: &str) -> String {
+    let bytes = part.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if bytes
+                .get(i + 1..i + 3)
+                .is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit)) =>
+            {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+                decoded.push(u8::from_str_radix(hex, 16).unwrap_or_default());
+                i += 2;
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Whether a decoded URI part can name a single file or directory. Parts
+/// come from whoever made the playlist, so one like `..` or `a/../../etc`
+/// must not lead out of the music directory.
+fn is_plain_name(part: &str) -> bool {
+    part != "." && part != ".." && !part.chars().any(|c| matches!(c, '/' | '\\' | ':' | '\0'))
+}
+
+/// Whether `path` is `music_dir` followed by plain names only.
+fn is_inside(path: &Path, music_dir: &Path) -> bool {
+    path.strip_prefix(music_dir).is_ok_and(|rest| {
+        rest.components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    })
+}
+
+/// Candidate paths under `music_dir` for a `spotify:local:` URI, most
+/// likely first: `<artist>/<album>/<track>` then `<artist> - <track>`, as
+/// FLAC then MP3. Empty for other URIs, ones without a track name and ones
+/// with a part that is not a plain name.
+pub fn guess_local_file_path(uri: &str, music_dir: &Path) -> Vec<PathBuf> {
+    let Some(rest) = uri.strip_prefix("spotify:local:") else {
+        return Vec::new();
+    };
+    let parts: Vec<String> = rest.split(':').map(decode_part).collect();
+    let [artist, album, track, ..] = parts.as_slice() else {
+        return Vec::new();
+    };
+    if track.is_empty() {
+        return Vec::new();
+    }
+    if [artist, album, track]
+        .iter()
+        .any(|part| !part.is_empty() && !is_plain_name(part))
+    {
+        return Vec::new();
+    }
+    let mut candidates = Vec::new();
+    for extension in EXTENSIONS {
+        if !artist.is_empty() && !album.is_empty() {
+            candidates.push(
+                music_dir
+                    .join(artist)
+                    .join(album)
+                    .join(format!("{}.{}", track, extension)),
+            );
+        }
+    }
+    for extension in EXTENSIONS {
+        let name = if artist.is_empty() {
+            format!("{}.{}", track, extension)
+        } else {
+            format!("{} - {}.{}", artist, track, extension)
+        };
+        candidates.push(music_dir.join(name));
+    }
+    candidates.retain(|path| is_inside(path, music_dir));
+    candidates
+}
+
+/// The first candidate from `guess_local_file_path` that exists.
+pub fn find_local_file(uri: &str, music_dir: &Path) -> Option<PathBuf> {
+    guess_local_file_path(uri, music_dir)
+        .into_iter()
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guesses_the_usual_layouts() {
+        let music = Path::new("/music");
+        let candidates =
+            guess_local_file_path("spotify:local:The+Band:Best+Of:Song+%231:215", music);
+        assert_eq!(
+            candidates,
+            [
+                music.join("The Band").join("Best Of").join("Song #1.flac"),
+                music.join("The Band").join("Best Of").join("Song #1.mp3"),
+                music.join("The Band - Song #1.flac"),
+                music.join("The Band - Song #1.mp3"),
+            ]
+        );
+    }
+
+    #[test]
+    fn stays_inside_the_music_dir() {
+        let music = Path::new("/music");
+        for uri in [
+            "spotify:local:..:..:passwd:1",
+            "spotify:local:a:..%2F..%2Fetc:passwd:1",
+            "spotify:local:a:b:..%5C..%5Cboot:1",
+            "spotify:local::%2Fetc%2Fpasswd:x:1",
+            "spotify:local:C%3A:b:c:1",
+            "spotify:local:a:b:%00:1",
+        ] {
+            assert!(guess_local_file_path(uri, music).is_empty(), "{}", uri);
+        }
+        // Dots inside a name are fine.
+        let candidates = guess_local_file_path("spotify:local:A:...And+Justice:One:1", music);
+        assert!(candidates.iter().all(|path| is_inside(path, music)));
+        assert_eq!(candidates.len(), 4);
+    }
+}
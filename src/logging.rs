@@ -0,0 +1,23 @@
+use log::{Level, LevelFilter};
+use std::io::Write;
+
+/// Plain `message` lines for progress output, prefixed by level for
+/// anything that is not routine. `RUST_LOG` still overrides the level.
+pub fn init(quiet: bool, verbose: bool) {
+    let level = if quiet {
+        LevelFilter::Warn
+    } else if verbose {
+        LevelFilter::Debug
+    } else {
+        LevelFilter::Info
+    };
+
+    env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .format(|buf, record| match record.level() {
+            Level::Info => writeln!(buf, "{}", record.args()),
+            level => writeln!(buf, "{}: {}", level.as_str().to_lowercase(), record.args()),
+        })
+        .init();
+}
@@ -1,263 +1,1105 @@
-use csv::Writer;
-use reqwest::{header, Client};
-use serde::{Deserialize, Serialize};
-use std::{error::Error, time::Duration};
-use tokio::time::sleep;
+use clap::Parser;
+use futures_util::{pin_mut, StreamExt};
+use log::{debug, error, info, warn};
+use std::{collections::HashMap, error::Error, path::Path, time::Instant};
 
-#[derive(Debug, Deserialize)]
-struct PaginatedTrackResponse {
-    items: Vec<TrackItem>,
-    next: Option<String>,
-}
+mod album_groups;
+mod album_runs;
+mod api;
+mod artwork;
+mod atomic;
+mod auth;
+mod blend;
+mod browse;
+mod cache;
+mod changelog;
+mod clean;
+mod cli;
+mod compilations;
+mod csv_schema;
+mod dashboard;
+mod dedupe;
+mod delta;
+mod disk;
+mod doctor;
+mod episodes;
+mod eta;
+mod export;
+mod filter;
+mod followers;
+mod git_backup;
+mod http;
+mod ids;
+mod import;
+mod index;
+mod library_diff;
+mod local_edits;
+mod local_files;
+mod logging;
+mod manifest;
+mod middleware;
+mod output;
+mod overlap;
+mod overrides;
+mod passport;
+mod paths;
+mod playlist_diff;
+mod provenance;
+mod quarantine;
+mod record;
+mod recovery;
+mod remove;
+mod render;
+mod reorder;
+mod report;
+mod retry;
+mod schema;
+mod schema_check;
+mod serde_helpers;
+mod setup;
+mod share_pack;
+mod spotify;
+mod state;
+mod stats;
+mod store;
+mod strict;
+mod table;
+mod template;
+#[cfg(test)]
+mod testdir;
+mod urls;
+mod warnings;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct TrackItem {
-    track: Option<Track>,
-}
+use api::{aborts_run, SpotifyAPI, API_BASE};
+use artwork::collect_garbage;
+use auth::{
+    EXPORT_SCOPES, FOLLOW_MODIFY_SCOPES, FOLLOW_SCOPES, IMAGE_UPLOAD_SCOPES, LIBRARY_SCOPES,
+    MODIFY_SCOPES,
+};
+use browse::browse;
+use cache::{cache_file, clear_cache};
+use changelog::{write_changelog, PreviousRun, CHANGES_MD};
+use cli::{ArtCommand, Cli, Command, DiffFormat, TopTracksFormat};
+use csv_schema::reimport_csv;
+use dashboard::{refresh_dashboard, write_dashboard};
+use delta::write_delta;
+use disk::{check_free_space, estimate_output_size};
+use doctor::run_doctor;
+use episodes::{find_new_episodes, write_new_episodes, write_new_episodes_m3u};
+use export::{export_playlists, ExportOutcome};
+use filter::filter_playlists_by_visibility;
+use followers::{find_run_indexes, follower_series, write_follower_series};
+use git_backup::{backup_files, commit_backup};
+use import::{create_from_csvs, import_playlist, ImportTarget};
+use index::{read_index, write_index, RunIndex, RunSummary, INDEX_JSON};
+use library_diff::{
+    diff_playlist_lists, read_playlist_snapshot, write_playlist_snapshot, PLAYLISTS_JSON,
+};
+use local_edits::LocalEdits;
+use manifest::{verify_checksums, write_manifest, MANIFEST_JSON};
+use overlap::{read_exported_tracks, Overlap};
+use passport::{read_passport, write_passport, Passport, PROFILE_JSON};
+use paths::OutputPaths;
+use playlist_diff::PlaylistDiff;
+use provenance::{inspect, Provenance};
+use quarantine::{retry_quarantine, write_quarantine, Quarantine, QUARANTINE_JSON};
+use record::{read_track_records, RecordOptions, TrackRecord, DEFAULT_FIELDS};
+use recovery::Recovery;
+use remove::remove_duplicates;
+use reorder::sort_playlist;
+use report::{
+    artist_frequency_report, rarest_tracks, read_artist_frequency_report, top_artists,
+    write_artist_frequency_report, ARTIST_FREQUENCY_CSV, RAREST_TRACKS, TOP_ARTISTS,
+};
+use schema::schema_json;
+use schema_check::validate_endpoint;
+use setup::run_setup;
+use share_pack::write_share_pack;
+use spotify::key_name;
+use state::{read_state, write_state, STATE_JSON};
+use stats::{format_hms, library_summary, PlaylistStats};
+use table::{terminal_width, TableOutput};
+use warnings::{ErrorCollector, Severity, EXPORT_WARNINGS_JSON, STRICT_EXIT_CODE};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct Track {
-    uri: Option<String>,
-    name: Option<String>,
-    artists: Vec<Artist>,
-    album: Album,
-    duration_ms: Option<u64>,
-    popularity: Option<u64>,
-    isrc: Option<String>,
-    preview_url: Option<String>,
-    explicit: Option<bool>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct Artist {
-    uri: Option<String>,
-    name: Option<String>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct Album {
-    uri: Option<String>,
-    name: Option<String>,
-    release_date: Option<String>,
-    artists: Vec<Artist>,
-    images: Vec<Image>,
-    disc_number: Option<u64>,
-    track_number: Option<u64>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct Image {
-    url: String,
-}
-
-#[derive(Debug, Deserialize)]
-struct PlaylistResponse {
-    items: Vec<Playlist>,
-    next: Option<String>,
-}
-
-#[derive(Debug, Deserialize)]
-struct Playlist {
-    name: String,
-    owner: Owner,
-    tracks: Tracks,
-}
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let (global, command) = Cli::parse().command();
+    logging::init(global.quiet, global.verbose);
 
-#[derive(Debug, Deserialize)]
-struct Owner {
-    display_name: String,
-}
+    match command {
+        Command::Export(args) | Command::ExportCategory(args) => {
+            let started = Instant::now();
+            let api = SpotifyAPI::from_args(&global).await?;
+            let mut missing = api.check_token_scopes(&EXPORT_SCOPES);
+            if args.liked_status() || args.saved_album_status() {
+                api.require_user_token("checking Liked Songs and saved albums")?;
+                missing.extend(api.check_token_scopes(&LIBRARY_SCOPES));
+            }
+            if !missing.is_empty() {
+                warn!("access token is missing scopes: {}", missing.join(", "));
+            }
 
-#[derive(Debug, Deserialize)]
-struct Tracks {
-    href: String,
-}
+            if args.whole_library() {
+                api.require_user_token("exporting the library")?;
+            }
+            let playlists = if !args.playlists.is_empty() {
+                let mut playlists = Vec::with_capacity(args.playlists.len());
+                for playlist in &args.playlists {
+                    playlists.push(api.get_playlist_metadata(playlist).await?);
+                }
+                playlists
+            } else if let Some(category) = &args.category {
+                api.get_category_playlists(category, args.country.as_deref(), args.category_limit)
+                    .await?
+            } else if args.only_owned {
+                api.get_owned_playlists().await?
+            } else if args.only_followed {
+                api.get_followed_playlists().await?
+            } else {
+                api.get_library_playlists().await?
+            };
 
-#[derive(Debug)]
-struct SpotifyAPI {
-    auth_token: String,
-    client: Client,
-}
+            let mut playlists = filter_playlists_by_visibility(playlists, args.visibility_filter());
+            let track_count = SpotifyAPI::get_total_track_count(&playlists);
+            info!(
+                "Will export approximately {} tracks across {} playlists",
+                track_count,
+                playlists.len()
+            );
+            let calls = SpotifyAPI::estimate_api_calls(&playlists, &args);
+            if args.dry_run {
+                println!("{}", calls);
+                return Ok(());
+            }
+            debug!("Estimated API requests:\n{}", calls);
+            check_free_space(
+                Path::new("."),
+                estimate_output_size(track_count, args.output.format),
+                args.min_free_space,
+            )?;
+            let errors = ErrorCollector::new(args.strict);
+            if args.include_followers {
+                api.enrich_playlist_followers(&mut playlists, &errors).await;
+            }
+            // An anonymous token belongs to no account.
+            let user = if api.is_public() {
+                None
+            } else {
+                match api.get_current_user().await {
+                    Ok(user) => Some(user),
+                    Err(e) => {
+                        warn!("Could not identify the account for provenance: {}", e);
+                        None
+                    }
+                }
+            };
+            let mut provenance = Provenance::new(user.as_ref().map(|user| user.id.clone()), &args);
+            if args.deterministic {
+                provenance = provenance.deterministic();
+            }
 
-impl SpotifyAPI {
-    fn new(auth_token: String) -> Self {
-        Self {
-            auth_token,
-            client: Client::new(),
-        }
-    }
+            let recovery = Recovery::sweep(Path::new("."));
+            recovery.report();
 
-    async fn get<T: for<'de> Deserialize<'de>>(&self, url: &str) -> Result<T, Box<dyn Error>> {
-        let res = self
-            .client
-            .get(url)
-            .header(header::AUTHORIZATION, format!("Bearer {}", self.auth_token))
-            .send()
+            // Read before the export overwrites it. Tracks fetched with
+            // --added-after are only the new ones and a category's playlists
+            // are not the library's, so there is nothing to compare them
+            // against.
+            let previous_run = if args.continues_history() {
+                PreviousRun::load(Path::new("."))
+            } else {
+                None
+            };
+            let mut paths = OutputPaths::new(global.long_paths);
+            let edits = LocalEdits::load(&paths.path(MANIFEST_JSON), args.on_local_edit);
+            let ExportOutcome {
+                exported,
+                out_of_space,
+                strict_stop,
+                outage_stop,
+                skipped,
+                kept_local,
+            } = export_playlists(
+                playlists,
+                &api,
+                &args,
+                &mut paths,
+                &provenance,
+                &errors,
+                &edits,
+            )
             .await?;
-
-        let status = res.status();
-        let body = res.text().await?;
-
-        if !status.is_success() {
-            eprintln!("HTTP {}: {}", status, body);
-            return Err(format!("Failed request: {}: {}", status, body).into());
+            for trip in api.breaker_trips() {
+                errors.report(
+                    Severity::Warning,
+                    &trip.endpoint,
+                    None,
+                    format!(
+                        "stopped retrying after repeated identical failures: {}",
+                        trip.error
+                    ),
+                );
+            }
+            // Whether every playlist in the library was exported.
+            let full = args.whole_library() && skipped.is_empty();
+            let mut index = RunIndex::from_exports(&exported, &provenance, args.keep_url_params);
+            if let Some(previous_run) = &previous_run {
+                index.carry_forward(previous_run.index(), &skipped);
+            }
+            index.restore(previous_run.as_ref().map(PreviousRun::index), &kept_local);
+            if !args.continues_history() {
+                // Only new tracks or editorial playlists were written; the
+                // index goes on describing the library's files.
+                index.playlists = read_index(Path::new(INDEX_JSON))
+                    .map(|previous| previous.playlists)
+                    .unwrap_or_default();
+            }
+            index.summary = Some(RunSummary {
+                duration_secs: started.elapsed().as_secs(),
+                requests: api.requests_sent(),
+                rate_limited: api.rate_limited(),
+                warnings: errors.len(),
+                incomplete_playlists: errors.incomplete_playlists(),
+                out_of_space,
+                removed_temp_files: recovery.removed_temp_files.len(),
+                interrupted_writes: recovery.interrupted_writes.len(),
+                missing_files: recovery.missing_files.len(),
+                outage_pause_secs: api.outage_pause().as_secs(),
+                outage_stop,
+            });
+            let quarantine = Quarantine {
+                record_options: args.record_options(),
+                filter: args.output.track_filter(),
+                pages: exported
+                    .iter()
+                    .flat_map(|export| export.quarantined.iter().cloned())
+                    .collect(),
+            };
+            if out_of_space || strict_stop || outage_stop {
+                // Save what the run got through while anything still fits.
+                let mut saved = vec![
+                    (
+                        INDEX_JSON,
+                        write_index(&paths.library_file(INDEX_JSON), &index),
+                    ),
+                    (
+                        QUARANTINE_JSON,
+                        write_quarantine(&paths.library_file(QUARANTINE_JSON), &quarantine),
+                    ),
+                    (
+                        EXPORT_WARNINGS_JSON,
+                        errors.write(&paths.library_file(EXPORT_WARNINGS_JSON)),
+                    ),
+                ];
+                if let Some(previous_run) = &previous_run {
+                    // The playlists not reached were not removed.
+                    let changes = write_changelog(
+                        &paths.library_file(CHANGES_MD),
+                        previous_run,
+                        &exported,
+                        false,
+                        args.changes_detail_lines,
+                    );
+                    saved.push((CHANGES_MD, changes));
+                }
+                let manifest = write_manifest(Path::new("."), paths.claimed(), &edits);
+                saved.push((MANIFEST_JSON, manifest.map(|_| ())));
+                for (file_name, result) in saved {
+                    if let Err(e) = result {
+                        error!("Could not save {}: {}", file_name, e);
+                    }
+                }
+                if let Some(runs_root) = &args.auto_dashboard {
+                    refresh_dashboard(runs_root);
+                }
+                if outage_stop {
+                    return Err(format!(
+                        "gave up waiting on a Spotify outage after {} playlists; what was \
+                         exported is saved, so run the export again once Spotify is back",
+                        exported.len()
+                    )
+                    .into());
+                }
+                if !out_of_space {
+                    let violations = errors.violations();
+                    eprintln!(
+                        "--strict stopped the export after {} playlists:",
+                        exported.len()
+                    );
+                    for violation in &violations {
+                        eprintln!("  {}: {}", violation.playlist, violation.message);
+                    }
+                    if !quarantine.pages.is_empty() {
+                        eprintln!(
+                            "Run `retry-quarantine` for the failed pages, then export again."
+                        );
+                    }
+                    std::process::exit(STRICT_EXIT_CODE);
+                }
+                return Err(format!(
+                    "ran out of disk space after {} playlists; the playlists reported above are incomplete",
+                    exported.len()
+                )
+                .into());
+            }
+            let artists = artist_frequency_report(&exported, args.compilations);
+            if args.artist_frequency_report {
+                write_artist_frequency_report(&paths.library_file(ARTIST_FREQUENCY_CSV), &artists)?;
+                info!("Finished writing: {}", ARTIST_FREQUENCY_CSV);
+            }
+            if let Some(previous_run) = &previous_run {
+                write_changelog(
+                    &paths.library_file(CHANGES_MD),
+                    previous_run,
+                    &exported,
+                    full,
+                    args.changes_detail_lines,
+                )?;
+                info!("Finished writing: {}", CHANGES_MD);
+            }
+            if let Some(dir) = &args.emit_delta {
+                let out = write_delta(dir, previous_run.as_ref(), &exported, &index, full)?;
+                info!("Finished writing: {}", out.display());
+            }
+            // The last run's index, to tell which playlists changed.
+            let previous_index = args
+                .git_backup
+                .as_ref()
+                .and_then(|_| read_index(Path::new(INDEX_JSON)).ok());
+            write_index(&paths.library_file(INDEX_JSON), &index)?;
+            info!("Finished writing: {}", INDEX_JSON);
+            write_quarantine(&paths.library_file(QUARANTINE_JSON), &quarantine)?;
+            if !quarantine.pages.is_empty() {
+                warn!(
+                    "{} pages could not be fetched; run `retry-quarantine` to try them again",
+                    quarantine.pages.len()
+                );
+            }
+            info!("All playlists backed up successfully.");
+            for export in &exported {
+                debug!("{}: {} items", export.playlist.name, export.tracks.len());
+            }
+            println!("{}", library_summary(&exported));
+            // A partial export's top artists say little about the library.
+            if full && args.added_after.is_none() && !artists.is_empty() {
+                println!("{}", top_artists(&artists, TOP_ARTISTS));
+            }
+            if args.market_count {
+                println!("{}", rarest_tracks(&exported, RAREST_TRACKS));
+            }
+            let paused = api.outage_pause();
+            if !paused.is_zero() {
+                warn!(
+                    "Paused {} waiting on Spotify outages",
+                    format_hms(paused.as_secs())
+                );
+            }
+            if !errors.is_empty() {
+                errors.write(&paths.library_file(EXPORT_WARNINGS_JSON))?;
+                warn!(
+                    "{} problems did not stop the export; see {}",
+                    errors.len(),
+                    EXPORT_WARNINGS_JSON
+                );
+            }
+            write_passport(
+                &paths.library_file(PROFILE_JSON),
+                &Passport::new(user.as_ref(), &exported, &provenance),
+            )?;
+            info!("Finished writing: {}", PROFILE_JSON);
+            write_manifest(Path::new("."), paths.claimed(), &edits)?;
+            info!("Finished writing: {}", MANIFEST_JSON);
+            if let Some(runs_root) = &args.auto_dashboard {
+                refresh_dashboard(runs_root);
+            }
+            if let Some(repo) = &args.git_backup {
+                commit_backup(repo, &backup_files(&paths), &index, previous_index.as_ref())?;
+            }
         }
-
-        serde_json::from_str::<T>(&body).map_err(|e| {
-            eprintln!("Deserialization error: {}", e);
-            eprintln!("Response body: {}", body);
-            Box::new(e) as Box<dyn Error>
-        })
-    }
-
-    async fn get_all_playlists(&self, url: &str) -> Result<Vec<Playlist>, Box<dyn Error>> {
-        let mut playlists = Vec::new();
-        let mut next = Some(url.to_string());
-
-        while let Some(url) = next {
-            let response: PlaylistResponse = self.get(&url).await?;
-            playlists.extend(response.items);
-            next = response.next;
-
-            if next.is_some() {
-                sleep(Duration::from_secs(2)).await;
+        Command::Setup => run_setup(global).await?,
+        Command::Browse(args) => browse(&args.dir)?,
+        Command::Reimport(args) => {
+            std::fs::create_dir_all(&args.out_dir)?;
+            for input in &args.files {
+                let Some(name) = input.file_name() else {
+                    return Err(format!("{}: not a file", input.display()).into());
+                };
+                let output = args.out_dir.join(name);
+                if output.canonicalize().ok() == input.canonicalize().ok() && output.exists() {
+                    return Err(format!(
+                        "{}: would overwrite the file being read; choose another --out-dir",
+                        input.display()
+                    )
+                    .into());
+                }
+                let (version, rows) = reimport_csv(input, &output)?;
+                info!(
+                    "{}: {}; wrote {} rows to {}",
+                    input.display(),
+                    version,
+                    rows,
+                    output.display()
+                );
             }
         }
-
-        Ok(playlists)
-    }
-
-    async fn get_playlist_tracks(&self, url: &str) -> Result<Vec<TrackItem>, Box<dyn Error>> {
-        let mut all_tracks = Vec::new();
-        let mut next_url = Some(url.to_string());
-
-        while let Some(current_url) = next_url {
-            let res = self
-                .client
-                .get(&current_url)
-                .header(header::AUTHORIZATION, format!("Bearer {}", self.auth_token))
-                .send()
+        Command::SharePack(args) => {
+            let output = args
+                .output
+                .unwrap_or_else(|| args.dir.join(args.format.file_name()));
+            write_share_pack(&args.dir, args.format, &output)?;
+            info!("Finished writing: {}", output.display());
+        }
+        Command::Schema(args) => println!("{}", schema_json(args.kind)?),
+        Command::ValidateSchema(args) => {
+            let api = SpotifyAPI::from_args(&global).await?;
+            api.require_user_token("validate-schema")?;
+            let missing = api.check_token_scopes(&EXPORT_SCOPES);
+            if !missing.is_empty() {
+                warn!("access token is missing scopes: {}", missing.join(", "));
+            }
+            let report = validate_endpoint(&api, args.endpoint).await?;
+            for (field, needed) in &report.missing {
+                if *needed {
+                    warn!("{} is missing, and parsing fails without it", field);
+                } else {
+                    warn!("{} is missing; it is read as empty", field);
+                }
+            }
+            for field in &report.extra {
+                info!("{} is not read by this tool", field);
+            }
+            println!(
+                "{} missing fields, {} unread fields",
+                report.missing.len(),
+                report.extra.len()
+            );
+            if report.missing.values().any(|needed| *needed) {
+                std::process::exit(1);
+            }
+        }
+        Command::RemoveTracks(args) => {
+            let api = SpotifyAPI::from_args(&global).await?;
+            if !args.dry_run {
+                api.require_user_token("remove-tracks")?;
+                let missing = api.check_token_scopes(&MODIFY_SCOPES);
+                if !missing.is_empty() {
+                    warn!("access token is missing scopes: {}", missing.join(", "));
+                }
+            }
+            remove_duplicates(&api, &args, global.plain).await?;
+        }
+        Command::SortPlaylist(args) => {
+            let api = SpotifyAPI::from_args(&global).await?;
+            if !args.dry_run {
+                api.require_user_token("sort-playlist")?;
+                let missing = api.check_token_scopes(&MODIFY_SCOPES);
+                if !missing.is_empty() {
+                    warn!("access token is missing scopes: {}", missing.join(", "));
+                }
+            }
+            sort_playlist(&api, &args, global.plain).await?;
+        }
+        Command::SetCover(args) => {
+            let api = SpotifyAPI::from_args(&global).await?;
+            api.require_user_token("set-cover")?;
+            let mut missing = api.check_token_scopes(&MODIFY_SCOPES);
+            missing.extend(api.check_token_scopes(&IMAGE_UPLOAD_SCOPES));
+            if !missing.is_empty() {
+                warn!("access token is missing scopes: {}", missing.join(", "));
+            }
+            api.upload_playlist_cover(&args.playlist, &args.image)
                 .await?;
-
-            let status = res.status();
-            let body = res.text().await?;
-
-            if !status.is_success() {
-                eprintln!("HTTP {}: {}", status, body);
-                return Err(format!("Failed request: {}: {}", status, body).into());
+            info!("Set the cover of playlist {}", args.playlist);
+        }
+        Command::UpdatePlaylist(args) => {
+            let api = SpotifyAPI::from_args(&global).await?;
+            api.require_user_token("update-playlist")?;
+            let missing = api.check_token_scopes(&MODIFY_SCOPES);
+            if !missing.is_empty() {
+                warn!("access token is missing scopes: {}", missing.join(", "));
             }
-
-            let response: PaginatedTrackResponse = serde_json::from_str(&body)?;
-            all_tracks.extend(response.items);
-            next_url = response.next;
-
-            if next_url.is_some() {
-                sleep(Duration::from_secs(1)).await;
+            api.update_playlist(
+                &args.playlist,
+                args.name.as_deref(),
+                args.description.as_deref(),
+                args.public,
+                args.collaborative,
+            )
+            .await?;
+            info!("Updated playlist {}", args.playlist);
+        }
+        Command::Art(args) => match args.command {
+            ArtCommand::Gc { dir, store } => {
+                let (removed, bytes) = collect_garbage(&dir, &store)?;
+                info!("Removed {} unreferenced covers ({} bytes)", removed, bytes);
+            }
+        },
+        Command::VerifyChecksums(args) => {
+            if !verify_checksums(&args.manifest)? {
+                std::process::exit(1);
+            }
+        }
+        Command::ClearCache => {
+            let dir = global
+                .cache_location()
+                .ok_or("no cache directory; pass --cache-dir")?;
+            let removed = clear_cache(&dir)?;
+            for path in &removed {
+                info!("Removed {}", path.display());
+            }
+            info!(
+                "Cleared {} cache files from {}",
+                removed.len(),
+                dir.display()
+            );
+        }
+        Command::Doctor => {
+            if !run_doctor(global).await? {
+                std::process::exit(1);
             }
         }
+        Command::Render(args) => render::render(&args, global.long_paths)?,
+        Command::EpisodesNew(args) => {
+            let api = SpotifyAPI::from_args(&global).await?;
+            api.require_user_token("episodes-new")?;
+            let missing = api.check_token_scopes(&LIBRARY_SCOPES);
+            if !missing.is_empty() {
+                warn!("access token is missing scopes: {}", missing.join(", "));
+            }
 
-        Ok(all_tracks)
-    }
-}
+            let today = chrono::Utc::now().date_naive();
+            let default_since = (today - chrono::Days::new(u64::from(args.days))).to_string();
+            let mut paths = OutputPaths::new(global.long_paths);
+            let mut state = if args.since_last_run {
+                Some(read_state(&paths.path(STATE_JSON))?)
+            } else {
+                None
+            };
 
-async fn export_to_csv(playlists: &[Playlist], api: &SpotifyAPI) -> Result<(), Box<dyn Error>> {
-    println!("Exporting playlists to CSV...");
+            let shows = api.get_saved_shows().await?;
+            let errors = ErrorCollector::default();
+            let episodes =
+                find_new_episodes(&api, &shows, &default_since, state.as_mut(), &errors).await;
 
-    for playlist in playlists {
-        let file_name = format!("{}.csv", playlist.name.replace("/", "_"));
-        let mut writer = Writer::from_path(&file_name)?;
+            let file_name = paths.reserve(&format!("New Episodes {}", today), "csv");
+            write_new_episodes(&paths.path(&file_name), &episodes)?;
+            info!(
+                "Finished writing: {} ({} new episodes across {} shows)",
+                file_name,
+                episodes.len(),
+                shows.len()
+            );
+            if args.m3u {
+                let m3u_name = paths.reserve(&format!("New Episodes {}", today), "m3u");
+                let linked = write_new_episodes_m3u(&paths.path(&m3u_name), &episodes)?;
+                info!(
+                    "Finished writing: {} ({} of {} new episodes link a YouTube video)",
+                    m3u_name,
+                    linked,
+                    episodes.len()
+                );
+            }
+            if let Some(state) = &state {
+                write_state(&paths.library_file(STATE_JSON), state)?;
+            }
+            errors.write(&paths.library_file(EXPORT_WARNINGS_JSON))?;
+        }
+        Command::Overlap(args) => {
+            let api = SpotifyAPI::from_args(&global).await?;
+            api.require_user_token("overlap")?;
+            let missing = api.check_token_scopes(&LIBRARY_SCOPES);
+            if !missing.is_empty() {
+                warn!("access token is missing scopes: {}", missing.join(", "));
+            }
+            let account_id = api.get_current_user().await?.id;
+            let to_records = |items: Vec<spotify::TrackItem>, added_by: &str| {
+                items
+                    .iter()
+                    .filter_map(|item| item.track.as_ref())
+                    .map(|track| {
+                        TrackRecord::from_track(
+                            track,
+                            added_by,
+                            chrono::Utc::now().to_string(),
+                            RecordOptions::default(),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            };
 
-        writer.write_record(&[
-            "Track URI",
-            "Track Name",
-            "Artist URI(s)",
-            "Artist Name(s)",
-            "Album URI",
-            "Album Name",
-            "Album Artist URI(s)",
-            "Album Artist Name(s)",
-            "Album Release Date",
-            "Album Image URL",
-            "Disc Number",
-            "Track Number",
-            "Track Duration (ms)",
-            "Track Preview URL",
-            "Explicit",
-            "Popularity",
-            "ISRC",
-            "Added By",
-            "Added At",
-        ])?;
+            info!("Reading Liked Songs and saved albums...");
+            let liked = to_records(api.get_liked_tracks().await?, &account_id);
+            let album_track_uris = api
+                .get_saved_album_track_uris()
+                .await?
+                .into_iter()
+                .collect();
 
-        let tracks = api.get_playlist_tracks(&playlist.tracks.href).await?;
+            let playlist_tracks = if args.source == "live" {
+                let playlists = api.get_library_playlists().await?;
+                let owners: HashMap<&str, &str> = playlists
+                    .iter()
+                    .map(|p| (p.id.as_str(), p.owner.display_name.as_str()))
+                    .collect();
+                let mut tracks = Vec::new();
+                let stream = api.stream_all_user_tracks_with_context(&playlists);
+                pin_mut!(stream);
+                while let Some(track) = stream.next().await {
+                    match track {
+                        Ok(track) => {
+                            if track.position == 0 {
+                                info!("Reading {}...", track.playlist_name);
+                            }
+                            let owner = owners
+                                .get(track.playlist_id.as_str())
+                                .copied()
+                                .unwrap_or_default();
+                            tracks.extend(to_records(vec![track.item], owner));
+                        }
+                        Err(e) if aborts_run(&*e) => return Err(e),
+                        Err(e) => warn!("{}", e),
+                    }
+                }
+                tracks
+            } else {
+                read_exported_tracks(Path::new(&args.source))?
+            };
 
-        for track_item in tracks {
-            if let Some(track) = track_item.track {
-                writer.write_record(&[
-                    track.uri.unwrap_or_default(),
-                    track.name.unwrap_or_default(),
-                    join_artist_uris(&track.artists),
-                    join_artist_names(&track.artists),
-                    track.album.uri.clone().unwrap_or_default(),
-                    track.album.name.clone().unwrap_or_default(),
-                    join_artist_uris(&track.album.artists),
-                    join_artist_names(&track.album.artists),
-                    track
-                        .album
-                        .release_date
-                        .clone()
-                        .unwrap_or_else(|| "Unknown".to_string()),
-                    track
-                        .album
-                        .images
-                        .first()
-                        .map_or("No Image".into(), |img| img.url.clone()),
-                    track.album.disc_number.unwrap_or(0).to_string(),
-                    track.album.track_number.unwrap_or(0).to_string(),
-                    track.duration_ms.unwrap_or(0).to_string(),
-                    track.preview_url.unwrap_or_default(),
-                    track.explicit.unwrap_or(false).to_string(),
-                    track.popularity.unwrap_or(0).to_string(),
-                    track.isrc.unwrap_or_default(),
+            let overlap = Overlap::analyze(liked, &album_track_uris, playlist_tracks);
+            overlap.print(global.plain);
+            if args.write_buckets {
+                overlap.write_buckets(&mut OutputPaths::new(global.long_paths))?;
+            }
+        }
+        Command::Inspect(args)
+            if args
+                .artifact
+                .file_name()
+                .is_some_and(|name| name == PROFILE_JSON) =>
+        {
+            println!("{}", read_passport(&args.artifact)?);
+        }
+        Command::Inspect(args) => match inspect(&args.artifact)? {
+            Some(provenance) => println!("{}", provenance),
+            None => return Err(format!("{} carries no provenance", args.artifact.display()).into()),
+        },
+        Command::RetryQuarantine(args) => {
+            let api = SpotifyAPI::from_args(&global).await?;
+            let recovered = retry_quarantine(&api, &args.quarantine).await?;
+            info!("Recovered {} quarantined pages", recovered);
+        }
+        Command::Import(args) => {
+            let api = SpotifyAPI::from_args(&global).await?;
+            api.require_user_token("import")?;
+            let missing = api.check_token_scopes(&MODIFY_SCOPES);
+            if !missing.is_empty() {
+                warn!("access token is missing scopes: {}", missing.join(", "));
+            }
+            let target = match (&args.replace_playlist, &args.playlist_name) {
+                (Some(playlist), _) => ImportTarget::Replace(playlist),
+                (None, Some(name)) => ImportTarget::New {
+                    name,
+                    public: args.public_playlist,
+                    description: &args.description,
+                },
+                (None, None) => {
+                    return Err("--playlist-name or --replace-playlist is required".into())
+                }
+            };
+            let replacing = matches!(target, ImportTarget::Replace(_));
+            let outcome = import_playlist(&api, &args.file, target, args.strict_ids).await?;
+            info!(
+                "{} playlist {} with {} tracks",
+                if replacing { "Replaced" } else { "Created" },
+                outcome.playlist_id,
+                outcome.added
+            );
+        }
+        Command::Create(args) => {
+            let api = SpotifyAPI::from_args(&global).await?;
+            api.require_user_token("create")?;
+            let missing = api.check_token_scopes(&MODIFY_SCOPES);
+            if !missing.is_empty() {
+                warn!("access token is missing scopes: {}", missing.join(", "));
+            }
+            let outcome = create_from_csvs(
+                &api,
+                &args.from_csv,
+                &args.name,
+                &args.description,
+                args.public_playlist,
+                args.strict_ids,
+            )
+            .await?;
+            info!(
+                "Created playlist {} with {} tracks",
+                outcome.playlist_id, outcome.added
+            );
+        }
+        Command::Dashboard(args) => {
+            let runs = find_run_indexes(&args.runs_root)?;
+            if runs.is_empty() {
+                return Err(
+                    format!("no {} found under {}", INDEX_JSON, args.runs_root.display()).into(),
+                );
+            }
+            write_dashboard(&runs, &args.output, args.runs)?;
+            info!("Finished writing: {}", args.output.display());
+        }
+        Command::FollowersHistory(args) => {
+            let runs = find_run_indexes(&args.backups_root)?;
+            if runs.is_empty() {
+                return Err(format!(
+                    "no {} found under {}",
+                    INDEX_JSON,
+                    args.backups_root.display()
+                )
+                .into());
+            }
+            let series = follower_series(&runs);
+            write_follower_series(&series, &mut OutputPaths::new(global.long_paths))?;
+            for playlist in &series {
+                println!("{}", playlist);
+            }
+        }
+        Command::List(args) => {
+            let api = SpotifyAPI::from_args(&global).await?;
+            let playlists = if args.playlists.is_empty() {
+                api.require_user_token("listing the library")?;
+                api.get_library_playlists().await?
+            } else {
+                let mut playlists = Vec::with_capacity(args.playlists.len());
+                for playlist in &args.playlists {
+                    playlists.push(api.get_playlist_metadata(playlist).await?);
+                }
+                playlists
+            };
+            let mut table =
+                TableOutput::new(vec!["Name", "Owner", "Tracks", "ID"]).align_right(&[2]);
+            for playlist in &playlists {
+                let count = match playlist.tracks.total {
+                    _ if args.with_counts => {
+                        api.get_playlist_total_tracks(&playlist.tracks.href).await
+                    }
+                    Some(total) => Ok(total),
+                    // The list endpoint leaves the count out now and then.
+                    None => api.get_playlist_track_count(&playlist.id).await,
+                };
+                let count = match count {
+                    Ok(total) => total.to_string(),
+                    Err(e) => {
+                        warn!("{}: {}", playlist.name, e);
+                        String::new()
+                    }
+                };
+                table.add_row(vec![
+                    playlist.name.clone(),
                     playlist.owner.display_name.clone(),
-                    chrono::Utc::now().to_string(),
-                ])?;
+                    count,
+                    playlist.id.clone(),
+                ]);
+            }
+            table.print(global.plain);
+            if args.compare_previous {
+                let dir = global.cache_location().ok_or(
+                    "--compare-previous keeps the previous listing in the cache; drop --no-cache",
+                )?;
+                let path = &cache_file(&dir, PLAYLISTS_JSON)?;
+                match read_playlist_snapshot(path) {
+                    Ok(previous) => {
+                        let diff = diff_playlist_lists(&previous, &playlists);
+                        if diff.is_empty() {
+                            println!("\nNo changes since the previous listing.");
+                        } else {
+                            println!();
+                            for playlist in &diff.added {
+                                println!("+ {} ({})", playlist.name, playlist.id);
+                            }
+                            for playlist in &diff.removed {
+                                println!("- {} ({})", playlist.name, playlist.id);
+                            }
+                            for (before, after) in &diff.changed {
+                                if before.name == after.name {
+                                    println!("~ {} ({})", after.name, after.id);
+                                } else {
+                                    println!(
+                                        "~ {} ({}), was \"{}\"",
+                                        after.name, after.id, before.name
+                                    );
+                                }
+                            }
+                        }
+                    }
+                    Err(_) if !path.exists() => {
+                        info!("No previous listing to compare with; saving this one")
+                    }
+                    Err(e) => warn!("Could not read the previous listing: {}", e),
+                }
+                write_playlist_snapshot(path, &playlists)?;
+            }
+        }
+        Command::TopTracks(args) => {
+            let api = SpotifyAPI::from_args(&global).await?;
+            let tracks = api
+                .get_artist_top_tracks(&args.artist, &args.market)
+                .await?;
+            let records: Vec<TrackRecord> = tracks
+                .iter()
+                .map(|track| {
+                    TrackRecord::from_track(track, "", String::new(), RecordOptions::default())
+                })
+                .collect();
+            match args.format {
+                TopTracksFormat::Csv => {
+                    let mut writer = csv::Writer::from_writer(std::io::stdout());
+                    writer.write_record(DEFAULT_FIELDS.iter().map(|field| field.header()))?;
+                    for record in &records {
+                        writer
+                            .write_record(DEFAULT_FIELDS.iter().map(|field| field.value(record)))?;
+                    }
+                    writer.flush()?;
+                }
+                TopTracksFormat::Table => {
+                    let mut table =
+                        TableOutput::new(vec!["#", "Track", "Artists", "Album", "Popularity"])
+                            .align_right(&[0, 4]);
+                    for (rank, record) in records.iter().enumerate() {
+                        table.add_row(vec![
+                            (rank + 1).to_string(),
+                            record.track_name.clone().unwrap_or_default(),
+                            record.artist_names.clone(),
+                            record.album_name.clone().unwrap_or_default(),
+                            record.popularity.map(|p| p.to_string()).unwrap_or_default(),
+                        ]);
+                    }
+                    table.print(global.plain);
+                }
+            }
+        }
+        Command::FollowArtists(args) => {
+            let mut artists = Vec::with_capacity(args.top);
+            for artist in read_artist_frequency_report(&args.from_csv)? {
+                if artists.len() == args.top {
+                    break;
+                }
+                // The compilations group, and artists credited without a URI.
+                if artist.uri.is_none() {
+                    warn!("Skipping {}: no artist URI", artist.name);
+                    continue;
+                }
+                artists.push(artist);
+            }
+            if args.dry_run {
+                let mut table =
+                    TableOutput::new(vec!["Artist", "Tracks", "Artist URI"]).align_right(&[1]);
+                for artist in &artists {
+                    table.add_row(vec![
+                        artist.name.clone(),
+                        artist.track_count.to_string(),
+                        artist.uri.clone().unwrap_or_default(),
+                    ]);
+                }
+                table.print(global.plain);
+                return Ok(());
+            }
+            let api = SpotifyAPI::from_args(&global).await?;
+            api.require_user_token("follow-artists")?;
+            let missing = api.check_token_scopes(&FOLLOW_MODIFY_SCOPES);
+            if !missing.is_empty() {
+                warn!("access token is missing scopes: {}", missing.join(", "));
+            }
+            let uris: Vec<&str> = artists
+                .iter()
+                .filter_map(|artist| artist.uri.as_deref())
+                .collect();
+            if args.unfollow {
+                api.unfollow_artists(&uris).await?;
+                info!("Unfollowed {} artists", uris.len());
+            } else {
+                api.follow_artists(&uris).await?;
+                info!("Followed {} artists", uris.len());
+            }
+        }
+        Command::FollowedArtists => {
+            let api = SpotifyAPI::from_args(&global).await?;
+            api.require_user_token("followed-artists")?;
+            let missing = api.check_token_scopes(&FOLLOW_SCOPES);
+            if !missing.is_empty() {
+                warn!("access token is missing scopes: {}", missing.join(", "));
+            }
+            let url = format!("{}/me/following?type=artist&limit=50", API_BASE);
+            let stream = api.get_followed_artists_stream(&url);
+            pin_mut!(stream);
+            let mut table =
+                TableOutput::new(vec!["Artist", "Genres", "Popularity", "Followers", "URI"])
+                    .align_right(&[2, 3]);
+            while let Some(artist) = stream.next().await {
+                let artist = artist?;
+                table.add_row(vec![
+                    artist.name,
+                    artist.genres.join(", "),
+                    artist.popularity.map(|p| p.to_string()).unwrap_or_default(),
+                    artist
+                        .followers
+                        .and_then(|followers| followers.total)
+                        .map(|total| total.to_string())
+                        .unwrap_or_default(),
+                    artist.uri,
+                ]);
+            }
+            table.print(global.plain);
+        }
+        Command::Categories(args) => {
+            let api = SpotifyAPI::from_args(&global).await?;
+            let categories = api.get_categories(args.country.as_deref()).await?;
+            let mut table = TableOutput::new(vec!["ID", "Name"]);
+            for category in categories {
+                table.add_row(vec![category.id, category.name]);
+            }
+            table.print(global.plain);
+        }
+        Command::TrackAnalysis(args) => {
+            let api = SpotifyAPI::from_args(&global).await?;
+            let analysis = api.get_audio_analysis(&args.track).await?;
+            println!("Tempo: {:.1} BPM", analysis.tempo);
+            println!("Time signature: {}/4", analysis.time_signature);
+            println!("Key: {}", key_name(analysis.key, analysis.mode));
+            println!("Loudness: {:.1} dB", analysis.loudness);
+            let mut table = TableOutput::new(vec![
+                "Start (s)",
+                "Duration (s)",
+                "Tempo",
+                "Time Signature",
+                "Key",
+                "Loudness (dB)",
+                "Confidence",
+            ])
+            .align_right(&[0, 1, 2, 3, 5, 6]);
+            for section in &analysis.sections {
+                table.add_row(vec![
+                    format!("{:.1}", section.start),
+                    format!("{:.1}", section.duration),
+                    format!("{:.1}", section.tempo),
+                    format!("{}/4", section.time_signature),
+                    key_name(section.key, section.mode),
+                    format!("{:.1}", section.loudness),
+                    format!("{:.2}", section.confidence),
+                ]);
+            }
+            println!();
+            table.print(global.plain);
+        }
+        Command::EpisodeInfo(args) => {
+            let api = SpotifyAPI::from_args(&global).await?;
+            let episode = api.get_episode(&args.episode).await?;
+            let show = episode.show.as_ref();
+            let field = |value: Option<&str>| value.unwrap_or("unknown").to_string();
+            println!("Name: {}", field(episode.name.as_deref()));
+            println!("URI: {}", field(episode.uri.as_deref()));
+            println!("Show: {}", field(show.and_then(|s| s.name.as_deref())));
+            println!(
+                "Publisher: {}",
+                field(show.and_then(|s| s.publisher.as_deref()))
+            );
+            println!("Released: {}", field(episode.release_date.as_deref()));
+            println!(
+                "Duration: {}",
+                episode
+                    .duration_ms
+                    .map_or("unknown".to_string(), |ms| format_hms(u64::from(ms) / 1000))
+            );
+            println!(
+                "Explicit: {}",
+                episode
+                    .explicit
+                    .map_or("unknown".to_string(), |e| e.to_string())
+            );
+            if let Some(description) = &episode.description {
+                println!("\n{}", description);
+            }
+        }
+        Command::Diff(args) => {
+            let diff = PlaylistDiff::compare(
+                read_track_records(&args.old)?,
+                read_track_records(&args.new)?,
+            );
+            match args.format {
+                DiffFormat::Json => println!("{}", serde_json::to_string_pretty(&diff)?),
+                DiffFormat::Unified => print!(
+                    "{}",
+                    diff.unified(
+                        &args.old.display().to_string(),
+                        &args.new.display().to_string()
+                    )
+                ),
+                DiffFormat::Table => {
+                    let mut table =
+                        TableOutput::new(vec!["Change", "Track", "Artist(s)", "Track URI"]);
+                    let changes = diff
+                        .removed
+                        .iter()
+                        .map(|record| ("removed", record))
+                        .chain(diff.added.iter().map(|record| ("added", record)));
+                    for (change, record) in changes {
+                        table.add_row(vec![
+                            change.to_string(),
+                            record.track_name.clone().unwrap_or_default(),
+                            record.artist_names.clone(),
+                            record.track_uri.clone().unwrap_or_default(),
+                        ]);
+                    }
+                    table.print(global.plain);
+                    println!("{}", diff);
+                }
+            }
+        }
+        Command::Stats(args) => {
+            let mut table = TableOutput::new(vec![
+                "File",
+                "Tracks",
+                "Duration (s)",
+                "Unknown Duration",
+                "Explicit",
+                "Compilations",
+                "Avg Popularity",
+                "Popularity 0",
+                "Popularity 1-33",
+                "Popularity 34-66",
+                "Popularity 67-100",
+                "Popularity Unknown",
+            ])
+            .align_right(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]);
+            let mut charts = Vec::new();
+            for path in &args.files {
+                let stats = PlaylistStats::from_records(&read_track_records(path)?);
+                table.add_row(vec![
+                    path.display().to_string(),
+                    stats.track_count.to_string(),
+                    (stats.total_duration_ms / 1000).to_string(),
+                    stats.unknown_duration_count.to_string(),
+                    stats.explicit_count.to_string(),
+                    stats.compilation_count.to_string(),
+                    stats
+                        .average_popularity
+                        .map(|avg| format!("{:.1}", avg))
+                        .unwrap_or_default(),
+                    stats.popularity.zero.to_string(),
+                    stats.popularity.low.to_string(),
+                    stats.popularity.medium.to_string(),
+                    stats.popularity.high.to_string(),
+                    stats.popularity.unknown.to_string(),
+                ]);
+                if args.release_years && stats.track_count > 0 {
+                    let width = terminal_width().unwrap_or(80);
+                    charts.push((path, stats.release_year_chart(width)));
+                }
+            }
+            table.print(global.plain);
+            for (path, chart) in charts {
+                println!("\n{}\n{}", path.display(), chart.trim_end());
             }
         }
-
-        writer.flush()?;
-        println!("Finished writing: {}", file_name);
     }
 
     Ok(())
 }
-
-fn join_artist_uris(artists: &[Artist]) -> String {
-    artists
-        .iter()
-        .map(|a| a.uri.clone().unwrap_or_default())
-        .collect::<Vec<_>>()
-        .join(", ")
-}
-
-fn join_artist_names(artists: &[Artist]) -> String {
-    artists
-        .iter()
-        .map(|a| a.name.clone().unwrap_or_default())
-        .collect::<Vec<_>>()
-        .join(", ")
-}
-
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    let token = "Dummy".to_string();
-    let api = SpotifyAPI::new(token);
-
-    let playlists = api
-        .get_all_playlists("https://api.spotify.com/v1/me/playlists?limit=50")
-        .await?;
-
-    export_to_csv(&playlists, &api).await?;
-    println!("All playlists backed up successfully.");
-    Ok(())
-}
@@ -0,0 +1,185 @@
+//! manifest.json: the SHA-256 of every file an export wrote, so a backup can
+//! later be checked for corruption or edits with `verify-checksums`.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    error::Error,
+    fmt::Write as _,
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+use crate::{
+    atomic::write_json_atomic,
+    local_edits::LocalEdits,
+    passport::{read_passport, PROFILE_JSON},
+    paths::RESERVED_NAMES,
+    record::{read_track_records, TrackRecord},
+};
+
+pub const MANIFEST_JSON: &str = "manifest.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Manifest {
+    pub files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ManifestEntry {
+    /// Relative to the manifest's directory.
+    pub path: String,
+    /// Lowercase hex.
+    pub sha256: String,
+    pub size: u64,
+    /// For playlist CSVs, the tracks written, as
+    /// `TrackRecord::identity_key`s, so the next run can merge into a CSV
+    /// edited by hand.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub track_keys: Option<Vec<String>>,
+    /// The file was left with edits made by hand; see `local_edits`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub local_edits: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyResult {
+    Ok,
+    Missing,
+    Corrupted { actual: String },
+}
+
+pub fn sha256_file(path: &Path) -> io::Result<(String, u64)> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    let mut size = 0u64;
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        size += read as u64;
+    }
+    Ok((hex(hasher), size))
+}
+
+fn hex(hasher: Sha256) -> String {
+    let mut hex = String::with_capacity(64);
+    for byte in hasher.finalize() {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    hex
+}
+
+pub fn sha256_bytes(bytes: &[u8]) -> String {
+    hex(Sha256::new_with_prefix(bytes))
+}
+
+/// Hashes the playlist files in `files` and whichever library-wide
+/// artifacts are in `dir`, and writes the manifest beside them. `edits`
+/// tells what each playlist CSV holds.
+pub fn write_manifest(
+    dir: &Path,
+    files: &[String],
+    edits: &LocalEdits,
+) -> Result<Manifest, Box<dyn Error>> {
+    let mut paths: Vec<&str> = files
+        .iter()
+        .map(String::as_str)
+        .filter(|name| *name != MANIFEST_JSON)
+        .collect();
+    // Left by earlier runs.
+    let earlier: Vec<&str> = RESERVED_NAMES
+        .iter()
+        .copied()
+        .filter(|name| *name != MANIFEST_JSON && !paths.contains(name) && dir.join(name).is_file())
+        .collect();
+    paths.extend(earlier);
+    let mut manifest = Manifest { files: Vec::new() };
+    for path in paths {
+        let (sha256, size) =
+            sha256_file(&dir.join(path)).map_err(|e| format!("{}: {}", path, e))?;
+        let state = edits.state(path);
+        manifest.files.push(ManifestEntry {
+            path: path.to_string(),
+            sha256,
+            size,
+            local_edits: state.as_ref().is_some_and(|state| state.local_edits),
+            track_keys: state.map(|state| state.track_keys),
+        });
+    }
+    write_json_atomic(&dir.join(MANIFEST_JSON), &manifest)?;
+    Ok(manifest)
+}
+
+/// Rehashes `file` in the manifest at `manifest`, if both list it, after
+/// the tool changed it outside an export.
+pub fn refresh_manifest_entry(manifest: &Path, file: &str) -> Result<(), Box<dyn Error>> {
+    let Ok(mut contents) = read_manifest(manifest) else {
+        return Ok(());
+    };
+    let dir = manifest.parent().unwrap_or(Path::new(""));
+    let Some(entry) = contents.files.iter_mut().find(|entry| entry.path == file) else {
+        return Ok(());
+    };
+    let path = dir.join(file);
+    (entry.sha256, entry.size) = sha256_file(&path).map_err(|e| format!("{}: {}", file, e))?;
+    if entry.track_keys.is_some() {
+        let records = read_track_records(&path)?;
+        entry.track_keys = Some(records.iter().map(TrackRecord::identity_key).collect());
+    }
+    write_json_atomic(manifest, &contents)
+}
+
+pub fn read_manifest(path: &Path) -> Result<Manifest, Box<dyn Error>> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(serde_json::from_reader(BufReader::new(file))?)
+}
+
+pub fn verify_file(path: &Path, expected_hash: &str) -> Result<VerifyResult, Box<dyn Error>> {
+    match sha256_file(path) {
+        Ok((actual, _)) if actual.eq_ignore_ascii_case(expected_hash) => Ok(VerifyResult::Ok),
+        Ok((actual, _)) => Ok(VerifyResult::Corrupted { actual }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(VerifyResult::Missing),
+        Err(e) => Err(format!("{}: {}", path.display(), e).into()),
+    }
+}
+
+/// Checks every file in the manifest at `manifest_path`, printing each
+/// failure and a summary. Returns whether all of them matched.
+pub fn verify_checksums(manifest_path: &Path) -> Result<bool, Box<dyn Error>> {
+    let manifest = read_manifest(manifest_path)?;
+    let dir = manifest_path.parent().unwrap_or(Path::new(""));
+    if let Ok(passport) = read_passport(&dir.join(PROFILE_JSON)) {
+        println!("{}\n", passport);
+    }
+    let (mut ok, mut missing, mut corrupted) = (0, 0, 0);
+    for entry in &manifest.files {
+        match verify_file(&dir.join(&entry.path), &entry.sha256)? {
+            VerifyResult::Ok => ok += 1,
+            VerifyResult::Missing => {
+                println!("MISSING    {}", entry.path);
+                missing += 1;
+            }
+            VerifyResult::Corrupted { actual } => {
+                println!(
+                    "CORRUPTED  {} (expected {}, found {})",
+                    entry.path, entry.sha256, actual
+                );
+                corrupted += 1;
+            }
+        }
+    }
+    println!(
+        "{}/{} files OK, {} files MISSING, {} files CORRUPTED",
+        ok,
+        manifest.files.len(),
+        missing,
+        corrupted
+    );
+    Ok(missing == 0 && corrupted == 0)
+}
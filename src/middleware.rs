@@ -0,0 +1,724 @@
+//! Retries, backoff, pacing and request counts for outbound HTTP. Every
+//! service the tool calls declares a [`ServicePolicy`] and sends through a
+//! [`Middleware`] built from it, so each gets the same retry rules, circuit
+//! breaker and rate limit handling; only the numbers differ.
+
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
+use reqwest::{header, header::HeaderMap, Client, Method, Response, StatusCode};
+use std::{
+    error::Error,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::time::sleep;
+
+use crate::eta::TimeEstimator;
+use crate::http::explain_send_error;
+use crate::retry::{CircuitBreaker, RetryClass, Trip};
+use crate::stats::format_hms;
+
+/// Retries for a single request before its error is returned.
+pub const MAX_RETRIES: u32 = 3;
+
+/// Below this many requests left in the window, requests are spread out
+/// over the rest of it instead of running into a 429.
+const LOW_REMAINING: u32 = 10;
+
+/// How long to keep waiting once a service looks down, rather than failing
+/// each request after its own short retry budget.
+#[derive(Debug, Clone)]
+pub struct OutagePolicy {
+    /// Consecutive 5xx responses, across requests, that count as an outage.
+    pub threshold: u32,
+    pub probe_interval: Duration,
+    pub max_wait: Duration,
+}
+
+impl Default for OutagePolicy {
+    fn default() -> Self {
+        Self {
+            threshold: 5,
+            probe_interval: Duration::from_secs(5 * 60),
+            max_wait: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+/// How one service is called.
+#[derive(Debug, Clone)]
+pub struct ServicePolicy {
+    /// The service as named in log messages.
+    pub name: &'static str,
+    /// Most requests to send per second; `None` leaves pacing to the
+    /// service's rate limit headers and 429s.
+    pub requests_per_sec: Option<f64>,
+    /// Failures to retry; the rest fail the request on the first error.
+    pub retry_on: Vec<RetryClass>,
+    pub max_retries: u32,
+    /// Wait as long as a response's `Retry-After` asks instead of backing
+    /// off exponentially.
+    pub retry_after: bool,
+    /// Waiting out outages, probing `probe_url` until it answers. Without
+    /// it, server errors only get the usual retries.
+    pub outage: Option<OutagePolicy>,
+    pub probe_url: Option<String>,
+}
+
+/// One request, rebuilt for every attempt.
+pub struct Outbound<'a> {
+    pub method: Method,
+    pub url: &'a str,
+    /// Sent as `Authorization: Bearer`.
+    pub bearer: Option<&'a str>,
+    pub body: Option<Body<'a>>,
+}
+
+/// What a request carries.
+#[derive(Debug, Clone, Copy)]
+pub enum Body<'a> {
+    Json(&'a serde_json::Value),
+    /// A base64-encoded JPEG, as playlist cover uploads take it.
+    Jpeg(&'a str),
+}
+
+#[derive(Debug)]
+pub struct Middleware {
+    client: Client,
+    policy: ServicePolicy,
+    breaker: CircuitBreaker,
+    consecutive_server_errors: AtomicU32,
+    outage_pause: Mutex<Duration>,
+    /// An outage outlasted `OutagePolicy::max_wait`; later server errors
+    /// only get the usual retries.
+    outage_gave_up: AtomicBool,
+    requests_sent: AtomicU32,
+    rate_limited: AtomicU32,
+    /// When the last response arrived and the budget it reported.
+    last_response: Mutex<Option<ResponseMeta>>,
+    /// When the last request went out, for `requests_per_sec`.
+    last_sent: Mutex<Option<Instant>>,
+    /// How long each request took, pacing included, for the export ETA.
+    response_times: Mutex<TimeEstimator>,
+}
+
+impl Middleware {
+    pub fn new(client: Client, policy: ServicePolicy) -> Self {
+        Self {
+            client,
+            policy,
+            breaker: CircuitBreaker::default(),
+            consecutive_server_errors: AtomicU32::new(0),
+            outage_pause: Mutex::new(Duration::ZERO),
+            outage_gave_up: AtomicBool::new(false),
+            requests_sent: AtomicU32::new(0),
+            rate_limited: AtomicU32::new(0),
+            last_response: Mutex::new(None),
+            last_sent: Mutex::new(None),
+            response_times: Mutex::new(TimeEstimator::default()),
+        }
+    }
+
+    pub fn policy(&self) -> &ServicePolicy {
+        &self.policy
+    }
+
+    pub fn policy_mut(&mut self) -> &mut ServicePolicy {
+        &mut self.policy
+    }
+
+    /// Requests sent so far, retries included.
+    pub fn requests_sent(&self) -> u32 {
+        self.requests_sent.load(Ordering::Relaxed)
+    }
+
+    /// Time spent on requests so far, pacing included.
+    pub fn request_time(&self) -> Duration {
+        self.response_times.lock().unwrap().total()
+    }
+
+    /// How long `remaining_calls` more requests and `overhead` should take;
+    /// `None` until enough requests have been timed.
+    pub fn estimate_remaining(
+        &self,
+        remaining_calls: usize,
+        overhead: Duration,
+    ) -> Option<Duration> {
+        self.response_times
+            .lock()
+            .unwrap()
+            .estimate(remaining_calls, overhead)
+    }
+
+    /// Responses so far that were HTTP 429.
+    pub fn rate_limited(&self) -> u32 {
+        self.rate_limited.load(Ordering::Relaxed)
+    }
+
+    /// Total time spent paused waiting for outages to end.
+    pub fn outage_pause(&self) -> Duration {
+        *self.outage_pause.lock().unwrap()
+    }
+
+    /// Whether an outage lasted longer than the policy waits.
+    pub fn outage_gave_up(&self) -> bool {
+        self.outage_gave_up.load(Ordering::SeqCst)
+    }
+
+    /// Endpoints the circuit breaker stopped retrying.
+    pub fn trips(&self) -> Vec<Trip> {
+        self.breaker.trips()
+    }
+
+    pub fn should_retry(&self, class: RetryClass, endpoint: &str) -> bool {
+        self.policy.retry_on.contains(&class) && !self.breaker.is_tripped(endpoint)
+    }
+
+    pub fn record_success(&self, endpoint: &str) {
+        self.breaker.record_success(endpoint);
+    }
+
+    pub fn record_failure(&self, endpoint: &str, error: &str) {
+        self.breaker.record_failure(endpoint, error);
+    }
+
+    /// The request budget as of the last response. `None` until a response
+    /// has arrived.
+    pub fn last_rate_limit_status(&self) -> Option<RateLimitStatus> {
+        let meta = (*self.last_response.lock().unwrap())?;
+        let now = Instant::now();
+        Some(RateLimitStatus {
+            requests_remaining: meta.rate_limit.map(|info| info.remaining),
+            reset_at: meta.rate_limit.and_then(|info| {
+                let left = info.reset.saturating_duration_since(now);
+                chrono::Duration::from_std(left)
+                    .ok()
+                    .map(|left| Utc::now() + left)
+            }),
+            last_request_at: meta.at,
+        })
+    }
+
+    /// Notes when a response arrived. A response without budget headers
+    /// keeps the budget an earlier one reported, as most endpoints never
+    /// send them.
+    fn record_response(&self, headers: &HeaderMap) {
+        let mut last = self.last_response.lock().unwrap();
+        let rate_limit =
+            parse_rate_limit_headers(headers).or_else(|| last.and_then(|meta| meta.rate_limit));
+        *last = Some(ResponseMeta {
+            at: Utc::now(),
+            rate_limit,
+        });
+    }
+
+    /// Sends `request`, retrying the failures the policy allows. Once
+    /// enough server errors arrive in a row, switches to waiting out the
+    /// outage instead. A POST is retried only where it cannot have been
+    /// applied: on rate limiting and failed connections.
+    pub async fn send(
+        &self,
+        request: &Outbound<'_>,
+        endpoint: &str,
+    ) -> Result<Response, Box<dyn Error>> {
+        let idempotent = request.method != Method::POST;
+        let max_retries = self.policy.max_retries;
+        let mut attempt = 0;
+        loop {
+            let started = Instant::now();
+            self.pace().await;
+            self.requests_sent.fetch_add(1, Ordering::Relaxed);
+            let mut builder = self.client.request(request.method.clone(), request.url);
+            if let Some(token) = request.bearer {
+                builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", token));
+            }
+            match request.body {
+                Some(Body::Json(body)) => builder = builder.json(body),
+                Some(Body::Jpeg(data)) => {
+                    builder = builder
+                        .header(header::CONTENT_TYPE, "image/jpeg")
+                        .body(data.to_string())
+                }
+                None => {}
+            }
+            let sent = builder.send().await;
+            self.response_times
+                .lock()
+                .unwrap()
+                .record(started.elapsed());
+            let res = match sent {
+                Ok(res) => res,
+                Err(e) if e.is_connect() || (idempotent && e.is_timeout()) => {
+                    self.breaker.record_failure(endpoint, &e.to_string());
+                    if !self.should_retry(RetryClass::Timeout, endpoint) || attempt >= max_retries {
+                        return Err(explain_send_error(e));
+                    }
+                    attempt += 1;
+                    warn!(
+                        "{}; retrying in {}s ({}/{})",
+                        e,
+                        1 << attempt,
+                        attempt,
+                        max_retries
+                    );
+                    sleep(Duration::from_secs(1 << attempt)).await;
+                    continue;
+                }
+                Err(e) => return Err(explain_send_error(e)),
+            };
+            let status = res.status();
+            self.record_response(res.headers());
+
+            let class = if status.is_server_error() {
+                Some(RetryClass::ServerError)
+            } else if status == StatusCode::TOO_MANY_REQUESTS {
+                self.rate_limited.fetch_add(1, Ordering::Relaxed);
+                Some(RetryClass::RateLimit)
+            } else {
+                None
+            };
+            if status.is_server_error() {
+                // Rate limiting says nothing about whether the endpoint
+                // works, so only server errors count towards its breaker.
+                self.breaker
+                    .record_failure(endpoint, &format!("HTTP {}", status));
+                let failures = self
+                    .consecutive_server_errors
+                    .fetch_add(1, Ordering::SeqCst)
+                    + 1;
+                // An outage is the whole service's, not one endpoint's, so
+                // it is waited out whether or not this endpoint's breaker
+                // has tripped.
+                if let Some(outage) = &self.policy.outage {
+                    if idempotent
+                        && failures >= outage.threshold
+                        && !self.outage_gave_up()
+                        && self.policy.retry_on.contains(&RetryClass::ServerError)
+                    {
+                        self.wait_for_recovery(outage, failures, request).await?;
+                        attempt = 0;
+                        continue;
+                    }
+                }
+            } else {
+                self.consecutive_server_errors.store(0, Ordering::SeqCst);
+            }
+
+            let retryable = class.is_some_and(|class| {
+                (idempotent || class == RetryClass::RateLimit) && self.should_retry(class, endpoint)
+            });
+            if !retryable || attempt >= max_retries {
+                return Ok(res);
+            }
+
+            attempt += 1;
+            let delay = retry_after(res.headers())
+                .filter(|_| self.policy.retry_after)
+                .unwrap_or(Duration::from_secs(1 << attempt));
+            warn!(
+                "HTTP {} from {}; retrying in {}s ({}/{})",
+                status,
+                request.url,
+                delay.as_secs(),
+                attempt,
+                max_retries
+            );
+            sleep(delay).await;
+        }
+    }
+
+    /// Keeps to `requests_per_sec`, and slows down ahead of a 429 when the
+    /// last response said the request budget is running out.
+    async fn pace(&self) {
+        if let Some(per_sec) = self.policy.requests_per_sec {
+            let interval = Duration::from_secs_f64(1.0 / per_sec);
+            let wait = {
+                let mut last_sent = self.last_sent.lock().unwrap();
+                let now = Instant::now();
+                let next = last_sent.map_or(now, |last| (last + interval).max(now));
+                *last_sent = Some(next);
+                next - now
+            };
+            if !wait.is_zero() {
+                sleep(wait).await;
+            }
+        }
+
+        let meta = *self.last_response.lock().unwrap();
+        let Some(info) = meta.and_then(|meta| meta.rate_limit) else {
+            return;
+        };
+        let delay = info.delay();
+        if delay.is_zero() {
+            return;
+        }
+        if info.remaining == 0 {
+            warn!(
+                "Request budget used up; waiting {}s for it to reset",
+                delay.as_secs()
+            );
+        } else {
+            debug!(
+                "{} requests left in this window; waiting {}ms",
+                info.remaining,
+                delay.as_millis()
+            );
+        }
+        sleep(delay).await;
+    }
+
+    async fn wait_for_recovery(
+        &self,
+        outage: &OutagePolicy,
+        failures: u32,
+        request: &Outbound<'_>,
+    ) -> Result<(), Box<dyn Error>> {
+        let name = self.policy.name;
+        warn!(
+            "{} returned {} server errors in a row; waiting on an upstream outage \
+             (probing every {}, giving up after {})",
+            name,
+            failures,
+            format_hms(outage.probe_interval.as_secs()),
+            format_hms(outage.max_wait.as_secs())
+        );
+        let started = Instant::now();
+        let probe_url = self.policy.probe_url.as_deref().unwrap_or(request.url);
+
+        let result = loop {
+            let remaining = outage.max_wait.saturating_sub(started.elapsed());
+            if remaining.is_zero() {
+                self.outage_gave_up.store(true, Ordering::SeqCst);
+                break Err(format!(
+                    "gave up after waiting {} for {} to recover",
+                    format_hms(outage.max_wait.as_secs()),
+                    name
+                )
+                .into());
+            }
+            sleep(outage.probe_interval.min(remaining)).await;
+
+            match self.probe(probe_url, request.bearer).await {
+                Ok(status) if !status.is_server_error() => {
+                    info!(
+                        "{} is responding again after {}",
+                        name,
+                        format_hms(started.elapsed().as_secs())
+                    );
+                    break Ok(());
+                }
+                Ok(status) => info!("{} still down (HTTP {})", name, status),
+                Err(e) => info!("{} still unreachable: {}", name, e),
+            }
+        };
+
+        *self.outage_pause.lock().unwrap() += started.elapsed();
+        self.consecutive_server_errors.store(0, Ordering::SeqCst);
+        result
+    }
+
+    /// A single GET, outside the retries and counts, to see whether the
+    /// service answers.
+    async fn probe(&self, url: &str, bearer: Option<&str>) -> Result<StatusCode, Box<dyn Error>> {
+        let mut builder = self.client.get(url);
+        if let Some(token) = bearer {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let res = builder.send().await.map_err(explain_send_error)?;
+        Ok(res.status())
+    }
+}
+
+/// The request budget some endpoints report in `X-RateLimit-*` headers.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitInfo {
+    pub remaining: u32,
+    pub reset: Instant,
+}
+
+/// What the last response said, kept for pacing and status reporting.
+#[derive(Debug, Clone, Copy)]
+struct ResponseMeta {
+    at: DateTime<Utc>,
+    rate_limit: Option<RateLimitInfo>,
+}
+
+/// The request budget as of the last response.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitStatus {
+    pub requests_remaining: Option<u32>,
+    pub reset_at: Option<DateTime<Utc>>,
+    pub last_request_at: DateTime<Utc>,
+}
+
+impl RateLimitInfo {
+    /// How long to wait before the next request: until the reset once the
+    /// budget is spent, an even share of the time left while it is low.
+    fn delay(&self) -> Duration {
+        let left = self.reset.saturating_duration_since(Instant::now());
+        match self.remaining {
+            0 => left,
+            remaining if remaining < LOW_REMAINING => left / (remaining + 1),
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+/// Reads `X-RateLimit-Remaining` and `X-RateLimit-Reset`, when both are
+/// sent. The reset may be a Unix timestamp or a number of seconds from now.
+pub fn parse_rate_limit_headers(headers: &HeaderMap) -> Option<RateLimitInfo> {
+    let number =
+        |name: &str| -> Option<u64> { headers.get(name)?.to_str().ok()?.trim().parse().ok() };
+    let remaining = u32::try_from(number("x-ratelimit-remaining")?).ok()?;
+    let reset = number("x-ratelimit-reset")?;
+    // Anything past 2001 in seconds is a timestamp rather than a delay.
+    let seconds = if reset > 1_000_000_000 {
+        reset.saturating_sub(Utc::now().timestamp().max(0) as u64)
+    } else {
+        reset
+    };
+    Some(RateLimitInfo {
+        remaining,
+        reset: Instant::now() + Duration::from_secs(seconds),
+    })
+}
+
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::retry::BREAKER_THRESHOLD;
+    use std::sync::{atomic::AtomicUsize, Arc};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    /// Serves HTTP on a local port, answering each request with the status
+    /// and body `respond` gives for its path and how many requests came
+    /// before it. Returns the base URL and a count of requests served.
+    pub async fn serve<F>(respond: F) -> (String, Arc<AtomicUsize>)
+    where
+        F: Fn(&str, usize) -> (u16, String) + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let served = Arc::new(AtomicUsize::new(0));
+        let count = Arc::clone(&served);
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else {
+                    return;
+                };
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match socket.read(&mut buf).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let request = String::from_utf8_lossy(&request);
+                let path = request.split(' ').nth(1).unwrap_or_default().to_string();
+                let (status, body) = respond(&path, count.fetch_add(1, Ordering::SeqCst));
+                let response = format!(
+                    "HTTP/1.1 {} Status\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nRetry-After: 0\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        (base, served)
+    }
+
+    fn policy(retry_on: Vec<RetryClass>) -> ServicePolicy {
+        ServicePolicy {
+            name: "Test",
+            requests_per_sec: None,
+            retry_on,
+            max_retries: MAX_RETRIES,
+            retry_after: true,
+            outage: None,
+            probe_url: None,
+        }
+    }
+
+    async fn get(middleware: &Middleware, url: &str) -> Result<Response, Box<dyn Error>> {
+        let request = Outbound {
+            method: Method::GET,
+            url,
+            bearer: None,
+            body: None,
+        };
+        middleware.send(&request, "/v1/test").await
+    }
+
+    /// Fails the first request with `status`, then succeeds; returns the
+    /// final status and the requests it took with and without `class`.
+    async fn first_fails_with(status: u16, class: RetryClass) -> [(u16, usize); 2] {
+        let mut outcomes = [(0, 0); 2];
+        for (outcome, retry_on) in outcomes.iter_mut().zip([vec![class], Vec::new()]) {
+            let (base, served) =
+                serve(move |_, n| (if n == 0 { status } else { 200 }, "{}".into())).await;
+            let middleware = Middleware::new(Client::new(), policy(retry_on));
+            let res = get(&middleware, &base).await.unwrap();
+            *outcome = (res.status().as_u16(), served.load(Ordering::SeqCst));
+        }
+        outcomes
+    }
+
+    #[tokio::test]
+    async fn rate_limits_are_retried_only_when_asked() {
+        assert_eq!(
+            first_fails_with(429, RetryClass::RateLimit).await,
+            [(200, 2), (429, 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retried_only_when_asked() {
+        assert_eq!(
+            first_fails_with(503, RetryClass::ServerError).await,
+            [(200, 2), (503, 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn timeouts_are_retried_only_when_asked() {
+        for (retry_on, attempts) in [(vec![RetryClass::Timeout], 2), (Vec::new(), 1)] {
+            // Accepts connections and never answers them.
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let accepted = Arc::new(AtomicUsize::new(0));
+            let count = Arc::clone(&accepted);
+            tokio::spawn(async move {
+                let mut open = Vec::new();
+                while let Ok((socket, _)) = listener.accept().await {
+                    count.fetch_add(1, Ordering::SeqCst);
+                    open.push(socket);
+                }
+            });
+            let client = Client::builder()
+                .timeout(Duration::from_millis(100))
+                .build()
+                .unwrap();
+            let middleware = Middleware::new(
+                client,
+                ServicePolicy {
+                    max_retries: 1,
+                    ..policy(retry_on)
+                },
+            );
+            assert!(get(&middleware, &url).await.is_err());
+            assert_eq!(accepted.load(Ordering::SeqCst), attempts);
+        }
+    }
+
+    #[tokio::test]
+    async fn rate_limiting_never_trips_the_breaker() {
+        let (base, _) = serve(|_, _| (429, "{}".into())).await;
+        let middleware = Middleware::new(Client::new(), policy(vec![RetryClass::RateLimit]));
+        for _ in 0..BREAKER_THRESHOLD {
+            get(&middleware, &base).await.unwrap();
+        }
+        assert!(middleware.trips().is_empty());
+        assert!(middleware.should_retry(RetryClass::RateLimit, "/v1/test"));
+        assert_eq!(
+            middleware.rate_limited(),
+            BREAKER_THRESHOLD * (MAX_RETRIES + 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn a_tripped_breaker_leaves_other_endpoints_working() {
+        // Profiles are down for good; playlist pages fail once in a while.
+        let (base, served) = serve(|path, n| match path {
+            "/v1/users/owner" => (502, "{}".into()),
+            _ if n % 3 == 0 => (503, "{}".into()),
+            _ => (200, r#"{"items":[]}"#.into()),
+        })
+        .await;
+        let middleware = Middleware::new(
+            Client::new(),
+            policy(vec![RetryClass::ServerError, RetryClass::RateLimit]),
+        );
+        let send = |url: String, endpoint: &'static str| {
+            let middleware = &middleware;
+            async move {
+                let request = Outbound {
+                    method: Method::GET,
+                    url: &url,
+                    bearer: None,
+                    body: None,
+                };
+                middleware.send(&request, endpoint).await.unwrap().status()
+            }
+        };
+
+        let mut pages = 0;
+        for _ in 0..6 {
+            let page = send(
+                format!("{}/v1/playlists/p/tracks", base),
+                "/v1/playlists/{id}/tracks",
+            );
+            assert_eq!(page.await, StatusCode::OK);
+            pages += 1;
+            let profile = send(format!("{}/v1/users/owner", base), "/v1/users/{id}");
+            assert_eq!(profile.await, StatusCode::BAD_GATEWAY);
+        }
+        assert_eq!(pages, 6);
+
+        let trips = middleware.trips();
+        assert_eq!(trips.len(), 1);
+        assert_eq!(trips[0].endpoint, "/v1/users/{id}");
+        assert!(trips[0].error.contains("502"), "{}", trips[0].error);
+        // Once tripped, a profile takes a single request.
+        let before = served.load(Ordering::SeqCst);
+        send(format!("{}/v1/users/owner", base), "/v1/users/{id}").await;
+        assert_eq!(served.load(Ordering::SeqCst), before + 1);
+        assert!(middleware.should_retry(RetryClass::ServerError, "/v1/playlists/{id}/tracks"));
+    }
+
+    #[tokio::test]
+    async fn outages_are_waited_out_even_past_a_tripped_breaker() {
+        let (base, _) = serve(|path, n| match path {
+            "/probe" => (200, "{}".into()),
+            _ if n == 0 => (503, "{}".into()),
+            _ => (200, "{}".into()),
+        })
+        .await;
+        let mut middleware = Middleware::new(Client::new(), policy(vec![RetryClass::ServerError]));
+        middleware.policy_mut().outage = Some(OutagePolicy {
+            threshold: 1,
+            probe_interval: Duration::from_millis(10),
+            max_wait: Duration::from_secs(5),
+        });
+        middleware.policy_mut().probe_url = Some(format!("{}/probe", base));
+        for _ in 0..BREAKER_THRESHOLD {
+            middleware.record_failure("/v1/test", "HTTP 503 Service Unavailable");
+        }
+        assert!(!middleware.should_retry(RetryClass::ServerError, "/v1/test"));
+
+        let res = get(&middleware, &format!("{}/v1/test", base))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(middleware.outage_pause() > Duration::ZERO);
+        assert!(!middleware.outage_gave_up());
+    }
+}
@@ -0,0 +1,419 @@
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fs::File, path::Path};
+
+use crate::{
+    atomic::write_json_atomic,
+    cli::OutputArgs,
+    local_edits::{LocalEdits, Resolution},
+    paths::OutputPaths,
+    provenance::Provenance,
+    record::{read_track_records, write_track_records, Field, TrackRecord, DEFAULT_FIELDS},
+};
+
+pub const LIBRARY_JSON: &str = "library.json";
+pub const EXPORT_CONFIG_JSON: &str = "export_config.json";
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// One CSV file per playlist
+    #[default]
+    Csv,
+    /// A single library.json holding every playlist
+    Json,
+    /// One file per playlist rendered from --template-file
+    Template,
+}
+
+/// The structured export. It carries every `TrackRecord` field so any other
+/// format can be rendered from it later without contacting the API.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct LibraryExport {
+    /// Columns the export was written with, so rendering reproduces them.
+    #[serde(default = "default_fields")]
+    pub fields: Vec<Field>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+    pub playlists: Vec<PlaylistRecords>,
+}
+
+/// How every writer in a run lays out its files, passed to each of them
+/// in place of separate flags. It is also written next to the output as
+/// export_config.json, a record of the layout for whoever reads the files
+/// later; the tool itself never reads it back, and a later export takes
+/// its layout from its own options. Only layout the tool can vary is
+/// here: CSVs always use commas, empty cells for missing values and
+/// Spotify's own spellings of booleans and dates, and are written one
+/// uncompressed file per playlist into the current directory.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct OutputConfig {
+    pub format: OutputFormat,
+    pub fields: Vec<Field>,
+    /// Write the provenance as a comment line ahead of each CSV's header.
+    pub csv_preamble: bool,
+    /// Re-read each CSV after writing it to check its row order.
+    pub verify_order: bool,
+}
+
+impl OutputConfig {
+    pub fn new(args: &OutputArgs, fields: Vec<Field>) -> Self {
+        Self {
+            format: args.format,
+            fields,
+            csv_preamble: args.csv_preamble,
+            verify_order: args.verify_order,
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        write_json_atomic(path, self)
+    }
+}
+
+fn default_fields() -> Vec<Field> {
+    DEFAULT_FIELDS.to_vec()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PlaylistRecords {
+    pub name: String,
+    pub owner: String,
+    pub tracks: Vec<TrackRecord>,
+}
+
+/// Writes the per-playlist file for formats that have one, returning its name.
+pub fn write_playlist(
+    playlist: &PlaylistRecords,
+    config: &OutputConfig,
+    provenance: Option<&Provenance>,
+    paths: &mut OutputPaths,
+    edits: Option<&LocalEdits>,
+) -> Result<Option<String>, Box<dyn Error>> {
+    match config.format {
+        OutputFormat::Csv => {
+            let file_name = paths.reserve(&playlist.name, "csv");
+            let resolution = match edits {
+                Some(edits) => {
+                    edits.resolve(&file_name, &paths.path(&file_name), &playlist.tracks)?
+                }
+                None => Resolution::Upstream,
+            };
+            let tracks = match &resolution {
+                Resolution::Upstream => &playlist.tracks,
+                Resolution::KeepLocal => return Ok(Some(file_name)),
+                Resolution::Merged(records) => records,
+            };
+            let preamble = match provenance {
+                Some(provenance) if config.csv_preamble => Some(provenance.to_preamble()?),
+                _ => None,
+            };
+            write_track_records(
+                &paths.path(&file_name),
+                tracks,
+                &config.fields,
+                preamble.as_deref(),
+            )?;
+            if config.verify_order {
+                verify_order(&paths.path(&file_name), tracks)?;
+            }
+            if let Some(edits) = edits {
+                edits.written(&file_name, &paths.path(&file_name))?;
+            }
+            Ok(Some(file_name))
+        }
+        // Templates need the API data and are written by the export itself.
+        OutputFormat::Json | OutputFormat::Template => Ok(None),
+    }
+}
+
+/// Writes the library-wide file for formats that have one, returning its name.
+pub fn write_library(
+    playlists: Vec<PlaylistRecords>,
+    config: &OutputConfig,
+    provenance: Option<&Provenance>,
+    paths: &mut OutputPaths,
+) -> Result<Option<String>, Box<dyn Error>> {
+    match config.format {
+        OutputFormat::Csv | OutputFormat::Template => Ok(None),
+        OutputFormat::Json => {
+            let library = LibraryExport {
+                fields: config.fields.clone(),
+                provenance: provenance.cloned(),
+                playlists,
+            };
+            write_json_atomic(&paths.library_file(LIBRARY_JSON), &library)?;
+            Ok(Some(LIBRARY_JSON.to_string()))
+        }
+    }
+}
+
+/// Loads a JSON export, given either the file itself or the directory it
+/// was written to.
+pub fn read_library(path: &Path) -> Result<LibraryExport, Box<dyn Error>> {
+    let path = if path.is_dir() {
+        path.join(LIBRARY_JSON)
+    } else {
+        path.to_path_buf()
+    };
+    let file = File::open(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+}
+
+/// Numbers a playlist's tracks 1..N in their current order. Runs on each
+/// written playlist, after anything that drops or moves rows.
+pub fn number_positions(playlist: &mut PlaylistRecords) {
+    for (index, record) in playlist.tracks.iter_mut().enumerate() {
+        record.position = Some(index + 1);
+    }
+}
+
+/// Checks that a written CSV has `records` in order, numbered 1..N.
+fn verify_order(path: &Path, records: &[TrackRecord]) -> Result<(), Box<dyn Error>> {
+    let written = read_track_records(path)?;
+    if written.len() != records.len() {
+        return Err(format!(
+            "{}: wrote {} rows but expected {}",
+            path.display(),
+            written.len(),
+            records.len()
+        )
+        .into());
+    }
+    for (index, (written, expected)) in written.iter().zip(records).enumerate() {
+        if written.position != Some(index + 1) {
+            return Err(format!(
+                "{}: row {} has position {:?}, expected {}",
+                path.display(),
+                index + 1,
+                written.position,
+                index + 1
+            )
+            .into());
+        }
+        if written.track_uri != expected.track_uri {
+            return Err(format!(
+                "{}: row {} is {:?}, expected {:?}",
+                path.display(),
+                index + 1,
+                written.track_uri,
+                expected.track_uri
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// With `--split-explicit`, moves explicit tracks into a separate
+/// "<name> (explicit)" playlist. Tracks of unknown explicitness stay put.
+pub fn split_explicit(playlist: PlaylistRecords) -> Vec<PlaylistRecords> {
+    let (explicit, clean): (Vec<_>, Vec<_>) = playlist
+        .tracks
+        .into_iter()
+        .partition(|record| record.explicit == Some(true));
+
+    let explicit = PlaylistRecords {
+        name: format!("{} (explicit)", playlist.name),
+        owner: playlist.owner.clone(),
+        tracks: explicit,
+    };
+    vec![
+        PlaylistRecords {
+            tracks: clean,
+            ..playlist
+        },
+        explicit,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cli::tests::output_args,
+        export::{prepare_records, split_if_requested},
+        record::tests::sample_record,
+        testdir::TestDir,
+    };
+
+    fn record(
+        uri: &str,
+        popularity: u8,
+        isrc: &str,
+        released: &str,
+        explicit: bool,
+    ) -> TrackRecord {
+        let mut record = sample_record(&format!("spotify:track:{}", uri), uri);
+        record.popularity = Some(popularity);
+        record.isrc = Some(isrc.to_string());
+        record.album_release_date = Some(released.to_string());
+        record.explicit = Some(explicit);
+        record
+    }
+
+    /// A DJ set where filtering, deduplication and splitting each drop rows.
+    fn set() -> PlaylistRecords {
+        PlaylistRecords {
+            name: "Set".to_string(),
+            owner: "DJ".to_string(),
+            tracks: vec![
+                record("intro", 50, "AA0000000001", "2020", false),
+                record("quiet", 5, "AA0000000002", "2020", false),
+                record("remix", 60, "AA0000000003", "2021", true),
+                record("peak", 90, "AA0000000004", "2019", false),
+                record("remix-original", 55, "AA0000000003", "2015", true),
+                record("interlude", 10, "AA0000000005", "2020", true),
+                record("peak-reissue", 85, "AA0000000004", "2022", false),
+                record("outro", 40, "AA0000000006", "2020", false),
+            ],
+        }
+    }
+
+    fn written(args: &[&str]) -> Vec<PlaylistRecords> {
+        let output = output_args(args);
+        let playlist = set();
+        let records = PlaylistRecords {
+            tracks: prepare_records(playlist.tracks, &playlist.name, &output, &mut Vec::new()),
+            ..playlist
+        };
+        split_if_requested(records, &output)
+    }
+
+    fn uris(playlist: &PlaylistRecords) -> Vec<&str> {
+        playlist
+            .tracks
+            .iter()
+            .map(|record| &record.track_uri.as_deref().unwrap()["spotify:track:".len()..])
+            .collect()
+    }
+
+    fn positions(playlist: &PlaylistRecords) -> Vec<Option<usize>> {
+        playlist
+            .tracks
+            .iter()
+            .map(|record| record.position)
+            .collect()
+    }
+
+    #[test]
+    fn positions_are_renumbered_after_every_dropped_row() {
+        let playlists = written(&[
+            "--min-popularity",
+            "20",
+            "--dedupe-key",
+            "isrc",
+            "--split-explicit",
+            "--include-position",
+        ]);
+
+        let [clean, explicit] = &playlists[..] else {
+            panic!("expected two playlists, got {}", playlists.len());
+        };
+        assert_eq!(uris(clean), ["intro", "peak", "outro"]);
+        assert_eq!(positions(clean), [Some(1), Some(2), Some(3)]);
+        assert_eq!(explicit.name, "Set (explicit)");
+        assert_eq!(uris(explicit), ["remix-original"]);
+        assert_eq!(positions(explicit), [Some(1)]);
+    }
+
+    #[test]
+    fn kept_rows_keep_their_places() {
+        for (args, kept) in [
+            (
+                &["--include-position"][..],
+                &[
+                    "intro",
+                    "quiet",
+                    "remix",
+                    "peak",
+                    "remix-original",
+                    "interlude",
+                    "peak-reissue",
+                    "outro",
+                ][..],
+            ),
+            (
+                &["--min-popularity", "20", "--include-position"],
+                &[
+                    "intro",
+                    "remix",
+                    "peak",
+                    "remix-original",
+                    "peak-reissue",
+                    "outro",
+                ],
+            ),
+            // A recording stays where its first copy was, whichever copy
+            // is kept.
+            (
+                &[
+                    "--dedupe-key",
+                    "isrc",
+                    "--prefer-release",
+                    "latest",
+                    "--include-position",
+                ],
+                &[
+                    "intro",
+                    "quiet",
+                    "remix",
+                    "peak-reissue",
+                    "interlude",
+                    "outro",
+                ],
+            ),
+            (
+                &[
+                    "--clean-only",
+                    "--min-popularity",
+                    "20",
+                    "--dedupe-key",
+                    "isrc",
+                    "--include-position",
+                ],
+                &["intro", "peak", "outro"],
+            ),
+        ] {
+            let playlists = written(args);
+            assert_eq!(uris(&playlists[0]), kept, "{:?}", args);
+            let numbered: Vec<_> = (1..=kept.len()).map(Some).collect();
+            assert_eq!(positions(&playlists[0]), numbered, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn verify_order_checks_what_was_written() {
+        let dir = TestDir::new();
+        let mut paths = OutputPaths::in_dir(dir.path());
+        let config = OutputConfig::new(
+            &output_args(&["--include-position", "--verify-order"]),
+            [DEFAULT_FIELDS.to_vec(), vec![Field::Position]].concat(),
+        );
+        let mut playlist = written(&["--include-position"]).remove(0);
+        let file_name = write_playlist(&playlist, &config, None, &mut paths, None)
+            .unwrap()
+            .unwrap();
+        let path = paths.path(&file_name);
+
+        let mut reordered = playlist.tracks.clone();
+        reordered.swap(0, 1);
+        let error = verify_order(&path, &reordered).unwrap_err().to_string();
+        assert!(error.contains("row 1 is"), "{}", error);
+
+        playlist.tracks[2].position = Some(4);
+        write_track_records(&path, &playlist.tracks, &config.fields, None).unwrap();
+        let error = verify_order(&path, &playlist.tracks)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("row 3 has position Some(4)"), "{}", error);
+
+        let error = verify_order(&path, &playlist.tracks[1..])
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("expected"), "{}", error);
+    }
+}
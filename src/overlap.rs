@@ -0,0 +1,236 @@
+//! How much of Liked Songs is already covered by saved albums and playlists,
+//! to slim the library down before migrating it. A track is the same track
+//! when either its URI or its ISRC matches, so relinked and regional copies
+//! of a song count once.
+
+use log::{info, warn};
+use std::{
+    collections::HashSet,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    output::{read_library, PlaylistRecords, LIBRARY_JSON},
+    paths::OutputPaths,
+    record::{read_track_records, write_track_records, TrackRecord, DEFAULT_FIELDS},
+    table::TableOutput,
+};
+
+const BUCKET_FILE_PREFIX: &str = "overlap ";
+
+/// Track identities, matched on URI or ISRC.
+#[derive(Debug, Default)]
+struct TrackSet {
+    uris: HashSet<String>,
+    isrcs: HashSet<String>,
+}
+
+impl TrackSet {
+    /// Adds `record`, returning whether it was not in the set yet.
+    fn insert(&mut self, record: &TrackRecord) -> bool {
+        let new = !self.contains(record);
+        self.uris.extend(record.track_uri.clone());
+        self.isrcs.extend(record.isrc.clone());
+        new
+    }
+
+    fn contains(&self, record: &TrackRecord) -> bool {
+        record
+            .track_uri
+            .as_ref()
+            .is_some_and(|uri| self.uris.contains(uri))
+            || record
+                .isrc
+                .as_ref()
+                .is_some_and(|isrc| self.isrcs.contains(isrc))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bucket {
+    LikedInAlbums,
+    LikedInPlaylists,
+    PlaylistOnly,
+    UniqueLiked,
+}
+
+impl Bucket {
+    const ALL: [Bucket; 4] = [
+        Bucket::LikedInAlbums,
+        Bucket::LikedInPlaylists,
+        Bucket::PlaylistOnly,
+        Bucket::UniqueLiked,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Bucket::LikedInAlbums => "liked-in-albums",
+            Bucket::LikedInPlaylists => "liked-in-playlists",
+            Bucket::PlaylistOnly => "playlist-only",
+            Bucket::UniqueLiked => "unique-liked",
+        }
+    }
+}
+
+/// Liked Songs split by where else each track is kept. A liked track can be
+/// both on a saved album and in a playlist, so the first two buckets
+/// overlap; a unique liked track is in neither.
+#[derive(Debug)]
+pub struct Overlap {
+    liked_count: usize,
+    playlist_count: usize,
+    liked_in_albums: Vec<TrackRecord>,
+    liked_in_playlists: Vec<TrackRecord>,
+    playlist_only: Vec<TrackRecord>,
+    unique_liked: Vec<TrackRecord>,
+}
+
+impl Overlap {
+    /// Duplicates within Liked Songs and across playlists are counted once.
+    pub fn analyze(
+        liked: Vec<TrackRecord>,
+        album_track_uris: &HashSet<String>,
+        playlist_tracks: Vec<TrackRecord>,
+    ) -> Self {
+        let mut liked_set = TrackSet::default();
+        let liked: Vec<TrackRecord> = liked
+            .into_iter()
+            .filter(|record| liked_set.insert(record))
+            .collect();
+        let mut playlist_set = TrackSet::default();
+        let playlist_tracks: Vec<TrackRecord> = playlist_tracks
+            .into_iter()
+            .filter(|record| playlist_set.insert(record))
+            .collect();
+
+        // Album listings carry no ISRC, so saved albums match on URI only.
+        let on_album = |record: &TrackRecord| {
+            record
+                .track_uri
+                .as_ref()
+                .is_some_and(|uri| album_track_uris.contains(uri))
+        };
+
+        let mut overlap = Self {
+            liked_count: liked.len(),
+            playlist_count: playlist_tracks.len(),
+            liked_in_albums: Vec::new(),
+            liked_in_playlists: Vec::new(),
+            playlist_only: playlist_tracks
+                .into_iter()
+                .filter(|record| !liked_set.contains(record))
+                .collect(),
+            unique_liked: Vec::new(),
+        };
+        for record in liked {
+            let in_album = on_album(&record);
+            let in_playlist = playlist_set.contains(&record);
+            if in_album {
+                overlap.liked_in_albums.push(record.clone());
+            }
+            if in_playlist {
+                overlap.liked_in_playlists.push(record.clone());
+            }
+            if !in_album && !in_playlist {
+                overlap.unique_liked.push(record);
+            }
+        }
+        overlap
+    }
+
+    fn tracks(&self, bucket: Bucket) -> &[TrackRecord] {
+        match bucket {
+            Bucket::LikedInAlbums => &self.liked_in_albums,
+            Bucket::LikedInPlaylists => &self.liked_in_playlists,
+            Bucket::PlaylistOnly => &self.playlist_only,
+            Bucket::UniqueLiked => &self.unique_liked,
+        }
+    }
+
+    /// Liked buckets are a share of Liked Songs; playlist-only tracks are a
+    /// share of the distinct tracks across playlists.
+    fn percent(&self, bucket: Bucket) -> Option<f64> {
+        let total = match bucket {
+            Bucket::PlaylistOnly => self.playlist_count,
+            _ => self.liked_count,
+        };
+        (total > 0).then(|| 100.0 * self.tracks(bucket).len() as f64 / total as f64)
+    }
+
+    pub fn print(&self, plain: bool) {
+        let mut table = TableOutput::new(vec!["Bucket", "Tracks", "Percent"]).align_right(&[1, 2]);
+        for bucket in Bucket::ALL {
+            table.add_row(vec![
+                bucket.name().to_string(),
+                self.tracks(bucket).len().to_string(),
+                self.percent(bucket)
+                    .map(|percent| format!("{:.1}%", percent))
+                    .unwrap_or_default(),
+            ]);
+        }
+        table.print(plain);
+    }
+
+    /// Writes `overlap <bucket>.csv` for each bucket.
+    pub fn write_buckets(&self, paths: &mut OutputPaths) -> Result<(), Box<dyn Error>> {
+        for bucket in Bucket::ALL {
+            let file_name =
+                paths.reserve(&format!("{}{}", BUCKET_FILE_PREFIX, bucket.name()), "csv");
+            write_track_records(
+                &paths.path(&file_name),
+                self.tracks(bucket),
+                &DEFAULT_FIELDS,
+                None,
+            )?;
+            info!("Finished writing: {}", file_name);
+        }
+        Ok(())
+    }
+}
+
+/// Every track of a previous export in `dir`: its library.json when it was
+/// exported as JSON, otherwise each playlist CSV.
+pub fn read_exported_tracks(dir: &Path) -> Result<Vec<TrackRecord>, Box<dyn Error>> {
+    Ok(read_exported_playlists(dir)?
+        .into_iter()
+        .flat_map(|playlist| playlist.tracks)
+        .collect())
+}
+
+/// The playlists of a previous export in `dir`, read the same way as
+/// [`read_exported_tracks`]. Playlists read from CSV are named after their
+/// file and have no owner.
+pub fn read_exported_playlists(dir: &Path) -> Result<Vec<PlaylistRecords>, Box<dyn Error>> {
+    if dir.join(LIBRARY_JSON).is_file() {
+        return Ok(read_library(dir)?.playlists);
+    }
+
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| format!("{}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "csv"))
+        .collect();
+    files.sort();
+
+    let mut playlists = Vec::new();
+    for path in files {
+        let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        // Reports and earlier overlap buckets are not playlists.
+        if stem.starts_with(BUCKET_FILE_PREFIX) || OutputPaths::is_reserved(&path) {
+            continue;
+        }
+        match read_track_records(&path) {
+            Ok(tracks) => playlists.push(PlaylistRecords {
+                name: stem.to_string(),
+                owner: String::new(),
+                tracks,
+            }),
+            Err(e) => warn!("{}: not a playlist export, skipped: {}", path.display(), e),
+        }
+    }
+    Ok(playlists)
+}
@@ -0,0 +1,234 @@
+//! Hand-picked YouTube videos for particular tracks, such as a specific
+//! live version, given in a CSV of `Key,Video ID` rows where the key is a
+//! track's `spotify:track:` URI (or link) or its ISRC. An override always
+//! wins: the video is written in the YouTube Video ID column of every row
+//! for that track.
+
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ids::{normalize_uri, normalize_video_id},
+    record::{csv_reader, is_valid_isrc, TrackRecord},
+};
+
+#[derive(Debug, Deserialize)]
+struct OverrideRow {
+    #[serde(rename = "Key")]
+    key: String,
+    #[serde(rename = "Video ID")]
+    video_id: String,
+}
+
+#[derive(Debug)]
+struct VideoOverride {
+    video_id: String,
+    line: u64,
+}
+
+#[derive(Debug)]
+pub struct VideoOverrides {
+    path: PathBuf,
+    by_key: HashMap<String, VideoOverride>,
+    used: HashSet<String>,
+}
+
+/// ISRCs are matched regardless of case.
+fn normalize_isrc(isrc: &str) -> String {
+    isrc.trim().to_uppercase()
+}
+
+/// A track URI, from a URI or link, or an ISRC.
+fn normalize_key(key: &str, strict_ids: bool) -> Result<String, String> {
+    let key = key.trim();
+    if !key.starts_with("spotify:") && !key.contains("open.spotify.com/") {
+        let isrc = normalize_isrc(key);
+        return if is_valid_isrc(&isrc) {
+            Ok(isrc)
+        } else {
+            Err(format!("{:?} is neither a track URI nor an ISRC", key))
+        };
+    }
+    let uri = normalize_uri(key, strict_ids)?;
+    if uri.starts_with("spotify:track:") {
+        Ok(uri)
+    } else {
+        Err(format!("{:?} is not a track", key))
+    }
+}
+
+impl VideoOverrides {
+    /// Fails on a malformed key or video ID, or on a key given twice with
+    /// different videos, naming the lines involved. Links are accepted for
+    /// both. A key repeated with the same video is allowed.
+    pub fn load(path: &Path, strict_ids: bool) -> Result<Self, Box<dyn Error>> {
+        let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut reader = csv_reader(file);
+        let headers = reader.headers()?.clone();
+        let mut by_key: HashMap<String, VideoOverride> = HashMap::new();
+        for row in reader.records() {
+            let row = row?;
+            let line = row.position().map_or(0, |position| position.line());
+            let row: OverrideRow = row
+                .deserialize(Some(&headers))
+                .map_err(|e| format!("{} line {}: {}", path.display(), line, e))?;
+            let at_line = |e: String| format!("{} line {}: {}", path.display(), line, e);
+            let video_id = normalize_video_id(&row.video_id).map_err(at_line)?;
+            let key = normalize_key(&row.key, strict_ids).map_err(at_line)?;
+            match by_key.get(&key) {
+                Some(existing) if existing.video_id != video_id => {
+                    return Err(format!(
+                        "{}: conflicting overrides for {}: line {} says {}, line {} says {}",
+                        path.display(),
+                        key,
+                        existing.line,
+                        existing.video_id,
+                        line,
+                        video_id
+                    )
+                    .into());
+                }
+                Some(_) => {}
+                None => {
+                    by_key.insert(key, VideoOverride { video_id, line });
+                }
+            }
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            by_key,
+            used: HashSet::new(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Sets the record's video when an override names its URI or, failing
+    /// that, its ISRC.
+    pub fn apply(&mut self, record: &mut TrackRecord) {
+        let keys = [
+            record.track_uri.clone(),
+            record.isrc.as_deref().map(normalize_isrc),
+        ];
+        for key in keys.into_iter().flatten() {
+            if let Some(found) = self.by_key.get(&key) {
+                record.youtube_video_id = Some(found.video_id.clone());
+                self.used.insert(key);
+                return;
+            }
+        }
+    }
+
+    /// Overrides that matched no exported track, with their lines, in file
+    /// order.
+    pub fn unused(&self) -> Vec<(u64, &str)> {
+        let mut unused: Vec<(u64, &str)> = self
+            .by_key
+            .iter()
+            .filter(|(key, _)| !self.used.contains(*key))
+            .map(|(key, found)| (found.line, key.as_str()))
+            .collect();
+        unused.sort();
+        unused
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{record::tests::sample_record, testdir::TestDir};
+    use std::fs;
+
+    fn load(dir: &TestDir, contents: &str, strict_ids: bool) -> Result<VideoOverrides, String> {
+        let path = dir.path().join("overrides.csv");
+        fs::write(&path, contents).unwrap();
+        VideoOverrides::load(&path, strict_ids).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn links_are_read_as_their_ids() {
+        let dir = TestDir::new();
+        let mut overrides = load(
+            &dir,
+            "Key,Video ID\n\
+             https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC?si=1,https://youtu.be/dQw4w9WgXcQ\n\
+             usrc17607839,https://www.youtube.com/watch?v=9bZkp7q19f0&t=1\n",
+            true,
+        )
+        .unwrap();
+
+        let mut by_uri = sample_record("spotify:track:4uLU6hMCjMI75M1A2tKUQC", "a");
+        let mut by_isrc = sample_record("spotify:track:other", "b");
+        by_isrc.isrc = Some("USRC17607839".to_string());
+        overrides.apply(&mut by_uri);
+        overrides.apply(&mut by_isrc);
+        assert_eq!(by_uri.youtube_video_id.as_deref(), Some("dQw4w9WgXcQ"));
+        assert_eq!(by_isrc.youtube_video_id.as_deref(), Some("9bZkp7q19f0"));
+        assert!(overrides.unused().is_empty());
+    }
+
+    #[test]
+    fn bad_rows_are_reported_with_file_and_line() {
+        let dir = TestDir::new();
+        let path = dir.path().join("overrides.csv");
+        let header = "Key,Video ID\nspotify:track:4uLU6hMCjMI75M1A2tKUQC,dQw4w9WgXcQ\n";
+        for (row, problem) in [
+            (
+                "spotify:track:4uLU6hMCjMI75M1A2tKUQ,dQw4w9WgXcQ",
+                "21 characters",
+            ),
+            (
+                "spotify:album:4aawyAB9vmqN3uQ7FjRGTy,dQw4w9WgXcQ",
+                "is not a track",
+            ),
+            (
+                "spotify:track:0OdUWJ0sBjDrqHygGUXeCF,dQw4w9WgXc",
+                "is not a YouTube video ID",
+            ),
+            ("not an isrc,dQw4w9WgXcQ", "neither a track URI nor an ISRC"),
+        ] {
+            let error = load(&dir, &format!("{}{}\n", header, row), false).unwrap_err();
+            let at = format!("{} line 3: ", path.display());
+            assert!(error.starts_with(&at), "{:?}", error);
+            assert!(error.contains(problem), "{:?}", error);
+        }
+
+        let lowered = "spotify:track:4ulu6hmcjmi75m1a2tkuqc,dQw4w9WgXcQ\n";
+        assert!(load(&dir, &format!("{}{}", header, lowered), false).is_ok());
+        let error = load(&dir, &format!("{}{}", header, lowered), true).unwrap_err();
+        assert!(
+            error.contains("line 3: ") && error.contains("lowercased"),
+            "{:?}",
+            error
+        );
+    }
+
+    #[test]
+    fn conflicting_rows_name_both_lines() {
+        let dir = TestDir::new();
+        let error = load(
+            &dir,
+            "Key,Video ID\n\
+             spotify:track:4uLU6hMCjMI75M1A2tKUQC,dQw4w9WgXcQ\n\
+             spotify:track:4uLU6hMCjMI75M1A2tKUQC,dQw4w9WgXcQ\n\
+             https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC,9bZkp7q19f0\n",
+            false,
+        )
+        .unwrap_err();
+        assert!(
+            error.ends_with(
+                "conflicting overrides for spotify:track:4uLU6hMCjMI75M1A2tKUQC: \
+                 line 2 says dQw4w9WgXcQ, line 4 says 9bZkp7q19f0"
+            ),
+            "{:?}",
+            error
+        );
+    }
+}
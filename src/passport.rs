@@ -0,0 +1,92 @@
+//! profile.json, the "backup passport": whose library a run exported, how
+//! big it was and with which tool and options, for making sense of a backup
+//! long after it was taken.
+
+use log::warn;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt, fs::File, io::BufReader, path::Path};
+
+use crate::{
+    atomic::write_json_atomic, export::PlaylistExport, provenance::Provenance, spotify::User,
+};
+
+pub const PROFILE_JSON: &str = "profile.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Passport {
+    /// Unknown for anonymous exports of public playlists.
+    pub account_id: Option<String>,
+    pub display_name: Option<String>,
+    /// Only sent with the user-read-private scope, as is `product`.
+    pub country: Option<String>,
+    /// The subscription tier, such as "premium" or "free".
+    pub product: Option<String>,
+    pub playlist_count: usize,
+    /// Items across every playlist, so a track in two playlists counts twice.
+    pub track_count: usize,
+    pub provenance: Provenance,
+}
+
+impl Passport {
+    pub fn new(user: Option<&User>, exported: &[PlaylistExport], provenance: &Provenance) -> Self {
+        Self {
+            account_id: user.map(|user| user.id.clone()),
+            display_name: user.and_then(|user| user.display_name.clone()),
+            country: user.and_then(|user| user.country.clone()),
+            product: user.and_then(|user| user.product.clone()),
+            playlist_count: exported.len(),
+            track_count: exported.iter().map(|export| export.tracks.len()).sum(),
+            provenance: provenance.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Passport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unknown = |value: &Option<String>| value.clone().unwrap_or_else(|| "unknown".into());
+        writeln!(
+            f,
+            "Backup of: {} ({})",
+            unknown(&self.display_name),
+            unknown(&self.account_id)
+        )?;
+        writeln!(
+            f,
+            "Country: {}, plan: {}",
+            unknown(&self.country),
+            unknown(&self.product)
+        )?;
+        writeln!(
+            f,
+            "Playlists: {}, tracks: {}",
+            self.playlist_count, self.track_count
+        )?;
+        write!(f, "{}", self.provenance)
+    }
+}
+
+pub fn write_passport(path: &Path, passport: &Passport) -> Result<(), Box<dyn Error>> {
+    write_json_atomic(path, passport)
+}
+
+pub fn read_passport(path: &Path) -> Result<Passport, Box<dyn Error>> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(serde_json::from_reader(BufReader::new(file))?)
+}
+
+/// Warns when the passport in `dir`, if there is one, belongs to an
+/// account other than `account_id`.
+pub fn warn_if_other_account(dir: &Path, account_id: &str) {
+    let Ok(passport) = read_passport(&dir.join(PROFILE_JSON)) else {
+        return;
+    };
+    if let Some(exported_from) = passport.account_id.filter(|id| id != account_id) {
+        warn!(
+            "{} was exported from account {}, not {}, the account being written to",
+            dir.display(),
+            exported_from,
+            account_id
+        );
+    }
+}
@@ -0,0 +1,327 @@
+//! Every output file's name and path. Names are made safe on every
+//! platform, not just the one running the export, since output directories
+//! are often synced or copied between machines.
+
+use log::warn;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    changelog::CHANGES_MD,
+    dedupe::DUPLICATES_CSV,
+    index::INDEX_JSON,
+    manifest::MANIFEST_JSON,
+    output::{EXPORT_CONFIG_JSON, LIBRARY_JSON},
+    passport::PROFILE_JSON,
+    quarantine::QUARANTINE_JSON,
+    report::ARTIST_FREQUENCY_CSV,
+    state::STATE_JSON,
+    warnings::EXPORT_WARNINGS_JSON,
+};
+
+/// Library-wide artifacts. They are reserved whether or not this run writes
+/// them, so a playlist's file name never depends on which flags were passed.
+pub const RESERVED_NAMES: [&str; 11] = [
+    LIBRARY_JSON,
+    EXPORT_CONFIG_JSON,
+    DUPLICATES_CSV,
+    ARTIST_FREQUENCY_CSV,
+    INDEX_JSON,
+    QUARANTINE_JSON,
+    EXPORT_WARNINGS_JSON,
+    STATE_JSON,
+    MANIFEST_JSON,
+    PROFILE_JSON,
+    CHANGES_MD,
+];
+
+/// Device names Windows reserves in every directory, with any extension.
+const WINDOWS_DEVICE_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Longest stem, in UTF-8 bytes, so a file name stays well under the 255
+/// bytes most filesystems allow even with a suffix and extension.
+const MAX_STEM_BYTES: usize = 200;
+
+/// The file stem a playlist called `name` is written under. Characters
+/// Windows forbids become `_`, trailing dots and spaces (which Windows
+/// drops) are removed, device names like `CON` get a `_` appended, and long
+/// names are cut short at a character boundary.
+pub fn file_stem(name: &str) -> String {
+    let mut stem = String::new();
+    for c in name.chars() {
+        let c = match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        };
+        if stem.len() + c.len_utf8() > MAX_STEM_BYTES {
+            break;
+        }
+        stem.push(c);
+    }
+    stem.truncate(stem.trim_end_matches(['.', ' ']).len());
+    if stem.is_empty() {
+        return "_".to_string();
+    }
+    let device = stem.split('.').next().unwrap_or_default().trim_end();
+    if WINDOWS_DEVICE_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(device))
+    {
+        stem.push('_');
+    }
+    stem
+}
+
+/// `path` in the `\\?\` form Windows needs for paths over 260 characters.
+#[cfg(windows)]
+fn extended_length(path: PathBuf) -> PathBuf {
+    let Ok(absolute) = std::path::absolute(&path) else {
+        return path;
+    };
+    let text = absolute.to_string_lossy();
+    if text.starts_with(r"\\?\") {
+        absolute
+    } else if let Some(share) = text.strip_prefix(r"\\") {
+        PathBuf::from(format!(r"\\?\UNC\{}", share))
+    } else {
+        PathBuf::from(format!(r"\\?\{}", text))
+    }
+}
+
+/// Other platforms have no such limit.
+#[cfg(not(windows))]
+fn extended_length(path: PathBuf) -> PathBuf {
+    path
+}
+
+/// Every file a run writes claims its name here first, so two writers can
+/// never silently overwrite each other's output.
+#[derive(Debug)]
+pub struct OutputPaths {
+    /// Lowercased, since common filesystems are case-insensitive.
+    taken: HashSet<String>,
+    /// The next suffix to try per wanted name, so a library with thousands
+    /// of "New Playlist"s does not rescan every suffix for each one.
+    next_suffix: HashMap<String, u32>,
+    /// Names handed out, in order, for the manifest.
+    claimed: Vec<String>,
+    /// Whether `path` gives extended-length paths on Windows.
+    long_paths: bool,
+    /// Where the files go; empty for the working directory.
+    dir: PathBuf,
+}
+
+impl OutputPaths {
+    pub fn new(long_paths: bool) -> Self {
+        Self {
+            taken: RESERVED_NAMES
+                .iter()
+                .map(|name| name.to_lowercase())
+                .collect(),
+            next_suffix: HashMap::new(),
+            claimed: Vec::new(),
+            long_paths,
+            dir: PathBuf::new(),
+        }
+    }
+
+    /// Like `new`, but writing into `dir` rather than the working directory.
+    #[cfg(test)]
+    pub fn in_dir(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            ..Self::new(false)
+        }
+    }
+
+    /// Claims a file name for a playlist, made safe by [`file_stem`]. A
+    /// name that is already taken, in any letter case, gets a " (2)",
+    /// " (3)", ... suffix before its extension; since playlists are written
+    /// in API order the result is deterministic.
+    pub fn reserve(&mut self, playlist_name: &str, extension: &str) -> String {
+        let stem = file_stem(playlist_name);
+        let wanted = format!("{}.{}", stem, extension);
+        if self.taken.insert(wanted.to_lowercase()) {
+            self.claimed.push(wanted.clone());
+            return wanted;
+        }
+
+        let next = self.next_suffix.entry(wanted.to_lowercase()).or_insert(2);
+        let file_name = loop {
+            let candidate = format!("{} ({}).{}", stem, next, extension);
+            *next += 1;
+            if self.taken.insert(candidate.to_lowercase()) {
+                break candidate;
+            }
+        };
+        warn!(
+            "{} is already written by this run; writing {} instead",
+            wanted, file_name
+        );
+        self.claimed.push(file_name.clone());
+        file_name
+    }
+
+    /// The name `reserve` would hand out for `playlist_name` without a
+    /// suffix, if nothing has claimed it yet.
+    pub fn peek(&self, playlist_name: &str, extension: &str) -> Option<String> {
+        let wanted = format!("{}.{}", file_stem(playlist_name), extension);
+        (!self.taken.contains(&wanted.to_lowercase())).then_some(wanted)
+    }
+
+    /// Where to write a file `reserve` handed out. With `--long-paths` on
+    /// Windows this is the extended-length form of its absolute path.
+    pub fn path(&self, file_name: &str) -> PathBuf {
+        let path = self.dir.join(file_name);
+        if self.long_paths {
+            extended_length(path)
+        } else {
+            path
+        }
+    }
+
+    /// Where to write `name`, one of the library-wide artifacts in
+    /// [`RESERVED_NAMES`], counting it among the files this run wrote.
+    pub fn library_file(&mut self, name: &str) -> PathBuf {
+        debug_assert!(RESERVED_NAMES.contains(&name), "{} is not reserved", name);
+        if !self.claimed.iter().any(|claimed| claimed == name) {
+            self.claimed.push(name.to_string());
+        }
+        self.path(name)
+    }
+
+    /// Every name `reserve` and `library_file` have handed out, in order.
+    pub fn claimed(&self) -> &[String] {
+        &self.claimed
+    }
+
+    /// Whether `path` is one of the library-wide artifacts rather than a
+    /// playlist's file.
+    pub fn is_reserved(path: &Path) -> bool {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| {
+                RESERVED_NAMES
+                    .iter()
+                    .any(|reserved| reserved.eq_ignore_ascii_case(name))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+    use std::fs;
+
+    /// Writes a file named `file_name` into `dir`, as the export would.
+    fn write(dir: &Path, file_name: &str) {
+        fs::write(dir.join(file_name), file_name).unwrap();
+    }
+
+    #[test]
+    fn long_names_fit_in_a_file_name() {
+        for name in ["x".repeat(400), "€".repeat(400), "🎵".repeat(400)] {
+            let stem = file_stem(&name);
+            assert!(stem.len() <= MAX_STEM_BYTES);
+            assert!(name.starts_with(&stem));
+        }
+        // Enough for a suffix and an extension on top.
+        let dir = TestDir::new();
+        let mut paths = OutputPaths::new(false);
+        for _ in 0..3 {
+            write(dir.path(), &paths.reserve(&"ü".repeat(300), "csv"));
+        }
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn names_differing_in_case_get_their_own_files() {
+        let dir = TestDir::new();
+        let mut paths = OutputPaths::new(false);
+        let names: Vec<String> = ["Road Trip", "ROAD TRIP", "road trip", "Index"]
+            .iter()
+            .map(|name| paths.reserve(name, "csv"))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "Road Trip.csv",
+                "ROAD TRIP (2).csv",
+                "road trip (3).csv",
+                "Index.csv"
+            ]
+        );
+        // Also a playlist named after a library-wide artifact.
+        assert_eq!(paths.reserve("INDEX", "json"), "INDEX (2).json");
+        for name in &names {
+            write(dir.path(), name);
+        }
+        for name in &names {
+            assert_eq!(fs::read_to_string(dir.path().join(name)).unwrap(), *name);
+        }
+    }
+
+    #[test]
+    fn forbidden_names_are_made_safe() {
+        let dir = TestDir::new();
+        let mut paths = OutputPaths::new(false);
+        for (name, expected) in [
+            ("AC/DC: Live?", "AC_DC_ Live_.csv"),
+            ("con", "con_.csv"),
+            ("Lpt1.mix", "Lpt1.mix_.csv"),
+            ("Trailing dots...", "Trailing dots.csv"),
+            ("...", "_.csv"),
+        ] {
+            let file_name = paths.reserve(name, "csv");
+            assert_eq!(file_name, expected);
+            write(dir.path(), &file_name);
+        }
+    }
+
+    #[test]
+    fn deep_directories_hold_long_names() {
+        let dir = TestDir::new();
+        let mut deep = dir.path().to_path_buf();
+        for level in 0..12 {
+            deep.push(format!("{}{}", "nested directory ", level).repeat(2));
+        }
+        fs::create_dir_all(&deep).unwrap();
+        let mut paths = OutputPaths::new(true);
+        let file_name = paths.reserve(&"a long playlist name ".repeat(20), "csv");
+        let path = paths.path(&file_name);
+        assert!(path.ends_with(&file_name));
+        write(&deep, &file_name);
+        assert!(deep.join(&file_name).is_file());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn long_paths_are_extended_length_on_windows() {
+        let path = OutputPaths::new(true).path("Road Trip.csv");
+        assert!(path.to_string_lossy().starts_with(r"\\?\"));
+        assert!(path.is_absolute());
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn long_paths_change_nothing_elsewhere() {
+        let path = OutputPaths::new(true).path("Road Trip.csv");
+        assert_eq!(path, Path::new("Road Trip.csv"));
+    }
+
+    #[test]
+    fn library_files_are_claimed_once() {
+        let mut paths = OutputPaths::new(false);
+        paths.reserve("Mix", "csv");
+        paths.library_file(INDEX_JSON);
+        paths.library_file(INDEX_JSON);
+        assert_eq!(paths.claimed(), ["Mix.csv", INDEX_JSON]);
+    }
+}
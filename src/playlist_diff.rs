@@ -0,0 +1,148 @@
+//! `diff`: which tracks one exported playlist CSV has that another lacks,
+//! typically two runs' copies of the same playlist. Order is ignored; the
+//! `--emit-delta` files cover moves.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+};
+
+use crate::record::TrackRecord;
+
+#[derive(Debug, Serialize)]
+pub struct PlaylistDiff {
+    /// In the new file only, in its order.
+    pub added: Vec<TrackRecord>,
+    /// In the old file only, in its order.
+    pub removed: Vec<TrackRecord>,
+    pub unchanged_count: usize,
+    pub compared_at: DateTime<Utc>,
+}
+
+fn describe(record: &TrackRecord) -> String {
+    let name = record.track_name.as_deref().unwrap_or("Untitled");
+    if record.artist_names.is_empty() {
+        name.to_string()
+    } else {
+        format!("{} - {}", record.artist_names, name)
+    }
+}
+
+/// How the rows of two copies of a playlist pair up, by position. Used by
+/// `diff`, `--emit-delta` and CHANGES.md alike, so they agree on what
+/// changed.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct TrackMatch {
+    /// Old and new positions of the rows in both, in new order.
+    pub matched: Vec<(usize, usize)>,
+    /// New positions of the rows only in the new copy, ascending.
+    pub added: Vec<usize>,
+    /// Old positions of the rows only in the old copy, ascending.
+    pub removed: Vec<usize>,
+}
+
+impl TrackMatch {
+    /// Rows match by `TrackRecord::identity_key`. Repeats of a track are
+    /// matched in order, the first old copy with the first new one, so a
+    /// second copy added to a playlist shows up as added.
+    pub fn new(old: &[TrackRecord], new: &[TrackRecord]) -> Self {
+        let mut unmatched: HashMap<String, VecDeque<usize>> = HashMap::new();
+        for (position, record) in old.iter().enumerate() {
+            unmatched
+                .entry(record.identity_key())
+                .or_default()
+                .push_back(position);
+        }
+        let mut found = Self::default();
+        for (position, record) in new.iter().enumerate() {
+            match unmatched
+                .get_mut(&record.identity_key())
+                .and_then(VecDeque::pop_front)
+            {
+                Some(old_position) => found.matched.push((old_position, position)),
+                None => found.added.push(position),
+            }
+        }
+        found.removed = unmatched.into_values().flatten().collect();
+        found.removed.sort_unstable();
+        found
+    }
+}
+
+impl PlaylistDiff {
+    pub fn compare(old: Vec<TrackRecord>, new: Vec<TrackRecord>) -> Self {
+        let found = TrackMatch::new(&old, &new);
+        let mut old: Vec<Option<TrackRecord>> = old.into_iter().map(Some).collect();
+        let mut new: Vec<Option<TrackRecord>> = new.into_iter().map(Some).collect();
+        PlaylistDiff {
+            added: found
+                .added
+                .iter()
+                .filter_map(|&position| new[position].take())
+                .collect(),
+            removed: found
+                .removed
+                .iter()
+                .filter_map(|&position| old[position].take())
+                .collect(),
+            unchanged_count: found.matched.len(),
+            compared_at: Utc::now(),
+        }
+    }
+
+    /// `git diff` style: a `-` line per removed track and a `+` line per
+    /// added one, under `---` and `+++` lines naming the files.
+    pub fn unified(&self, old_name: &str, new_name: &str) -> String {
+        let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+        for record in &self.removed {
+            out.push_str(&format!("-{}\n", describe(record)));
+        }
+        for record in &self.added {
+            out.push_str(&format!("+{}\n", describe(record)));
+        }
+        out
+    }
+}
+
+impl fmt::Display for PlaylistDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} removed, {} unchanged (compared {})",
+            self.added.len(),
+            self.removed.len(),
+            self.unchanged_count,
+            self.compared_at.format("%Y-%m-%d %H:%M:%S UTC")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::tests::sample_record;
+
+    fn tracks(names: &[&str]) -> Vec<TrackRecord> {
+        names
+            .iter()
+            .map(|name| sample_record(&format!("spotify:track:{}", name), name))
+            .collect()
+    }
+
+    #[test]
+    fn repeats_match_one_for_one_in_order() {
+        let old = tracks(&["a", "b", "a", "c"]);
+        let new = tracks(&["a", "a", "a", "c"]);
+        let found = TrackMatch::new(&old, &new);
+        assert_eq!(found.matched, [(0, 0), (2, 1), (3, 3)]);
+        assert_eq!(found.added, [2]);
+        assert_eq!(found.removed, [1]);
+
+        let diff = PlaylistDiff::compare(old, new);
+        assert_eq!(diff.unchanged_count, 3);
+        assert_eq!(diff.added[0].track_name.as_deref(), Some("a"));
+        assert_eq!(diff.removed[0].track_name.as_deref(), Some("b"));
+    }
+}
@@ -0,0 +1,200 @@
+//! What produced an artifact: the tool's version and commit, the account
+//! and the effective options. It is written into the run summary, JSON
+//! sidecars, the SQLite store's `meta` table and, with `--csv-preamble`,
+//! each CSV, and `inspect` reads it back from any of them. The tool writes
+//! no archives, so there is no archive comment to carry it.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use crate::store::{read_store_provenance, SQLITE_HEADER};
+
+pub const TOOL_NAME: &str = env!("CARGO_PKG_NAME");
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const GIT_COMMIT: Option<&str> = option_env!("RIMUSIC_GIT_COMMIT");
+
+/// Prefix of the optional CSV preamble line carrying the provenance.
+const PREAMBLE_PREFIX: &str = "# provenance: ";
+
+/// What produced an artifact. Everything but `generated_at` is stable for a
+/// given build, account and set of options; `generated_at` is volatile, and
+/// left out under `--deterministic`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Provenance {
+    pub tool: String,
+    pub version: String,
+    pub git_commit: Option<String>,
+    /// Spotify user ID of the exported library, if it could be fetched.
+    pub account_id: Option<String>,
+    /// The effective export options, defaults included. Never holds the token.
+    pub options: serde_json::Value,
+    /// RFC 3339 timestamp. Volatile; `None` under `--deterministic`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generated_at: Option<String>,
+}
+
+impl Provenance {
+    pub fn new<T: Serialize>(account_id: Option<String>, options: &T) -> Self {
+        Self {
+            tool: TOOL_NAME.to_string(),
+            version: VERSION.to_string(),
+            git_commit: GIT_COMMIT.map(str::to_string),
+            account_id,
+            options: serde_json::to_value(options).unwrap_or_default(),
+            generated_at: Some(chrono::Utc::now().to_rfc3339()),
+        }
+    }
+
+    /// Leaves out the volatile fields, so that the same build exporting the
+    /// same account with the same options records the same provenance.
+    pub fn deterministic(mut self) -> Self {
+        self.generated_at = None;
+        self
+    }
+
+    /// The single comment line written ahead of a CSV's header.
+    pub fn to_preamble(&self) -> Result<String, Box<dyn Error>> {
+        Ok(format!(
+            "{}{}",
+            PREAMBLE_PREFIX,
+            serde_json::to_string(self)?
+        ))
+    }
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Tool: {} {}", self.tool, self.version)?;
+        writeln!(
+            f,
+            "Commit: {}",
+            self.git_commit.as_deref().unwrap_or("unknown")
+        )?;
+        writeln!(
+            f,
+            "Account: {}",
+            self.account_id.as_deref().unwrap_or("unknown")
+        )?;
+        writeln!(
+            f,
+            "Generated at: {}",
+            self.generated_at
+                .as_deref()
+                .unwrap_or("not recorded (--deterministic)")
+        )?;
+        write!(
+            f,
+            "Options: {}",
+            serde_json::to_string_pretty(&self.options).map_err(|_| fmt::Error)?
+        )
+    }
+}
+
+/// Reads the provenance of a file the tool wrote: the `provenance` member of
+/// a JSON artifact, the `meta` table of an SQLite store, or the preamble
+/// line of a CSV. `None` if it has none.
+pub fn inspect(path: &Path) -> Result<Option<Provenance>, Box<dyn Error>> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut reader = BufReader::new(file);
+
+    if reader.fill_buf()?.starts_with(SQLITE_HEADER) {
+        return read_store_provenance(path);
+    }
+
+    if path.extension().is_some_and(|ext| ext == "json") {
+        let mut value: serde_json::Value = serde_json::from_reader(reader)?;
+        return Ok(
+            match value.get_mut("provenance").map(serde_json::Value::take) {
+                Some(serde_json::Value::Null) | None => None,
+                Some(provenance) => Some(serde_json::from_value(provenance)?),
+            },
+        );
+    }
+
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && line.starts_with('#') {
+        if let Some(json) = line.trim_end().strip_prefix(PREAMBLE_PREFIX) {
+            return Ok(Some(serde_json::from_str(json)?));
+        }
+        line.clear();
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        store::{RunFilters, Store, URL_SCHEME},
+        testdir::TestDir,
+    };
+    use std::fs;
+
+    fn provenance() -> Provenance {
+        let options = serde_json::json!({"format": "csv", "only_owned": true});
+        Provenance::new(Some("wizzler".to_string()), &options)
+    }
+
+    #[test]
+    fn is_stable_under_deterministic() {
+        let first = serde_json::to_string(&provenance().deterministic()).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let second = serde_json::to_string(&provenance().deterministic()).unwrap();
+        assert_eq!(first, second);
+        assert!(!first.contains("generated_at"));
+        assert!(provenance().generated_at.is_some());
+    }
+
+    #[test]
+    fn inspect_reads_every_kind_of_artifact() {
+        let dir = TestDir::new();
+        let expected = provenance();
+
+        let csv = dir.path().join("Road Trip.csv");
+        fs::write(
+            &csv,
+            format!("{}\nTrack Name\nSong\n", expected.to_preamble().unwrap()),
+        )
+        .unwrap();
+        let json = dir.path().join("index.json");
+        fs::write(
+            &json,
+            serde_json::to_string(&serde_json::json!({"provenance": expected})).unwrap(),
+        )
+        .unwrap();
+        let db = dir.path().join("library.db");
+        let store = Store::open(&format!("{}{}", URL_SCHEME, db.display())).unwrap();
+        store
+            .begin_run(&[], Some(&expected), &RunFilters::default())
+            .unwrap();
+        drop(store);
+
+        for path in [&csv, &json, &db] {
+            let found = inspect(path).unwrap().unwrap();
+            assert_eq!(
+                serde_json::to_value(&found).unwrap(),
+                serde_json::to_value(&expected).unwrap(),
+                "{}",
+                path.display()
+            );
+        }
+    }
+
+    #[test]
+    fn artifacts_without_provenance_have_none() {
+        let dir = TestDir::new();
+        let csv = dir.path().join("plain.csv");
+        fs::write(&csv, "Track Name\nSong\n").unwrap();
+        assert!(inspect(&csv).unwrap().is_none());
+        let db = dir.path().join("library.db");
+        Store::open(&format!("{}{}", URL_SCHEME, db.display())).unwrap();
+        assert!(inspect(&db).unwrap().is_none());
+    }
+}
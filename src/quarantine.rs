@@ -0,0 +1,200 @@
+//! Pages of playlist items that kept failing during an export. The export
+//! carries on without them, so the playlist's CSV has a gap where the page's
+//! rows belong; `retry-quarantine` fetches just those pages and splices the
+//! recovered rows back in at that position.
+
+use clap::ValueEnum;
+use csv::{ByteRecord, Writer};
+use log::{info, warn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::BufReader,
+    path::Path,
+};
+
+use crate::{
+    api::{aborts_run, item_range, SpotifyAPI},
+    atomic::{write_bytes_atomic, write_json_atomic},
+    filter::TrackFilter,
+    manifest::{refresh_manifest_entry, MANIFEST_JSON},
+    record::{csv_reader, Field, RecordOptions, TrackRecord},
+};
+
+pub const QUARANTINE_JSON: &str = "quarantine.json";
+
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Quarantine {
+    /// The export's settings, so recovered rows are built the same way.
+    #[serde(flatten)]
+    pub record_options: RecordOptions,
+    pub filter: TrackFilter,
+    pub pages: Vec<QuarantinedPage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct QuarantinedPage {
+    pub playlist_id: String,
+    pub playlist_name: String,
+    pub owner: String,
+    pub url: String,
+    pub offset: u32,
+    pub limit: u32,
+    pub error: String,
+    /// The CSV the page's rows belong in.
+    pub file: Option<String>,
+    /// The data row (0-based, after the header) the page's rows start at.
+    /// `None` when the output was sorted, split or numbered, since rows then
+    /// have no fixed position; such pages can only be recovered by exporting
+    /// again.
+    pub row: Option<usize>,
+}
+
+impl QuarantinedPage {
+    /// Whether `self` comes after `other` in the same file, so inserting
+    /// `other`'s rows moves `self` down.
+    fn follows(&self, other: &QuarantinedPage) -> bool {
+        self.file == other.file
+            && match (self.row, other.row) {
+                (Some(a), Some(b)) => a > b || (a == b && self.offset > other.offset),
+                _ => false,
+            }
+    }
+}
+
+/// Writes the quarantine, or removes a stale one when nothing failed.
+pub fn write_quarantine(path: &Path, quarantine: &Quarantine) -> Result<(), Box<dyn Error>> {
+    if quarantine.pages.is_empty() {
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        return Ok(());
+    }
+    write_json_atomic(path, quarantine)
+}
+
+pub fn read_quarantine(path: &Path) -> Result<Quarantine, Box<dyn Error>> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(serde_json::from_reader(BufReader::new(file))?)
+}
+
+/// Retries every quarantined page, splicing recovered rows into their CSVs
+/// and keeping the pages that still fail. Returns how many were recovered.
+pub async fn retry_quarantine(api: &SpotifyAPI, path: &Path) -> Result<usize, Box<dyn Error>> {
+    let mut quarantine = read_quarantine(path)?;
+    let mut pages = std::mem::take(&mut quarantine.pages);
+    // Last position first, so splicing never moves a page not yet handled.
+    pages.sort_by(|a, b| (&a.file, b.row, b.offset).cmp(&(&b.file, a.row, a.offset)));
+
+    let mut recovered = 0;
+    let mut pages = pages.into_iter();
+    while let Some(page) = pages.next() {
+        let (Some(file), Some(row)) = (&page.file, page.row) else {
+            warn!(
+                "{} {}: rows have no fixed position in sorted, split or numbered output; \
+                 export again to recover them",
+                page.playlist_name,
+                item_range(page.offset, page.limit)
+            );
+            quarantine.pages.push(page);
+            continue;
+        };
+
+        let response = match api
+            .get_playlist_tracks_page(&page.url, page.offset, page.limit)
+            .await
+        {
+            Ok(response) => response,
+            Err(e) if aborts_run(&*e) => {
+                // Keep every page not recovered yet for the next try.
+                quarantine.pages.push(page);
+                quarantine.pages.extend(pages);
+                write_quarantine(path, &quarantine)?;
+                refresh_manifest_entry(Path::new(MANIFEST_JSON), &path.to_string_lossy())?;
+                return Err(e);
+            }
+            Err(e) => {
+                warn!("{} items still failing: {}", page.playlist_name, e);
+                quarantine.pages.push(QuarantinedPage {
+                    error: e.to_string(),
+                    ..page
+                });
+                continue;
+            }
+        };
+
+        let records: Vec<TrackRecord> = response
+            .items
+            .iter()
+            .filter_map(|item| Some((item, item.track.as_ref()?)))
+            .map(|(item, track)| {
+                TrackRecord::from_track(
+                    track,
+                    &page.owner,
+                    item.added_at.clone().unwrap_or_default(),
+                    quarantine.record_options,
+                )
+            })
+            .filter(|record| quarantine.filter.matches(record))
+            .collect();
+        splice_rows(Path::new(file), row, &records)?;
+        refresh_manifest_entry(Path::new(MANIFEST_JSON), file)?;
+        info!(
+            "Recovered {} rows into {} at row {}",
+            records.len(),
+            file,
+            row
+        );
+
+        for waiting in quarantine.pages.iter_mut().filter(|p| p.follows(&page)) {
+            waiting.row = waiting.row.map(|r| r + records.len());
+        }
+        recovered += 1;
+    }
+
+    write_quarantine(path, &quarantine)?;
+    refresh_manifest_entry(Path::new(MANIFEST_JSON), &path.to_string_lossy())?;
+    Ok(recovered)
+}
+
+/// Inserts `records` before data row `row` of a CSV written by the export,
+/// leaving every other byte of the file as it was.
+fn splice_rows(path: &Path, row: usize, records: &[TrackRecord]) -> Result<(), Box<dyn Error>> {
+    let contents = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let mut reader = csv_reader(contents.as_slice());
+    let fields = reader
+        .headers()?
+        .iter()
+        .map(|header| {
+            Field::value_variants()
+                .iter()
+                .copied()
+                .find(|field| field.header() == header)
+                .ok_or_else(|| format!("{}: unknown column {:?}", path.display(), header))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut at = reader.position().byte() as usize;
+    let mut record = ByteRecord::new();
+    for _ in 0..row {
+        if !reader.read_byte_record(&mut record)? {
+            return Err(format!("{} has fewer than {} rows", path.display(), row).into());
+        }
+        at = reader.position().byte() as usize;
+    }
+
+    let mut writer = Writer::from_writer(Vec::new());
+    for record in records {
+        writer.write_record(fields.iter().map(|f| f.value(record)))?;
+    }
+    let inserted = writer.into_inner().map_err(|e| e.to_string())?;
+
+    let mut spliced = Vec::with_capacity(contents.len() + inserted.len());
+    spliced.extend_from_slice(&contents[..at]);
+    spliced.extend_from_slice(&inserted);
+    spliced.extend_from_slice(&contents[at..]);
+    write_bytes_atomic(path, &spliced)
+}
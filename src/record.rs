@@ -0,0 +1,857 @@
+use clap::ValueEnum;
+use csv::{Reader, ReaderBuilder, Writer};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    error::Error,
+    fs::File,
+    io::{Read, Write},
+    path::Path,
+    str::FromStr,
+};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{
+    spotify::{Artist, Image, Track},
+    urls::output_url,
+};
+
+/// A CSV column. Every CSV writer takes the list of fields to emit, so
+/// optional columns only appear when the feature producing them is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Field {
+    TrackUri,
+    TrackName,
+    ArtistUris,
+    ArtistNames,
+    AlbumUri,
+    AlbumName,
+    AlbumArtistUris,
+    AlbumArtistNames,
+    AlbumReleaseDate,
+    AlbumImageUrl,
+    DiscNumber,
+    TrackNumber,
+    DurationMs,
+    PreviewUrl,
+    Explicit,
+    Popularity,
+    Isrc,
+    AddedBy,
+    AddedAt,
+    Substituted,
+    PlaylistFollowers,
+    LikelyAddedFor,
+    AlbumImageWidth,
+    AlbumImageHeight,
+    Position,
+    AlbumRun,
+    AlbumImageFile,
+    AddedByProfile,
+    MarketCount,
+    Rare,
+    Playlists,
+    YoutubeVideoId,
+    LocalFilePath,
+    Liked,
+    SavedAlbum,
+}
+
+pub const DEFAULT_FIELDS: [Field; 19] = [
+    Field::TrackUri,
+    Field::TrackName,
+    Field::ArtistUris,
+    Field::ArtistNames,
+    Field::AlbumUri,
+    Field::AlbumName,
+    Field::AlbumArtistUris,
+    Field::AlbumArtistNames,
+    Field::AlbumReleaseDate,
+    Field::AlbumImageUrl,
+    Field::DiscNumber,
+    Field::TrackNumber,
+    Field::DurationMs,
+    Field::PreviewUrl,
+    Field::Explicit,
+    Field::Popularity,
+    Field::Isrc,
+    Field::AddedBy,
+    Field::AddedAt,
+];
+
+impl Field {
+    /// The CSV header, which is also the field's key in JSON exports.
+    pub fn header(self) -> &'static str {
+        match self {
+            Field::TrackUri => "Track URI",
+            Field::TrackName => "Track Name",
+            Field::ArtistUris => "Artist URI(s)",
+            Field::ArtistNames => "Artist Name(s)",
+            Field::AlbumUri => "Album URI",
+            Field::AlbumName => "Album Name",
+            Field::AlbumArtistUris => "Album Artist URI(s)",
+            Field::AlbumArtistNames => "Album Artist Name(s)",
+            Field::AlbumReleaseDate => "Album Release Date",
+            Field::AlbumImageUrl => "Album Image URL",
+            Field::DiscNumber => "Disc Number",
+            Field::TrackNumber => "Track Number",
+            Field::DurationMs => "Track Duration (ms)",
+            Field::PreviewUrl => "Track Preview URL",
+            Field::Explicit => "Explicit",
+            Field::Popularity => "Popularity",
+            Field::Isrc => "ISRC",
+            Field::AddedBy => "Added By",
+            Field::AddedAt => "Added At",
+            Field::Substituted => "Substituted",
+            Field::PlaylistFollowers => "Playlist Followers",
+            Field::LikelyAddedFor => "Likely Added For",
+            Field::AlbumImageWidth => "Album Image Width",
+            Field::AlbumImageHeight => "Album Image Height",
+            Field::Position => "Position",
+            Field::AlbumRun => "Album Run",
+            Field::AlbumImageFile => "Album Image File",
+            Field::AddedByProfile => "Added By Profile",
+            Field::MarketCount => "Market Count",
+            Field::Rare => "Rare",
+            Field::Playlists => "Playlists",
+            Field::YoutubeVideoId => "YouTube Video ID",
+            Field::LocalFilePath => "Local File Path",
+            Field::Liked => "Liked",
+            Field::SavedAlbum => "Saved Album",
+        }
+    }
+
+    /// The cell for `record`; missing values are empty.
+    pub fn value(self, record: &TrackRecord) -> String {
+        fn opt<T: ToString>(value: &Option<T>) -> String {
+            value.as_ref().map(T::to_string).unwrap_or_default()
+        }
+
+        match self {
+            Field::TrackUri => opt(&record.track_uri),
+            Field::TrackName => opt(&record.track_name),
+            Field::ArtistUris => record.artist_uris.clone(),
+            Field::ArtistNames => record.artist_names.clone(),
+            Field::AlbumUri => opt(&record.album_uri),
+            Field::AlbumName => opt(&record.album_name),
+            Field::AlbumArtistUris => record.album_artist_uris.clone(),
+            Field::AlbumArtistNames => record.album_artist_names.clone(),
+            Field::AlbumReleaseDate => opt(&record.album_release_date),
+            Field::AlbumImageUrl => opt(&record.album_image_url),
+            Field::DiscNumber => opt(&record.disc_number),
+            Field::TrackNumber => opt(&record.track_number),
+            Field::DurationMs => opt(&record.duration_ms),
+            Field::PreviewUrl => opt(&record.preview_url),
+            Field::Explicit => opt(&record.explicit),
+            Field::Popularity => opt(&record.popularity),
+            Field::Isrc => opt(&record.isrc),
+            Field::AddedBy => opt(&record.added_by),
+            Field::AddedAt => opt(&record.added_at),
+            Field::Substituted => opt(&record.substituted),
+            Field::PlaylistFollowers => opt(&record.playlist_followers),
+            Field::LikelyAddedFor => opt(&record.likely_added_for),
+            Field::AlbumImageWidth => opt(&record.album_image_width),
+            Field::AlbumImageHeight => opt(&record.album_image_height),
+            Field::Position => opt(&record.position),
+            Field::AlbumRun => opt(&record.album_run),
+            Field::AlbumImageFile => opt(&record.album_image_file),
+            Field::AddedByProfile => opt(&record.added_by_profile),
+            Field::MarketCount => opt(&record.market_count),
+            Field::Rare => opt(&record.rare),
+            Field::Playlists => opt(&record.playlists),
+            Field::YoutubeVideoId => opt(&record.youtube_video_id),
+            Field::LocalFilePath => opt(&record.local_file_path),
+            Field::Liked => opt(&record.liked),
+            Field::SavedAlbum => opt(&record.saved_album),
+        }
+    }
+}
+
+/// One exported row. Missing values stay `None` and are written as empty
+/// cells, so a popularity of `0` and an unknown popularity survive a
+/// CSV round-trip as different values.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TrackRecord {
+    #[serde(rename = "Track URI")]
+    pub track_uri: Option<String>,
+    #[serde(rename = "Track Name")]
+    pub track_name: Option<String>,
+    #[serde(rename = "Artist URI(s)")]
+    pub artist_uris: String,
+    #[serde(rename = "Artist Name(s)")]
+    pub artist_names: String,
+    #[serde(rename = "Album URI")]
+    pub album_uri: Option<String>,
+    #[serde(rename = "Album Name")]
+    pub album_name: Option<String>,
+    #[serde(rename = "Album Artist URI(s)")]
+    pub album_artist_uris: String,
+    #[serde(rename = "Album Artist Name(s)")]
+    pub album_artist_names: String,
+    #[serde(rename = "Album Release Date")]
+    pub album_release_date: Option<String>,
+    #[serde(rename = "Album Image URL")]
+    pub album_image_url: Option<String>,
+    #[serde(rename = "Disc Number")]
+    pub disc_number: Option<u32>,
+    #[serde(rename = "Track Number")]
+    pub track_number: Option<u32>,
+    #[serde(rename = "Track Duration (ms)")]
+    pub duration_ms: Option<u32>,
+    #[serde(rename = "Track Preview URL")]
+    pub preview_url: Option<String>,
+    #[serde(rename = "Explicit")]
+    pub explicit: Option<bool>,
+    #[serde(rename = "Popularity")]
+    pub popularity: Option<u8>,
+    #[serde(rename = "ISRC")]
+    pub isrc: Option<String>,
+    #[serde(rename = "Added By")]
+    pub added_by: Option<String>,
+    #[serde(rename = "Added At")]
+    pub added_at: Option<String>,
+    /// URI of the explicit track this clean version replaced.
+    #[serde(
+        rename = "Substituted",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub substituted: Option<String>,
+    /// Follower count of the playlist the row belongs to.
+    #[serde(
+        rename = "Playlist Followers",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub playlist_followers: Option<u64>,
+    /// Heuristic guess at the Blend member a track was added for.
+    #[serde(
+        rename = "Likely Added For",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub likely_added_for: Option<String>,
+    /// Dimensions of the image in Album Image URL.
+    #[serde(
+        rename = "Album Image Width",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub album_image_width: Option<u64>,
+    #[serde(
+        rename = "Album Image Height",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub album_image_height: Option<u64>,
+    /// `album`, `single` or `compilation`. Not a CSV column; it only helps
+    /// choose between releases when deduplicating.
+    #[serde(
+        rename = "Album Type",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub album_type: Option<String>,
+    /// 1-based place in the written playlist, counted after filtering.
+    #[serde(rename = "Position", default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<usize>,
+    /// URI of the album when the row is part of a run of that album's
+    /// tracks, in album order.
+    #[serde(rename = "Album Run", default, skip_serializing_if = "Option::is_none")]
+    pub album_run: Option<String>,
+    /// The downloaded cover, relative to the export directory.
+    #[serde(
+        rename = "Album Image File",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub album_image_file: Option<String>,
+    /// Display name of the user who added the track, which differs from
+    /// Added By (the playlist owner) in collaborative playlists.
+    #[serde(
+        rename = "Added By Profile",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub added_by_profile: Option<String>,
+    /// How many markets the track is available in.
+    #[serde(
+        rename = "Market Count",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub market_count: Option<usize>,
+    /// Available in fewer than `RARE_MARKETS` markets, so likely to go.
+    #[serde(rename = "Rare", default, skip_serializing_if = "Option::is_none")]
+    pub rare: Option<bool>,
+    /// Every playlist holding the track, in `--group-by album` exports.
+    #[serde(rename = "Playlists", default, skip_serializing_if = "Option::is_none")]
+    pub playlists: Option<String>,
+    /// The video picked for the track in a `--video-overrides` file.
+    #[serde(
+        rename = "YouTube Video ID",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub youtube_video_id: Option<String>,
+    /// Where a local file track was found under `--music-dir`.
+    #[serde(
+        rename = "Local File Path",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub local_file_path: Option<String>,
+    /// Whether the track is in Liked Songs, with `--include-liked-status`.
+    #[serde(rename = "Liked", default, skip_serializing_if = "Option::is_none")]
+    pub liked: Option<bool>,
+    /// Whether the album is among the saved albums, with
+    /// `--include-saved-status`.
+    #[serde(
+        rename = "Saved Album",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub saved_album: Option<bool>,
+}
+
+/// A track available in fewer markets than this is rare.
+pub const RARE_MARKETS: usize = 10;
+
+/// Settings that change how a track becomes a record.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema)]
+pub struct RecordOptions {
+    #[serde(default)]
+    pub normalize_artists: bool,
+    #[serde(default)]
+    pub image_size: ImageSelectionStrategy,
+    /// Keep tracking parameters in preview and image URLs.
+    #[serde(default)]
+    pub keep_url_params: bool,
+    /// Fill in Market Count and Rare.
+    #[serde(default)]
+    pub market_count: bool,
+}
+
+/// Which of an album's images goes in the Album Image URL column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImageSelectionStrategy {
+    /// The first image listed, which Spotify makes the largest.
+    #[default]
+    First,
+    Last,
+    Largest,
+    Smallest,
+    /// The first image at least this many pixels wide and high.
+    AtLeast(u32),
+}
+
+impl FromStr for ImageSelectionStrategy {
+    type Err = String;
+
+    /// Accepts `first`, `last`, `largest`, `smallest` or `<N>px`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first" => Ok(Self::First),
+            "last" => Ok(Self::Last),
+            "largest" => Ok(Self::Largest),
+            "smallest" => Ok(Self::Smallest),
+            _ => s
+                .strip_suffix("px")
+                .and_then(|n| n.parse().ok())
+                .map(Self::AtLeast)
+                .ok_or_else(|| {
+                    format!(
+                        "expected first, last, largest, smallest or <N>px, got {:?}",
+                        s
+                    )
+                }),
+        }
+    }
+}
+
+/// Picks an image by `strategy`. Images without dimensions are passed over
+/// by the size-based strategies, and `AtLeast` gives `None` when no image is
+/// big enough.
+pub fn select_album_image(images: &[Image], strategy: ImageSelectionStrategy) -> Option<&Image> {
+    let area = |image: &&Image| Some(image.width? * image.height?);
+    match strategy {
+        ImageSelectionStrategy::First => images.first(),
+        ImageSelectionStrategy::Last => images.last(),
+        ImageSelectionStrategy::Largest => {
+            images.iter().filter(|i| area(i).is_some()).max_by_key(area)
+        }
+        ImageSelectionStrategy::Smallest => {
+            images.iter().filter(|i| area(i).is_some()).min_by_key(area)
+        }
+        ImageSelectionStrategy::AtLeast(min) => images.iter().find(|image| {
+            let min = u64::from(min);
+            image.width.is_some_and(|w| w >= min) && image.height.is_some_and(|h| h >= min)
+        }),
+    }
+}
+
+impl TrackRecord {
+    /// Which track this is, to match the same track across files and runs.
+    /// Local files and other tracks without a URI fall back to their names.
+    pub fn identity_key(&self) -> String {
+        match &self.track_uri {
+            Some(uri) => uri.clone(),
+            None => format!(
+                "{}|{}|{}",
+                self.track_name.as_deref().unwrap_or_default(),
+                self.artist_names,
+                self.album_name.as_deref().unwrap_or_default()
+            ),
+        }
+    }
+
+    pub fn from_track(
+        track: &Track,
+        added_by: &str,
+        added_at: String,
+        options: RecordOptions,
+    ) -> Self {
+        let normalize_artists = options.normalize_artists;
+        let mut artists = track.artists.clone();
+        deduplicate_artists(&mut artists);
+        let mut album_artists = track.album.artists.clone();
+        deduplicate_artists(&mut album_artists);
+        let image = select_album_image(&track.album.images, options.image_size);
+        let url = |url: &String| output_url(url, options.keep_url_params);
+        Self {
+            track_uri: track.uri.clone(),
+            track_name: track.name.clone(),
+            artist_uris: join_artist_uris(&artists),
+            artist_names: join_artist_names(&artists, normalize_artists),
+            album_uri: track.album.uri.clone(),
+            album_name: track.album.name.clone(),
+            album_artist_uris: join_artist_uris(&album_artists),
+            album_artist_names: join_artist_names(&album_artists, normalize_artists),
+            album_release_date: track.album.release_date.clone(),
+            album_image_url: image.map(|img| url(&img.url)),
+            disc_number: track.disc_number,
+            track_number: track.track_number,
+            duration_ms: track.duration_ms,
+            preview_url: track.preview_url.as_ref().map(url),
+            explicit: track.explicit,
+            popularity: track.popularity,
+            isrc: track.isrc().map(str::to_string),
+            added_by: Some(added_by.to_string()),
+            added_at: Some(added_at),
+            substituted: None,
+            playlist_followers: None,
+            likely_added_for: None,
+            album_image_width: image.and_then(|img| img.width),
+            album_image_height: image.and_then(|img| img.height),
+            album_type: track.album.album_type.clone(),
+            position: None,
+            album_run: None,
+            album_image_file: None,
+            added_by_profile: None,
+            market_count: track.market_count.filter(|_| options.market_count),
+            rare: track
+                .market_count
+                .filter(|_| options.market_count)
+                .map(|count| count < RARE_MARKETS),
+            playlists: None,
+            youtube_video_id: None,
+            local_file_path: None,
+            liked: track.is_liked,
+            saved_album: track.is_saved_album,
+        }
+    }
+}
+
+pub fn write_track_records(
+    path: &Path,
+    records: &[TrackRecord],
+    fields: &[Field],
+    preamble: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let mut file = File::create(path)?;
+    if let Some(preamble) = preamble {
+        writeln!(file, "{}", preamble)?;
+    }
+    let mut writer = Writer::from_writer(file);
+    writer.write_record(fields.iter().map(|f| f.header()))?;
+    for record in records {
+        writer.write_record(fields.iter().map(|f| f.value(record)))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// A reader that skips the optional `#` preamble ahead of the header.
+pub fn csv_reader<R: Read>(reader: R) -> Reader<R> {
+    ReaderBuilder::new().comment(Some(b'#')).from_reader(reader)
+}
+
+pub fn read_track_records(path: &Path) -> Result<Vec<TrackRecord>, Box<dyn Error>> {
+    let mut reader = csv_reader(File::open(path)?);
+    let mut records = Vec::new();
+    for record in reader.deserialize() {
+        records.push(record?);
+    }
+    Ok(records)
+}
+
+/// Like `read_track_records`, with the line each record starts on, for
+/// reporting problems in files edited by hand.
+pub fn read_numbered_track_records(path: &Path) -> Result<Vec<(u64, TrackRecord)>, Box<dyn Error>> {
+    let mut reader = csv_reader(File::open(path)?);
+    let headers = reader.headers()?.clone();
+    let mut records = Vec::new();
+    for row in reader.records() {
+        let row = row?;
+        let line = row.position().map_or(0, |position| position.line());
+        records.push((line, row.deserialize(Some(&headers))?));
+    }
+    Ok(records)
+}
+
+pub fn join_artist_uris(artists: &[Artist]) -> String {
+    artists
+        .iter()
+        .map(|a| a.uri.clone().unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Drops repeated credits, which Spotify sometimes sends for the same
+/// artist. Artists are the same by URI, or by name when they have none;
+/// the first credit is kept.
+pub fn deduplicate_artists(artists: &mut Vec<Artist>) {
+    let mut seen = HashSet::new();
+    artists.retain(|artist| {
+        let key = match (&artist.uri, &artist.name) {
+            (Some(uri), _) => (true, uri.clone()),
+            (None, name) => (false, name.clone().unwrap_or_default()),
+        };
+        seen.insert(key)
+    });
+}
+
+pub fn join_artist_names(artists: &[Artist], normalize: bool) -> String {
+    artists
+        .iter()
+        .map(|a| match (&a.name, normalize) {
+            (Some(name), true) => normalize_artist_name(name),
+            (Some(name), false) => name.clone(),
+            (None, _) => String::new(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Checks the ISRC layout: country code, registrant, year and designation
+/// (`CC-XXX-YY-NNNNN` without the dashes).
+pub fn is_valid_isrc(isrc: &str) -> bool {
+    let bytes = isrc.as_bytes();
+    bytes.len() == 12
+        && bytes[..2].iter().all(u8::is_ascii_alphabetic)
+        && bytes[2..5].iter().all(u8::is_ascii_alphanumeric)
+        && bytes[5..].iter().all(u8::is_ascii_digit)
+}
+
+/// Folds the formatting differences Spotify has between spellings of the
+/// same artist: "The Beatles", "Beatles, The" and "ｔｈｅ beatles (feat. X)"
+/// all become "beatles".
+pub fn normalize_artist_name(name: &str) -> String {
+    let mut name: String = name.nfkc().collect::<String>().to_lowercase();
+
+    // Only whole markers, so "(Feather Edit)" or "(Featurette)" stay.
+    for marker in ["feat.", "feat ", "featuring ", "ft."] {
+        for open in ['(', '['] {
+            if let Some(index) = name.find(&format!("{}{}", open, marker)) {
+                name.truncate(index);
+            }
+        }
+    }
+
+    let mut name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    if let Some(rest) = name.strip_prefix("the ") {
+        name = rest.to_string();
+    } else if let Some(rest) = name.strip_suffix(", the") {
+        name = rest.to_string();
+    }
+    name.trim().to_string()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// A record with only a URI, a name and an artist, for tests elsewhere.
+    pub fn sample_record(uri: &str, name: &str) -> TrackRecord {
+        serde_json::from_value(serde_json::json!({
+            "Track URI": uri,
+            "Track Name": name,
+            "Artist URI(s)": "spotify:artist:1",
+            "Artist Name(s)": "Artist",
+            "Album URI": null,
+            "Album Name": null,
+            "Album Artist URI(s)": "",
+            "Album Artist Name(s)": "",
+            "Album Release Date": null,
+            "Album Image URL": null,
+            "Disc Number": null,
+            "Track Number": null,
+            "Track Duration (ms)": null,
+            "Track Preview URL": null,
+            "Explicit": null,
+            "Popularity": null,
+            "ISRC": null,
+            "Added By": null,
+            "Added At": null,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn csv_round_trip_keeps_missing_and_zero_apart() {
+        let dir = crate::testdir::TestDir::new();
+        let path = dir.path().join("p.csv");
+        let mut zero = sample_record("spotify:track:1", "Zero");
+        zero.popularity = Some(0);
+        let unknown = sample_record("spotify:track:2", "Unknown");
+        write_track_records(&path, &[zero, unknown], &DEFAULT_FIELDS, Some("# preamble")).unwrap();
+        let read = read_track_records(&path).unwrap();
+        assert_eq!(read[0].popularity, Some(0));
+        assert_eq!(read[1].popularity, None);
+    }
+
+    #[test]
+    fn disc_and_track_numbers_come_from_the_track() {
+        // Trimmed from a real playlist items page.
+        let item: crate::spotify::TrackItem = serde_json::from_str(
+            r#"{
+                "added_at": "2023-04-01T10:00:00Z",
+                "added_by": {"id": "someone", "type": "user"},
+                "is_local": false,
+                "track": {
+                    "album": {
+                        "album_type": "album",
+                        "artists": [{"name": "Radiohead", "uri": "spotify:artist:4Z8W4fKeB5YxbusRsdQVPb"}],
+                        "images": [{"height": 640, "url": "https://i.scdn.co/image/ab67616d0000b273", "width": 640}],
+                        "name": "OK Computer",
+                        "release_date": "1997-05-28",
+                        "release_date_precision": "day",
+                        "total_tracks": 12,
+                        "uri": "spotify:album:6dVIqQ8qmQ5GBnJ9shOYGE"
+                    },
+                    "artists": [{"name": "Radiohead", "uri": "spotify:artist:4Z8W4fKeB5YxbusRsdQVPb"}],
+                    "disc_number": 1,
+                    "duration_ms": 383893,
+                    "explicit": false,
+                    "external_ids": {"isrc": "GBAYE9700102"},
+                    "name": "Paranoid Android",
+                    "popularity": 79,
+                    "preview_url": null,
+                    "track_number": 2,
+                    "type": "track",
+                    "uri": "spotify:track:6LgJvl0Xdtc73RJ1mmpotq"
+                }
+            }"#,
+        )
+        .unwrap();
+        let track = item.track.unwrap();
+        let record =
+            TrackRecord::from_track(&track, "owner", String::new(), RecordOptions::default());
+        assert_eq!(record.disc_number, Some(1));
+        assert_eq!(record.track_number, Some(2));
+        assert_eq!(record.isrc.as_deref(), Some("GBAYE9700102"));
+    }
+
+    #[test]
+    fn normalizes_every_spelling_of_an_artist() {
+        for (name, normalized) in [
+            ("The Beatles", "beatles"),
+            ("Beatles, The", "beatles"),
+            ("THE BEATLES", "beatles"),
+            ("  The   Beatles  ", "beatles"),
+            ("ｔｈｅ Ｂｅａｔｌｅｓ", "beatles"),
+            ("Drake (feat. Rihanna)", "drake"),
+            ("Drake (Featuring Rihanna)", "drake"),
+            ("Drake (ft. Rihanna)", "drake"),
+            ("Drake [feat. Rihanna]", "drake"),
+            ("Drake (feat Rihanna)", "drake"),
+            ("Drake [featuring Rihanna]", "drake"),
+            ("The Weeknd (feat. Daft Punk)", "weeknd"),
+            ("Beyonce\u{301}", "beyoncé"),
+            ("ﬁnn", "finn"),
+        ] {
+            assert_eq!(normalize_artist_name(name), normalized, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn leaves_names_that_only_look_like_suffixes_alone() {
+        assert_eq!(
+            normalize_artist_name("Theatre of Tragedy"),
+            "theatre of tragedy"
+        );
+        assert_eq!(normalize_artist_name("The The"), "the");
+        assert_eq!(normalize_artist_name("Them"), "them");
+        assert_eq!(normalize_artist_name("Feather (Live)"), "feather (live)");
+        assert_eq!(normalize_artist_name("Feat. Nobody"), "feat. nobody");
+        assert_eq!(normalize_artist_name("Featurecast"), "featurecast");
+        assert_eq!(
+            normalize_artist_name("X (Feather Edit)"),
+            "x (feather edit)"
+        );
+        assert_eq!(
+            normalize_artist_name("Band (Featurette)"),
+            "band (featurette)"
+        );
+        assert_eq!(normalize_artist_name("Band [Feats]"), "band [feats]");
+        assert_eq!(normalize_artist_name(""), "");
+    }
+
+    #[test]
+    fn joins_artist_names_normalized_only_when_asked() {
+        let artists: Vec<Artist> = serde_json::from_value(serde_json::json!([
+            {"name": "The Beatles", "uri": "spotify:artist:1"},
+            {"name": null, "uri": "spotify:artist:2"},
+            {"name": "Billy Preston (feat. Someone)", "uri": "spotify:artist:3"},
+        ]))
+        .unwrap();
+        assert_eq!(
+            join_artist_names(&artists, false),
+            "The Beatles, , Billy Preston (feat. Someone)"
+        );
+        assert_eq!(
+            join_artist_names(&artists, true),
+            "beatles, , billy preston"
+        );
+    }
+
+    fn images() -> Vec<Image> {
+        serde_json::from_str(
+            r#"[
+                {"height": null, "url": "https://mosaic.scdn.co/unsized", "width": null},
+                {"height": 300, "url": "https://i.scdn.co/image/medium", "width": 300},
+                {"height": 640, "url": "https://i.scdn.co/image/large", "width": 640},
+                {"height": 64, "url": "https://i.scdn.co/image/small", "width": 64}
+            ]"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn size_based_image_choices_use_the_dimensions() {
+        let images = images();
+        let chosen = |strategy: &str| {
+            select_album_image(&images, strategy.parse().unwrap()).map(|image| image.url.as_str())
+        };
+        assert_eq!(chosen("first"), Some("https://mosaic.scdn.co/unsized"));
+        assert_eq!(chosen("last"), Some("https://i.scdn.co/image/small"));
+        assert_eq!(chosen("largest"), Some("https://i.scdn.co/image/large"));
+        assert_eq!(chosen("smallest"), Some("https://i.scdn.co/image/small"));
+        assert_eq!(chosen("300px"), Some("https://i.scdn.co/image/medium"));
+        assert_eq!(chosen("301px"), Some("https://i.scdn.co/image/large"));
+        assert_eq!(chosen("641px"), None);
+    }
+
+    #[test]
+    fn image_dimensions_follow_the_chosen_image_into_their_columns() {
+        let mut track: Track = serde_json::from_value(serde_json::json!({
+            "uri": "spotify:track:1",
+            "name": "Song",
+            "artists": [],
+            "album": {"name": "Album", "artists": [], "images": []},
+            "duration_ms": 200000,
+            "popularity": 50
+        }))
+        .unwrap();
+        track.album.images = images();
+        let options = RecordOptions {
+            image_size: ImageSelectionStrategy::Largest,
+            ..RecordOptions::default()
+        };
+        let record = TrackRecord::from_track(&track, "owner", String::new(), options);
+        assert_eq!(
+            record.album_image_url.as_deref(),
+            Some("https://i.scdn.co/image/large")
+        );
+        assert_eq!(Field::AlbumImageWidth.value(&record), "640");
+        assert_eq!(Field::AlbumImageHeight.value(&record), "640");
+
+        // The first image has no size, so both columns stay empty.
+        let record =
+            TrackRecord::from_track(&track, "owner", String::new(), RecordOptions::default());
+        assert_eq!(Field::AlbumImageWidth.value(&record), "");
+        assert_eq!(Field::AlbumImageHeight.value(&record), "");
+    }
+
+    fn artists(credits: serde_json::Value) -> Vec<Artist> {
+        serde_json::from_value(credits).unwrap()
+    }
+
+    #[test]
+    fn repeated_credits_are_dropped_by_uri() {
+        let mut credits = artists(serde_json::json!([
+            {"uri": "spotify:artist:A", "name": "Foo"},
+            {"uri": "spotify:artist:B", "name": "Bar"},
+            {"uri": "spotify:artist:A", "name": "Foo"},
+            // Renamed since, but the same artist.
+            {"uri": "spotify:artist:B", "name": "Bar (old name)"},
+        ]));
+        deduplicate_artists(&mut credits);
+        assert_eq!(join_artist_names(&credits, false), "Foo, Bar");
+        assert_eq!(
+            join_artist_uris(&credits),
+            "spotify:artist:A, spotify:artist:B"
+        );
+    }
+
+    #[test]
+    fn credits_without_a_uri_are_compared_by_name() {
+        let mut credits = artists(serde_json::json!([
+            {"uri": null, "name": "Local Band"},
+            {"uri": null, "name": "Local Band"},
+            {"uri": null, "name": "Other Band"},
+            // Has a URI, so it is not the same as the local one.
+            {"uri": "spotify:artist:L", "name": "Local Band"},
+            {"uri": null, "name": null},
+            {"uri": null, "name": null},
+        ]));
+        deduplicate_artists(&mut credits);
+        assert_eq!(
+            join_artist_names(&credits, false),
+            "Local Band, Other Band, Local Band, "
+        );
+    }
+
+    #[test]
+    fn records_credit_each_artist_once() {
+        let track: Track = serde_json::from_value(serde_json::json!({
+            "uri": "spotify:track:1",
+            "name": "Song",
+            "artists": [
+                {"uri": "spotify:artist:A", "name": "Foo"},
+                {"uri": "spotify:artist:A", "name": "Foo"},
+                {"uri": "spotify:artist:B", "name": "Bar"}
+            ],
+            "album": {
+                "name": "Album",
+                "artists": [
+                    {"uri": "spotify:artist:A", "name": "Foo"},
+                    {"uri": "spotify:artist:A", "name": "Foo"}
+                ],
+                "images": []
+            },
+            "duration_ms": 200000,
+            "popularity": 50
+        }))
+        .unwrap();
+        let record =
+            TrackRecord::from_track(&track, "owner", String::new(), RecordOptions::default());
+        assert_eq!(record.artist_names, "Foo, Bar");
+        assert_eq!(record.artist_uris, "spotify:artist:A, spotify:artist:B");
+        assert_eq!(record.album_artist_names, "Foo");
+        assert_eq!(record.album_artist_uris, "spotify:artist:A");
+    }
+}
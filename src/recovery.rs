@@ -0,0 +1,319 @@
+//! Cleaning up after an earlier run that crashed or was killed, before an
+//! export starts: temporary files it never renamed into place, and
+//! manifest entries for files that are no longer there.
+
+use log::warn;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    atomic::{write_json_atomic, TEMP_DIR},
+    index::INDEX_JSON,
+    manifest::{read_manifest, MANIFEST_JSON},
+};
+
+/// What the sweep found and did.
+#[derive(Debug, Default)]
+pub struct Recovery {
+    /// Temporaries removed, with the file each was meant to replace.
+    pub removed_temp_files: Vec<(PathBuf, PathBuf)>,
+    /// Files a temporary was meant to create that never appeared: the
+    /// crash came before their first write finished.
+    pub interrupted_writes: Vec<PathBuf>,
+    /// Files the manifest listed that are gone, now dropped from it so the
+    /// next export writes them afresh.
+    pub missing_files: Vec<String>,
+}
+
+/// The file a temporary named `<name>.<pid>-<n>.tmp` replaces. Earlier
+/// versions named them `<name>.<pid>.tmp`, or `.<name>.<pid>.tmp` beside
+/// their files.
+fn temp_target(file_name: &str) -> Option<&str> {
+    let (name, suffix) = file_name.strip_suffix(".tmp")?.rsplit_once('.')?;
+    let numbered = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit());
+    let unique = match suffix.split_once('-') {
+        Some((pid, n)) => numbered(pid) && numbered(n),
+        None => numbered(suffix),
+    };
+    if name.is_empty() || !unique {
+        return None;
+    }
+    Some(name)
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+impl Recovery {
+    /// Sweeps `dir` and its immediate subdirectories. Only temporaries
+    /// older than the last run to finish, going by index.json, are
+    /// removed, so a run still going in the same directory keeps its own.
+    pub fn sweep(dir: &Path) -> Self {
+        let mut recovery = Recovery::default();
+        if let Some(finished) = modified(&dir.join(INDEX_JSON)) {
+            let mut dirs = vec![dir.to_path_buf()];
+            if let Ok(entries) = fs::read_dir(dir) {
+                dirs.extend(
+                    entries
+                        .flatten()
+                        .map(|entry| entry.path())
+                        .filter(|path| path.is_dir() && !path.ends_with(TEMP_DIR)),
+                );
+            }
+            for dir in dirs {
+                recovery.sweep_temp_files(&dir, finished);
+            }
+        }
+        recovery.reconcile_manifest(dir);
+        recovery
+    }
+
+    fn sweep_temp_files(&mut self, dir: &Path, finished: SystemTime) {
+        let legacy = fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|name| name.starts_with('.') && temp_target(&name[1..]).is_some())
+            });
+        let temp_dir = dir.join(TEMP_DIR);
+        let current = fs::read_dir(&temp_dir).into_iter().flatten().flatten();
+        for entry in current.chain(legacy) {
+            let path = entry.path();
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let Some(target) = temp_target(file_name.trim_start_matches('.')) else {
+                continue;
+            };
+            if modified(&path).is_none_or(|at| at >= finished) {
+                continue;
+            }
+            let target = dir.join(target);
+            if let Err(e) = fs::remove_file(&path) {
+                warn!("Could not remove {}: {}", path.display(), e);
+                continue;
+            }
+            if !target.exists() {
+                self.interrupted_writes.push(target.clone());
+            }
+            self.removed_temp_files.push((path, target));
+        }
+        let _ = fs::remove_dir(&temp_dir);
+    }
+
+    fn reconcile_manifest(&mut self, dir: &Path) {
+        let path = dir.join(MANIFEST_JSON);
+        let Ok(mut manifest) = read_manifest(&path) else {
+            return;
+        };
+        let before = manifest.files.len();
+        manifest.files.retain(|entry| {
+            let present = dir.join(&entry.path).is_file();
+            if !present {
+                self.missing_files.push(entry.path.clone());
+            }
+            present
+        });
+        if manifest.files.len() != before {
+            if let Err(e) = write_json_atomic(&path, &manifest) {
+                warn!("Could not update {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Logs what was found, one warning per problem.
+    pub fn report(&self) {
+        for (temp, target) in &self.removed_temp_files {
+            warn!(
+                "Removed {}, left by an earlier run that stopped while writing {}",
+                temp.display(),
+                target.display()
+            );
+        }
+        for target in &self.interrupted_writes {
+            warn!(
+                "{} was never written: an earlier run stopped before finishing it",
+                target.display()
+            );
+        }
+        for file in &self.missing_files {
+            warn!(
+                "{} is listed in {} but missing; dropped it from the manifest",
+                file, MANIFEST_JSON
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        atomic::{write_atomic, write_bytes_atomic},
+        manifest::{Manifest, ManifestEntry},
+        testdir::TestDir,
+    };
+    use std::{
+        io::Write,
+        panic::{self, AssertUnwindSafe},
+        time::Duration,
+    };
+
+    /// Starts replacing `path` and dies after writing `partial`, leaving
+    /// the temporary behind as a killed run does.
+    fn crash_while_writing(path: &Path, partial: &[u8]) {
+        let crashed = panic::catch_unwind(AssertUnwindSafe(|| {
+            write_atomic(path, |writer| {
+                writer.write_all(partial)?;
+                writer.flush()?;
+                panic!("killed");
+            })
+        }));
+        assert!(crashed.is_err());
+    }
+
+    /// Backdates every temporary under `dir`, so the run that left them
+    /// stopped before the last one finished.
+    fn age_temporaries(dir: &Path) {
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        for entry in fs::read_dir(dir.join(TEMP_DIR)).unwrap().flatten() {
+            let file = fs::File::options().write(true).open(entry.path()).unwrap();
+            file.set_modified(an_hour_ago).unwrap();
+        }
+    }
+
+    fn finish_run(dir: &Path) {
+        write_bytes_atomic(&dir.join(INDEX_JSON), b"{}").unwrap();
+    }
+
+    #[test]
+    fn the_run_after_a_crash_clears_what_the_writer_left() {
+        let dir = TestDir::new();
+        let sub = dir.path().join("playlists");
+        fs::create_dir(&sub).unwrap();
+        finish_run(dir.path());
+        let library = dir.path().join("library.json");
+        write_bytes_atomic(&library, b"{\"playlists\":[]}").unwrap();
+
+        // One crash while replacing a file, one while writing a new one.
+        crash_while_writing(&library, b"{\"playli");
+        crash_while_writing(&sub.join("Road Trip.csv"), b"Track URI,Track Na");
+        age_temporaries(dir.path());
+        age_temporaries(&sub);
+        finish_run(dir.path());
+
+        let recovery = Recovery::sweep(dir.path());
+
+        let mut targets: Vec<&PathBuf> = recovery
+            .removed_temp_files
+            .iter()
+            .map(|(_, target)| target)
+            .collect();
+        targets.sort();
+        assert_eq!(targets, vec![&library, &sub.join("Road Trip.csv")]);
+        assert_eq!(recovery.interrupted_writes, vec![sub.join("Road Trip.csv")]);
+        assert!(recovery.missing_files.is_empty());
+        // The earlier version survives; no temporary or its directory does.
+        assert_eq!(fs::read(&library).unwrap(), b"{\"playlists\":[]}");
+        assert!(!dir.path().join(TEMP_DIR).exists());
+        assert!(!sub.join(TEMP_DIR).exists());
+
+        // Nothing is left for the next sweep.
+        let again = Recovery::sweep(dir.path());
+        assert!(again.removed_temp_files.is_empty());
+        assert!(again.interrupted_writes.is_empty());
+    }
+
+    #[test]
+    fn temporaries_of_a_run_still_going_are_kept() {
+        let dir = TestDir::new();
+        let path = dir.path().join("state.json");
+
+        // No run has finished here yet: nothing can be judged stale.
+        crash_while_writing(&path, b"{");
+        age_temporaries(dir.path());
+        assert!(Recovery::sweep(dir.path()).removed_temp_files.is_empty());
+
+        // Newer than the last finished run, as a run going on now writes.
+        finish_run(dir.path());
+        crash_while_writing(&path, b"{");
+        let recovery = Recovery::sweep(dir.path());
+        assert_eq!(recovery.removed_temp_files.len(), 1);
+        assert_eq!(fs::read_dir(dir.path().join(TEMP_DIR)).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn temporaries_beside_their_files_from_earlier_versions_are_removed() {
+        let dir = TestDir::new();
+        let temp = dir.path().join(".state.json.4242.tmp");
+        fs::write(&temp, b"{").unwrap();
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        fs::File::options()
+            .write(true)
+            .open(&temp)
+            .unwrap()
+            .set_modified(an_hour_ago)
+            .unwrap();
+        // Not a temporary, whatever it looks like.
+        fs::write(dir.path().join(".notes.tmp"), b"").unwrap();
+        finish_run(dir.path());
+
+        let recovery = Recovery::sweep(dir.path());
+
+        assert_eq!(
+            recovery.removed_temp_files,
+            vec![(temp.clone(), dir.path().join("state.json"))]
+        );
+        assert_eq!(
+            recovery.interrupted_writes,
+            vec![dir.path().join("state.json")]
+        );
+        assert!(!temp.exists());
+        assert!(dir.path().join(".notes.tmp").exists());
+    }
+
+    #[test]
+    fn files_gone_from_disk_are_dropped_from_the_manifest() {
+        let dir = TestDir::new();
+        fs::write(dir.path().join("Kept.csv"), b"Track URI\n").unwrap();
+        let entry = |path: &str| ManifestEntry {
+            path: path.to_string(),
+            sha256: String::new(),
+            size: 0,
+            track_keys: None,
+            local_edits: false,
+        };
+        let manifest = Manifest {
+            files: vec![entry("Kept.csv"), entry("Lost.csv")],
+        };
+        write_json_atomic(&dir.path().join(MANIFEST_JSON), &manifest).unwrap();
+
+        let recovery = Recovery::sweep(dir.path());
+
+        assert_eq!(recovery.missing_files, vec!["Lost.csv".to_string()]);
+        let kept: Vec<String> = read_manifest(&dir.path().join(MANIFEST_JSON))
+            .unwrap()
+            .files
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect();
+        assert_eq!(kept, vec!["Kept.csv".to_string()]);
+        assert!(Recovery::sweep(dir.path()).missing_files.is_empty());
+    }
+
+    #[test]
+    fn recognises_every_temporary_name() {
+        assert_eq!(temp_target("state.json.4242-7.tmp"), Some("state.json"));
+        assert_eq!(temp_target("state.json.4242.tmp"), Some("state.json"));
+        assert_eq!(temp_target("Road Trip.csv.1-0.tmp"), Some("Road Trip.csv"));
+        assert_eq!(temp_target("state.json.4242-.tmp"), None);
+        assert_eq!(temp_target("notes.tmp"), None);
+        assert_eq!(temp_target(".4242.tmp"), None);
+    }
+}
@@ -0,0 +1,79 @@
+//! `remove-tracks`: taking the copies a `--dedupe-key` export dropped out
+//! of the playlist on Spotify too, from the duplicates.csv it wrote.
+
+use log::{info, warn};
+use std::{collections::HashSet, error::Error};
+
+use crate::{
+    api::SpotifyAPI,
+    cli::RemoveTracksArgs,
+    dedupe::{read_duplicates, Duplicate},
+    ids::normalize_uri,
+    table::TableOutput,
+};
+
+/// Removes the dropped copies the CSV lists for the playlist, or with
+/// `--dry-run` only prints them. Spotify removes a track by URI, every
+/// occurrence at once, so a copy with the same URI as the one kept is
+/// left alone.
+pub async fn remove_duplicates(
+    api: &SpotifyAPI,
+    args: &RemoveTracksArgs,
+    plain: bool,
+) -> Result<(), Box<dyn Error>> {
+    let playlist = api.get_playlist_metadata(&args.playlist).await?;
+    let duplicates = read_duplicates(&args.from_csv)?;
+    let listed: Vec<&Duplicate> = duplicates
+        .iter()
+        .filter(|duplicate| duplicate.playlist == playlist.name)
+        .collect();
+    if listed.is_empty() {
+        return Err(format!(
+            "{}: no duplicates listed for {}",
+            args.from_csv.display(),
+            playlist.name
+        )
+        .into());
+    }
+
+    let mut seen = HashSet::new();
+    let mut removals = Vec::new();
+    for duplicate in listed {
+        let Some(dropped) = &duplicate.dropped_uri else {
+            continue;
+        };
+        let uri = normalize_uri(dropped, args.strict_ids)
+            .map_err(|e| format!("{}: {}", args.from_csv.display(), e))?;
+        if duplicate.kept_uri.as_deref() == Some(dropped.as_str()) {
+            warn!(
+                "{}: not removing {}: it is also the copy kept, and removing it would remove both",
+                playlist.name, uri
+            );
+            continue;
+        }
+        if seen.insert(uri.clone()) {
+            removals.push((uri, duplicate));
+        }
+    }
+
+    if args.dry_run {
+        let mut table = TableOutput::new(vec!["Track URI", "Album", "Kept Album"]);
+        for (uri, duplicate) in &removals {
+            table.add_row(vec![
+                uri.clone(),
+                duplicate.dropped_album.clone().unwrap_or_default(),
+                duplicate.kept_album.clone().unwrap_or_default(),
+            ]);
+        }
+        table.print(plain);
+        println!("{}: would remove {} tracks", playlist.name, removals.len());
+        return Ok(());
+    }
+
+    let uris: Vec<&str> = removals.iter().map(|(uri, _)| uri.as_str()).collect();
+    api.remove_tracks_from_playlist(&playlist.id, &uris)
+        .await
+        .map_err(|e| format!("{}: {}", playlist.name, e))?;
+    info!("{}: removed {} tracks", playlist.name, uris.len());
+    Ok(())
+}
@@ -0,0 +1,259 @@
+use log::info;
+use std::error::Error;
+
+use crate::{
+    cli::{OutputArgs, RenderArgs},
+    dedupe::{write_duplicates, DUPLICATES_CSV},
+    export::{prepare_records, split_if_requested},
+    output::{
+        read_library, write_library, write_playlist, LibraryExport, OutputConfig, OutputFormat,
+        PlaylistRecords,
+    },
+    paths::OutputPaths,
+    record::Field,
+    store::Store,
+};
+
+/// Re-runs the writers over a JSON export or a stored run. Since both carry
+/// every record field, the result matches what a direct export would have
+/// written.
+pub fn render(args: &RenderArgs, long_paths: bool) -> Result<(), Box<dyn Error>> {
+    if args.output.format == OutputFormat::Template {
+        return Err(
+            "templates need the playlist data from the API; use `export --format template`".into(),
+        );
+    }
+
+    let library = match (&args.store, &args.input) {
+        (Some(url), _) => Store::open(url)?.read_run(args.run)?,
+        (None, Some(input)) => read_library(input)?,
+        (None, None) => return Err("nothing to render".into()),
+    };
+    render_library(library, &args.output, &mut OutputPaths::new(long_paths))
+}
+
+/// Writes `library` in the format `output` asks for, into `paths`.
+fn render_library(
+    library: LibraryExport,
+    output: &OutputArgs,
+    paths: &mut OutputPaths,
+) -> Result<(), Box<dyn Error>> {
+    let mut rendered = Vec::with_capacity(library.playlists.len());
+    let mut fields = library.fields.clone();
+    if output.include_position && !fields.contains(&Field::Position) {
+        fields.push(Field::Position);
+    }
+    if output.album_runs && !fields.contains(&Field::AlbumRun) {
+        fields.push(Field::AlbumRun);
+    }
+    let config = OutputConfig::new(output, fields);
+    // Keeps the original export's provenance: rendering adds no new data.
+    let provenance = library.provenance.as_ref();
+
+    let mut duplicates = Vec::new();
+    for playlist in library.playlists {
+        let records = PlaylistRecords {
+            tracks: prepare_records(playlist.tracks, &playlist.name, output, &mut duplicates),
+            ..playlist
+        };
+        for records in split_if_requested(records, output) {
+            if let Some(file_name) = write_playlist(&records, &config, provenance, paths, None)? {
+                info!("Finished writing: {}", file_name);
+            }
+            rendered.push(records);
+        }
+    }
+
+    if output.dedupe_key.is_some() {
+        write_duplicates(&paths.library_file(DUPLICATES_CSV), &duplicates)?;
+        info!(
+            "Dropped {} duplicate tracks; see {}",
+            duplicates.len(),
+            DUPLICATES_CSV
+        );
+    }
+    if let Some(file_name) = write_library(rendered, &config, provenance, paths)? {
+        info!("Finished writing: {}", file_name);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        atomic::TEMP_DIR, cli::tests::output_args, output::LIBRARY_JSON, provenance::Provenance,
+        record::TrackRecord, testdir::TestDir,
+    };
+    use std::{collections::BTreeMap, fs, path::Path};
+
+    /// Every record field set, across albums, duplicates and unknowns.
+    fn record(
+        uri: &str,
+        name: &str,
+        isrc: &str,
+        popularity: Option<u8>,
+        explicit: bool,
+    ) -> TrackRecord {
+        let album = &isrc[..2];
+        serde_json::from_value(serde_json::json!({
+            "Track URI": uri,
+            "Track Name": name,
+            "Artist URI(s)": "spotify:artist:1, spotify:artist:2",
+            "Artist Name(s)": "Ärtist, \"Guest\"",
+            "Album URI": format!("spotify:album:{}", album),
+            "Album Name": format!("Album {}", album),
+            "Album Artist URI(s)": "spotify:artist:1",
+            "Album Artist Name(s)": "Ärtist",
+            "Album Release Date": "2011-04",
+            "Album Image URL": format!("https://i.scdn.co/image/{}", album),
+            "Disc Number": 1,
+            "Track Number": isrc[isrc.len() - 1..].parse::<u32>().unwrap(),
+            "Track Duration (ms)": 201_000,
+            "Track Preview URL": null,
+            "Explicit": explicit,
+            "Popularity": popularity,
+            "ISRC": isrc,
+            "Added By": "owner",
+            "Added At": "2024-01-02T03:04:05Z",
+            "Substituted": null,
+            "Playlist Followers": 12,
+            "Album Image Width": 640,
+            "Album Image Height": 640,
+            "Album Type": if album == "CC" { "compilation" } else { "album" },
+        }))
+        .unwrap()
+    }
+
+    fn library() -> Vec<PlaylistRecords> {
+        let playlist = |name: &str, tracks| PlaylistRecords {
+            name: name.to_string(),
+            owner: "Owner".to_string(),
+            tracks,
+        };
+        vec![
+            playlist(
+                "Road Trip",
+                vec![
+                    record("spotify:track:a1", "One", "AA0000000001", Some(70), false),
+                    record("spotify:track:a2", "Two", "AA0000000002", Some(40), true),
+                    record("spotify:track:a3", "Three", "AA0000000003", None, false),
+                    record("spotify:track:c1", "One", "AA0000000001", Some(90), false),
+                    record(
+                        "spotify:track:b5",
+                        "Five, \"quoted\"",
+                        "BB0000000005",
+                        Some(5),
+                        true,
+                    ),
+                ],
+            ),
+            // Claims "road trip (2).csv" behind the first one.
+            playlist(
+                "road trip",
+                vec![record(
+                    "spotify:track:b1",
+                    "Café",
+                    "BB0000000001",
+                    Some(0),
+                    false,
+                )],
+            ),
+        ]
+    }
+
+    /// What `export` writes with these output options, following its steps.
+    fn export(dir: &Path, output: &OutputArgs, provenance: &Provenance) {
+        let mut fields = crate::record::DEFAULT_FIELDS.to_vec();
+        fields.extend([
+            Field::PlaylistFollowers,
+            Field::AlbumImageWidth,
+            Field::AlbumImageHeight,
+        ]);
+        if output.include_position {
+            fields.push(Field::Position);
+        }
+        if output.album_runs {
+            fields.push(Field::AlbumRun);
+        }
+        let config = OutputConfig::new(output, fields);
+        let mut paths = OutputPaths::in_dir(dir);
+        let mut duplicates = Vec::new();
+        let mut rendered = Vec::new();
+        for playlist in library() {
+            let records = PlaylistRecords {
+                tracks: prepare_records(playlist.tracks, &playlist.name, output, &mut duplicates),
+                ..playlist
+            };
+            for records in split_if_requested(records, output) {
+                write_playlist(&records, &config, Some(provenance), &mut paths, None).unwrap();
+                rendered.push(records);
+            }
+        }
+        if output.dedupe_key.is_some() {
+            write_duplicates(&paths.library_file(DUPLICATES_CSV), &duplicates).unwrap();
+        }
+        write_library(rendered, &config, Some(provenance), &mut paths).unwrap();
+    }
+
+    /// Every file written into `dir`, by name.
+    fn files(dir: &Path) -> BTreeMap<String, Vec<u8>> {
+        fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap())
+            .filter(|entry| entry.file_name() != TEMP_DIR)
+            .map(|entry| {
+                let name = entry.file_name().into_string().unwrap();
+                (name, fs::read(entry.path()).unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rendering_a_json_export_matches_a_direct_csv_export() {
+        let provenance = Provenance::new(Some("owner".to_string()), &serde_json::json!({}));
+        let json = TestDir::new();
+        export(
+            json.path(),
+            &output_args(&["--format", "json"]),
+            &provenance,
+        );
+
+        for args in [
+            &["--csv-preamble"][..],
+            &[
+                "--min-popularity",
+                "20",
+                "--dedupe-key",
+                "isrc",
+                "--sort-by",
+                "popularity",
+                "--descending",
+                "--include-position",
+                "--verify-order",
+            ],
+            &[
+                "--split-explicit",
+                "--album-runs",
+                "--album-run-min-length",
+                "2",
+                "--include-position",
+            ],
+            &["--clean-only", "--strict-filters", "--sort-by", "name"],
+        ] {
+            let output = output_args(args);
+            let direct = TestDir::new();
+            export(direct.path(), &output, &provenance);
+
+            let rendered = TestDir::new();
+            let library = read_library(json.path()).unwrap();
+            render_library(library, &output, &mut OutputPaths::in_dir(rendered.path())).unwrap();
+
+            let direct = files(direct.path());
+            assert!(direct.contains_key("road trip (2).csv"), "{:?}", args);
+            assert!(!direct.contains_key(LIBRARY_JSON));
+            assert_eq!(files(rendered.path()), direct, "{:?}", args);
+        }
+    }
+}
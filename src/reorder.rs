@@ -0,0 +1,262 @@
+//! Sorting a playlist on Spotify itself. Each reorder request moves one
+//! item, so only the items outside the longest run already in sorted order
+//! are moved, which is the fewest requests that can sort it.
+
+use log::info;
+use std::error::Error;
+
+use crate::{
+    api::SpotifyAPI,
+    cli::SortPlaylistArgs,
+    filter::{compare_records, SortKey},
+    record::{RecordOptions, TrackRecord},
+    table::TableOutput,
+};
+
+/// One reorder request, in the API's terms: the item at `range_start` is
+/// moved to just before the item currently at `insert_before`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Move {
+    pub range_start: usize,
+    pub insert_before: usize,
+}
+
+/// Indices of `records` in sorted order. The sort is stable, and items
+/// without a track, such as removed or local ones, go last.
+pub fn sorted_order(records: &[Option<TrackRecord>], key: SortKey, descending: bool) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..records.len()).collect();
+    order.sort_by(|&a, &b| match (&records[a], &records[b]) {
+        (Some(a), Some(b)) => compare_records(a, b, key, descending),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+    order
+}
+
+/// Marks the items of a longest strictly increasing run of `ranks`.
+pub fn longest_increasing(ranks: &[usize]) -> Vec<bool> {
+    // tails[len] is the index ending the best run of length len + 1 found so far.
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = vec![None; ranks.len()];
+    for (i, &rank) in ranks.iter().enumerate() {
+        let len = tails.partition_point(|&t| ranks[t] < rank);
+        previous[i] = len.checked_sub(1).map(|l| tails[l]);
+        if len == tails.len() {
+            tails.push(i);
+        } else {
+            tails[len] = i;
+        }
+    }
+    let mut in_run = vec![false; ranks.len()];
+    let mut next = tails.last().copied();
+    while let Some(i) = next {
+        in_run[i] = true;
+        next = previous[i];
+    }
+    in_run
+}
+
+/// The moves that turn the playlist's current order into `target`, a
+/// permutation of its indices. Items already in sorted order relative to
+/// each other stay; every other item is moved, in target order, to just
+/// after the item that precedes it there.
+pub fn plan_moves(target: &[usize]) -> Vec<Move> {
+    let mut rank = vec![0; target.len()];
+    for (position, &item) in target.iter().enumerate() {
+        rank[item] = position;
+    }
+    let mut placed = longest_increasing(&rank);
+
+    let mut current: Vec<usize> = (0..target.len()).collect();
+    let mut moves = Vec::new();
+    for (position, &item) in target.iter().enumerate() {
+        if placed[item] {
+            continue;
+        }
+        let from = current
+            .iter()
+            .position(|&i| i == item)
+            .expect("item in playlist");
+        let to = match position {
+            0 => 0,
+            _ => {
+                current
+                    .iter()
+                    .position(|&i| i == target[position - 1])
+                    .expect("item in playlist")
+                    + 1
+            }
+        };
+        if to != from && to != from + 1 {
+            moves.push(Move {
+                range_start: from,
+                insert_before: to,
+            });
+            current.remove(from);
+            current.insert(if to > from { to - 1 } else { to }, item);
+        }
+        placed[item] = true;
+    }
+    moves
+}
+
+/// Sorts the playlist in place on Spotify, or with `--dry-run` only prints
+/// the order it would end up in.
+pub async fn sort_playlist(
+    api: &SpotifyAPI,
+    args: &SortPlaylistArgs,
+    plain: bool,
+) -> Result<(), Box<dyn Error>> {
+    let playlist = api.get_playlist_metadata(&args.playlist).await?;
+    let (items, failed) = api
+        .get_playlist_tracks(&playlist.tracks.href, playlist.tracks.total)
+        .await?;
+    if !failed.is_empty() {
+        return Err(format!(
+            "{}: {} pages could not be fetched; not reordering a partial playlist",
+            playlist.name,
+            failed.len()
+        )
+        .into());
+    }
+
+    let records: Vec<Option<TrackRecord>> = items
+        .iter()
+        .map(|item| {
+            item.track.as_ref().map(|track| {
+                TrackRecord::from_track(
+                    track,
+                    &playlist.owner.display_name,
+                    item.added_at.clone().unwrap_or_default(),
+                    RecordOptions::default(),
+                )
+            })
+        })
+        .collect();
+    let target = sorted_order(&records, args.sort_by, args.descending);
+    let moves = plan_moves(&target);
+
+    if args.dry_run {
+        let mut table = TableOutput::new(vec!["Position", "Track", "Artist(s)"]).align_right(&[0]);
+        for (position, &item) in target.iter().enumerate() {
+            let record = records[item].as_ref();
+            table.add_row(vec![
+                (position + 1).to_string(),
+                record
+                    .and_then(|r| r.track_name.clone())
+                    .unwrap_or_else(|| "(unavailable)".into()),
+                record.map(|r| r.artist_names.clone()).unwrap_or_default(),
+            ]);
+        }
+        table.print(plain);
+        println!(
+            "{}: sorting would take {} reorder requests",
+            playlist.name,
+            moves.len()
+        );
+        return Ok(());
+    }
+
+    for (done, step) in moves.iter().enumerate() {
+        api.reorder_playlist_track(
+            &playlist.id,
+            step.range_start as u32,
+            step.insert_before as u32,
+        )
+        .await
+        .map_err(|e| {
+            format!(
+                "{}: reordering stopped after {} of {} moves: {}",
+                playlist.name,
+                done,
+                moves.len(),
+                e
+            )
+        })?;
+    }
+    info!("{}: sorted with {} moves", playlist.name, moves.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plays `moves` against a playlist in its current order, as Spotify
+    /// applies reorder requests.
+    fn apply(len: usize, moves: &[Move]) -> Vec<usize> {
+        let mut playlist: Vec<usize> = (0..len).collect();
+        for m in moves {
+            let item = playlist.remove(m.range_start);
+            let to = if m.insert_before > m.range_start {
+                m.insert_before - 1
+            } else {
+                m.insert_before
+            };
+            playlist.insert(to, item);
+        }
+        playlist
+    }
+
+    /// Length of a longest increasing run of the items' places in `target`,
+    /// the slow way.
+    fn lis_len(target: &[usize]) -> usize {
+        let mut rank = vec![0; target.len()];
+        for (position, &item) in target.iter().enumerate() {
+            rank[item] = position;
+        }
+        let mut best = vec![1; rank.len()];
+        for i in 0..rank.len() {
+            for j in 0..i {
+                if rank[j] < rank[i] {
+                    best[i] = best[i].max(best[j] + 1);
+                }
+            }
+        }
+        best.into_iter().max().unwrap_or(0)
+    }
+
+    fn assert_sorts(target: &[usize]) -> usize {
+        let moves = plan_moves(target);
+        assert_eq!(apply(target.len(), &moves), target, "{:?}", moves);
+        assert_eq!(moves.len(), target.len() - lis_len(target), "{:?}", target);
+        moves.len()
+    }
+
+    #[test]
+    fn an_already_sorted_playlist_needs_no_moves() {
+        assert_eq!(assert_sorts(&[0, 1, 2, 3, 4]), 0);
+        assert_eq!(assert_sorts(&[]), 0);
+    }
+
+    #[test]
+    fn a_reversed_playlist_moves_all_but_one() {
+        assert_eq!(assert_sorts(&[4, 3, 2, 1, 0]), 4);
+    }
+
+    #[test]
+    fn one_item_out_of_place_takes_one_move() {
+        assert_eq!(assert_sorts(&[0, 2, 3, 4, 1]), 1);
+        assert_eq!(assert_sorts(&[4, 0, 1, 2, 3]), 1);
+        assert_eq!(assert_sorts(&[1, 2, 0, 3, 4]), 1);
+    }
+
+    #[test]
+    fn every_order_of_six_items_is_reached_in_the_fewest_moves() {
+        fn permutations(prefix: &mut Vec<usize>, left: &mut Vec<usize>) {
+            if left.is_empty() {
+                assert_sorts(prefix);
+                return;
+            }
+            for i in 0..left.len() {
+                let item = left.remove(i);
+                prefix.push(item);
+                permutations(prefix, left);
+                prefix.pop();
+                left.insert(i, item);
+            }
+        }
+        permutations(&mut Vec::new(), &mut (0..6).collect());
+    }
+}
@@ -0,0 +1,401 @@
+use csv::{Reader, Writer};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    error::Error,
+    fmt::Write as _,
+    path::Path,
+};
+
+use crate::{
+    compilations::{is_compilation, CompilationPolicy, COMPILATIONS_GROUP},
+    export::PlaylistExport,
+    record::{join_artist_names, RARE_MARKETS},
+    spotify::Track,
+};
+
+pub const ARTIST_FREQUENCY_CSV: &str = "artist_frequency.csv";
+
+/// Artists listed after a full export.
+pub const TOP_ARTISTS: usize = 10;
+
+/// Tracks listed after an export with `--market-count`.
+pub const RAREST_TRACKS: usize = 10;
+
+/// How often one artist (by name) appears across the library.
+#[derive(Debug, Clone)]
+pub struct ArtistFrequency {
+    pub name: String,
+    /// The URI of the first track credit seen with this name.
+    pub uri: Option<String>,
+    pub track_count: usize,
+    /// Distinct playlists with at least one of the artist's tracks.
+    pub playlist_count: usize,
+    /// Mean popularity of the artist's tracks, over those where it is known;
+    /// `None` when it is known for none.
+    pub avg_popularity: Option<f64>,
+}
+
+#[derive(Default)]
+struct Tally<'a> {
+    uri: Option<&'a str>,
+    track_count: usize,
+    playlists: HashSet<&'a str>,
+    popularity_sum: u64,
+    popularity_known: u64,
+}
+
+impl<'a> Tally<'a> {
+    fn count(&mut self, track: &Track, playlist_id: &'a str) {
+        self.track_count += 1;
+        self.playlists.insert(playlist_id);
+        if let Some(popularity) = track.popularity {
+            self.popularity_sum += u64::from(popularity);
+            self.popularity_known += 1;
+        }
+    }
+}
+
+/// Counts each artist's tracks across every playlist, most tracks first
+/// and then by name. A track credited to several artists counts once for
+/// each. Tracks from compilations are counted as `compilations` says.
+pub fn artist_frequency_report(
+    all_exports: &[PlaylistExport],
+    compilations: CompilationPolicy,
+) -> Vec<ArtistFrequency> {
+    let mut tallies: HashMap<&str, Tally> = HashMap::new();
+
+    for export in all_exports {
+        let playlist_id = export.playlist.id.as_str();
+        for track in export.tracks.iter().filter_map(|item| item.track.as_ref()) {
+            if is_compilation(track) {
+                match compilations {
+                    CompilationPolicy::OwnGroup => {
+                        tallies
+                            .entry(COMPILATIONS_GROUP)
+                            .or_default()
+                            .count(track, playlist_id);
+                        continue;
+                    }
+                    CompilationPolicy::ByTrackArtist => {}
+                    CompilationPolicy::Skip => continue,
+                }
+            }
+            let mut seen = BTreeSet::new();
+            for artist in &track.artists {
+                let Some(name) = artist.name.as_deref() else {
+                    continue;
+                };
+                let tally = tallies.entry(name).or_default();
+                if tally.uri.is_none() {
+                    tally.uri = artist.uri.as_deref();
+                }
+                if seen.insert(name) {
+                    tally.count(track, playlist_id);
+                }
+            }
+        }
+    }
+
+    let mut artists: Vec<ArtistFrequency> = tallies
+        .into_iter()
+        .map(|(name, tally)| ArtistFrequency {
+            name: name.to_string(),
+            uri: tally.uri.map(str::to_string),
+            track_count: tally.track_count,
+            playlist_count: tally.playlists.len(),
+            avg_popularity: (tally.popularity_known > 0)
+                .then(|| tally.popularity_sum as f64 / tally.popularity_known as f64),
+        })
+        .collect();
+    artists.sort_by(|a, b| {
+        b.track_count
+            .cmp(&a.track_count)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    artists
+}
+
+pub fn write_artist_frequency_report(
+    path: &Path,
+    artists: &[ArtistFrequency],
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = Writer::from_path(path)?;
+    writer.write_record([
+        "Artist Name",
+        "Artist URI",
+        "Track Count",
+        "Playlist Count",
+        "Average Popularity",
+    ])?;
+    for artist in artists {
+        writer.write_record([
+            artist.name.as_str(),
+            artist.uri.as_deref().unwrap_or_default(),
+            &artist.track_count.to_string(),
+            &artist.playlist_count.to_string(),
+            &artist
+                .avg_popularity
+                .map(|average| format!("{:.1}", average))
+                .unwrap_or_default(),
+        ])?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// A row of artist_frequency.csv.
+#[derive(Deserialize)]
+struct ArtistFrequencyRow {
+    #[serde(rename = "Artist Name")]
+    name: String,
+    #[serde(rename = "Artist URI")]
+    uri: Option<String>,
+    #[serde(rename = "Track Count")]
+    track_count: usize,
+    #[serde(rename = "Playlist Count")]
+    playlist_count: usize,
+    /// Empty when no track's popularity is known.
+    #[serde(rename = "Average Popularity")]
+    avg_popularity: Option<f64>,
+}
+
+/// Reads a report written by `write_artist_frequency_report`, in its order.
+pub fn read_artist_frequency_report(path: &Path) -> Result<Vec<ArtistFrequency>, Box<dyn Error>> {
+    let mut reader = Reader::from_path(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    reader
+        .deserialize()
+        .map(|row| {
+            let row: ArtistFrequencyRow = row.map_err(|e| format!("{}: {}", path.display(), e))?;
+            Ok(ArtistFrequency {
+                name: row.name,
+                uri: row.uri,
+                track_count: row.track_count,
+                playlist_count: row.playlist_count,
+                avg_popularity: row.avg_popularity,
+            })
+        })
+        .collect()
+}
+
+/// The `limit` tracks available in the fewest markets, each listed once,
+/// as a numbered list for the console.
+pub fn rarest_tracks(all_exports: &[PlaylistExport], limit: usize) -> String {
+    let mut seen = HashSet::new();
+    let mut tracks: Vec<(&Track, usize)> = all_exports
+        .iter()
+        .flat_map(|export| export.tracks.iter())
+        .filter_map(|item| item.track.as_ref())
+        .filter_map(|track| Some((track, track.market_count?)))
+        .filter(|(track, _)| track.uri.as_ref().is_none_or(|uri| seen.insert(uri)))
+        .collect();
+    tracks.sort_by_key(|(_, count)| *count);
+
+    let mut text = String::from("Rarest tracks:");
+    for (rank, (track, count)) in tracks.iter().take(limit).enumerate() {
+        let _ = write!(
+            text,
+            "\n{:>3}. {} - {} ({} markets{})",
+            rank + 1,
+            join_artist_names(&track.artists, false),
+            track.name.as_deref().unwrap_or("<unknown>"),
+            count,
+            if *count < RARE_MARKETS { ", rare" } else { "" }
+        );
+    }
+    text
+}
+
+/// The first `limit` artists as a numbered list for the console.
+pub fn top_artists(artists: &[ArtistFrequency], limit: usize) -> String {
+    let mut text = String::from("Top artists:");
+    for (rank, artist) in artists.iter().take(limit).enumerate() {
+        let _ = write!(
+            text,
+            "\n{:>3}. {} ({} tracks in {} playlists)",
+            rank + 1,
+            artist.name,
+            artist.track_count,
+            artist.playlist_count
+        );
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        record::{RecordOptions, TrackRecord},
+        spotify::TrackItem,
+        testdir::TestDir,
+    };
+
+    fn item(
+        uri: &str,
+        artists: &[&str],
+        album_type: &str,
+        album_artist: &str,
+    ) -> serde_json::Value {
+        serde_json::json!({
+            "track": {
+                "uri": uri,
+                "name": uri,
+                "artists": artists
+                    .iter()
+                    .map(|name| serde_json::json!({"name": name, "uri": format!("spotify:artist:{}", name)}))
+                    .collect::<Vec<_>>(),
+                "album": {
+                    "name": "Album",
+                    "album_type": album_type,
+                    "artists": [{"name": album_artist, "uri": null}],
+                    "images": []
+                },
+                "duration_ms": 200000,
+                "popularity": 50
+            }
+        })
+    }
+
+    fn playlist_export(id: &str, items: Vec<serde_json::Value>) -> PlaylistExport {
+        let tracks: Vec<TrackItem> =
+            serde_json::from_value(serde_json::Value::Array(items)).unwrap();
+        let playlist = serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": id,
+            "collaborative": false,
+            "public": true,
+            "owner": {"id": "owner", "display_name": "Owner", "external_urls": {}},
+            "tracks": {"href": "", "total": tracks.len()},
+            "description": null,
+            "snapshot_id": "snapshot",
+            "external_urls": {},
+            "images": null
+        }))
+        .unwrap();
+        let records = tracks
+            .iter()
+            .filter_map(|item| item.track.as_ref())
+            .map(|track| {
+                TrackRecord::from_track(track, "Owner", String::new(), RecordOptions::default())
+            })
+            .collect();
+        PlaylistExport {
+            playlist,
+            tracks,
+            file: None,
+            records,
+            quarantined: Vec::new(),
+        }
+    }
+
+    /// Two playlists: one with an album track and two tracks from a
+    /// soundtrack, one with a track from a "Various Artists" collection.
+    fn library() -> Vec<PlaylistExport> {
+        vec![
+            playlist_export(
+                "first",
+                vec![
+                    item("spotify:track:a", &["Alpha"], "album", "Alpha"),
+                    item("spotify:track:b", &["Alpha"], "compilation", "Film Studio"),
+                    item(
+                        "spotify:track:c",
+                        &["Beta", "Alpha"],
+                        "compilation",
+                        "Film Studio",
+                    ),
+                ],
+            ),
+            playlist_export(
+                "second",
+                vec![item(
+                    "spotify:track:d",
+                    &["Gamma"],
+                    "album",
+                    "Various Artists",
+                )],
+            ),
+        ]
+    }
+
+    fn counts(report: &[ArtistFrequency]) -> Vec<(&str, usize, usize)> {
+        report
+            .iter()
+            .map(|artist| {
+                (
+                    artist.name.as_str(),
+                    artist.track_count,
+                    artist.playlist_count,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn own_group_gathers_compilation_tracks_in_one_row() {
+        let report = artist_frequency_report(&library(), CompilationPolicy::OwnGroup);
+        assert_eq!(
+            counts(&report),
+            vec![(COMPILATIONS_GROUP, 3, 2), ("Alpha", 1, 1)]
+        );
+        assert_eq!(report[0].uri, None);
+    }
+
+    #[test]
+    fn by_track_artist_credits_the_performers() {
+        let report = artist_frequency_report(&library(), CompilationPolicy::ByTrackArtist);
+        assert_eq!(
+            counts(&report),
+            vec![("Alpha", 3, 1), ("Beta", 1, 1), ("Gamma", 1, 1)]
+        );
+        assert!(report
+            .iter()
+            .all(|artist| artist.name != COMPILATIONS_GROUP));
+    }
+
+    #[test]
+    fn skip_leaves_compilation_tracks_out() {
+        let report = artist_frequency_report(&library(), CompilationPolicy::Skip);
+        assert_eq!(counts(&report), vec![("Alpha", 1, 1)]);
+    }
+
+    #[test]
+    fn unknown_average_popularity_is_an_empty_cell() {
+        let mut unknown = item("spotify:track:u", &["Delta"], "album", "Delta");
+        unknown["track"]["popularity"] = serde_json::Value::Null;
+        let mut unpopular = item("spotify:track:z", &["Epsilon"], "album", "Epsilon");
+        unpopular["track"]["popularity"] = 0.into();
+        let report = artist_frequency_report(
+            &[playlist_export("third", vec![unknown, unpopular])],
+            CompilationPolicy::OwnGroup,
+        );
+        let average = |name: &str| {
+            report
+                .iter()
+                .find(|artist| artist.name == name)
+                .unwrap()
+                .avg_popularity
+        };
+        assert_eq!(average("Delta"), None);
+        assert_eq!(average("Epsilon"), Some(0.0));
+
+        let dir = TestDir::new();
+        let path = dir.path().join(ARTIST_FREQUENCY_CSV);
+        write_artist_frequency_report(&path, &report).unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(
+            written.contains("Delta,spotify:artist:Delta,1,1,\n"),
+            "{}",
+            written
+        );
+        assert!(
+            written.contains("Epsilon,spotify:artist:Epsilon,1,1,0.0\n"),
+            "{}",
+            written
+        );
+        let read = read_artist_frequency_report(&path).unwrap();
+        let averages: Vec<Option<f64>> = read.iter().map(|artist| artist.avg_popularity).collect();
+        assert_eq!(averages, vec![None, Some(0.0)]);
+    }
+}
@@ -0,0 +1,176 @@
+//! Which failures are retried, and a circuit breaker that stops retrying an
+//! endpoint that keeps failing the same way. Once tripped, requests to that
+//! endpoint fail on the first error, so whatever depends on it degrades the
+//! way it does for any other failed request instead of stalling the run.
+
+use clap::ValueEnum;
+use log::warn;
+use reqwest::Url;
+use std::{collections::HashMap, sync::Mutex};
+
+/// Identical failures in a row, against one endpoint, that trip its
+/// breaker. Above the outage threshold, so a short outage is waited out
+/// before any breaker gives up.
+pub const BREAKER_THRESHOLD: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RetryClass {
+    /// HTTP 429 rate limiting
+    #[value(name = "429")]
+    RateLimit,
+    /// HTTP 5xx server errors, including waiting out outages
+    #[value(name = "5xx")]
+    ServerError,
+    /// Requests that time out or cannot connect
+    Timeout,
+    /// Successful responses that cannot be parsed
+    Parse,
+}
+
+#[derive(Debug, Default)]
+struct EndpointState {
+    last_error: String,
+    consecutive: u32,
+    tripped: bool,
+}
+
+/// A breaker that gave up on an endpoint, with the error that tripped it.
+#[derive(Debug, Clone)]
+pub struct Trip {
+    pub endpoint: String,
+    pub error: String,
+}
+
+#[derive(Debug, Default)]
+pub struct CircuitBreaker {
+    endpoints: Mutex<HashMap<String, EndpointState>>,
+}
+
+impl CircuitBreaker {
+    pub fn is_tripped(&self, endpoint: &str) -> bool {
+        self.endpoints
+            .lock()
+            .unwrap()
+            .get(endpoint)
+            .is_some_and(|state| state.tripped)
+    }
+
+    /// Counts a failure; only a run of identical ones trips the breaker.
+    pub fn record_failure(&self, endpoint: &str, error: &str) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let state = endpoints.entry(endpoint.to_string()).or_default();
+        if state.last_error == error {
+            state.consecutive += 1;
+        } else {
+            state.last_error = error.to_string();
+            state.consecutive = 1;
+        }
+        if !state.tripped && state.consecutive >= BREAKER_THRESHOLD {
+            state.tripped = true;
+            warn!(
+                "{} failed {} times in a row with {}; no longer retrying it this run",
+                endpoint, state.consecutive, error
+            );
+        }
+    }
+
+    pub fn record_success(&self, endpoint: &str) {
+        if let Some(state) = self.endpoints.lock().unwrap().get_mut(endpoint) {
+            state.consecutive = 0;
+        }
+    }
+
+    pub fn trips(&self) -> Vec<Trip> {
+        let endpoints = self.endpoints.lock().unwrap();
+        let mut trips: Vec<Trip> = endpoints
+            .iter()
+            .filter(|(_, state)| state.tripped)
+            .map(|(endpoint, state)| Trip {
+                endpoint: endpoint.clone(),
+                error: state.last_error.clone(),
+            })
+            .collect();
+        trips.sort_by(|a, b| a.endpoint.cmp(&b.endpoint));
+        trips
+    }
+}
+
+/// Segments naming a collection; the segment after one is an ID.
+const COLLECTIONS: [&str; 7] = [
+    "albums",
+    "artists",
+    "episodes",
+    "playlists",
+    "shows",
+    "tracks",
+    "users",
+];
+
+/// The endpoint a URL calls, with IDs and the query left out, e.g.
+/// `/v1/playlists/{id}/tracks`.
+pub fn endpoint_class(url: &str) -> String {
+    let Ok(url) = Url::parse(url) else {
+        return url.to_string();
+    };
+    let mut class = String::new();
+    let (mut before, mut previous) = ("", "");
+    for segment in url.path_segments().into_iter().flatten() {
+        class.push('/');
+        // Under /me they are the user's saved items, as in
+        // /me/tracks/contains, rather than collections.
+        class.push_str(if COLLECTIONS.contains(&previous) && before != "me" {
+            "{id}"
+        } else {
+            segment
+        });
+        (before, previous) = (previous, segment);
+    }
+    class
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_identical_failures_in_a_row_trip() {
+        let breaker = CircuitBreaker::default();
+        for _ in 1..BREAKER_THRESHOLD {
+            breaker.record_failure("/v1/users/{id}", "HTTP 502 Bad Gateway");
+        }
+        // A different error starts the count again, as does a success.
+        breaker.record_failure("/v1/users/{id}", "HTTP 503 Service Unavailable");
+        assert!(!breaker.is_tripped("/v1/users/{id}"));
+        for _ in 2..BREAKER_THRESHOLD {
+            breaker.record_failure("/v1/users/{id}", "HTTP 503 Service Unavailable");
+        }
+        breaker.record_success("/v1/users/{id}");
+        breaker.record_failure("/v1/users/{id}", "HTTP 503 Service Unavailable");
+        assert!(!breaker.is_tripped("/v1/users/{id}"));
+        assert!(breaker.trips().is_empty());
+
+        for _ in 0..BREAKER_THRESHOLD {
+            breaker.record_failure("/v1/users/{id}", "HTTP 503 Service Unavailable");
+        }
+        assert!(breaker.is_tripped("/v1/users/{id}"));
+        assert!(!breaker.is_tripped("/v1/playlists/{id}/tracks"));
+        let trips = breaker.trips();
+        assert_eq!(trips.len(), 1);
+        assert_eq!(trips[0].endpoint, "/v1/users/{id}");
+        assert_eq!(trips[0].error, "HTTP 503 Service Unavailable");
+    }
+
+    #[test]
+    fn endpoint_classes_leave_out_ids_and_queries() {
+        assert_eq!(
+            endpoint_class(
+                "https://api.spotify.com/v1/playlists/37i9dQZF1DXcBWIGoYBM5M/tracks?offset=100"
+            ),
+            "/v1/playlists/{id}/tracks"
+        );
+        assert_eq!(
+            endpoint_class("https://api.spotify.com/v1/me/tracks/contains?ids=a,b"),
+            "/v1/me/tracks/contains"
+        );
+    }
+}
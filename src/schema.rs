@@ -0,0 +1,313 @@
+//! JSON Schema documents for the JSON files this tool writes, generated
+//! from the types that write them so they cannot drift apart. Each schema's
+//! `$id` carries the tool version, for consumers that pin one.
+
+use clap::ValueEnum;
+use schemars::{schema::RootSchema, schema_for};
+use serde_json::Value;
+use std::error::Error;
+
+use crate::{
+    index::RunIndex,
+    manifest::Manifest,
+    output::{LibraryExport, OutputConfig},
+    passport::Passport,
+    provenance::{TOOL_NAME, VERSION},
+    quarantine::Quarantine,
+    record::TrackRecord,
+    state::RunState,
+    warnings::ReportError,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SchemaKind {
+    /// library.json, written by --format json
+    Library,
+    /// A single track record, as in library.json
+    TrackRecord,
+    /// index.json, including the run summary
+    Index,
+    /// quarantine.json
+    Quarantine,
+    /// export_warnings.json
+    Warnings,
+    /// export_config.json
+    ExportConfig,
+    /// state.json
+    State,
+    /// manifest.json
+    Manifest,
+    /// profile.json, the backup passport
+    Profile,
+}
+
+impl SchemaKind {
+    fn root_schema(self) -> RootSchema {
+        match self {
+            SchemaKind::Library => schema_for!(LibraryExport),
+            SchemaKind::TrackRecord => schema_for!(TrackRecord),
+            SchemaKind::Index => schema_for!(RunIndex),
+            SchemaKind::Quarantine => schema_for!(Quarantine),
+            SchemaKind::Warnings => schema_for!(Vec<ReportError>),
+            SchemaKind::ExportConfig => schema_for!(OutputConfig),
+            SchemaKind::State => schema_for!(RunState),
+            SchemaKind::Manifest => schema_for!(Manifest),
+            SchemaKind::Profile => schema_for!(Passport),
+        }
+    }
+}
+
+/// The schema for `kind` as pretty-printed JSON.
+pub fn schema_json(kind: SchemaKind) -> Result<String, Box<dyn Error>> {
+    let mut schema = serde_json::to_value(kind.root_schema())?;
+    let name = kind
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default();
+    if let Value::Object(fields) = &mut schema {
+        fields.insert(
+            "$id".into(),
+            format!("urn:{}:{}:{}", TOOL_NAME, VERSION, name).into(),
+        );
+    }
+    Ok(serde_json::to_string_pretty(&schema)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cli::tests::output_args,
+        export::PlaylistExport,
+        index::{write_index, RunSummary, INDEX_JSON},
+        local_edits::{LocalEditPolicy, LocalEdits},
+        manifest::{write_manifest, MANIFEST_JSON},
+        output::{write_library, write_playlist, EXPORT_CONFIG_JSON, LIBRARY_JSON},
+        passport::{write_passport, PROFILE_JSON},
+        paths::OutputPaths,
+        provenance::Provenance,
+        quarantine::{write_quarantine, QuarantinedPage, QUARANTINE_JSON},
+        record::{Field, RecordOptions, DEFAULT_FIELDS},
+        spotify::{TrackItem, User},
+        state::{write_state, ShowState, STATE_JSON},
+        testdir::TestDir,
+        warnings::{ErrorCollector, Severity, EXPORT_WARNINGS_JSON},
+    };
+    use std::{fs, path::Path};
+
+    fn playlist_export() -> PlaylistExport {
+        let tracks: Vec<TrackItem> = serde_json::from_value(serde_json::json!([
+            {
+                "added_at": "2023-04-01T10:00:00Z",
+                "added_by": {"id": "owner"},
+                "track": {
+                    "uri": "spotify:track:6LgJvl0Xdtc73RJ1mmpotq",
+                    "name": "Paranoid Android",
+                    "artists": [{"name": "Radiohead", "uri": "spotify:artist:4Z8W4fKeB5YxbusRsdQVPb"}],
+                    "album": {
+                        "name": "OK Computer",
+                        "uri": "spotify:album:6dVIqQ8qmQ5GBnJ9shOYGE",
+                        "album_type": "album",
+                        "release_date": "1997-05-28",
+                        "artists": [{"name": "Radiohead", "uri": "spotify:artist:4Z8W4fKeB5YxbusRsdQVPb"}],
+                        "images": [{"url": "https://i.scdn.co/image/ab67616d", "width": 640, "height": 640}]
+                    },
+                    "disc_number": 1,
+                    "track_number": 2,
+                    "duration_ms": 383893,
+                    "explicit": false,
+                    "popularity": 79,
+                    "external_ids": {"isrc": "GBAYE9700102"}
+                }
+            },
+            // Very old playlists have neither, and local files lack most fields.
+            {
+                "track": {
+                    "uri": "spotify:local:Artist:Album:Song:200",
+                    "name": "Song",
+                    "artists": [{"name": "Artist", "uri": null}],
+                    "album": {"name": "Album", "artists": [], "images": []},
+                    "duration_ms": 200000,
+                    "popularity": null
+                }
+            }
+        ]))
+        .unwrap();
+        let playlist = serde_json::from_value(serde_json::json!({
+            "id": "37i9dQZF1DXcBWIGoYBM5M",
+            "name": "Today's Mix",
+            "collaborative": false,
+            "public": true,
+            "owner": {
+                "id": "owner",
+                "display_name": "Owner",
+                "external_urls": {"spotify": "https://open.spotify.com/user/owner"}
+            },
+            "tracks": {"href": "https://api.spotify.com/v1/playlists/37i9dQZF1DXcBWIGoYBM5M/tracks", "total": 2},
+            "description": "Fixture",
+            "snapshot_id": "MTcsNDg",
+            "external_urls": {"spotify": "https://open.spotify.com/playlist/37i9dQZF1DXcBWIGoYBM5M"},
+            "images": null
+        }))
+        .unwrap();
+        let records = tracks
+            .iter()
+            .filter_map(|item| item.track.as_ref())
+            .map(|track| {
+                TrackRecord::from_track(track, "Owner", String::new(), RecordOptions::default())
+            })
+            .collect();
+        PlaylistExport {
+            playlist,
+            tracks,
+            file: None,
+            records,
+            quarantined: Vec::new(),
+        }
+    }
+
+    /// Checks the JSON file `name` in `dir` against the schema for `kind`.
+    fn assert_valid(dir: &Path, name: &str, kind: SchemaKind) {
+        let artifact: Value = serde_json::from_slice(&fs::read(dir.join(name)).unwrap()).unwrap();
+        assert_matches(&artifact, kind);
+    }
+
+    fn assert_matches(instance: &Value, kind: SchemaKind) {
+        let schema: Value = serde_json::from_str(&schema_json(kind).unwrap()).unwrap();
+        let validator = jsonschema::validator_for(&schema).unwrap();
+        let errors: Vec<String> = validator
+            .iter_errors(instance)
+            .map(|e| format!("{} at {}", e, e.instance_path))
+            .collect();
+        assert!(errors.is_empty(), "{:?}: {:#?}", kind, errors);
+    }
+
+    #[test]
+    fn every_artifact_matches_its_schema() {
+        let dir = TestDir::new();
+        let mut export = playlist_export();
+        let provenance = Provenance::new(Some("owner".to_string()), &serde_json::json!({"x": 1}));
+        let fields = [DEFAULT_FIELDS.to_vec(), vec![Field::Position]].concat();
+        let mut paths = OutputPaths::in_dir(dir.path());
+        let edits = LocalEdits::load(&dir.path().join(MANIFEST_JSON), LocalEditPolicy::Overwrite);
+
+        let csv = OutputConfig::new(&output_args(&["--csv-preamble"]), fields.clone());
+        let records = crate::output::PlaylistRecords {
+            name: export.playlist.name.clone(),
+            owner: "Owner".to_string(),
+            tracks: export.records.clone(),
+        };
+        export.file =
+            write_playlist(&records, &csv, Some(&provenance), &mut paths, Some(&edits)).unwrap();
+        let json = OutputConfig::new(&output_args(&["--format", "json"]), fields);
+        write_library(vec![records], &json, Some(&provenance), &mut paths).unwrap();
+        json.write(&paths.library_file(EXPORT_CONFIG_JSON)).unwrap();
+
+        let mut index = RunIndex::from_exports(std::slice::from_ref(&export), &provenance, false);
+        index.summary = Some(RunSummary {
+            duration_secs: 12,
+            requests: 40,
+            rate_limited: 1,
+            warnings: 1,
+            incomplete_playlists: 1,
+            out_of_space: false,
+            removed_temp_files: 0,
+            interrupted_writes: 0,
+            missing_files: 0,
+            outage_pause_secs: 0,
+            outage_stop: false,
+        });
+        write_index(&paths.library_file(INDEX_JSON), &index).unwrap();
+
+        let quarantine = Quarantine {
+            record_options: RecordOptions::default(),
+            filter: output_args(&["--min-popularity", "10"]).track_filter(),
+            pages: vec![QuarantinedPage {
+                playlist_id: export.playlist.id.clone(),
+                playlist_name: export.playlist.name.clone(),
+                owner: "Owner".to_string(),
+                url: export.playlist.tracks.href.clone(),
+                offset: 100,
+                limit: 100,
+                error: "502 Bad Gateway".to_string(),
+                file: export.file.clone(),
+                row: Some(2),
+            }],
+        };
+        write_quarantine(&paths.library_file(QUARANTINE_JSON), &quarantine).unwrap();
+
+        let errors = ErrorCollector::new(false);
+        errors.report(Severity::Error, "Today's Mix", None, "page failed");
+        errors.report(
+            Severity::Warning,
+            "Today's Mix",
+            Some("spotify:track:1"),
+            "no ISRC",
+        );
+        errors
+            .write(&dir.path().join(EXPORT_WARNINGS_JSON))
+            .unwrap();
+
+        let mut state = RunState::default();
+        state.shows.insert(
+            "show".to_string(),
+            ShowState {
+                last_release_date: Some("2024-05-01".to_string()),
+                listed: ["episode".to_string()].into(),
+            },
+        );
+        write_state(&paths.library_file(STATE_JSON), &state).unwrap();
+
+        let user: User = serde_json::from_value(serde_json::json!({
+            "id": "owner",
+            "display_name": "Owner",
+            "country": "NL",
+            "product": "premium"
+        }))
+        .unwrap();
+        let passport = Passport::new(Some(&user), std::slice::from_ref(&export), &provenance);
+        write_passport(&paths.library_file(PROFILE_JSON), &passport).unwrap();
+
+        write_manifest(dir.path(), paths.claimed(), &edits).unwrap();
+
+        assert_valid(dir.path(), LIBRARY_JSON, SchemaKind::Library);
+        assert_valid(dir.path(), EXPORT_CONFIG_JSON, SchemaKind::ExportConfig);
+        assert_valid(dir.path(), INDEX_JSON, SchemaKind::Index);
+        assert_valid(dir.path(), QUARANTINE_JSON, SchemaKind::Quarantine);
+        assert_valid(dir.path(), EXPORT_WARNINGS_JSON, SchemaKind::Warnings);
+        assert_valid(dir.path(), STATE_JSON, SchemaKind::State);
+        assert_valid(dir.path(), PROFILE_JSON, SchemaKind::Profile);
+        assert_valid(dir.path(), MANIFEST_JSON, SchemaKind::Manifest);
+
+        let library: Value =
+            serde_json::from_slice(&fs::read(dir.path().join(LIBRARY_JSON)).unwrap()).unwrap();
+        let records = library["playlists"][0]["tracks"].as_array().unwrap();
+        assert_eq!(records.len(), 2);
+        for record in records {
+            assert_matches(record, SchemaKind::TrackRecord);
+        }
+    }
+
+    #[test]
+    fn schemas_reject_what_the_tool_would_not_write() {
+        let schema: Value =
+            serde_json::from_str(&schema_json(SchemaKind::TrackRecord).unwrap()).unwrap();
+        let mut record = serde_json::to_value(&playlist_export().records[0]).unwrap();
+        assert!(jsonschema::is_valid(&schema, &record));
+
+        record["Popularity"] = "high".into();
+        assert!(!jsonschema::is_valid(&schema, &record));
+        record["Popularity"] = 79.into();
+        record.as_object_mut().unwrap().remove("Artist Name(s)");
+        assert!(!jsonschema::is_valid(&schema, &record));
+    }
+
+    #[test]
+    fn schema_ids_carry_the_version() {
+        let schema: Value = serde_json::from_str(&schema_json(SchemaKind::Index).unwrap()).unwrap();
+        assert_eq!(
+            schema["$id"],
+            format!("urn:{}:{}:index", TOOL_NAME, VERSION).as_str()
+        );
+    }
+}
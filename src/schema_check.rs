@@ -0,0 +1,141 @@
+//! `validate-schema`: whether live API responses still have the fields the
+//! response types read, and which fields they have that the types do not.
+//! The expected fields come from the types' JSON Schemas, so the check
+//! cannot drift from what deserialization actually reads. Unlike
+//! `--strict-api`, it looks at every item of a response and reports
+//! everything it finds instead of failing on the first surprise.
+
+use clap::ValueEnum;
+use schemars::{schema_for, JsonSchema};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+};
+
+use crate::{
+    api::{SpotifyAPI, API_BASE},
+    spotify::{Playlist, PlaylistResponse, TrackItem},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ApiEndpoint {
+    /// The first page of /me/playlists, against the playlist type
+    Playlists,
+    /// The first page of the first playlist's items, against the track
+    /// item type
+    Tracks,
+}
+
+#[derive(Debug, Default)]
+pub struct SchemaReport {
+    /// Fields the type reads that some item lacked, such as
+    /// `items[].owner.id`, and whether deserialization needs them.
+    pub missing: BTreeMap<String, bool>,
+    /// Fields some item had that the type does not read.
+    pub extra: BTreeSet<String>,
+}
+
+/// Follows `$ref`s into `definitions`, and `Option`s to what they wrap.
+fn resolve<'a>(schema: &'a Value, definitions: &'a Value) -> &'a Value {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        let name = reference.trim_start_matches("#/definitions/");
+        return resolve(&definitions[name], definitions);
+    }
+    for key in ["allOf", "anyOf", "oneOf"] {
+        let wrapped = schema
+            .get(key)
+            .and_then(Value::as_array)
+            .and_then(|options| {
+                options
+                    .iter()
+                    .find(|option| option.get("type").and_then(Value::as_str) != Some("null"))
+            });
+        if let Some(wrapped) = wrapped {
+            return resolve(wrapped, definitions);
+        }
+    }
+    schema
+}
+
+fn compare(
+    value: &Value,
+    schema: &Value,
+    definitions: &Value,
+    path: &str,
+    report: &mut SchemaReport,
+) {
+    let schema = resolve(schema, definitions);
+    match value {
+        Value::Object(fields) => {
+            let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+                return;
+            };
+            let required: Vec<&str> = schema
+                .get("required")
+                .and_then(Value::as_array)
+                .map(|names| names.iter().filter_map(Value::as_str).collect())
+                .unwrap_or_default();
+            for (name, property) in properties {
+                let field_path = format!("{}.{}", path, name);
+                match fields.get(name) {
+                    Some(field) => compare(field, property, definitions, &field_path, report),
+                    None => {
+                        let needed = required.contains(&name.as_str());
+                        *report.missing.entry(field_path).or_default() |= needed;
+                    }
+                }
+            }
+            for name in fields.keys().filter(|name| !properties.contains_key(*name)) {
+                report.extra.insert(format!("{}.{}", path, name));
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for item in items {
+                    compare(
+                        item,
+                        item_schema,
+                        definitions,
+                        &format!("{}[]", path),
+                        report,
+                    );
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Checks each of `response["items"]` against `T`.
+fn check_items<T: JsonSchema>(response: &Value) -> Result<SchemaReport, Box<dyn Error>> {
+    let root = serde_json::to_value(schema_for!(T))?;
+    let items = response
+        .get("items")
+        .and_then(Value::as_array)
+        .ok_or("the response has no items")?;
+    let mut report = SchemaReport::default();
+    for item in items {
+        compare(item, &root, &root["definitions"], "items[]", &mut report);
+    }
+    Ok(report)
+}
+
+/// Fetches a page from `endpoint` and checks it.
+pub async fn validate_endpoint(
+    api: &SpotifyAPI,
+    endpoint: ApiEndpoint,
+) -> Result<SchemaReport, Box<dyn Error>> {
+    let list_url = format!("{}/me/playlists?limit=50", API_BASE);
+    match endpoint {
+        ApiEndpoint::Playlists => check_items::<Playlist>(&api.get::<Value>(&list_url).await?),
+        ApiEndpoint::Tracks => {
+            let playlists: PlaylistResponse = api.get(&list_url).await?;
+            let playlist = playlists
+                .items
+                .first()
+                .ok_or("the library has no playlists to read items from")?;
+            check_items::<TrackItem>(&api.get::<Value>(&playlist.tracks.href).await?)
+        }
+    }
+}
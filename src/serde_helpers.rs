@@ -0,0 +1,146 @@
+/// Treats `""` and `null` as `None`, and writes `None` back out as `""`.
+///
+/// JSON responses use `null` for missing strings while CSV has no null, so an
+/// exported empty cell has to come back as `None` when the file is re-read.
+/// Pair with `#[serde(default)]` so absent keys also map to `None`.
+pub mod empty_string_as_none {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(value: &Option<String>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(value.as_deref().unwrap_or_default())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = Option::<String>::deserialize(deserializer)?;
+        Ok(value.filter(|s| !s.is_empty()))
+    }
+}
+
+/// Reads a flag sent as `true`/`false`, `1`/`0` or `"true"`/`"false"`,
+/// since some responses encode booleans as numbers or strings. `null` is
+/// `None`; pair with `#[serde(default)]` so an absent key is too.
+pub fn de_bool_flexible<'de, D>(deserializer: D) -> Result<Option<bool>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::{de::Error, Deserialize};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Flag {
+        Bool(bool),
+        Number(u64),
+        Text(String),
+    }
+
+    match Option::<Flag>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Flag::Bool(value)) => Ok(Some(value)),
+        Some(Flag::Number(0)) => Ok(Some(false)),
+        Some(Flag::Number(1)) => Ok(Some(true)),
+        Some(Flag::Number(n)) => Err(D::Error::custom(format!("expected 0 or 1, got {}", n))),
+        Some(Flag::Text(text)) => match text.to_ascii_lowercase().as_str() {
+            "true" | "1" => Ok(Some(true)),
+            "false" | "0" => Ok(Some(false)),
+            _ => Err(D::Error::custom(format!(
+                "expected \"true\" or \"false\", got {:?}",
+                text
+            ))),
+        },
+    }
+}
+
+/// Reads an array as just its length, without keeping its elements. `null`
+/// is `None`; pair with `#[serde(default)]` so an absent key is too.
+pub fn de_seq_len<'de, D>(deserializer: D) -> Result<Option<usize>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::{IgnoredAny, SeqAccess, Visitor};
+    use std::fmt;
+
+    struct SeqLen;
+
+    impl<'de> Visitor<'de> for SeqLen {
+        type Value = Option<usize>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an array or null")
+        }
+
+        fn visit_unit<E>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_none<E>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            deserializer.deserialize_seq(self)
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut len = 0;
+            while seq.next_element::<IgnoredAny>()?.is_some() {
+                len += 1;
+            }
+            Ok(Some(len))
+        }
+    }
+
+    deserializer.deserialize_option(SeqLen)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    struct Flagged {
+        #[serde(default, deserialize_with = "de_bool_flexible")]
+        explicit: Option<bool>,
+    }
+
+    fn explicit(json: &str) -> Result<Option<bool>, serde_json::Error> {
+        serde_json::from_str::<Flagged>(json).map(|flagged| flagged.explicit)
+    }
+
+    #[test]
+    fn reads_every_representation_of_a_flag() {
+        assert_eq!(explicit(r#"{"explicit": true}"#).unwrap(), Some(true));
+        assert_eq!(explicit(r#"{"explicit": false}"#).unwrap(), Some(false));
+        assert_eq!(explicit(r#"{"explicit": 1}"#).unwrap(), Some(true));
+        assert_eq!(explicit(r#"{"explicit": 0}"#).unwrap(), Some(false));
+        assert_eq!(explicit(r#"{"explicit": "true"}"#).unwrap(), Some(true));
+        assert_eq!(explicit(r#"{"explicit": "False"}"#).unwrap(), Some(false));
+        assert_eq!(explicit(r#"{"explicit": null}"#).unwrap(), None);
+        assert_eq!(explicit("{}").unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_anything_else() {
+        for json in [
+            r#"{"explicit": 2}"#,
+            r#"{"explicit": -1}"#,
+            r#"{"explicit": "yes"}"#,
+            r#"{"explicit": ""}"#,
+            r#"{"explicit": [true]}"#,
+        ] {
+            assert!(explicit(json).is_err(), "{}", json);
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! `setup`: a first-run walk through what an export needs, asked on the
+//! terminal so it works the same over SSH. Every question can be skipped
+//! with Enter. The answers end up in a small shell profile in the output
+//! directory, which sets the token and default format for later runs.
+
+use clap::ValueEnum;
+use std::{
+    env,
+    error::Error,
+    fs::{self, OpenOptions},
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+use crate::{
+    cli::GlobalArgs,
+    doctor::{check_token, print_results},
+    output::OutputFormat,
+};
+
+pub const SETUP_ENV: &str = "rimusic-convert.env";
+
+/// Read by `--format`, so the profile can set the default.
+pub const FORMAT_ENV: &str = "RIMUSIC_CONVERT_FORMAT";
+
+/// Asks `question` and returns the trimmed answer, or `None` when it was
+/// skipped with Enter or stdin has ended.
+fn ask(question: &str) -> Result<Option<String>, Box<dyn Error>> {
+    print!("{} ", question);
+    io::stdout().flush()?;
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        println!();
+        return Ok(None);
+    }
+    let answer = line.trim();
+    Ok((!answer.is_empty()).then(|| answer.to_string()))
+}
+
+fn confirm(question: &str, default: bool) -> Result<bool, Box<dyn Error>> {
+    let hint = if default { "[Y/n]" } else { "[y/N]" };
+    loop {
+        match ask(&format!("{} {}", question, hint))? {
+            None => return Ok(default),
+            Some(answer) => match answer.to_lowercase().as_str() {
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                _ => println!("Please answer y or n."),
+            },
+        }
+    }
+}
+
+/// Quotes `value` for a POSIX shell.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+fn format_name(format: OutputFormat) -> String {
+    format
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default()
+}
+
+/// Writes the profile, readable only by its owner since it holds the token.
+fn write_profile(path: &Path, token: Option<&str>, format: OutputFormat) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    writeln!(
+        file,
+        "# Written by `rimusic-convert setup`. Load it with `. ./{}`.",
+        SETUP_ENV
+    )?;
+    if let Some(token) = token {
+        writeln!(file, "export SPOTIFY_TOKEN={}", shell_quote(token))?;
+    }
+    writeln!(file, "export {}={}", FORMAT_ENV, format_name(format))?;
+    Ok(())
+}
+
+pub async fn run_setup(global: GlobalArgs) -> Result<(), Box<dyn Error>> {
+    println!("This sets up exports from Spotify. Press Enter to skip any question.");
+    println!();
+
+    // Only pasted tokens are supported; this tool has no login flow.
+    println!("1. Access token");
+    let token = match global.token.clone() {
+        Some(token) => {
+            println!("Using the token from --token or SPOTIFY_TOKEN.");
+            Some(token)
+        }
+        None => {
+            println!(
+                "Get one with the playlist-read-private and playlist-read-collaborative scopes \
+                 (add user-library-read for saved shows and Liked Songs)."
+            );
+            ask("Paste it here:")?
+        }
+    };
+    if token.is_none() {
+        println!("Skipped; only public playlists can be exported, with --public --playlist.");
+    }
+    println!();
+
+    if let Some(token) = &token {
+        println!("2. Check the token");
+        if confirm("Check it against the API now?", true)? {
+            let results = check_token(token.clone(), global.http.build_client()?).await;
+            print_results(&results);
+        }
+        println!();
+    }
+
+    println!("3. Output directory");
+    let dir = PathBuf::from(ask("Where should exports go? [.]")?.unwrap_or_else(|| ".".into()));
+    fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    println!();
+
+    println!("4. Default format");
+    let format = loop {
+        match ask("csv (one file per playlist) or json (a single library.json)? [csv]")? {
+            None => break OutputFormat::Csv,
+            Some(answer) => match OutputFormat::from_str(&answer, true) {
+                Ok(format @ (OutputFormat::Csv | OutputFormat::Json)) => break format,
+                // A template needs a file of its own; pass --format template
+                // with --template-file when exporting.
+                _ => println!("Please answer csv or json."),
+            },
+        }
+    };
+    println!();
+
+    let profile = dir.join(SETUP_ENV);
+    let write = !profile.exists() || confirm(&format!("Overwrite {}?", profile.display()), false)?;
+    if write {
+        write_profile(&profile, token.as_deref(), format)
+            .map_err(|e| format!("{}: {}", profile.display(), e))?;
+        println!("Wrote {}. Before exporting, run:", profile.display());
+        println!(
+            "    cd {} && . ./{}",
+            shell_quote(&dir.to_string_lossy()),
+            SETUP_ENV
+        );
+        println!();
+    }
+
+    if let Some(token) = &token {
+        if confirm(
+            "List your playlists and show what an export would fetch (a dry run)?",
+            true,
+        )? {
+            process::Command::new(env::current_exe()?)
+                .args(["export", "--dry-run"])
+                .current_dir(&dir)
+                .env("SPOTIFY_TOKEN", token)
+                .env(FORMAT_ENV, format_name(format))
+                .status()?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,141 @@
+//! A single page listing every playlist of an export with its link, for
+//! handing a library over to the people it was shared with. Built from the
+//! export's index.json alone, without contacting the API.
+
+use clap::ValueEnum;
+use std::{error::Error, fmt::Write as _, fs, path::Path};
+
+use crate::{
+    dashboard::escape_html,
+    index::{read_index, IndexEntry, RunIndex, INDEX_JSON},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ShareFormat {
+    #[default]
+    Markdown,
+    Html,
+}
+
+impl ShareFormat {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            ShareFormat::Markdown => "share-pack.md",
+            ShareFormat::Html => "share-pack.html",
+        }
+    }
+}
+
+/// Backslash-escapes what Markdown would otherwise format.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '|'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// "12 tracks, collaborative, 40 followers"
+fn details(entry: &IndexEntry) -> String {
+    let mut details = vec![format!("{} tracks", entry.track_count)];
+    if entry.collaborative {
+        details.push("collaborative".into());
+    }
+    if let Some(followers) = entry.followers {
+        details.push(format!("{} followers", followers));
+    }
+    details.join(", ")
+}
+
+fn render_markdown(index: &RunIndex) -> String {
+    let mut text = format!(
+        "# Playlists\n\n{} playlists, exported {}.\n",
+        index.playlists.len(),
+        index.exported_at
+    );
+    for entry in &index.playlists {
+        let name = escape_markdown(&entry.name);
+        let _ = write!(
+            text,
+            "\n## {}\n\n",
+            match &entry.url {
+                Some(url) => format!("[{}](<{}>)", name, url),
+                None => name,
+            }
+        );
+        if let Some(cover) = &entry.cover_url {
+            let _ = writeln!(text, "![Cover](<{}>)\n", cover);
+        }
+        let owner = escape_markdown(&entry.owner);
+        let _ = writeln!(
+            text,
+            "By {}. {}.",
+            match &entry.owner_url {
+                Some(url) => format!("[{}](<{}>)", owner, url),
+                None => owner,
+            },
+            details(entry)
+        );
+        if let Some(description) = &entry.description {
+            let _ = writeln!(text, "\n> {}", escape_markdown(description));
+        }
+    }
+    text
+}
+
+fn render_html(index: &RunIndex) -> String {
+    let link = |text: &str, url: &Option<String>| match url {
+        Some(url) => format!("<a href=\"{}\">{}</a>", escape_html(url), escape_html(text)),
+        None => escape_html(text),
+    };
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Playlists</title>\
+         <style>body {{ font-family: system-ui, sans-serif; margin: 2em; max-width: 50em; }} \
+         section {{ display: flex; gap: 1em; margin-bottom: 1.5em; }} \
+         img {{ width: 96px; height: 96px; object-fit: cover; }} \
+         p {{ margin: 0.2em 0; }}</style></head><body>\n\
+         <h1>Playlists</h1>\n<p>{} playlists, exported {}.</p>\n",
+        index.playlists.len(),
+        escape_html(&index.exported_at)
+    );
+    for entry in &index.playlists {
+        html.push_str("<section>");
+        if let Some(cover) = &entry.cover_url {
+            let _ = write!(html, "<img src=\"{}\" alt=\"\">", escape_html(cover));
+        }
+        let _ = write!(
+            html,
+            "<div><h2>{}</h2><p>By {}. {}.</p>",
+            link(&entry.name, &entry.url),
+            link(&entry.owner, &entry.owner_url),
+            details(entry)
+        );
+        if let Some(description) = &entry.description {
+            let _ = write!(html, "<p>{}</p>", escape_html(description));
+        }
+        html.push_str("</div></section>\n");
+    }
+    html.push_str("</body></html>\n");
+    html
+}
+
+/// Writes the page for the export in `dir` to `output`.
+pub fn write_share_pack(
+    dir: &Path,
+    format: ShareFormat,
+    output: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let index = read_index(&dir.join(INDEX_JSON))?;
+    let page = match format {
+        ShareFormat::Markdown => render_markdown(&index),
+        ShareFormat::Html => render_html(&index),
+    };
+    fs::write(output, page).map_err(|e| format!("{}: {}", output.display(), e))?;
+    Ok(())
+}
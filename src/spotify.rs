@@ -0,0 +1,493 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::serde_helpers::{de_bool_flexible, de_seq_len, empty_string_as_none};
+
+#[derive(Debug, Deserialize)]
+pub struct PaginatedTrackResponse {
+    pub items: Vec<TrackItem>,
+    pub next: Option<String>,
+    #[serde(default)]
+    pub total: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct TrackItem {
+    pub track: Option<Track>,
+    /// When the item was added, in RFC 3339. Missing for very old playlists.
+    #[serde(default)]
+    pub added_at: Option<String>,
+    /// Who added the item. Missing for very old playlists.
+    #[serde(default)]
+    pub added_by: Option<AddedBy>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct AddedBy {
+    pub id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct Track {
+    #[serde(default, with = "empty_string_as_none")]
+    #[schemars(with = "Option<String>")]
+    pub uri: Option<String>,
+    #[serde(default, with = "empty_string_as_none")]
+    #[schemars(with = "Option<String>")]
+    pub name: Option<String>,
+    pub artists: Vec<Artist>,
+    pub album: Album,
+    pub duration_ms: Option<u32>,
+    pub popularity: Option<u8>,
+    #[serde(default)]
+    pub disc_number: Option<u32>,
+    #[serde(default)]
+    pub track_number: Option<u32>,
+    /// Never sent by the API; see `isrc()`.
+    #[serde(default, with = "empty_string_as_none")]
+    #[schemars(skip)]
+    pub isrc: Option<String>,
+    #[serde(default)]
+    pub external_ids: ExternalIds,
+    #[serde(default, with = "empty_string_as_none")]
+    #[schemars(with = "Option<String>")]
+    pub preview_url: Option<String>,
+    #[serde(default, deserialize_with = "de_bool_flexible")]
+    #[schemars(with = "Option<bool>")]
+    pub explicit: Option<bool>,
+    /// How many markets the track is available in. Only the count of
+    /// `available_markets` is kept, since the list runs to nearly 200 codes.
+    #[serde(
+        rename = "available_markets",
+        default,
+        deserialize_with = "de_seq_len",
+        skip_serializing
+    )]
+    #[schemars(with = "Option<Vec<String>>")]
+    pub market_count: Option<usize>,
+    /// Whether the track is in Liked Songs. The API does not say; only
+    /// set with `--include-liked-status`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(skip)]
+    pub is_liked: Option<bool>,
+    /// Whether the track's album is among the saved albums; only set with
+    /// `--include-saved-status`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(skip)]
+    pub is_saved_album: Option<bool>,
+}
+
+impl Track {
+    /// The API reports the ISRC under `external_ids`; a top-level `isrc`
+    /// (as in re-imported data) takes precedence.
+    pub fn isrc(&self) -> Option<&str> {
+        self.isrc.as_deref().or(self.external_ids.isrc.as_deref())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct ExternalIds {
+    #[serde(default, with = "empty_string_as_none")]
+    #[schemars(with = "Option<String>")]
+    pub isrc: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct Artist {
+    #[serde(default, with = "empty_string_as_none")]
+    #[schemars(with = "Option<String>")]
+    pub uri: Option<String>,
+    #[serde(default, with = "empty_string_as_none")]
+    #[schemars(with = "Option<String>")]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct Album {
+    #[serde(default, with = "empty_string_as_none")]
+    #[schemars(with = "Option<String>")]
+    pub uri: Option<String>,
+    /// `album`, `single` or `compilation`.
+    #[serde(default, with = "empty_string_as_none")]
+    #[schemars(with = "Option<String>")]
+    pub album_type: Option<String>,
+    #[serde(default, with = "empty_string_as_none")]
+    #[schemars(with = "Option<String>")]
+    pub name: Option<String>,
+    #[serde(default, with = "empty_string_as_none")]
+    #[schemars(with = "Option<String>")]
+    pub release_date: Option<String>,
+    pub artists: Vec<Artist>,
+    pub images: Vec<Image>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct Image {
+    pub url: String,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TopTracksResponse {
+    pub tracks: Vec<Track>,
+}
+
+/// A browse category, such as a genre or mood.
+#[derive(Debug, Deserialize)]
+pub struct Category {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CategoriesResponse {
+    pub categories: CategoryPage,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CategoryPage {
+    pub items: Vec<Category>,
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CategoryPlaylistsResponse {
+    pub playlists: CategoryPlaylistPage,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CategoryPlaylistPage {
+    /// `None` for playlists Spotify lists but will not show.
+    pub items: Vec<Option<Playlist>>,
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlaylistResponse {
+    pub items: Vec<Playlist>,
+    pub next: Option<String>,
+    /// Playlists across every page.
+    #[serde(default)]
+    pub total: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Playlist {
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub collaborative: bool,
+    /// `None` when Spotify does not report the playlist's visibility.
+    pub public: Option<bool>,
+    pub owner: Owner,
+    pub tracks: Tracks,
+    #[serde(default, with = "empty_string_as_none")]
+    #[schemars(with = "Option<String>")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub snapshot_id: Option<String>,
+    /// Only known after `SpotifyAPI::enrich_playlist_followers`.
+    #[serde(default)]
+    #[schemars(skip)]
+    pub followers_count: Option<u64>,
+    #[serde(default)]
+    pub external_urls: Option<ExternalUrls>,
+    /// Largest first. Spotify sends `null` for a playlist without a cover.
+    #[serde(default)]
+    pub images: Option<Vec<Image>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ExternalUrls {
+    pub spotify: Option<String>,
+}
+
+/// The part of the full playlist object the list endpoint omits.
+#[derive(Debug, Deserialize)]
+pub struct PlaylistFollowers {
+    pub followers: Option<Followers>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Followers {
+    pub total: Option<u64>,
+}
+
+/// An artist as the followed artists endpoint returns them, with the
+/// details a track's artists leave out.
+#[derive(Debug, Deserialize)]
+pub struct FullArtist {
+    pub uri: String,
+    pub name: String,
+    #[serde(default)]
+    pub genres: Vec<String>,
+    pub popularity: Option<u32>,
+    pub followers: Option<Followers>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FollowedArtistsResponse {
+    pub artists: FollowedArtistsPage,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FollowedArtistsPage {
+    pub items: Vec<FullArtist>,
+    pub cursors: Option<Cursors>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Cursors {
+    /// The last artist of this page, to continue after.
+    pub after: Option<String>,
+}
+
+/// Just the item count of a full playlist object.
+#[derive(Debug, Deserialize)]
+pub struct PlaylistTrackCount {
+    pub tracks: TrackCount,
+}
+
+/// Just the item count of a page of playlist items, or of the `tracks` of
+/// a playlist object.
+#[derive(Debug, Deserialize)]
+pub struct TrackCount {
+    pub total: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Owner {
+    #[serde(default)]
+    pub id: String,
+    pub display_name: String,
+    #[serde(default)]
+    pub external_urls: Option<ExternalUrls>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Tracks {
+    pub href: String,
+    #[serde(default)]
+    pub total: Option<u32>,
+}
+
+/// The playlist version a change produced.
+#[derive(Debug, Deserialize)]
+pub struct SnapshotResponse {
+    pub snapshot_id: String,
+}
+
+/// Another user's profile, as anyone may see it.
+#[derive(Debug, Deserialize)]
+pub struct PublicUser {
+    pub id: String,
+    pub display_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct User {
+    pub id: String,
+    pub display_name: Option<String>,
+    /// Only sent with the user-read-private scope, as is `product`.
+    #[serde(default)]
+    pub country: Option<String>,
+    #[serde(default)]
+    pub product: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchResponse {
+    pub tracks: SearchTracks,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchTracks {
+    pub items: Vec<Track>,
+}
+
+/// A podcast episode, as returned by `GET /episodes/{id}`. Episodes listed
+/// under a show omit `show`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Episode {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default, with = "empty_string_as_none")]
+    pub uri: Option<String>,
+    #[serde(default, with = "empty_string_as_none")]
+    pub name: Option<String>,
+    #[serde(default, with = "empty_string_as_none")]
+    pub description: Option<String>,
+    pub duration_ms: Option<u32>,
+    pub release_date: Option<String>,
+    pub explicit: Option<bool>,
+    pub show: Option<Show>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Show {
+    #[serde(default)]
+    pub id: String,
+    pub uri: Option<String>,
+    pub name: Option<String>,
+    pub publisher: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SavedShowResponse {
+    pub items: Vec<SavedShow>,
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SavedShow {
+    pub show: Show,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EpisodeResponse {
+    /// Spotify sends `null` for episodes that are no longer available.
+    pub items: Vec<Option<Episode>>,
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SavedAlbumResponse {
+    pub items: Vec<SavedAlbumItem>,
+    pub next: Option<String>,
+    #[serde(default)]
+    pub total: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SavedAlbumItem {
+    /// When the album was saved, in RFC 3339.
+    #[serde(default)]
+    pub added_at: String,
+    pub album: AlbumDetail,
+}
+
+/// A saved album with the first page of its tracks.
+#[derive(Debug, Deserialize)]
+pub struct AlbumDetail {
+    #[serde(default)]
+    pub name: String,
+    pub tracks: AlbumTrackPage,
+}
+
+/// An album's own track listing, whose tracks carry no album or ISRC.
+#[derive(Debug, Deserialize)]
+pub struct AlbumTrackPage {
+    pub items: Vec<AlbumTrack>,
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AlbumTrack {
+    #[serde(default, with = "empty_string_as_none")]
+    pub uri: Option<String>,
+}
+
+/// The overall figures and sections of a track's audio analysis. Spotify
+/// nests the overall figures under `track`; beats, bars, tatums and
+/// segments are not read.
+#[derive(Debug, Deserialize)]
+#[serde(from = "AudioAnalysisResponse")]
+pub struct AudioAnalysis {
+    /// Beats per minute.
+    pub tempo: f64,
+    /// Beats per bar.
+    pub time_signature: u32,
+    /// Pitch class, 0 for C up to 11 for B; -1 when no key was detected.
+    pub key: i32,
+    /// 1 for major, 0 for minor.
+    pub mode: i32,
+    /// Average loudness in dB.
+    pub loudness: f64,
+    pub sections: Vec<Section>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AudioAnalysisResponse {
+    track: AudioAnalysisTrack,
+    #[serde(default)]
+    sections: Vec<Section>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AudioAnalysisTrack {
+    tempo: f64,
+    time_signature: u32,
+    key: i32,
+    mode: i32,
+    loudness: f64,
+}
+
+impl From<AudioAnalysisResponse> for AudioAnalysis {
+    fn from(response: AudioAnalysisResponse) -> Self {
+        let track = response.track;
+        Self {
+            tempo: track.tempo,
+            time_signature: track.time_signature,
+            key: track.key,
+            mode: track.mode,
+            loudness: track.loudness,
+            sections: response.sections,
+        }
+    }
+}
+
+/// A stretch of a track with its own tempo, key and loudness, such as a
+/// verse or a chorus. Times are in seconds.
+#[derive(Debug, Deserialize)]
+pub struct Section {
+    pub start: f64,
+    pub duration: f64,
+    pub confidence: f64,
+    pub loudness: f64,
+    pub tempo: f64,
+    pub key: i32,
+    pub mode: i32,
+    pub time_signature: u32,
+}
+
+/// A key as written, e.g. `F# minor`; `unknown` for Spotify's -1.
+pub fn key_name(key: i32, mode: i32) -> String {
+    const PITCHES: [&str; 12] = [
+        "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+    ];
+    let Some(pitch) = usize::try_from(key).ok().and_then(|key| PITCHES.get(key)) else {
+        return "unknown".to_string();
+    };
+    match mode {
+        1 => format!("{} major", pitch),
+        0 => format!("{} minor", pitch),
+        _ => pitch.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn images_keep_their_dimensions() {
+        // As in an album object; playlist images often have null sizes.
+        let images: Vec<Image> = serde_json::from_str(
+            r#"[
+                {"height": 640, "url": "https://i.scdn.co/image/large", "width": 640},
+                {"height": 300, "url": "https://i.scdn.co/image/medium", "width": 300},
+                {"height": null, "url": "https://mosaic.scdn.co/image", "width": null},
+                {"url": "https://i.scdn.co/image/bare"}
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(images[0].url, "https://i.scdn.co/image/large");
+        assert_eq!((images[0].width, images[0].height), (Some(640), Some(640)));
+        assert_eq!((images[1].width, images[1].height), (Some(300), Some(300)));
+        assert_eq!((images[2].width, images[2].height), (None, None));
+        assert_eq!((images[3].width, images[3].height), (None, None));
+    }
+}
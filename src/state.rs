@@ -0,0 +1,44 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fs::File,
+    io::BufReader,
+    path::Path,
+};
+
+use crate::atomic::write_json_atomic;
+
+pub const STATE_JSON: &str = "state.json";
+
+/// What the tool remembers between runs.
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct RunState {
+    /// Keyed by show ID.
+    #[serde(default)]
+    pub shows: BTreeMap<String, ShowState>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ShowState {
+    /// Newest release date listed so far, as `YYYY-MM-DD`.
+    pub last_release_date: Option<String>,
+    /// Episodes already listed that a date alone cannot rule out: those
+    /// released on `last_release_date` and those without a release date.
+    #[serde(default)]
+    pub listed: BTreeSet<String>,
+}
+
+/// Reads the state, starting fresh when there is none yet.
+pub fn read_state(path: &Path) -> Result<RunState, Box<dyn Error>> {
+    if !path.exists() {
+        return Ok(RunState::default());
+    }
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(serde_json::from_reader(BufReader::new(file))?)
+}
+
+pub fn write_state(path: &Path, state: &RunState) -> Result<(), Box<dyn Error>> {
+    write_json_atomic(path, state)
+}
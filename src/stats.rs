@@ -0,0 +1,282 @@
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::{self, Write as _},
+};
+
+use crate::{
+    compilations::is_compilation_record, export::PlaylistExport, quarantine::QUARANTINE_JSON,
+    record::TrackRecord,
+};
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PopularityBuckets {
+    pub zero: usize,
+    pub low: usize,
+    pub medium: usize,
+    pub high: usize,
+    pub unknown: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PlaylistStats {
+    pub track_count: usize,
+    pub total_duration_ms: u64,
+    pub unknown_duration_count: usize,
+    pub explicit_count: usize,
+    /// Tracks from compilation albums.
+    pub compilation_count: usize,
+    pub popularity: PopularityBuckets,
+    /// Average over tracks with a known popularity only.
+    pub average_popularity: Option<f64>,
+    pub release_year_histogram: BTreeMap<u32, usize>,
+    /// Tracks without a readable release date.
+    pub unknown_release_year: usize,
+}
+
+impl PlaylistStats {
+    pub fn from_records(records: &[TrackRecord]) -> Self {
+        let mut stats = Self {
+            track_count: records.len(),
+            ..Self::default()
+        };
+        let mut popularity_sum = 0u64;
+
+        for record in records {
+            match record.duration_ms {
+                Some(ms) => stats.total_duration_ms += u64::from(ms),
+                None => stats.unknown_duration_count += 1,
+            }
+            if record.explicit == Some(true) {
+                stats.explicit_count += 1;
+            }
+            if is_compilation_record(record) {
+                stats.compilation_count += 1;
+            }
+            match record.popularity {
+                None => stats.popularity.unknown += 1,
+                Some(0) => stats.popularity.zero += 1,
+                Some(1..=33) => stats.popularity.low += 1,
+                Some(34..=66) => stats.popularity.medium += 1,
+                Some(_) => stats.popularity.high += 1,
+            }
+            popularity_sum += u64::from(record.popularity.unwrap_or(0));
+        }
+
+        let known = stats.track_count - stats.popularity.unknown;
+        if known > 0 {
+            stats.average_popularity = Some(popularity_sum as f64 / known as f64);
+        }
+        stats.release_year_histogram = release_year_histogram(records);
+        stats.unknown_release_year =
+            stats.track_count - stats.release_year_histogram.values().sum::<usize>();
+        stats
+    }
+
+    /// The release years as horizontal bars fitted to `width` columns, the
+    /// longest bar for the most common year. Tracks without a date come
+    /// last as "Unknown".
+    pub fn release_year_chart(&self, width: usize) -> String {
+        let mut rows: Vec<(String, usize)> = self
+            .release_year_histogram
+            .iter()
+            .map(|(year, &count)| (year.to_string(), count))
+            .collect();
+        if self.unknown_release_year > 0 {
+            rows.push(("Unknown".to_string(), self.unknown_release_year));
+        }
+        let max = rows.iter().map(|(_, count)| *count).max().unwrap_or(0);
+        let label_width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+        let count_width = max.to_string().len();
+        let bar_width = width.saturating_sub(label_width + count_width + 2).max(1);
+
+        let mut chart = String::new();
+        for (label, count) in rows {
+            // Any track at all gets a visible bar.
+            let bar = (count * bar_width / max).max(1);
+            let _ = writeln!(
+                chart,
+                "{:>label_width$} {} {}",
+                label,
+                "█".repeat(bar),
+                count
+            );
+        }
+        chart
+    }
+}
+
+/// The year of an album release date, which Spotify sends as `YYYY-MM-DD`,
+/// `YYYY-MM` or `YYYY` depending on how precisely it is known.
+fn release_year(date: &str) -> Option<u32> {
+    date.get(..4)?.parse().ok()
+}
+
+/// Counts tracks by release year. Tracks without a readable date are left
+/// out.
+pub fn release_year_histogram(records: &[TrackRecord]) -> BTreeMap<u32, usize> {
+    let mut histogram = BTreeMap::new();
+    for record in records {
+        if let Some(year) = record.album_release_date.as_deref().and_then(release_year) {
+            *histogram.entry(year).or_insert(0) += 1;
+        }
+    }
+    histogram
+}
+
+impl fmt::Display for PlaylistStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Tracks: {}", self.track_count)?;
+        writeln!(
+            f,
+            "Duration: {} ({} unknown)",
+            format_hms(self.total_duration_ms / 1000),
+            self.unknown_duration_count
+        )?;
+        writeln!(f, "Explicit: {}", self.explicit_count)?;
+        writeln!(f, "From compilations: {}", self.compilation_count)?;
+        writeln!(
+            f,
+            "Popularity: 0: {}, 1-33: {}, 34-66: {}, 67-100: {}, unknown: {}",
+            self.popularity.zero,
+            self.popularity.low,
+            self.popularity.medium,
+            self.popularity.high,
+            self.popularity.unknown
+        )?;
+        match self.average_popularity {
+            Some(avg) => write!(f, "Average popularity: {:.1}", avg),
+            None => write!(f, "Average popularity: unknown"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LibrarySummary {
+    pub playlist_count: usize,
+    /// Distinct tracks by URI; tracks without a URI each count once.
+    pub unique_tracks: usize,
+    /// Sum over every playlist entry, so a track in two playlists counts twice.
+    pub total_duration_ms: u64,
+    /// Playlists with pages left in quarantine.
+    pub partial_playlists: usize,
+}
+
+pub fn library_summary(all_exports: &[PlaylistExport]) -> LibrarySummary {
+    let mut uris = HashSet::new();
+    let mut summary = LibrarySummary {
+        playlist_count: all_exports.len(),
+        partial_playlists: all_exports
+            .iter()
+            .filter(|export| !export.quarantined.is_empty())
+            .count(),
+        ..LibrarySummary::default()
+    };
+
+    for track in all_exports
+        .iter()
+        .flat_map(|export| &export.tracks)
+        .filter_map(|item| item.track.as_ref())
+    {
+        let is_new = match &track.uri {
+            Some(uri) => uris.insert(uri.as_str()),
+            None => true,
+        };
+        if is_new {
+            summary.unique_tracks += 1;
+        }
+        summary.total_duration_ms += u64::from(track.duration_ms.unwrap_or(0));
+    }
+
+    summary
+}
+
+impl fmt::Display for LibrarySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Total library: {} playlists, {} unique tracks, {} total duration",
+            self.playlist_count,
+            self.unique_tracks,
+            format_hms(self.total_duration_ms / 1000)
+        )?;
+        if self.partial_playlists > 0 {
+            write!(
+                f,
+                " ({} partial, see {})",
+                self.partial_playlists, QUARANTINE_JSON
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats seconds as `Xh Ym Zs`.
+pub fn format_hms(total_secs: u64) -> String {
+    format!(
+        "{}h {}m {}s",
+        total_secs / 3600,
+        total_secs % 3600 / 60,
+        total_secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::tests::sample_record;
+
+    fn with_popularity(uri: &str, popularity: Option<u8>) -> TrackRecord {
+        let mut record = sample_record(uri, uri);
+        record.popularity = popularity;
+        record
+    }
+
+    #[test]
+    fn unknown_popularity_is_counted_apart_and_left_out_of_the_average() {
+        let stats = PlaylistStats::from_records(&[
+            with_popularity("spotify:track:a", Some(0)),
+            with_popularity("spotify:track:b", Some(33)),
+            with_popularity("spotify:track:c", Some(66)),
+            with_popularity("spotify:track:d", Some(100)),
+            with_popularity("spotify:track:e", None),
+            with_popularity("spotify:track:f", None),
+        ]);
+
+        assert_eq!(stats.track_count, 6);
+        assert_eq!(stats.popularity.zero, 1);
+        assert_eq!(stats.popularity.low, 1);
+        assert_eq!(stats.popularity.medium, 1);
+        assert_eq!(stats.popularity.high, 1);
+        assert_eq!(stats.popularity.unknown, 2);
+        assert_eq!(stats.average_popularity, Some(199.0 / 4.0));
+    }
+
+    #[test]
+    fn average_popularity_is_unknown_when_no_track_has_one() {
+        let stats = PlaylistStats::from_records(&[
+            with_popularity("spotify:track:a", None),
+            with_popularity("spotify:track:b", None),
+        ]);
+
+        assert_eq!(stats.popularity.unknown, 2);
+        assert_eq!(stats.average_popularity, None);
+        assert!(stats.to_string().contains("Average popularity: unknown"));
+
+        assert_eq!(PlaylistStats::from_records(&[]).average_popularity, None);
+    }
+
+    #[test]
+    fn compilation_tracks_are_counted() {
+        let mut soundtrack = sample_record("spotify:track:a", "a");
+        soundtrack.album_artist_names = "Various Artists".to_string();
+        let mut collection = sample_record("spotify:track:b", "b");
+        collection.album_artist_names = "Label, various artists".to_string();
+        let album = sample_record("spotify:track:c", "c");
+
+        let stats = PlaylistStats::from_records(&[soundtrack, collection, album]);
+
+        assert_eq!(stats.compilation_count, 2);
+        assert!(stats.to_string().contains("From compilations: 2"));
+    }
+}
@@ -0,0 +1,749 @@
+//! An SQLite library kept across runs, for `--store sqlite://library.db`.
+//! Each run adds a row to `runs` and the playlists it exported to
+//! `playlist_runs`. `playlist_track_history` holds one row per version of a
+//! track in a playlist: the same record, for the same copy of the track,
+//! from `valid_from_run` up to but not including `valid_to_run` (NULL while
+//! it is still there). A record whose values change gets a new version, so
+//! older runs keep the values they saw. `playlist_run_tracks` puts each
+//! run's versions in that run's order, so a track moving does not make new
+//! versions. Reading a past run back is then a query, and `render --store`
+//! writes it out again.
+//!
+//! Which playlists and tracks a run sees depends on its filters, so every
+//! run records them and each set of filters keeps its own history: a track
+//! a new `--min-popularity` leaves out was not removed, and a playlist
+//! `--only-owned` leaves out was not deleted.
+
+use rusqlite::{
+    params, Connection, OpenFlags, OptionalExtension, Transaction, TransactionBehavior,
+};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    path::Path,
+    time::Duration,
+};
+
+use crate::{
+    cli::ExportArgs,
+    dedupe::{DedupeKey, PreferRelease},
+    filter::TrackFilter,
+    output::{LibraryExport, PlaylistRecords},
+    provenance::Provenance,
+    record::{Field, RecordOptions, TrackRecord},
+};
+
+pub const URL_SCHEME: &str = "sqlite://";
+
+/// How every SQLite database file starts.
+pub const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+/// How long a write waits for another connection's to finish.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Applied in order; `PRAGMA user_version` counts those already applied, so
+/// opening a database written by an older version brings it up to date.
+/// Only ever append to this list.
+const MIGRATIONS: &[&str] = &[
+    "
+    CREATE TABLE runs (
+        id INTEGER PRIMARY KEY,
+        exported_at TEXT NOT NULL,
+        fields TEXT NOT NULL,
+        provenance TEXT,
+        complete INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE playlist_runs (
+        run_id INTEGER NOT NULL REFERENCES runs(id),
+        playlist_id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        owner TEXT NOT NULL,
+        PRIMARY KEY (run_id, playlist_id)
+    );
+    CREATE TABLE playlist_track_history (
+        id INTEGER PRIMARY KEY,
+        playlist_id TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        track_key TEXT NOT NULL,
+        record TEXT NOT NULL,
+        valid_from_run INTEGER NOT NULL REFERENCES runs(id),
+        valid_to_run INTEGER REFERENCES runs(id)
+    );
+    CREATE INDEX playlist_track_history_open
+        ON playlist_track_history (playlist_id, valid_to_run);
+    ",
+    // Filters per run and history per filter set; versions matched by
+    // track and copy instead of position, with each run's order kept
+    // apart. Earlier runs all count as having the same filters.
+    "
+    ALTER TABLE runs ADD COLUMN filters TEXT NOT NULL DEFAULT '{}';
+    ALTER TABLE playlist_track_history ADD COLUMN filters TEXT NOT NULL DEFAULT '{}';
+    ALTER TABLE playlist_track_history ADD COLUMN occurrence INTEGER NOT NULL DEFAULT 0;
+    UPDATE playlist_track_history SET occurrence = (
+        SELECT ranked.occurrence FROM (
+            SELECT id, ROW_NUMBER() OVER (
+                PARTITION BY playlist_id, track_key ORDER BY position
+            ) - 1 AS occurrence
+            FROM playlist_track_history WHERE valid_to_run IS NULL
+        ) AS ranked
+        WHERE ranked.id = playlist_track_history.id
+    )
+    WHERE valid_to_run IS NULL;
+    CREATE TABLE playlist_run_tracks (
+        run_id INTEGER NOT NULL REFERENCES runs(id),
+        playlist_id TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        history_id INTEGER NOT NULL REFERENCES playlist_track_history(id),
+        PRIMARY KEY (run_id, playlist_id, position)
+    );
+    INSERT INTO playlist_run_tracks (run_id, playlist_id, position, history_id)
+        SELECT pr.run_id, pr.playlist_id, h.position, h.id
+        FROM playlist_runs pr JOIN playlist_track_history h
+            ON h.playlist_id = pr.playlist_id
+            AND h.valid_from_run <= pr.run_id
+            AND (h.valid_to_run IS NULL OR h.valid_to_run > pr.run_id);
+    DROP INDEX playlist_track_history_open;
+    CREATE INDEX playlist_track_history_open
+        ON playlist_track_history (playlist_id, filters, valid_to_run);
+    ",
+    "
+    CREATE TABLE meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    ",
+];
+
+/// The provenance in the `meta` table of the store at `path`, opened read
+/// only and as it is, for `inspect`. `None` if no run recorded one.
+pub fn read_store_provenance(path: &Path) -> Result<Option<Provenance>, Box<dyn Error>> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    let has_meta = conn
+        .query_row(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'meta'",
+            [],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some();
+    if !has_meta {
+        return Ok(None);
+    }
+    let mut fields = serde_json::Map::new();
+    let mut query = conn.prepare("SELECT key, value FROM meta")?;
+    for row in query.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })? {
+        let (key, value) = row?;
+        fields.insert(key, serde_json::from_str(&value)?);
+    }
+    if fields.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_value(serde_json::Value::Object(
+        fields,
+    ))?))
+}
+
+/// What decided which playlists and tracks a run recorded.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RunFilters {
+    pub only_owned: bool,
+    pub only_followed: bool,
+    /// `--collaborative-only`, `--private-only` or `--public-only`.
+    pub visibility: String,
+    pub track_filter: TrackFilter,
+    pub dedupe_key: Option<DedupeKey>,
+    pub prefer_release: Option<PreferRelease>,
+    pub prefer_clean_version: bool,
+    pub record_options: RecordOptions,
+}
+
+impl RunFilters {
+    pub fn from_args(args: &ExportArgs) -> Self {
+        Self {
+            only_owned: args.only_owned,
+            only_followed: args.only_followed,
+            visibility: format!("{:?}", args.visibility_filter()),
+            track_filter: args.output.track_filter(),
+            dedupe_key: args.output.dedupe_key,
+            prefer_release: args.output.dedupe_key.map(|_| args.output.prefer_release),
+            prefer_clean_version: args.prefer_clean_version,
+            record_options: args.record_options(),
+        }
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), Box<dyn Error>> {
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    let version = version as usize;
+    if version > MIGRATIONS.len() {
+        return Err(format!(
+            "the store was written by a newer version (schema {}, this version knows {})",
+            version,
+            MIGRATIONS.len()
+        )
+        .into());
+    }
+    let tx = conn.transaction()?;
+    for migration in &MIGRATIONS[version..] {
+        tx.execute_batch(migration)?;
+    }
+    tx.pragma_update(None, "user_version", MIGRATIONS.len() as i64)?;
+    tx.commit()?;
+    Ok(())
+}
+
+pub struct Store {
+    conn: Connection,
+}
+
+impl Store {
+    /// Opens or creates the database at `url` (`sqlite://<path>`) and
+    /// migrates it to the current schema.
+    pub fn open(url: &str) -> Result<Self, Box<dyn Error>> {
+        let path = url
+            .strip_prefix(URL_SCHEME)
+            .ok_or_else(|| format!("unsupported store {:?}; expected {}<path>", url, URL_SCHEME))?;
+        let mut conn = Connection::open(Path::new(path)).map_err(|e| format!("{}: {}", path, e))?;
+        // Several runs, or tasks of one, may have the store open at once:
+        // readers then never block the writer, and a writer waits its turn
+        // rather than failing with "database is locked".
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        migrate(&mut conn)?;
+        Ok(Self { conn })
+    }
+
+    /// Starts a run with `filters` and returns its ID.
+    pub fn begin_run(
+        &self,
+        fields: &[Field],
+        provenance: Option<&Provenance>,
+        filters: &RunFilters,
+    ) -> Result<i64, Box<dyn Error>> {
+        self.conn.execute(
+            "INSERT INTO runs (exported_at, fields, provenance, filters) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                chrono::Utc::now().to_rfc3339(),
+                serde_json::to_string(fields)?,
+                provenance.map(serde_json::to_string).transpose()?,
+                serde_json::to_string(filters)?,
+            ],
+        )?;
+        let run = self.conn.last_insert_rowid();
+        if let Some(provenance) = provenance {
+            self.write_meta(provenance)?;
+        }
+        Ok(run)
+    }
+
+    /// Replaces `meta` with the fields of `provenance`, one row each with
+    /// the value as JSON, so that it describes the run that wrote last.
+    /// Each run's own is in `runs.provenance`.
+    fn write_meta(&self, provenance: &Provenance) -> Result<(), Box<dyn Error>> {
+        let serde_json::Value::Object(fields) = serde_json::to_value(provenance)? else {
+            return Err("provenance is not an object".into());
+        };
+        self.conn.execute("DELETE FROM meta", [])?;
+        for (key, value) in fields {
+            self.conn.execute(
+                "INSERT INTO meta (key, value) VALUES (?1, ?2)",
+                params![key, serde_json::to_string(&value)?],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Records what `run` exported of one playlist, in order. A track
+    /// still there with the same values keeps its version; a changed one
+    /// gets a new version. Versions no longer there are closed, unless the
+    /// playlist is `partial`, since missing pages say nothing about what
+    /// they held.
+    pub fn record_playlist(
+        &mut self,
+        run: i64,
+        playlist_id: &str,
+        playlist: &PlaylistRecords,
+        partial: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        // Taking the write lock up front: a transaction that read first
+        // could not wait for another writer, only fail.
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let filters = run_filters(&tx, run)?;
+        tx.execute(
+            "INSERT OR REPLACE INTO playlist_runs (run_id, playlist_id, name, owner)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![run, playlist_id, playlist.name, playlist.owner],
+        )?;
+        tx.execute(
+            "DELETE FROM playlist_run_tracks WHERE run_id = ?1 AND playlist_id = ?2",
+            params![run, playlist_id],
+        )?;
+        let mut open = open_versions(&tx, playlist_id, &filters)?;
+        let mut copies: HashMap<String, i64> = HashMap::new();
+        for (position, record) in playlist.tracks.iter().enumerate() {
+            let key = record.identity_key();
+            let occurrence = copies.entry(key.clone()).or_default();
+            let copy = *occurrence;
+            *occurrence += 1;
+            let json = serde_json::to_string(record)?;
+            let id = match open.remove(&(key.clone(), copy)) {
+                Some((id, previous)) if previous == json => id,
+                changed => {
+                    if let Some((id, _)) = changed {
+                        close_version(&tx, id, run)?;
+                    }
+                    tx.execute(
+                        "INSERT INTO playlist_track_history
+                         (playlist_id, filters, position, track_key, occurrence, record,
+                          valid_from_run)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        params![playlist_id, filters, position as i64, key, copy, json, run],
+                    )?;
+                    tx.last_insert_rowid()
+                }
+            };
+            tx.execute(
+                "INSERT INTO playlist_run_tracks (run_id, playlist_id, position, history_id)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![run, playlist_id, position as i64, id],
+            )?;
+        }
+        if !partial {
+            for (id, _) in open.into_values() {
+                close_version(&tx, id, run)?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Ends `run`. A complete run covered every playlist its filters let
+    /// through, so playlists that earlier runs with the same filters
+    /// exported and this one did not have been deleted, unfollowed or no
+    /// longer pass the filters, and their tracks are closed; after a
+    /// partial run they are left as they were.
+    pub fn finish_run(&self, run: i64, complete: bool) -> Result<(), Box<dyn Error>> {
+        if complete {
+            self.conn.execute(
+                "UPDATE playlist_track_history SET valid_to_run = ?1
+                 WHERE valid_to_run IS NULL
+                     AND filters = (SELECT filters FROM runs WHERE id = ?1)
+                     AND playlist_id NOT IN
+                         (SELECT playlist_id FROM playlist_runs WHERE run_id = ?1)",
+                params![run],
+            )?;
+        }
+        self.conn.execute(
+            "UPDATE runs SET complete = ?1 WHERE id = ?2",
+            params![complete, run],
+        )?;
+        Ok(())
+    }
+
+    /// The file name of every cover a stored record has ever named in its
+    /// Album Image File, in any run.
+    pub fn album_image_files(&self) -> Result<HashSet<String>, Box<dyn Error>> {
+        let mut query = self
+            .conn
+            .prepare("SELECT record FROM playlist_track_history")?;
+        let mut files = HashSet::new();
+        for json in query.query_map([], |row| row.get::<_, String>(0))? {
+            let record: TrackRecord = serde_json::from_str(&json?)?;
+            let name = record
+                .album_image_file
+                .as_deref()
+                .and_then(|file| Path::new(file).file_name())
+                .map(|name| name.to_string_lossy().into_owned());
+            files.extend(name);
+        }
+        Ok(files)
+    }
+
+    /// The playlists `run` exported, as they were then; without a run, the
+    /// latest complete one.
+    pub fn read_run(&self, run: Option<i64>) -> Result<LibraryExport, Box<dyn Error>> {
+        let run = match run {
+            Some(run) => run,
+            None => self
+                .conn
+                .query_row("SELECT MAX(id) FROM runs WHERE complete = 1", [], |row| {
+                    row.get::<_, Option<i64>>(0)
+                })?
+                .ok_or("the store has no complete run")?,
+        };
+        let (fields, provenance): (String, Option<String>) = self
+            .conn
+            .query_row(
+                "SELECT fields, provenance FROM runs WHERE id = ?1",
+                params![run],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?
+            .ok_or_else(|| format!("the store has no run {}", run))?;
+
+        let mut playlists_query = self.conn.prepare(
+            "SELECT playlist_id, name, owner FROM playlist_runs
+             WHERE run_id = ?1 ORDER BY rowid",
+        )?;
+        let mut tracks_query = self.conn.prepare(
+            "SELECT h.record FROM playlist_run_tracks t
+             JOIN playlist_track_history h ON h.id = t.history_id
+             WHERE t.playlist_id = ?1 AND t.run_id = ?2
+             ORDER BY t.position",
+        )?;
+        let mut playlists = Vec::new();
+        let rows = playlists_query.query_map(params![run], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+        for row in rows {
+            let (id, name, owner) = row?;
+            let tracks = tracks_query
+                .query_map(params![id, run], |row| row.get::<_, String>(0))?
+                .map(|json| Ok(serde_json::from_str(&json?)?))
+                .collect::<Result<Vec<TrackRecord>, Box<dyn Error>>>()?;
+            playlists.push(PlaylistRecords {
+                name,
+                owner,
+                tracks,
+            });
+        }
+
+        Ok(LibraryExport {
+            fields: serde_json::from_str(&fields)?,
+            provenance: provenance
+                .map(|json| serde_json::from_str(&json))
+                .transpose()?,
+            playlists,
+        })
+    }
+}
+
+/// The filters `run` was recorded with, as stored.
+fn run_filters(tx: &Transaction, run: i64) -> Result<String, Box<dyn Error>> {
+    tx.query_row(
+        "SELECT filters FROM runs WHERE id = ?1",
+        params![run],
+        |row| row.get(0),
+    )
+    .optional()?
+    .ok_or_else(|| format!("the store has no run {}", run).into())
+}
+
+/// Open versions by track identity key and copy (0 for the first, 1 for a
+/// repeat, ...), as their ID and record.
+type OpenVersions = HashMap<(String, i64), (i64, String)>;
+
+/// The open versions of a playlist's tracks under `filters`.
+fn open_versions(
+    tx: &Transaction,
+    playlist_id: &str,
+    filters: &str,
+) -> Result<OpenVersions, Box<dyn Error>> {
+    let mut query = tx.prepare(
+        "SELECT id, track_key, occurrence, record FROM playlist_track_history
+         WHERE playlist_id = ?1 AND filters = ?2 AND valid_to_run IS NULL",
+    )?;
+    let rows = query.query_map(params![playlist_id, filters], |row| {
+        Ok(((row.get(1)?, row.get(2)?), (row.get(0)?, row.get(3)?)))
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+fn close_version(tx: &Transaction, id: i64, run: i64) -> Result<(), Box<dyn Error>> {
+    tx.execute(
+        "UPDATE playlist_track_history SET valid_to_run = ?1 WHERE id = ?2",
+        params![run, id],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{record::tests::sample_record, testdir::TestDir};
+
+    fn store(dir: &TestDir) -> Store {
+        Store::open(&format!(
+            "{}{}",
+            URL_SCHEME,
+            dir.path().join("library.db").display()
+        ))
+        .unwrap()
+    }
+
+    fn playlist(tracks: &[TrackRecord]) -> PlaylistRecords {
+        PlaylistRecords {
+            name: "Road Trip".to_string(),
+            owner: "me".to_string(),
+            tracks: tracks.to_vec(),
+        }
+    }
+
+    fn names(library: &LibraryExport) -> Vec<Vec<String>> {
+        library
+            .playlists
+            .iter()
+            .map(|playlist| {
+                playlist
+                    .tracks
+                    .iter()
+                    .map(|track| track.track_name.clone().unwrap_or_default())
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn history_rows(store: &Store) -> i64 {
+        store
+            .conn
+            .query_row("SELECT COUNT(*) FROM playlist_track_history", [], |row| {
+                row.get(0)
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn moves_keep_versions_and_each_run_its_order() {
+        let dir = TestDir::new();
+        let mut store = store(&dir);
+        let a = sample_record("spotify:track:a", "A");
+        let b = sample_record("spotify:track:b", "B");
+        let filters = RunFilters::default();
+
+        let first = store.begin_run(&[], None, &filters).unwrap();
+        store
+            .record_playlist(first, "p1", &playlist(&[a.clone(), b.clone()]), false)
+            .unwrap();
+        store.finish_run(first, true).unwrap();
+        let second = store.begin_run(&[], None, &filters).unwrap();
+        store
+            .record_playlist(second, "p1", &playlist(&[b.clone(), a.clone()]), false)
+            .unwrap();
+        store.finish_run(second, true).unwrap();
+
+        assert_eq!(history_rows(&store), 2);
+        assert_eq!(names(&store.read_run(Some(first)).unwrap()), [["A", "B"]]);
+        assert_eq!(names(&store.read_run(Some(second)).unwrap()), [["B", "A"]]);
+    }
+
+    #[test]
+    fn changed_values_get_a_new_version_and_old_runs_keep_theirs() {
+        let dir = TestDir::new();
+        let mut store = store(&dir);
+        let filters = RunFilters::default();
+        let mut a = sample_record("spotify:track:a", "A");
+        a.popularity = Some(10);
+
+        let first = store.begin_run(&[], None, &filters).unwrap();
+        store
+            .record_playlist(first, "p1", &playlist(std::slice::from_ref(&a)), false)
+            .unwrap();
+        store.finish_run(first, true).unwrap();
+        a.popularity = Some(20);
+        let second = store.begin_run(&[], None, &filters).unwrap();
+        store
+            .record_playlist(second, "p1", &playlist(&[a]), false)
+            .unwrap();
+        store.finish_run(second, true).unwrap();
+
+        let popularity = |run| store.read_run(Some(run)).unwrap().playlists[0].tracks[0].popularity;
+        assert_eq!(popularity(first), Some(10));
+        assert_eq!(popularity(second), Some(20));
+    }
+
+    #[test]
+    fn repeated_tracks_are_separate_copies() {
+        let dir = TestDir::new();
+        let mut store = store(&dir);
+        let filters = RunFilters::default();
+        let a = sample_record("spotify:track:a", "A");
+        let first = store.begin_run(&[], None, &filters).unwrap();
+        store
+            .record_playlist(first, "p1", &playlist(&[a.clone(), a.clone()]), false)
+            .unwrap();
+        let second = store.begin_run(&[], None, &filters).unwrap();
+        store
+            .record_playlist(second, "p1", &playlist(&[a]), false)
+            .unwrap();
+        assert_eq!(names(&store.read_run(Some(first)).unwrap()), [["A", "A"]]);
+        assert_eq!(names(&store.read_run(Some(second)).unwrap()), [["A"]]);
+    }
+
+    #[test]
+    fn deletions_are_only_inferred_under_the_same_filters() {
+        let dir = TestDir::new();
+        let mut store = store(&dir);
+        let everything = RunFilters::default();
+        let owned = RunFilters {
+            only_owned: true,
+            ..RunFilters::default()
+        };
+        let a = sample_record("spotify:track:a", "A");
+        let open_rows = |store: &Store| -> i64 {
+            store
+                .conn
+                .query_row(
+                    "SELECT COUNT(*) FROM playlist_track_history
+                     WHERE playlist_id = 'followed' AND valid_to_run IS NULL",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+
+        let first = store.begin_run(&[], None, &everything).unwrap();
+        store
+            .record_playlist(
+                first,
+                "followed",
+                &playlist(std::slice::from_ref(&a)),
+                false,
+            )
+            .unwrap();
+        store.finish_run(first, true).unwrap();
+        // --only-owned leaves the followed playlist out; it was not deleted.
+        let second = store.begin_run(&[], None, &owned).unwrap();
+        store.finish_run(second, true).unwrap();
+        assert_eq!(open_rows(&store), 1);
+        // A complete run with the same filters that lacks it means it went.
+        let third = store.begin_run(&[], None, &everything).unwrap();
+        store.finish_run(third, true).unwrap();
+        assert_eq!(open_rows(&store), 0);
+    }
+
+    #[test]
+    fn partial_playlists_close_nothing() {
+        let dir = TestDir::new();
+        let mut store = store(&dir);
+        let filters = RunFilters::default();
+        let a = sample_record("spotify:track:a", "A");
+        let first = store.begin_run(&[], None, &filters).unwrap();
+        store
+            .record_playlist(first, "p1", &playlist(&[a]), false)
+            .unwrap();
+        let second = store.begin_run(&[], None, &filters).unwrap();
+        store
+            .record_playlist(second, "p1", &playlist(&[]), true)
+            .unwrap();
+        let third = store.begin_run(&[], None, &filters).unwrap();
+        store
+            .record_playlist(third, "p1", &playlist(&[]), false)
+            .unwrap();
+        let open: i64 = store
+            .conn
+            .query_row(
+                "SELECT valid_to_run FROM playlist_track_history",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(open, third);
+    }
+
+    #[test]
+    fn migrates_a_first_version_store() {
+        let dir = TestDir::new();
+        let path = dir.path().join("library.db");
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(MIGRATIONS[0]).unwrap();
+            conn.pragma_update(None, "user_version", 1).unwrap();
+            let a = serde_json::to_string(&sample_record("spotify:track:a", "A")).unwrap();
+            let b = serde_json::to_string(&sample_record("spotify:track:b", "B")).unwrap();
+            conn.execute_batch(&format!(
+                "INSERT INTO runs (id, exported_at, fields, complete) VALUES
+                     (1, '2024-01-01T00:00:00Z', '[]', 1),
+                     (2, '2024-02-01T00:00:00Z', '[]', 1);
+                 INSERT INTO playlist_runs VALUES (1, 'p1', 'Road Trip', 'me'),
+                     (2, 'p1', 'Road Trip', 'me');
+                 INSERT INTO playlist_track_history
+                     (playlist_id, position, track_key, record, valid_from_run, valid_to_run)
+                     VALUES ('p1', 0, 'spotify:track:a', '{a}', 1, NULL),
+                            ('p1', 1, 'spotify:track:b', '{b}', 1, 2),
+                            ('p1', 1, 'spotify:track:a', '{a}', 2, NULL);"
+            ))
+            .unwrap();
+        }
+
+        let mut store = store(&dir);
+        let version: i64 = store
+            .conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version as usize, MIGRATIONS.len());
+        assert_eq!(names(&store.read_run(Some(1)).unwrap()), [["A", "B"]]);
+        assert_eq!(names(&store.read_run(Some(2)).unwrap()), [["A", "A"]]);
+        assert_eq!(names(&store.read_run(None).unwrap()), [["A", "A"]]);
+
+        // Earlier runs count as unfiltered; a new one carries on from them.
+        let a = sample_record("spotify:track:a", "A");
+        let third = store.begin_run(&[], None, &RunFilters::default()).unwrap();
+        store.finish_run(third, true).unwrap();
+        let run = store.begin_run(&[], None, &RunFilters::default()).unwrap();
+        store
+            .record_playlist(run, "p1", &playlist(&[a.clone(), a]), false)
+            .unwrap();
+        assert_eq!(names(&store.read_run(Some(run)).unwrap()), [["A", "A"]]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn concurrent_runs_all_land() {
+        let dir = std::sync::Arc::new(TestDir::new());
+        // Created up front, as two runs never both create a store.
+        drop(store(&dir));
+        let mut tasks = Vec::new();
+        for task in 0..32 {
+            let dir = std::sync::Arc::clone(&dir);
+            tasks.push(tokio::task::spawn_blocking(move || {
+                let mut store = store(&dir);
+                let track = sample_record(&format!("spotify:track:{}", task), "A");
+                let run = store.begin_run(&[], None, &RunFilters::default()).unwrap();
+                store
+                    .record_playlist(run, &format!("p{}", task), &playlist(&[track]), false)
+                    .unwrap();
+                store.finish_run(run, true).unwrap();
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let store = store(&dir);
+        let (runs, tracks): (i64, i64) = store
+            .conn
+            .query_row(
+                "SELECT (SELECT COUNT(*) FROM runs WHERE complete = 1),
+                        (SELECT COUNT(*) FROM playlist_track_history)",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((runs, tracks), (32, 32));
+        let check: String = store
+            .conn
+            .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(check, "ok");
+    }
+
+    #[test]
+    fn refuses_a_newer_schema() {
+        let dir = TestDir::new();
+        let path = dir.path().join("library.db");
+        Connection::open(&path)
+            .unwrap()
+            .pragma_update(None, "user_version", MIGRATIONS.len() as i64 + 1)
+            .unwrap();
+        assert!(Store::open(&format!("{}{}", URL_SCHEME, path.display())).is_err());
+    }
+}
@@ -0,0 +1,560 @@
+//! Strict checking of API responses, for noticing when Spotify adds fields.
+//! Normal runs ignore anything the response types do not model; with
+//! `--strict-api` a field that is neither read by this tool nor one of the
+//! documented fields it deliberately ignores fails the request instead.
+//! Fields are known per kind of object, going by where in the response
+//! they appear: a field valid on an album is still unknown on a track.
+
+use serde_json::Value;
+use std::fmt;
+
+/// The kinds of object the Spotify endpoints this tool calls return. Each
+/// has its own fields, so a field one kind has, such as an audio analysis
+/// section's `key`, is still unknown on another, such as a track.
+#[derive(Debug, Clone, Copy)]
+enum Shape {
+    /// Not checked: a scalar, a list of scalars, or a free-form object.
+    Any,
+    /// A page of the given items, by offset or by cursor. Playlists listed
+    /// by `/me/playlists` have one with only `href` and `total` as their
+    /// `tracks`.
+    Page(&'static Shape),
+    Cursors,
+    PlaylistItem,
+    /// The `track` of a playlist item: a track or, going by its `type`, an
+    /// episode.
+    Playable,
+    VideoThumbnail,
+    Track,
+    LinkedTrack,
+    Album,
+    Artist,
+    Image,
+    ExternalUrls,
+    ExternalIds,
+    Followers,
+    Restrictions,
+    Copyright,
+    Playlist,
+    User,
+    ExplicitContent,
+    SavedTrack,
+    SavedAlbum,
+    SavedShow,
+    Category,
+    CategoriesResponse,
+    CategoryPlaylistsResponse,
+    FollowedArtistsResponse,
+    TopTracksResponse,
+    SearchResponse,
+    Show,
+    Episode,
+    ResumePoint,
+    AudioAnalysis,
+    AnalysisMeta,
+    AnalysisTrack,
+    TimeInterval,
+    Section,
+    Segment,
+}
+
+use Shape::*;
+
+/// The fields of a page, whatever its items.
+const PAGE_FIELDS: &[&str] = &[
+    "href", "items", "limit", "next", "offset", "previous", "total", "cursors",
+];
+
+impl Shape {
+    /// Every field of the object, whether or not this tool reads it, with
+    /// the shape of its value. `None` for shapes that are not checked.
+    fn fields(self) -> Option<&'static [(&'static str, Shape)]> {
+        Some(match self {
+            Any | Page(_) | Playable => return None,
+            Cursors => &[("after", Any), ("before", Any)],
+            PlaylistItem => &[
+                ("added_at", Any),
+                ("added_by", User),
+                ("is_local", Any),
+                ("primary_color", Any),
+                ("track", Playable),
+                ("video_thumbnail", VideoThumbnail),
+            ],
+            VideoThumbnail => &[("url", Any)],
+            // `episode` and `track` flag which of the two a playlist item is.
+            Track => &[
+                ("album", Album),
+                ("artists", Artist),
+                ("available_markets", Any),
+                ("disc_number", Any),
+                ("duration_ms", Any),
+                ("episode", Any),
+                ("explicit", Any),
+                ("external_ids", ExternalIds),
+                ("external_urls", ExternalUrls),
+                ("href", Any),
+                ("id", Any),
+                ("is_local", Any),
+                ("is_playable", Any),
+                ("linked_from", LinkedTrack),
+                ("name", Any),
+                ("popularity", Any),
+                ("preview_url", Any),
+                ("restrictions", Restrictions),
+                ("track", Any),
+                ("track_number", Any),
+                ("type", Any),
+                ("uri", Any),
+            ],
+            LinkedTrack => &[
+                ("external_urls", ExternalUrls),
+                ("href", Any),
+                ("id", Any),
+                ("type", Any),
+                ("uri", Any),
+            ],
+            Album => &[
+                ("album_group", Any),
+                ("album_type", Any),
+                ("artists", Artist),
+                ("available_markets", Any),
+                ("copyrights", Copyright),
+                ("external_ids", ExternalIds),
+                ("external_urls", ExternalUrls),
+                ("genres", Any),
+                ("href", Any),
+                ("id", Any),
+                ("images", Image),
+                ("is_playable", Any),
+                ("label", Any),
+                ("name", Any),
+                ("popularity", Any),
+                ("release_date", Any),
+                ("release_date_precision", Any),
+                ("restrictions", Restrictions),
+                ("total_tracks", Any),
+                ("tracks", Page(&Track)),
+                ("type", Any),
+                ("uri", Any),
+            ],
+            Artist => &[
+                ("external_urls", ExternalUrls),
+                ("followers", Followers),
+                ("genres", Any),
+                ("href", Any),
+                ("id", Any),
+                ("images", Image),
+                ("name", Any),
+                ("popularity", Any),
+                ("type", Any),
+                ("uri", Any),
+            ],
+            Image => &[("height", Any), ("url", Any), ("width", Any)],
+            ExternalUrls => &[("spotify", Any)],
+            ExternalIds => &[("ean", Any), ("isrc", Any), ("upc", Any)],
+            Followers => &[("href", Any), ("total", Any)],
+            Restrictions => &[("reason", Any)],
+            Copyright => &[("text", Any), ("type", Any)],
+            Playlist => &[
+                ("collaborative", Any),
+                ("description", Any),
+                ("external_urls", ExternalUrls),
+                ("followers", Followers),
+                ("href", Any),
+                ("id", Any),
+                ("images", Image),
+                ("name", Any),
+                ("owner", User),
+                ("primary_color", Any),
+                ("public", Any),
+                ("snapshot_id", Any),
+                ("tracks", Page(&PlaylistItem)),
+                ("type", Any),
+                ("uri", Any),
+            ],
+            User => &[
+                ("country", Any),
+                ("display_name", Any),
+                ("email", Any),
+                ("explicit_content", ExplicitContent),
+                ("external_urls", ExternalUrls),
+                ("followers", Followers),
+                ("href", Any),
+                ("id", Any),
+                ("images", Image),
+                ("product", Any),
+                ("type", Any),
+                ("uri", Any),
+            ],
+            ExplicitContent => &[("filter_enabled", Any), ("filter_locked", Any)],
+            SavedTrack => &[("added_at", Any), ("track", Track)],
+            SavedAlbum => &[("added_at", Any), ("album", Album)],
+            SavedShow => &[("added_at", Any), ("show", Show)],
+            Category => &[("href", Any), ("icons", Image), ("id", Any), ("name", Any)],
+            CategoriesResponse => &[("categories", Page(&Category))],
+            CategoryPlaylistsResponse => &[("message", Any), ("playlists", Page(&Playlist))],
+            FollowedArtistsResponse => &[("artists", Page(&Artist))],
+            TopTracksResponse => &[("tracks", Track)],
+            SearchResponse => &[
+                ("albums", Page(&Album)),
+                ("artists", Page(&Artist)),
+                ("episodes", Page(&Episode)),
+                ("playlists", Page(&Playlist)),
+                ("shows", Page(&Show)),
+                ("tracks", Page(&Track)),
+            ],
+            Show => &[
+                ("available_markets", Any),
+                ("copyrights", Copyright),
+                ("description", Any),
+                ("episodes", Page(&Episode)),
+                ("explicit", Any),
+                ("external_urls", ExternalUrls),
+                ("href", Any),
+                ("html_description", Any),
+                ("id", Any),
+                ("images", Image),
+                ("is_externally_hosted", Any),
+                ("languages", Any),
+                ("media_type", Any),
+                ("name", Any),
+                ("publisher", Any),
+                ("total_episodes", Any),
+                ("type", Any),
+                ("uri", Any),
+            ],
+            Episode => &[
+                ("audio_preview_url", Any),
+                ("description", Any),
+                ("duration_ms", Any),
+                ("episode", Any),
+                ("explicit", Any),
+                ("external_urls", ExternalUrls),
+                ("href", Any),
+                ("html_description", Any),
+                ("id", Any),
+                ("images", Image),
+                ("is_externally_hosted", Any),
+                ("is_playable", Any),
+                ("language", Any),
+                ("languages", Any),
+                ("name", Any),
+                ("release_date", Any),
+                ("release_date_precision", Any),
+                ("restrictions", Restrictions),
+                ("resume_point", ResumePoint),
+                ("show", Show),
+                ("track", Any),
+                ("type", Any),
+                ("uri", Any),
+            ],
+            ResumePoint => &[("fully_played", Any), ("resume_position_ms", Any)],
+            AudioAnalysis => &[
+                ("bars", TimeInterval),
+                ("beats", TimeInterval),
+                ("meta", AnalysisMeta),
+                ("sections", Section),
+                ("segments", Segment),
+                ("tatums", TimeInterval),
+                ("track", AnalysisTrack),
+            ],
+            AnalysisMeta => &[
+                ("analysis_time", Any),
+                ("analyzer_version", Any),
+                ("detailed_status", Any),
+                ("input_process", Any),
+                ("platform", Any),
+                ("status_code", Any),
+                ("timestamp", Any),
+            ],
+            AnalysisTrack => &[
+                ("analysis_channels", Any),
+                ("analysis_sample_rate", Any),
+                ("code_version", Any),
+                ("codestring", Any),
+                ("duration", Any),
+                ("echoprint_version", Any),
+                ("echoprintstring", Any),
+                ("end_of_fade_in", Any),
+                ("key", Any),
+                ("key_confidence", Any),
+                ("loudness", Any),
+                ("mode", Any),
+                ("mode_confidence", Any),
+                ("num_samples", Any),
+                ("offset_seconds", Any),
+                ("rhythm_version", Any),
+                ("rhythmstring", Any),
+                ("sample_md5", Any),
+                ("start_of_fade_out", Any),
+                ("synch_version", Any),
+                ("synchstring", Any),
+                ("tempo", Any),
+                ("tempo_confidence", Any),
+                ("time_signature", Any),
+                ("time_signature_confidence", Any),
+                ("window_seconds", Any),
+            ],
+            TimeInterval => &[("confidence", Any), ("duration", Any), ("start", Any)],
+            Section => &[
+                ("confidence", Any),
+                ("duration", Any),
+                ("key", Any),
+                ("key_confidence", Any),
+                ("loudness", Any),
+                ("mode", Any),
+                ("mode_confidence", Any),
+                ("start", Any),
+                ("tempo", Any),
+                ("tempo_confidence", Any),
+                ("time_signature", Any),
+                ("time_signature_confidence", Any),
+            ],
+            Segment => &[
+                ("confidence", Any),
+                ("duration", Any),
+                ("loudness_end", Any),
+                ("loudness_max", Any),
+                ("loudness_max_time", Any),
+                ("loudness_start", Any),
+                ("pitches", Any),
+                ("start", Any),
+                ("timbre", Any),
+            ],
+        })
+    }
+
+    /// The shape of a field of this one, or `None` if it has no such
+    /// field.
+    fn field(self, name: &str) -> Option<Shape> {
+        match self {
+            Any => Some(Any),
+            Page(item) => PAGE_FIELDS.contains(&name).then_some(match name {
+                "items" => *item,
+                "cursors" => Cursors,
+                _ => Any,
+            }),
+            _ => self
+                .fields()?
+                .iter()
+                .find(|(field, _)| *field == name)
+                .map(|(_, shape)| *shape),
+        }
+    }
+}
+
+/// What `endpoint`, as `retry::endpoint_class` names it, responds with, or
+/// `None` for endpoints whose responses are not checked.
+fn response_shape(endpoint: &str) -> Option<Shape> {
+    let path = endpoint.strip_prefix("/v1").unwrap_or(endpoint);
+    if path.starts_with("/audio-analysis/") {
+        return Some(AudioAnalysis);
+    }
+    Some(match path {
+        "/me" | "/users/{id}" => User,
+        "/me/playlists" | "/users/{id}/playlists" => Page(&Playlist),
+        "/me/tracks" => Page(&SavedTrack),
+        "/me/albums" => Page(&SavedAlbum),
+        "/me/shows" => Page(&SavedShow),
+        "/me/following" => FollowedArtistsResponse,
+        "/playlists/{id}" => Playlist,
+        "/playlists/{id}/tracks" => Page(&PlaylistItem),
+        "/playlists/{id}/images" => Image,
+        "/artists/{id}/top-tracks" => TopTracksResponse,
+        "/episodes/{id}" => Episode,
+        "/shows/{id}/episodes" => Page(&Episode),
+        "/search" => SearchResponse,
+        "/browse/categories" => CategoriesResponse,
+        _ if path.starts_with("/browse/categories/") => {
+            if path.ends_with("/playlists") {
+                CategoryPlaylistsResponse
+            } else {
+                Category
+            }
+        }
+        _ => return None,
+    })
+}
+
+/// An API response that does not match what this tool knows about.
+#[derive(Debug)]
+pub enum StrictError {
+    /// `field` appeared at `context`, a path such as `items[3].track`.
+    UnknownField { field: String, context: String },
+}
+
+impl fmt::Display for StrictError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StrictError::UnknownField { field, context } => {
+                write!(f, "unknown field {:?} in {}", field, context)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StrictError {}
+
+/// Fails on the first field anywhere in `value` that the object it is on
+/// does not have. `endpoint` is the endpoint that sent `value`, as
+/// `retry::endpoint_class` names it; responses of endpoints not known here
+/// pass unchecked.
+pub fn check_known_fields(value: &Value, endpoint: &str) -> Result<(), StrictError> {
+    match response_shape(endpoint) {
+        Some(shape) => check(value, shape, endpoint),
+        None => Ok(()),
+    }
+}
+
+fn check(value: &Value, shape: Shape, context: &str) -> Result<(), StrictError> {
+    match value {
+        Value::Object(fields) => {
+            let shape = match shape {
+                Playable if value["type"] == "episode" => Episode,
+                Playable => Track,
+                shape => shape,
+            };
+            for (field, value) in fields {
+                let Some(field_shape) = shape.field(field) else {
+                    return Err(StrictError::UnknownField {
+                        field: field.clone(),
+                        context: context.to_string(),
+                    });
+                };
+                check(value, field_shape, &format!("{}.{}", context, field))?;
+            }
+            Ok(())
+        }
+        Value::Array(items) => items
+            .iter()
+            .enumerate()
+            .try_for_each(|(index, item)| check(item, shape, &format!("{}[{}]", context, index))),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_a_cursor_page() {
+        // A followed artists page, as Spotify sends it.
+        let page: Value = serde_json::from_str(
+            r#"{
+                "artists": {
+                    "href": "https://api.spotify.com/v1/me/following?type=artist&limit=1",
+                    "limit": 1,
+                    "next": "https://api.spotify.com/v1/me/following?type=artist&after=0OdUWJ0sBjDrqHygGUXeCF&limit=1",
+                    "cursors": {"after": "0OdUWJ0sBjDrqHygGUXeCF", "before": null},
+                    "total": 12,
+                    "items": [{
+                        "external_urls": {"spotify": "https://open.spotify.com/artist/0OdUWJ0sBjDrqHygGUXeCF"},
+                        "followers": {"href": null, "total": 1031},
+                        "genres": ["indie"],
+                        "href": "https://api.spotify.com/v1/artists/0OdUWJ0sBjDrqHygGUXeCF",
+                        "id": "0OdUWJ0sBjDrqHygGUXeCF",
+                        "images": [{"height": 640, "url": "https://i.scdn.co/image/ab6761610000e5eb", "width": 640}],
+                        "name": "Band of Horses",
+                        "popularity": 65,
+                        "type": "artist",
+                        "uri": "spotify:artist:0OdUWJ0sBjDrqHygGUXeCF"
+                    }]
+                }
+            }"#,
+        )
+        .unwrap();
+        check_known_fields(&page, "/v1/me/following").unwrap();
+    }
+
+    #[test]
+    fn accepts_category_pages() {
+        let categories = serde_json::json!({
+            "categories": {
+                "href": "https://api.spotify.com/v1/browse/categories?offset=0&limit=1",
+                "items": [{
+                    "href": "https://api.spotify.com/v1/browse/categories/toplists",
+                    "icons": [{"height": 275, "url": "https://t.scdn.co/media/derived/toplists.jpg", "width": 275}],
+                    "id": "toplists",
+                    "name": "Top Lists"
+                }],
+                "limit": 1,
+                "next": null,
+                "offset": 0,
+                "previous": null,
+                "total": 1
+            }
+        });
+        check_known_fields(&categories, "/v1/browse/categories").unwrap();
+        let playlists = serde_json::json!({
+            "message": "Popular playlists",
+            "playlists": {
+                "href": "https://api.spotify.com/v1/browse/categories/toplists/playlists",
+                "items": [null],
+                "limit": 1,
+                "next": null,
+                "offset": 0,
+                "previous": null,
+                "total": 1
+            }
+        });
+        check_known_fields(&playlists, "/v1/browse/categories/toplists/playlists").unwrap();
+    }
+
+    #[test]
+    fn names_where_an_unknown_field_is() {
+        let page = serde_json::json!({"items": [{"id": "1"}, {"id": "2", "mood": "calm"}]});
+        let error = check_known_fields(&page, "/v1/me/playlists").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown field \"mood\" in /v1/me/playlists.items[1]"
+        );
+    }
+
+    #[test]
+    fn fields_of_one_object_are_unknown_on_another() {
+        // `key` and `timestamp` belong to audio analyses, not tracks.
+        for field in ["key", "timestamp"] {
+            let mut track = serde_json::json!({
+                "added_at": "2024-01-01T00:00:00Z",
+                "track": {"id": "1", "name": "Song", "type": "track"}
+            });
+            track["track"][field] = serde_json::json!(1);
+            let page = serde_json::json!({"items": [track], "total": 1});
+            let error = check_known_fields(&page, "/v1/playlists/{id}/tracks").unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!(
+                    "unknown field {:?} in /v1/playlists/{{id}}/tracks.items[0].track",
+                    field
+                )
+            );
+        }
+        let analysis = serde_json::json!({
+            "meta": {"timestamp": 1},
+            "sections": [{"key": 5, "start": 0.0}]
+        });
+        check_known_fields(&analysis, "/v1/audio-analysis/1").unwrap();
+        // A page field is not a track field either.
+        let track = serde_json::json!({"id": "1", "next": null});
+        assert!(check_known_fields(
+            &serde_json::json!({"tracks": [track]}),
+            "/v1/artists/{id}/top-tracks"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn playlist_items_are_checked_as_what_their_type_says() {
+        let page = serde_json::json!({
+            "items": [
+                {"track": {"type": "episode", "show": {"name": "Pod"}, "resume_point": {"fully_played": false}}},
+                {"track": {"type": "track", "album": {"name": "Album"}}},
+                {"track": null}
+            ]
+        });
+        check_known_fields(&page, "/v1/playlists/{id}/tracks").unwrap();
+        let page = serde_json::json!({"items": [{"track": {"type": "track", "show": {}}}]});
+        assert!(check_known_fields(&page, "/v1/playlists/{id}/tracks").is_err());
+    }
+}
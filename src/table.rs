@@ -0,0 +1,190 @@
+use std::io::{self, IsTerminal};
+
+/// Widest a column gets when the terminal width is unknown.
+const MAX_COLUMN_WIDTH: usize = 40;
+
+/// Narrowest a column is squeezed to when fitting the terminal.
+const MIN_COLUMN_WIDTH: usize = 6;
+
+/// Rows printed for people or for scripts. On a terminal they become an
+/// aligned table fitted to its width; otherwise, or with `--plain`, they are
+/// tab-separated lines with a header line first. Scripts rely on the plain
+/// form, so columns may be added at the end but never reordered.
+#[derive(Debug)]
+pub struct TableOutput {
+    headers: Vec<&'static str>,
+    right_aligned: Vec<usize>,
+    rows: Vec<Vec<String>>,
+}
+
+impl TableOutput {
+    pub fn new(headers: Vec<&'static str>) -> Self {
+        Self {
+            headers,
+            right_aligned: Vec::new(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Right-aligns the given columns in the table form, for numbers.
+    pub fn align_right(mut self, columns: &[usize]) -> Self {
+        self.right_aligned.extend_from_slice(columns);
+        self
+    }
+
+    pub fn add_row(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    pub fn print(&self, plain: bool) {
+        if plain || !io::stdout().is_terminal() {
+            print!("{}", self.to_plain());
+        } else {
+            print!("{}", self.to_table(terminal_width()));
+        }
+    }
+
+    fn to_plain(&self) -> String {
+        // Tabs and newlines inside a value would break the line format.
+        let clean = |value: &str| value.replace(['\t', '\n', '\r'], " ");
+        let mut out = self.headers.join("\t");
+        out.push('\n');
+        for row in &self.rows {
+            let cells: Vec<String> = row.iter().map(|cell| clean(cell)).collect();
+            out.push_str(&cells.join("\t"));
+            out.push('\n');
+        }
+        out
+    }
+
+    fn to_table(&self, terminal_width: Option<usize>) -> String {
+        let widths = self.column_widths(terminal_width);
+        let mut out = String::new();
+
+        let header: Vec<String> = self.headers.iter().map(|h| h.to_string()).collect();
+        self.push_line(&mut out, &header, &widths);
+        let rule: Vec<String> = widths.iter().map(|&w| "─".repeat(w)).collect();
+        out.push_str(&rule.join("─┼─"));
+        out.push('\n');
+        for row in &self.rows {
+            self.push_line(&mut out, row, &widths);
+        }
+        out
+    }
+
+    fn push_line(&self, out: &mut String, cells: &[String], widths: &[usize]) {
+        let cells: Vec<String> = widths
+            .iter()
+            .enumerate()
+            .map(|(index, &width)| {
+                let cell = cells.get(index).map(String::as_str).unwrap_or_default();
+                let cell = truncate_to_width(&cell.replace(['\t', '\n', '\r'], " "), width);
+                let padding = " ".repeat(width - display_width(&cell));
+                if self.right_aligned.contains(&index) {
+                    padding + &cell
+                } else {
+                    cell + &padding
+                }
+            })
+            .collect();
+        out.push_str(cells.join(" │ ").trim_end());
+        out.push('\n');
+    }
+
+    /// Each column is as wide as its widest value, capped so the table fits
+    /// the terminal. The widest columns give up space first.
+    fn column_widths(&self, terminal_width: Option<usize>) -> Vec<usize> {
+        let mut widths: Vec<usize> = self
+            .headers
+            .iter()
+            .enumerate()
+            .map(|(index, header)| {
+                self.rows
+                    .iter()
+                    .filter_map(|row| row.get(index))
+                    .map(|cell| display_width(cell))
+                    .chain(std::iter::once(display_width(header)))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        let Some(terminal_width) = terminal_width else {
+            widths
+                .iter_mut()
+                .for_each(|w| *w = (*w).min(MAX_COLUMN_WIDTH));
+            return widths;
+        };
+        let separators = 3 * widths.len().saturating_sub(1);
+        while widths.iter().sum::<usize>() + separators > terminal_width {
+            let Some(widest) = widths.iter_mut().filter(|w| **w > MIN_COLUMN_WIDTH).max() else {
+                break;
+            };
+            *widest -= 1;
+        }
+        widths
+    }
+}
+
+/// The width of the terminal standard output is on, or `COLUMNS` when set,
+/// which overrides it. `None` when neither is known.
+pub fn terminal_width() -> Option<usize> {
+    if let Some(columns) = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+    {
+        return Some(columns);
+    }
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+    let (columns, _) = ratatui::crossterm::terminal::size().ok()?;
+    Some(usize::from(columns)).filter(|&columns| columns > 0)
+}
+
+/// Cuts `text` to at most `width` terminal columns, marking the cut with `…`.
+fn truncate_to_width(text: &str, width: usize) -> String {
+    if display_width(text) <= width {
+        return text.to_string();
+    }
+    let mut out = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = char_width(c);
+        if used + w + 1 > width {
+            break;
+        }
+        out.push(c);
+        used += w;
+    }
+    out.push('…');
+    out
+}
+
+fn display_width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
+}
+
+/// Terminal columns a character takes: 2 for CJK and emoji, 0 for combining
+/// marks, joiners and variation selectors, 1 otherwise.
+fn char_width(c: char) -> usize {
+    match u32::from(c) {
+        0x0300..=0x036F | 0x200B..=0x200F | 0x20D0..=0x20FF | 0xFE00..=0xFE0F => 0,
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F680..=0x1F6FF
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
@@ -0,0 +1,80 @@
+use serde::Serialize;
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+use tera::{Context, Tera};
+
+use crate::{
+    paths::OutputPaths,
+    provenance::Provenance,
+    record::TrackRecord,
+    spotify::{Playlist, TrackItem},
+    stats::PlaylistStats,
+};
+
+const TEMPLATE_NAME: &str = "playlist";
+
+/// Renders a user-supplied Tera template once per playlist.
+pub struct TemplateWriter {
+    tera: Tera,
+    extension: String,
+    provenance: Provenance,
+}
+
+/// What a template can use: the playlist as returned by the API, every
+/// fetched item, the rows after filters and sorting, and their statistics.
+#[derive(Serialize)]
+struct TemplateContext<'a> {
+    playlist: &'a Playlist,
+    tracks: &'a [TrackItem],
+    records: &'a [TrackRecord],
+    stats: PlaylistStats,
+    provenance: &'a Provenance,
+}
+
+impl TemplateWriter {
+    /// Output files take the template's inner extension, so `embed.json.tera`
+    /// produces `<playlist>.json`. Without one they end in `.txt`.
+    pub fn from_file(path: &Path, provenance: Provenance) -> Result<Self, Box<dyn Error>> {
+        let mut tera = Tera::default();
+        tera.add_template_file(path, Some(TEMPLATE_NAME))
+            .map_err(|e| format!("could not load template {}: {}", path.display(), e))?;
+
+        let stem = PathBuf::from(path.file_stem().unwrap_or_default());
+        let extension = match (path.extension(), stem.extension()) {
+            (Some(ext), Some(inner)) if ext == "tera" => inner.to_string_lossy().into_owned(),
+            _ => "txt".to_string(),
+        };
+
+        Ok(Self {
+            tera,
+            extension,
+            provenance,
+        })
+    }
+
+    pub fn write(
+        &self,
+        playlist: &Playlist,
+        tracks: &[TrackItem],
+        records: &[TrackRecord],
+        paths: &mut OutputPaths,
+    ) -> Result<String, Box<dyn Error>> {
+        let context = serde_json::to_value(TemplateContext {
+            playlist,
+            tracks,
+            records,
+            stats: PlaylistStats::from_records(records),
+            provenance: &self.provenance,
+        })?;
+        let rendered = self
+            .tera
+            .render(TEMPLATE_NAME, &Context::from_serialize(context)?)?;
+
+        let file_name = paths.reserve(&playlist.name, &self.extension);
+        fs::write(paths.path(&file_name), rendered)?;
+        Ok(file_name)
+    }
+}
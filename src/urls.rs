@@ -0,0 +1,140 @@
+//! Cleaning the URLs that end up in exported records. Share links carry
+//! `?si=` and campaign parameters that identify who shared them and differ
+//! between runs; dropping them keeps the output stable and private.
+
+use reqwest::Url;
+
+/// Query parameters that only track how a link was shared.
+const TRACKING_PARAMS: [&str; 9] = [
+    "si",
+    "utm_source",
+    "utm_medium",
+    "utm_campaign",
+    "utm_term",
+    "utm_content",
+    "context",
+    "nd",
+    "_branch_match_id",
+];
+
+/// Query parameters of signed URLs that stop working after a while.
+const EXPIRING_PARAMS: [&str; 7] = [
+    "expires",
+    "x-amz-expires",
+    "x-amz-signature",
+    "signature",
+    "key-pair-id",
+    "sig",
+    "se",
+];
+
+/// Drops tracking parameters and lowercases the scheme and host. The rest
+/// of the query is kept byte for byte, in its order. Anything that does not
+/// parse as a URL is kept as it is.
+pub fn normalize_url(url: &str) -> String {
+    let Ok(mut parsed) = Url::parse(url) else {
+        return url.to_string();
+    };
+    if let Some(query) = parsed.query() {
+        let kept: Vec<&str> = query
+            .split('&')
+            .filter(|pair| {
+                let key = pair.split('=').next().unwrap_or_default();
+                !TRACKING_PARAMS.contains(&key)
+            })
+            .collect();
+        if kept.len() != query.split('&').count() {
+            let kept = kept.join("&");
+            parsed.set_query((!kept.is_empty()).then_some(kept.as_str()));
+        }
+    }
+    parsed.into()
+}
+
+/// `url` as it goes into the output: normalized unless the user asked to
+/// keep the parameters.
+pub fn output_url(url: &str, keep_params: bool) -> String {
+    if keep_params {
+        url.to_string()
+    } else {
+        normalize_url(url)
+    }
+}
+
+/// Whether `url` carries a signature or expiry, so a link to it is likely
+/// to die.
+pub fn looks_expiring(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|parsed| {
+        parsed
+            .query_pairs()
+            .any(|(key, _)| EXPIRING_PARAMS.contains(&key.to_ascii_lowercase().as_str()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_share_tracking() {
+        assert_eq!(
+            normalize_url("https://open.spotify.com/track/abc?si=123&utm_source=copy-link"),
+            "https://open.spotify.com/track/abc"
+        );
+        assert_eq!(
+            normalize_url("https://example.com/a?b=1&si=2&c=3"),
+            "https://example.com/a?b=1&c=3"
+        );
+    }
+
+    #[test]
+    fn leaves_an_untracked_query_alone() {
+        for url in [
+            "https://i.scdn.co/image/ab67?b=1%2C2&a=x+y",
+            "https://example.com/a?z=1&a=2&z=3",
+            "https://example.com/a?flag&b=%7E",
+        ] {
+            assert_eq!(normalize_url(url), url);
+        }
+    }
+
+    #[test]
+    fn lowercases_scheme_and_host() {
+        assert_eq!(
+            normalize_url("HTTPS://Open.Spotify.COM/track/ABC"),
+            "https://open.spotify.com/track/ABC"
+        );
+    }
+
+    #[test]
+    fn keeps_what_does_not_parse() {
+        assert_eq!(normalize_url("not a url?si=1"), "not a url?si=1");
+    }
+
+    #[test]
+    fn is_deterministic_and_idempotent() {
+        let url = "HTTPS://Open.Spotify.com/playlist/x?si=abc&b=%20&context=y&a=1";
+        let once = normalize_url(url);
+        assert_eq!(once, "https://open.spotify.com/playlist/x?b=%20&a=1");
+        assert_eq!(normalize_url(url), once);
+        assert_eq!(normalize_url(&once), once);
+    }
+
+    #[test]
+    fn keep_params_opts_out() {
+        let url = "https://open.spotify.com/track/abc?si=123";
+        assert_eq!(output_url(url, true), url);
+        assert_eq!(output_url(url, false), "https://open.spotify.com/track/abc");
+    }
+
+    #[test]
+    fn spots_signed_links() {
+        assert!(looks_expiring(
+            "https://cdn.example.com/a.jpg?Expires=1&Signature=x"
+        ));
+        assert!(looks_expiring(
+            "https://x.blob.core.windows.net/a?se=2024&sig=y"
+        ));
+        assert!(!looks_expiring("https://i.scdn.co/image/ab67616d0000b273"));
+    }
+}
@@ -0,0 +1,128 @@
+use log::{error, warn};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::{collections::HashSet, error::Error, fs::File, io::BufWriter, path::Path, sync::Mutex};
+
+pub const EXPORT_WARNINGS_JSON: &str = "export_warnings.json";
+
+/// The exit code of a run stopped by `--strict`, so schedulers can tell an
+/// incomplete backup from a run that could not start.
+pub const STRICT_EXIT_CODE: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Something was skipped or guessed, but the output is complete.
+    Warning,
+    /// Data is missing from the output.
+    Error,
+}
+
+/// A problem that was worth reporting but not worth aborting the export for.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ReportError {
+    pub message: String,
+    pub playlist: String,
+    pub track_uri: Option<String>,
+    pub severity: Severity,
+}
+
+/// Gathers non-fatal problems over a run so they can be reviewed afterwards
+/// instead of scrolling back through the log.
+#[derive(Debug, Default)]
+pub struct ErrorCollector {
+    errors: Mutex<Vec<ReportError>>,
+    /// Under `--strict`, anything that leaves data out is an error.
+    strict: bool,
+}
+
+impl ErrorCollector {
+    pub fn new(strict: bool) -> Self {
+        Self {
+            strict,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Logs the problem and keeps it for `export_warnings.json`.
+    pub fn report(
+        &self,
+        severity: Severity,
+        playlist: &str,
+        track_uri: Option<&str>,
+        message: impl Into<String>,
+    ) {
+        let message = message.into();
+        match severity {
+            Severity::Warning => warn!("{}: {}", playlist, message),
+            Severity::Error => error!("{}: {}", playlist, message),
+        }
+        self.errors.lock().unwrap().push(ReportError {
+            message,
+            playlist: playlist.to_string(),
+            track_uri: track_uri.map(str::to_string),
+            severity,
+        });
+    }
+
+    /// Reports output that differs from what Spotify holds for a reason
+    /// other than a failure, such as unavailable items. A warning, unless
+    /// the run is strict.
+    pub fn report_incomplete(
+        &self,
+        playlist: &str,
+        track_uri: Option<&str>,
+        message: impl Into<String>,
+    ) {
+        let severity = if self.strict {
+            Severity::Error
+        } else {
+            Severity::Warning
+        };
+        self.report(severity, playlist, track_uri, message);
+    }
+
+    /// Every error so far, in the order reported.
+    pub fn violations(&self) -> Vec<ReportError> {
+        let errors = self.errors.lock().unwrap();
+        errors
+            .iter()
+            .filter(|error| error.severity == Severity::Error)
+            .cloned()
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// How many distinct playlists had an error, i.e. are missing data.
+    pub fn incomplete_playlists(&self) -> usize {
+        let errors = self.errors.lock().unwrap();
+        errors
+            .iter()
+            .filter(|error| error.severity == Severity::Error)
+            .map(|error| error.playlist.as_str())
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    /// Writes every collected problem, or nothing when the run was clean.
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let errors = self.errors.lock().unwrap();
+        if errors.is_empty() {
+            return Ok(());
+        }
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, &*errors)?;
+        Ok(())
+    }
+}